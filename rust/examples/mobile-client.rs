@@ -8,15 +8,7 @@ use xaynet_client::mobile_client::{
     participant::{AggregationConfig, ParticipantSettings},
     MobileClient,
 };
-use xaynet_core::mask::{
-    BoundType,
-    DataType,
-    FromPrimitives,
-    GroupType,
-    MaskConfig,
-    Model,
-    ModelType,
-};
+use xaynet_core::mask::{FromPrimitives, Model};
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "Test Drive")]
@@ -45,14 +37,11 @@ fn get_participant_settings() -> ParticipantSettings {
     ParticipantSettings {
         secret_key,
         aggregation_config: AggregationConfig {
-            mask: MaskConfig {
-                group_type: GroupType::Prime,
-                data_type: DataType::F32,
-                bound_type: BoundType::B0,
-                model_type: ModelType::M3,
-            },
             scalar: 1_f64,
+            dp: None,
         },
+        max_chunk_size: None,
+        dp_spent_rounds: 0,
     }
 }
 
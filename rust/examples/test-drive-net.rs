@@ -1,6 +1,8 @@
 #[macro_use]
 extern crate tracing;
 
+use std::{fs, path::PathBuf};
+
 use structopt::StructOpt;
 use tokio::signal;
 use tracing_subscriber::*;
@@ -30,6 +32,17 @@ struct Opt {
     period: u64,
     #[structopt(default_value = "10", short, help = "The number of clients")]
     nb_client: u32,
+    #[structopt(
+        long,
+        help = "The maximum size of a message in bytes, larger messages are sent in chunks"
+    )]
+    max_chunk_size: Option<usize>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "The path to a PEM encoded root certificate to trust in addition to the system's"
+    )]
+    root_certificate: Option<PathBuf>,
 }
 
 /// Test-drive script of a (local, but networked) federated
@@ -48,9 +61,22 @@ async fn main() -> Result<(), ClientError<HttpApiClientError>> {
     let len = opt.len as usize;
     let model = Model::from_primitives(vec![0; len].into_iter()).unwrap();
 
+    let root_certificate = opt.root_certificate.as_ref().map(|path| {
+        fs::read(path).unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e))
+    });
+
     let mut clients = Vec::with_capacity(opt.nb_client as usize);
     for id in 0..opt.nb_client {
-        let mut client = Client::new(opt.period, id, HttpApiClient::new(&opt.url))?;
+        let api_client = match &root_certificate {
+            Some(certificate) => {
+                HttpApiClient::with_root_certificates(&opt.url, Some(certificate))?
+            }
+            None => HttpApiClient::new(&opt.url),
+        };
+        let mut client = Client::new(opt.period, id, api_client)?;
+        if let Some(max_chunk_size) = opt.max_chunk_size {
+            client = client.with_max_chunk_size(max_chunk_size);
+        }
         client.local_model = Some(model.clone());
         let join_hdl = tokio::spawn(async move {
             tokio::select! {
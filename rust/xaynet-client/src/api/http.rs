@@ -1,10 +1,11 @@
-use crate::api::ApiClient;
-use reqwest::{self, Client, Response, StatusCode};
+use crate::api::{ApiClient, RetryableError};
+use reqwest::{self, header::CONTENT_TYPE, Certificate, Client, Response, StatusCode};
 use thiserror::Error;
 use xaynet_core::{
-    common::RoundParameters,
-    crypto::ByteObject,
-    mask::Model,
+    common::{MessageRejection, MessageRejectionBody, RoundParameters, SignedRoundParameters},
+    crypto::{ByteObject, PublicSigningKey},
+    mask::{Model, TypedModel},
+    message::FromBytes,
     SumDict,
     SumParticipantPublicKey,
     UpdateSeedDict,
@@ -18,6 +19,8 @@ pub struct HttpApiClient {
     client: Client,
     /// Coordinator URL
     address: String,
+    /// Pinned long-term public signing key of the coordinator
+    coordinator_key: Option<PublicSigningKey>,
 }
 
 impl HttpApiClient {
@@ -28,8 +31,43 @@ impl HttpApiClient {
         Self {
             client: Client::new(),
             address: address.into(),
+            coordinator_key: None,
         }
     }
+
+    /// Creates a client which additionally trusts the given PEM encoded
+    /// root certificates, e.g. for a coordinator with a self-signed
+    /// certificate.
+    ///
+    /// # Errors
+    /// Fails if a certificate cannot be parsed.
+    pub fn with_root_certificates<S, C>(
+        address: S,
+        certificates: C,
+    ) -> Result<Self, HttpApiClientError>
+    where
+        S: Into<String>,
+        C: IntoIterator,
+        C::Item: AsRef<[u8]>,
+    {
+        let mut builder = Client::builder();
+        for certificate in certificates {
+            builder = builder.add_root_certificate(Certificate::from_pem(certificate.as_ref())?);
+        }
+        Ok(Self {
+            client: builder.build()?,
+            address: address.into(),
+            coordinator_key: None,
+        })
+    }
+
+    /// Pins the long-term public signing key of the coordinator. The
+    /// round parameters are then rejected unless they are signed with
+    /// the corresponding signing key.
+    pub fn with_coordinator_key(mut self, coordinator_key: PublicSigningKey) -> Self {
+        self.coordinator_key = Some(coordinator_key);
+        self
+    }
 }
 
 /// Error returned by an [`HttpApiClient`]
@@ -41,10 +79,28 @@ pub enum HttpApiClientError {
     #[error("HTTP request failed: {0}")]
     Http(#[from] reqwest::Error),
 
+    #[error("the coordinator rejected the message: {0}")]
+    Rejected(MessageRejection),
+
+    #[error("the round parameters are not signed by the pinned coordinator key")]
+    InvalidSignature,
+
     #[error("Unexpected response from the coordinator: {:?}", .0)]
     UnexpectedResponse(Response),
 }
 
+impl RetryableError for HttpApiClientError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            HttpApiClientError::Http(_) => true,
+            HttpApiClientError::Rejected(rejection) => rejection.is_retryable(),
+            HttpApiClientError::Deserialize(_)
+            | HttpApiClientError::InvalidSignature
+            | HttpApiClientError::UnexpectedResponse(_) => false,
+        }
+    }
+}
+
 impl From<bincode::Error> for HttpApiClientError {
     fn from(e: bincode::Error) -> Self {
         Self::Deserialize(format!("{:?}", e))
@@ -65,8 +121,14 @@ impl ApiClient for HttpApiClient {
         let url = format!("{}/params", self.address);
         let resp = self.client.get(&url).send().await?.error_for_status()?;
         if let StatusCode::OK = resp.status() {
-            let body = resp.bytes().await?; //
-            Ok(bincode::deserialize(&body[..])?)
+            let body = resp.bytes().await?;
+            let signed: SignedRoundParameters = bincode::deserialize(&body[..])?;
+            match self.coordinator_key {
+                Some(ref coordinator_key) if !signed.verify(coordinator_key) => {
+                    Err(HttpApiClientError::InvalidSignature)
+                }
+                _ => Ok(signed.params),
+            }
         } else {
             Err(HttpApiClientError::UnexpectedResponse(resp))
         }
@@ -120,11 +182,28 @@ impl ApiClient for HttpApiClient {
 
     async fn get_model(&mut self) -> Result<Option<Model>, Self::Error> {
         let url = format!("{}/model", self.address);
-        let resp = self.client.get(&url).send().await?.error_for_status()?;
+        let resp = self
+            .client
+            .get(&url)
+            .header("Accept", TypedModel::MEDIA_TYPE)
+            .send()
+            .await?
+            .error_for_status()?;
         match resp.status() {
             StatusCode::OK => {
+                // the coordinator falls back to bincode if it can't serve a typed model
+                let is_typed = resp
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .map_or(false, |content_type| content_type == TypedModel::MEDIA_TYPE);
                 let body = resp.bytes().await?;
-                Ok(Some(bincode::deserialize(&body[..])?))
+                if is_typed {
+                    let model = TypedModel::from_bytes(&body)
+                        .map_err(|e| HttpApiClientError::Deserialize(format!("{:?}", e)))?;
+                    Ok(Some(model.into_model()))
+                } else {
+                    Ok(Some(bincode::deserialize(&body[..])?))
+                }
             }
             StatusCode::NO_CONTENT => Ok(None),
             _ => Err(HttpApiClientError::UnexpectedResponse(resp)),
@@ -133,12 +212,18 @@ impl ApiClient for HttpApiClient {
 
     async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), Self::Error> {
         let url = format!("{}/message", self.address);
-        self.client
-            .post(&url)
-            .body(msg)
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+        let resp = self.client.post(&url).body(msg).send().await?;
+        if let Err(e) = resp.error_for_status_ref() {
+            // the coordinator explains why it rejected the message in
+            // the response body
+            return match resp.json::<MessageRejectionBody>().await {
+                Ok(body) => Err(HttpApiClientError::Rejected(body.error)),
+                Err(_) => Err(HttpApiClientError::Http(e)),
+            };
+        }
+        match resp.status() {
+            StatusCode::OK => Ok(()),
+            _ => Err(HttpApiClientError::UnexpectedResponse(resp)),
+        }
     }
 }
@@ -1,7 +1,7 @@
-use crate::api::ApiClient;
+use crate::api::{ApiClient, RetryableError};
 use thiserror::Error;
 use xaynet_core::{
-    common::RoundParameters,
+    common::{MessageRejection, RoundParameters},
     mask::Model,
     SumDict,
     SumParticipantPublicKey,
@@ -42,12 +42,21 @@ pub enum InMemoryApiClientError {
     Fetch(#[from] FetchError),
 }
 
+impl RetryableError for InMemoryApiClientError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            InMemoryApiClientError::Message(err) => MessageRejection::from(err).is_retryable(),
+            InMemoryApiClientError::Fetch(_) => true,
+        }
+    }
+}
+
 #[async_trait]
 impl ApiClient for InMemoryApiClient {
     type Error = InMemoryApiClientError;
 
     async fn get_round_params(&mut self) -> Result<RoundParameters, Self::Error> {
-        Ok(self.fetcher.round_params().await?)
+        Ok(self.fetcher.round_params().await?.params)
     }
 
     async fn get_sums(&mut self) -> Result<Option<SumDict>, Self::Error> {
@@ -74,6 +83,6 @@ impl ApiClient for InMemoryApiClient {
     }
 
     async fn send_message(&mut self, message: Vec<u8>) -> Result<(), Self::Error> {
-        Ok(self.message_handler.handle_message(message).await?)
+        Ok(self.message_handler.handle_message(message, None).await?)
     }
 }
@@ -16,10 +16,17 @@ use xaynet_core::{
     UpdateSeedDict,
 };
 
+/// An error of an [`ApiClient`] which tells whether a failed request may be retried.
+pub trait RetryableError {
+    /// Checks whether the request may succeed when it is sent again, i.e. whether it failed due
+    /// to a transport error or a temporary rejection by the coordinator.
+    fn is_retryable(&self) -> bool;
+}
+
 /// An interface that API clients implement
 #[async_trait]
 pub trait ApiClient {
-    type Error: ::std::fmt::Debug + ::std::error::Error + 'static;
+    type Error: ::std::fmt::Debug + ::std::error::Error + RetryableError + 'static;
 
     /// Retrieve the current round parameters
     async fn get_round_params(&mut self) -> Result<RoundParameters, Self::Error>;
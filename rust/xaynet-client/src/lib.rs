@@ -50,16 +50,29 @@ use std::time::Duration;
 use thiserror::Error;
 use tokio::time;
 
-use xaynet_core::{crypto::ByteObject, mask::Model, CoordinatorPublicKey, InitError};
+use xaynet_core::{
+    common::RoundParameters,
+    crypto::ByteObject,
+    mask::{DpConfig, DpError, MaskConfigPair, MaskingError, Model, PrivacyAccountant},
+    message::Message,
+    CoordinatorPublicKey,
+    InitError,
+};
 
 #[doc(hidden)]
 pub mod mobile_client;
 
 pub mod api;
+use api::RetryableError;
 
+mod multipart;
 mod participant;
 pub use participant::{Participant, Task};
 
+/// The number of times a message (or message chunk) is sent before
+/// giving up.
+const MAX_SEND_ATTEMPTS: usize = 3;
+
 #[derive(Clone, Debug)]
 /// A primitive model cached on the heap.
 ///
@@ -78,6 +91,10 @@ pub enum PetError {
     InvalidMask,
     #[error("Invalid model")]
     InvalidModel,
+    #[error("Masking failed: {0}")]
+    Masking(#[from] MaskingError),
+    #[error("Differential privacy failed: {0}")]
+    Privacy(#[from] DpError),
 }
 
 #[derive(Debug, Error)]
@@ -100,6 +117,10 @@ pub enum ClientError<E: ::std::error::Error + ::std::fmt::Debug + 'static> {
 
     #[error("round outdated")]
     RoundOutdated,
+
+    #[error("the message was rejected permanently: {0}")]
+    /// The coordinator rejected a message, which it won't accept when it is sent again.
+    MessageRejected(E),
 }
 
 /// A client of the federated learning service
@@ -119,6 +140,9 @@ pub struct Client<C: api::ApiClient> {
     coordinator_pk: CoordinatorPublicKey,
     pub has_new_coord_pk_since_last_check: bool,
 
+    /// Masking configurations of the current round
+    mask_config: MaskConfigPair,
+
     pub global_model: Option<Model>,
     pub cached_model: Option<CachedModel>,
     pub has_new_global_model_since_last_check: bool,
@@ -128,6 +152,12 @@ pub struct Client<C: api::ApiClient> {
     pub local_model: Option<Model>,
     pub scalar: f64,
 
+    /// Maximum size in bytes of a message before it is split into chunks
+    max_chunk_size: Option<usize>,
+
+    /// Accountant of the privacy budget, if differential privacy is enabled
+    privacy_accountant: Option<PrivacyAccountant>,
+
     /// Identifier for this client
     id: u32,
 
@@ -154,6 +184,7 @@ where
             interval: time::interval(Duration::from_secs(period)),
             coordinator_pk: CoordinatorPublicKey::zeroed(),
             has_new_coord_pk_since_last_check: false,
+            mask_config: RoundParameters::default().mask_config,
 
             global_model: None,
             cached_model: None,
@@ -163,11 +194,47 @@ where
             local_model: None,
             scalar: 1.0,
 
+            max_chunk_size: None,
+
+            privacy_accountant: None,
+
             id,
             client: api,
         })
     }
 
+    /// Sets the maximum size in bytes of the messages sent by the
+    /// [`Client`]. Larger messages are split into chunks.
+    pub fn with_max_chunk_size(mut self, max_chunk_size: usize) -> Self {
+        self.max_chunk_size = Some(max_chunk_size);
+        self
+    }
+
+    /// Enables client-level differential privacy. The local model is
+    /// privatized with the Gaussian mechanism configured by `config`
+    /// before it is masked, as long as the privacy budget suffices.
+    pub fn with_differential_privacy(mut self, config: DpConfig) -> Self {
+        self.privacy_accountant = Some(PrivacyAccountant::new(config));
+        self
+    }
+
+    /// Enables client-level differential privacy with an accountant
+    /// which may have spent some of the privacy budget already, e.g.
+    /// the [`privacy_accountant()`] of a previous [`Client`] restored
+    /// via [`PrivacyAccountant::with_spent_rounds()`].
+    ///
+    /// [`privacy_accountant()`]: Client::privacy_accountant
+    pub fn with_privacy_accountant(mut self, accountant: PrivacyAccountant) -> Self {
+        self.privacy_accountant = Some(accountant);
+        self
+    }
+
+    /// Gets the accountant of the privacy budget, if differential
+    /// privacy is enabled.
+    pub fn privacy_accountant(&self) -> Option<&PrivacyAccountant> {
+        self.privacy_accountant.as_ref()
+    }
+
     /// Starts the [`Client`] loop, iterating indefinitely over each federated
     /// learning round.
     ///
@@ -204,6 +271,7 @@ where
             if round_params.pk != self.coordinator_pk {
                 debug!(client_id = %self.id, "new round parameters received, determining task.");
                 self.coordinator_pk = round_params.pk;
+                self.mask_config = round_params.mask_config;
                 let round_seed = round_params.seed.as_slice();
                 self.participant.compute_signatures(round_seed);
                 let (sum_frac, upd_frac) = (round_params.sum, round_params.update);
@@ -236,9 +304,7 @@ where
     async fn summer(&mut self) -> Result<Task, ClientError<C::Error>> {
         info!(client_id = %self.id, "selected to sum");
         let msg = self.participant.compose_sum_message(self.coordinator_pk);
-        let sealed_msg = self.participant.seal_message(&self.coordinator_pk, &msg);
-
-        self.client.send_message(sealed_msg).await?;
+        self.send_message(&msg).await?;
 
         debug!(client_id = %self.id, "polling for model/mask length");
         let length = loop {
@@ -259,13 +325,12 @@ where
                 debug!(client_id = %self.id, "seed dict received, sending sum2 message.");
                 let msg = self
                     .participant
-                    .compose_sum2_message(self.coordinator_pk, &seeds, length)
+                    .compose_sum2_message(self.coordinator_pk, &seeds, length, self.mask_config)
                     .map_err(|e| {
                         error!("failed to compose sum2 message with seeds: {:?}", &seeds);
                         ClientError::ParticipantErr(e)
                     })?;
-                let sealed_msg = self.participant.seal_message(&self.coordinator_pk, &msg);
-                self.client.send_message(sealed_msg).await?;
+                self.send_message(&msg).await?;
 
                 info!(client_id = %self.id, "sum participant completed a round");
                 break Ok(Task::Sum);
@@ -294,14 +359,27 @@ where
         loop {
             if let Some(sums) = self.client.get_sums().await? {
                 debug!(client_id = %self.id, "sum dict received, sending update message.");
-                let msg = self.participant.compose_update_message(
-                    self.coordinator_pk,
-                    &sums,
-                    self.scalar,
-                    model,
-                );
-                let sealed_msg = self.participant.seal_message(&self.coordinator_pk, &msg);
-                self.client.send_message(sealed_msg).await?;
+                let model = match self.privacy_accountant {
+                    Some(ref mut accountant) => accountant.privatize(model).map_err(|e| {
+                        error!("failed to privatize the local model: {}", e);
+                        ClientError::ParticipantErr(e.into())
+                    })?,
+                    None => model,
+                };
+                let msg = self
+                    .participant
+                    .compose_update_message(
+                        self.coordinator_pk,
+                        &sums,
+                        self.scalar,
+                        model,
+                        self.mask_config,
+                    )
+                    .map_err(|e| {
+                        error!("failed to compose update message: {}", e);
+                        ClientError::ParticipantErr(e)
+                    })?;
+                self.send_message(&msg).await?;
 
                 info!(client_id = %self.id, "update participant completed a round");
                 break Ok(Task::Update);
@@ -311,6 +389,48 @@ where
         }
     }
 
+    /// Seals the message and sends it to the coordinator, split into
+    /// chunks if it exceeds the maximum chunk size. Chunks that cannot
+    /// be sent due to a retryable error are retried until
+    /// [`MAX_SEND_ATTEMPTS`] is reached. A permanent rejection of a
+    /// chunk gives up on the whole message.
+    async fn send_message(&mut self, msg: &Message) -> Result<(), ClientError<C::Error>> {
+        let mut pending = match self.max_chunk_size {
+            Some(max_chunk_size) => {
+                self.participant
+                    .seal_message_chunks(&self.coordinator_pk, msg, max_chunk_size)
+            }
+            None => vec![self.participant.seal_message(&self.coordinator_pk, msg)],
+        };
+
+        let mut attempt = 1;
+        loop {
+            let mut failed = Vec::new();
+            let mut error = None;
+            for sealed_msg in pending {
+                if let Err(err) = self.client.send_message(sealed_msg.clone()).await {
+                    if !err.is_retryable() {
+                        warn!(client_id = %self.id, "message rejected: {:?}", err);
+                        return Err(ClientError::MessageRejected(err));
+                    }
+                    warn!(client_id = %self.id, "failed to send message: {:?}", err);
+                    failed.push(sealed_msg);
+                    error = Some(err);
+                }
+            }
+            match error {
+                None => return Ok(()),
+                Some(err) if attempt == MAX_SEND_ATTEMPTS => return Err(err.into()),
+                Some(_) => {
+                    debug!(client_id = %self.id, "retrying {} message(s)", failed.len());
+                    pending = failed;
+                    attempt += 1;
+                    self.interval.tick().await;
+                }
+            }
+        }
+    }
+
     fn set_global_model(&mut self, model: Model) {
         debug!(client_id = %self.id, "updating global model");
         self.global_model = Some(model);
@@ -1,5 +1,5 @@
 use crate::{
-    api::ApiClient,
+    api::{ApiClient, RetryableError},
     mobile_client::participant::{
         Awaiting,
         Participant,
@@ -12,7 +12,12 @@ use crate::{
     ClientError,
 };
 use derive_more::From;
-use xaynet_core::{common::RoundParameters, crypto::ByteObject, mask::Model, InitError};
+use xaynet_core::{
+    common::RoundParameters,
+    crypto::ByteObject,
+    mask::{Model, PrivacyAccountant},
+    InitError,
+};
 
 use crate::PetError;
 
@@ -25,6 +30,9 @@ pub trait LocalModel {
 pub struct ClientState<Type> {
     participant: Participant<Type>,
     round_params: RoundParameters,
+    /// The sealed messages (or message chunks) of the current task
+    /// that have not been sent successfully yet.
+    pending_messages: Vec<Vec<u8>>,
 }
 
 impl<Type> ClientState<Type> {
@@ -42,6 +50,34 @@ impl<Type> ClientState<Type> {
         }
     }
 
+    /// Sends the pending messages. The messages that cannot be sent
+    /// due to a retryable error remain pending, so that only those are
+    /// sent again on the next attempt. A permanent rejection of a
+    /// message discards all the pending messages.
+    async fn send_pending_messages<T: ApiClient>(
+        &mut self,
+        api: &mut T,
+    ) -> Result<(), ClientError<T::Error>> {
+        let mut failed = Vec::new();
+        let mut error = None;
+        for message in self.pending_messages.drain(..) {
+            if let Err(err) = api.send_message(message.clone()).await {
+                if !err.is_retryable() {
+                    warn!("message rejected: {:?}", err);
+                    return Err(ClientError::MessageRejected(err));
+                }
+                warn!("failed to send message: {:?}", err);
+                failed.push(message);
+                error = Some(err);
+            }
+        }
+        self.pending_messages = failed;
+        match error {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+
     fn reset(self) -> ClientState<Awaiting> {
         warn!("reset client");
         ClientState::<Awaiting>::new(self.participant.reset(), self.round_params)
@@ -53,6 +89,7 @@ impl ClientState<Awaiting> {
         Self {
             participant,
             round_params,
+            pending_messages: Vec::new(),
         }
     }
 
@@ -76,6 +113,7 @@ impl ClientState<Awaiting> {
         let Self {
             participant,
             round_params,
+            ..
         } = self;
 
         match participant.determine_role(
@@ -100,6 +138,7 @@ impl ClientState<Sum> {
         Self {
             participant,
             round_params,
+            pending_messages: Vec::new(),
         }
     }
 
@@ -108,7 +147,9 @@ impl ClientState<Sum> {
 
         match self.run(api).await {
             Ok(_) => self.into_sum2().into(),
-            Err(ClientError::RoundOutdated) => self.reset().into(),
+            Err(ClientError::RoundOutdated) | Err(ClientError::MessageRejected(_)) => {
+                self.reset().into()
+            }
             Err(err) => {
                 error!("{:?}", err);
                 self.into()
@@ -119,13 +160,15 @@ impl ClientState<Sum> {
     async fn run<T: ApiClient>(&mut self, api: &mut T) -> Result<(), ClientError<T::Error>> {
         self.check_round_freshness(api).await?;
 
-        let sum_msg = self.participant.compose_sum_message(self.round_params.pk);
-        let sealed_msg = self
-            .participant
-            .seal_message(&self.round_params.pk, &sum_msg);
+        if self.pending_messages.is_empty() {
+            let sum_msg = self.participant.compose_sum_message(self.round_params.pk);
+            self.pending_messages = self
+                .participant
+                .seal_message(&self.round_params.pk, &sum_msg);
+        }
 
         debug!("sending sum message");
-        api.send_message(sealed_msg).await?;
+        self.send_pending_messages(api).await?;
         debug!("sum message sent");
         Ok(())
     }
@@ -140,6 +183,7 @@ impl ClientState<Update> {
         Self {
             participant,
             round_params,
+            pending_messages: Vec::new(),
         }
     }
 
@@ -151,7 +195,9 @@ impl ClientState<Update> {
         info!("selected to update");
 
         match self.run(api, local_model).await {
-            Ok(_) | Err(ClientError::RoundOutdated) => self.reset().into(),
+            Ok(_) | Err(ClientError::RoundOutdated) | Err(ClientError::MessageRejected(_)) => {
+                self.reset().into()
+            }
             Err(err) => {
                 error!("{:?}", err);
                 self.into()
@@ -166,6 +212,13 @@ impl ClientState<Update> {
     ) -> Result<(), ClientError<T::Error>> {
         self.check_round_freshness(api).await?;
 
+        if !self.pending_messages.is_empty() {
+            debug!("sending remaining parts of the update message");
+            self.send_pending_messages(api).await?;
+            info!("update participant completed a round");
+            return Ok(());
+        }
+
         debug!("polling for local model");
         let local_model = local_model
             .get_local_model()
@@ -178,15 +231,24 @@ impl ClientState<Update> {
             .await?
             .ok_or(ClientError::TooEarly("sum dict"))?;
 
-        let upd_msg =
-            self.participant
-                .compose_update_message(self.round_params.pk, &sums, local_model);
-        let sealed_msg = self
+        let upd_msg = self
+            .participant
+            .compose_update_message(
+                self.round_params.pk,
+                &sums,
+                local_model,
+                self.round_params.mask_config,
+            )
+            .map_err(|e| {
+                error!("failed to compose update message: {}", e);
+                ClientError::ParticipantErr(e)
+            })?;
+        self.pending_messages = self
             .participant
             .seal_message(&self.round_params.pk, &upd_msg);
 
         debug!("sending update message");
-        api.send_message(sealed_msg).await?;
+        self.send_pending_messages(api).await?;
         info!("update participant completed a round");
         Ok(())
     }
@@ -197,6 +259,7 @@ impl ClientState<Sum2> {
         Self {
             participant,
             round_params,
+            pending_messages: Vec::new(),
         }
     }
 
@@ -204,7 +267,9 @@ impl ClientState<Sum2> {
         info!("selected to sum2");
 
         match self.run(api).await {
-            Ok(_) | Err(ClientError::RoundOutdated) => self.reset().into(),
+            Ok(_) | Err(ClientError::RoundOutdated) | Err(ClientError::MessageRejected(_)) => {
+                self.reset().into()
+            }
             Err(err) => {
                 error!("{:?}", err);
                 self.into()
@@ -215,6 +280,13 @@ impl ClientState<Sum2> {
     async fn run<T: ApiClient>(&mut self, api: &mut T) -> Result<(), ClientError<T::Error>> {
         self.check_round_freshness(api).await?;
 
+        if !self.pending_messages.is_empty() {
+            debug!("sending remaining parts of the sum2 message");
+            self.send_pending_messages(api).await?;
+            info!("sum participant completed a round");
+            return Ok(());
+        }
+
         debug!("polling for model/mask length");
         let length = api
             .get_mask_length()
@@ -232,17 +304,22 @@ impl ClientState<Sum2> {
 
         let sum2_msg = self
             .participant
-            .compose_sum2_message(self.round_params.pk, &seeds, length as usize)
+            .compose_sum2_message(
+                self.round_params.pk,
+                &seeds,
+                length as usize,
+                self.round_params.mask_config,
+            )
             .map_err(|e| {
                 error!("failed to compose sum2 message with seeds: {:?}", &seeds);
                 ClientError::ParticipantErr(e)
             })?;
-        let sealed_msg = self
+        self.pending_messages = self
             .participant
             .seal_message(&self.round_params.pk, &sum2_msg);
 
         debug!("sending sum2 message");
-        api.send_message(sealed_msg).await?;
+        self.send_pending_messages(api).await?;
         info!("sum participant completed a round");
         Ok(())
     }
@@ -276,4 +353,117 @@ impl ClientStateMachine {
             ClientStateMachine::Sum2(state) => state.next(api).await,
         }
     }
+
+    /// Gets the accountant of the privacy budget, if differential privacy is enabled.
+    pub fn privacy_accountant(&self) -> Option<&PrivacyAccountant> {
+        match self {
+            ClientStateMachine::Awaiting(state) => state.participant.privacy_accountant(),
+            ClientStateMachine::Sum(state) => state.participant.privacy_accountant(),
+            ClientStateMachine::Update(state) => state.participant.privacy_accountant(),
+            ClientStateMachine::Sum2(state) => state.participant.privacy_accountant(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::HttpApiClientError,
+        mobile_client::{participant::AggregationConfig, MobileClient},
+    };
+    use xaynet_core::{
+        common::MessageRejection,
+        mask::Model,
+        SumDict,
+        SumParticipantPublicKey,
+        UpdateSeedDict,
+    };
+
+    /// An API client which rejects every message with the given rejection.
+    struct RejectingApi {
+        rejection: MessageRejection,
+        sent: usize,
+    }
+
+    #[async_trait]
+    impl ApiClient for RejectingApi {
+        type Error = HttpApiClientError;
+
+        async fn get_round_params(&mut self) -> Result<RoundParameters, Self::Error> {
+            unimplemented!()
+        }
+
+        async fn get_sums(&mut self) -> Result<Option<SumDict>, Self::Error> {
+            unimplemented!()
+        }
+
+        async fn get_seeds(
+            &mut self,
+            _pk: SumParticipantPublicKey,
+        ) -> Result<Option<UpdateSeedDict>, Self::Error> {
+            unimplemented!()
+        }
+
+        async fn get_mask_length(&mut self) -> Result<Option<u64>, Self::Error> {
+            unimplemented!()
+        }
+
+        async fn get_model(&mut self) -> Result<Option<Model>, Self::Error> {
+            unimplemented!()
+        }
+
+        async fn send_message(&mut self, _msg: Vec<u8>) -> Result<(), Self::Error> {
+            self.sent += 1;
+            Err(HttpApiClientError::Rejected(self.rejection))
+        }
+    }
+
+    fn client_state() -> ClientState<Awaiting> {
+        let settings = ParticipantSettings {
+            secret_key: MobileClient::create_participant_secret_key(),
+            aggregation_config: AggregationConfig {
+                scalar: 1.0,
+                dp: None,
+            },
+            max_chunk_size: None,
+            dp_spent_rounds: 0,
+        };
+        let mut state = ClientState::<Awaiting>::new(
+            Participant::<Awaiting>::new(settings.into()),
+            RoundParameters::default(),
+        );
+        state.pending_messages = vec![vec![1], vec![2]];
+        state
+    }
+
+    #[tokio::test]
+    async fn test_send_pending_messages_retryable() {
+        let mut state = client_state();
+        let mut api = RejectingApi {
+            rejection: MessageRejection::Overloaded,
+            sent: 0,
+        };
+        assert!(matches!(
+            state.send_pending_messages(&mut api).await,
+            Err(ClientError::Api(_))
+        ));
+        assert_eq!(api.sent, 2);
+        assert_eq!(state.pending_messages, vec![vec![1], vec![2]]);
+    }
+
+    #[tokio::test]
+    async fn test_send_pending_messages_rejected() {
+        let mut state = client_state();
+        let mut api = RejectingApi {
+            rejection: MessageRejection::NotUpdateEligible,
+            sent: 0,
+        };
+        assert!(matches!(
+            state.send_pending_messages(&mut api).await,
+            Err(ClientError::MessageRejected(_))
+        ));
+        assert_eq!(api.sent, 1);
+        assert!(state.pending_messages.is_empty());
+    }
 }
@@ -0,0 +1,173 @@
+//! The unversioned client state of earlier releases.
+//!
+//! Earlier releases serialized the [`ClientStateMachine`] without a version. Such a state is
+//! migrated on restore by keeping the participant credentials and its scalar. The task of the
+//! current round is abandoned, hence the client awaits its task in the next round.
+use xaynet_core::{
+    common::RoundSeed,
+    crypto::SigningKeyPair,
+    mask::MaskConfig,
+    CoordinatorPublicKey,
+    InitError,
+    ParticipantTaskSignature,
+    SumParticipantEphemeralPublicKey,
+    SumParticipantEphemeralSecretKey,
+};
+
+use crate::mobile_client::{
+    client::ClientStateMachine as CurrentClientStateMachine,
+    participant::{AggregationConfig as CurrentAggregationConfig, ParticipantSettings},
+};
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct AggregationConfig {
+    mask: MaskConfig,
+    scalar: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct ParticipantState {
+    keys: SigningKeyPair,
+    aggregation_config: AggregationConfig,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct Participant<Task> {
+    inner: Task,
+    state: ParticipantState,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct Awaiting;
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct Sum {
+    ephm_pk: SumParticipantEphemeralPublicKey,
+    ephm_sk: SumParticipantEphemeralSecretKey,
+    sum_signature: ParticipantTaskSignature,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct Update {
+    sum_signature: ParticipantTaskSignature,
+    update_signature: ParticipantTaskSignature,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct Sum2 {
+    ephm_pk: SumParticipantEphemeralPublicKey,
+    ephm_sk: SumParticipantEphemeralSecretKey,
+    sum_signature: ParticipantTaskSignature,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct RoundParameters {
+    pk: CoordinatorPublicKey,
+    sum: f64,
+    update: f64,
+    seed: RoundSeed,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct ClientState<Task> {
+    participant: Participant<Task>,
+    round_params: RoundParameters,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) enum ClientStateMachine {
+    Awaiting(ClientState<Awaiting>),
+    Sum(ClientState<Sum>),
+    Update(ClientState<Update>),
+    Sum2(ClientState<Sum2>),
+}
+
+impl ClientStateMachine {
+    /// Migrates the state to the current [`ClientStateMachine`], which awaits its task.
+    ///
+    /// [`ClientStateMachine`]: CurrentClientStateMachine
+    pub(super) fn migrate(self) -> Result<CurrentClientStateMachine, InitError> {
+        let state = match self {
+            Self::Awaiting(state) => state.participant.state,
+            Self::Sum(state) => state.participant.state,
+            Self::Update(state) => state.participant.state,
+            Self::Sum2(state) => state.participant.state,
+        };
+        CurrentClientStateMachine::new(ParticipantSettings {
+            secret_key: state.keys.secret,
+            aggregation_config: CurrentAggregationConfig {
+                scalar: state.aggregation_config.scalar,
+                dp: None,
+            },
+            max_chunk_size: None,
+            dp_spent_rounds: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xaynet_core::{
+        crypto::{ByteObject, EncryptKeyPair},
+        mask::{BoundType, DataType, GroupType, ModelType},
+    };
+
+    use super::*;
+    use crate::mobile_client::{ClientStateName, MobileClient};
+
+    #[test]
+    fn test_migrate() {
+        sodiumoxide::init().unwrap();
+        let keys = SigningKeyPair::generate();
+        let ephm_keys = EncryptKeyPair::generate();
+        let legacy_state = ClientStateMachine::Sum(ClientState {
+            participant: Participant {
+                inner: Sum {
+                    ephm_pk: ephm_keys.public,
+                    ephm_sk: ephm_keys.secret,
+                    sum_signature: ParticipantTaskSignature::zeroed(),
+                },
+                state: ParticipantState {
+                    keys: keys.clone(),
+                    aggregation_config: AggregationConfig {
+                        mask: MaskConfig {
+                            group_type: GroupType::Prime,
+                            data_type: DataType::F32,
+                            bound_type: BoundType::B0,
+                            model_type: ModelType::M3,
+                        },
+                        scalar: 0.5,
+                    },
+                },
+            },
+            round_params: RoundParameters {
+                pk: CoordinatorPublicKey::generate(),
+                sum: 0.5,
+                update: 0.5,
+                seed: RoundSeed::generate(),
+            },
+        });
+        let bytes = bincode::serialize(&legacy_state).unwrap();
+
+        // the participant keeps its credentials and scalar, but awaits its task
+        let client = MobileClient::restore("http://localhost:8081", &bytes).unwrap();
+        assert!(matches!(
+            client.get_current_state(),
+            ClientStateName::Awaiting
+        ));
+        let expected = MobileClient::init(
+            "http://localhost:8081",
+            ParticipantSettings {
+                secret_key: keys.secret,
+                aggregation_config: CurrentAggregationConfig {
+                    scalar: 0.5,
+                    dp: None,
+                },
+                max_chunk_size: None,
+                dp_spent_rounds: 0,
+            },
+        )
+        .unwrap();
+        assert_eq!(client.serialize(), expected.serialize());
+    }
+}
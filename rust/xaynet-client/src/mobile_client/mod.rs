@@ -1,4 +1,5 @@
 pub mod client;
+mod legacy;
 pub mod participant;
 
 use crate::{
@@ -10,8 +11,8 @@ use crate::{
 };
 use thiserror::Error;
 use xaynet_core::{
-    crypto::{SecretSigningKey, SigningKeyPair},
-    mask::Model,
+    crypto::{PublicSigningKey, SecretSigningKey, SigningKeyPair},
+    mask::{Model, PrivacyAccountant},
     InitError,
 };
 
@@ -30,8 +31,18 @@ pub enum MobileClientError {
     #[error("API request failed: {0}")]
     /// API request failed.
     Api(#[from] HttpApiClientError),
+    #[error("unsupported version {0} of the serialized mobile client")]
+    /// The serialized mobile client has an unsupported version.
+    Version(u16),
 }
 
+/// The magic bytes which prefix a serialized client state. The unversioned states of earlier
+/// releases start with the bincode encoded enum variant index of the state instead.
+const STATE_MAGIC: &[u8; 4] = b"XNMC";
+
+/// The version of the serialized client state.
+const STATE_VERSION: u16 = 1;
+
 pub struct MobileClient {
     api: HttpApiClient,
     local_model: LocalModelCache,
@@ -63,12 +74,24 @@ impl MobileClient {
 
     /// Restores a client from its serialized state.
     ///
+    /// The unversioned states of earlier releases are migrated by keeping the participant
+    /// credentials and aggregation configuration. The task of the current round is abandoned in
+    /// this case, hence the client awaits its task in the next round.
+    ///
     /// # Errors
     ///
-    /// Fails if the serialized state is corrupted and the client cannot be restored
-    /// or if the crypto module cannot be initialized.
+    /// Fails if the serialized state is corrupted or of an unsupported version and the client
+    /// cannot be restored or if the crypto module cannot be initialized.
     pub fn restore(url: &str, bytes: &[u8]) -> Result<Self, MobileClientError> {
-        let client_state: ClientStateMachine = bincode::deserialize(bytes)?;
+        let client_state = if bytes.len() >= 6 && bytes[..4] == STATE_MAGIC[..] {
+            match u16::from_le_bytes([bytes[4], bytes[5]]) {
+                STATE_VERSION => bincode::deserialize(&bytes[6..])?,
+                version => return Err(MobileClientError::Version(version)),
+            }
+        } else {
+            warn!("migrating the unversioned state of an earlier release");
+            bincode::deserialize::<legacy::ClientStateMachine>(bytes)?.migrate()?
+        };
         Ok(Self::new(url, client_state))
     }
 
@@ -82,8 +105,21 @@ impl MobileClient {
         }
     }
 
+    /// Pins the long-term public signing key of the coordinator. The
+    /// client then only proceeds with round parameters which are signed
+    /// with the corresponding signing key.
+    ///
+    /// The key is not part of the serialized state, hence it must be
+    /// pinned again after the client is restored.
+    pub fn with_coordinator_key(mut self, coordinator_key: PublicSigningKey) -> Self {
+        self.api = self.api.with_coordinator_key(coordinator_key);
+        self
+    }
+
     /// Serializes the current state of the client.
     ///
+    /// The state is prefixed with its version, so that later releases can restore it.
+    ///
     /// # Note
     ///
     /// The serialized state is **not encrypted** and contains sensitive data such as the
@@ -100,7 +136,10 @@ impl MobileClient {
         // - https://github.com/servo/bincode/issues/293
         // - https://github.com/servo/bincode/issues/255
         // - https://github.com/servo/bincode/issues/130#issuecomment-284641263
-        bincode::serialize(&self.client_state).unwrap()
+        let mut bytes = STATE_MAGIC.to_vec();
+        bytes.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(&self.client_state).unwrap());
+        bytes
     }
 
     /// Fetches and returns the latest global model from the coordinator.
@@ -157,6 +196,12 @@ impl MobileClient {
         }
     }
 
+    /// Returns the accountant of the privacy budget, which keeps track of the privacy budget
+    /// spent over the rounds. Returns `None` if differential privacy is disabled.
+    pub fn get_privacy_accountant(&self) -> Option<&PrivacyAccountant> {
+        self.client_state.privacy_accountant()
+    }
+
     /// Sets the local model.
     ///
     /// The local model is only sent if the client has been selected as an update client.
@@ -208,3 +253,71 @@ impl LocalModel for LocalModelCache {
         self.0.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mobile_client::participant::AggregationConfig;
+    use xaynet_core::mask::DpConfig;
+
+    fn client() -> MobileClient {
+        MobileClient::init(
+            "http://localhost:8081",
+            ParticipantSettings {
+                secret_key: MobileClient::create_participant_secret_key(),
+                aggregation_config: AggregationConfig {
+                    scalar: 1.0,
+                    dp: None,
+                },
+                max_chunk_size: Some(1024),
+                dp_spent_rounds: 0,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_serialize_and_restore() {
+        let bytes = client().serialize();
+        assert_eq!(&bytes[..4], STATE_MAGIC);
+        let restored = MobileClient::restore("http://localhost:8081", &bytes).unwrap();
+        assert_eq!(restored.serialize(), bytes);
+    }
+
+    #[test]
+    fn test_restore_unsupported_version() {
+        let mut bytes = client().serialize();
+        bytes[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            MobileClient::restore("http://localhost:8081", &bytes),
+            Err(MobileClientError::Version(version)) if version == STATE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_init_with_spent_privacy_budget() {
+        let dp = DpConfig {
+            clipping_bound: 1.,
+            noise_multiplier: 1.,
+            delta: 1e-5,
+            max_epsilon: None,
+        };
+        let client = MobileClient::init(
+            "http://localhost:8081",
+            ParticipantSettings {
+                secret_key: MobileClient::create_participant_secret_key(),
+                aggregation_config: AggregationConfig {
+                    scalar: 1.0,
+                    dp: Some(dp),
+                },
+                max_chunk_size: None,
+                dp_spent_rounds: 3,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            client.get_privacy_accountant(),
+            Some(&PrivacyAccountant::with_spent_rounds(dp, 3))
+        );
+    }
+}
@@ -52,7 +52,6 @@ mod tests {
     use sodiumoxide::randombytes::randombytes;
     use xaynet_core::{
         crypto::{ByteObject, SigningKeyPair},
-        mask::{BoundType, DataType, GroupType, MaskConfig, ModelType},
         ParticipantPublicKey,
         ParticipantSecretKey,
     };
@@ -61,18 +60,14 @@ mod tests {
         sodiumoxide::init().unwrap();
 
         let aggregation_config = AggregationConfig {
-            mask: MaskConfig {
-                group_type: GroupType::Prime,
-                data_type: DataType::F32,
-                bound_type: BoundType::B0,
-                model_type: ModelType::M3,
-            },
-
             scalar: 1_f64,
+            dp: None,
         };
         ParticipantState {
             keys: SigningKeyPair::generate(),
             aggregation_config,
+            max_chunk_size: None,
+            privacy_accountant: None,
         }
     }
 
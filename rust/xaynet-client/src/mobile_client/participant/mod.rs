@@ -6,12 +6,14 @@
 use derive_more::From;
 use xaynet_core::{
     crypto::SigningKeyPair,
-    mask::MaskConfig,
+    mask::{DpConfig, PrivacyAccountant},
     message::Message,
     CoordinatorPublicKey,
     ParticipantSecretKey,
 };
 
+use crate::multipart;
+
 pub mod awaiting;
 pub mod sum;
 pub mod sum2;
@@ -21,22 +23,37 @@ pub use self::{awaiting::Awaiting, sum::Sum, sum2::Sum2, update::Update};
 
 #[derive(Serialize, Deserialize)]
 pub struct AggregationConfig {
-    pub mask: MaskConfig,
     pub scalar: f64,
+    /// The configuration of the differential privacy of the local
+    /// model. `None` disables the differential privacy.
+    pub dp: Option<DpConfig>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ParticipantState {
     // credentials
     pub keys: SigningKeyPair,
-    // Mask config
+    // Aggregation config
     pub aggregation_config: AggregationConfig,
+    // Maximum size in bytes of a message before it is split into chunks
+    pub max_chunk_size: Option<usize>,
+    // Accountant of the privacy budget, if differential privacy is enabled
+    pub privacy_accountant: Option<PrivacyAccountant>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ParticipantSettings {
     pub secret_key: ParticipantSecretKey,
     pub aggregation_config: AggregationConfig,
+    /// The maximum size in bytes of a message. Larger messages are
+    /// split into chunks. `None` disables the splitting.
+    pub max_chunk_size: Option<usize>,
+    /// The number of rounds in which the privacy budget has already
+    /// been spent, e.g. by a previous installation of the
+    /// participant. It is ignored if the differential privacy is
+    /// disabled.
+    #[serde(default)]
+    pub dp_spent_rounds: u32,
 }
 
 impl From<ParticipantSettings> for ParticipantState {
@@ -44,6 +61,8 @@ impl From<ParticipantSettings> for ParticipantState {
         ParticipantSettings {
             secret_key,
             aggregation_config,
+            max_chunk_size,
+            dp_spent_rounds,
         }: ParticipantSettings,
     ) -> ParticipantState {
         ParticipantState {
@@ -51,7 +70,11 @@ impl From<ParticipantSettings> for ParticipantState {
                 public: secret_key.public_key(),
                 secret: secret_key,
             },
+            privacy_accountant: aggregation_config
+                .dp
+                .map(|dp| PrivacyAccountant::with_spent_rounds(dp, dp_spent_rounds)),
             aggregation_config,
+            max_chunk_size,
         }
     }
 }
@@ -74,11 +97,23 @@ impl<Task> Participant<Task> {
     ///
     /// The message is signed with the participant secret signing
     /// key. `pk` is the coordinator public key, used to encrypt the
-    /// final message
-    pub fn seal_message(&self, pk: &CoordinatorPublicKey, message: &Message) -> Vec<u8> {
-        let mut buf = vec![0; message.buffer_length()];
-        message.to_bytes(&mut buf, &self.state.keys.secret);
-        pk.encrypt(&buf[..])
+    /// final message. If the signed message exceeds the maximum chunk
+    /// size, it is split into chunks which are signed and encrypted
+    /// separately.
+    pub fn seal_message(&self, pk: &CoordinatorPublicKey, message: &Message) -> Vec<Vec<u8>> {
+        let secret = &self.state.keys.secret;
+        match self.state.max_chunk_size {
+            Some(max_chunk_size) => {
+                multipart::seal_message_chunks(message, secret, pk, max_chunk_size)
+            }
+            None => vec![pk.encrypt(&multipart::serialize_message(message, secret)[..])],
+        }
+    }
+
+    /// Gets the accountant of the privacy budget, if differential
+    /// privacy is enabled.
+    pub fn privacy_accountant(&self) -> Option<&PrivacyAccountant> {
+        self.state.privacy_accountant.as_ref()
     }
 
     /// Resets the client.
@@ -1,6 +1,7 @@
 use super::{Participant, ParticipantState};
+use crate::PetError;
 use xaynet_core::{
-    mask::{MaskObject, MaskSeed, Masker, Model},
+    mask::{MaskConfigPair, MaskObject, MaskSeed, Masker, Model},
     message::{Message, Update as UpdateMessage},
     CoordinatorPublicKey,
     LocalSeedDict,
@@ -29,18 +30,34 @@ impl Participant<Update> {
     }
 
     /// Compose an update message given the coordinator public key, sum
-    /// dictionary, model scalar and local model update.
+    /// dictionary, local model update and masking configurations of the
+    /// round.
+    ///
+    /// If differential privacy is enabled, the local model is
+    /// privatized and a round of the privacy budget is spent. The
+    /// local model is clipped to the bounds of the masking
+    /// configuration before it is masked.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PetError`] if the privacy budget is exhausted or if
+    /// the local model can't be masked, for example because the model
+    /// scalar is out of bounds.
     pub fn compose_update_message(
-        &self,
+        &mut self,
         coordinator_pk: CoordinatorPublicKey,
         sum_dict: &SumDict,
-
         local_model: Model,
-    ) -> Message {
-        let (mask_seed, masked_model, masked_scalar) = self.mask_model(local_model);
+        mask_config: MaskConfigPair,
+    ) -> Result<Message, PetError> {
+        let local_model = match self.state.privacy_accountant {
+            Some(ref mut accountant) => accountant.privatize(local_model)?,
+            None => local_model,
+        };
+        let (mask_seed, masked_model, masked_scalar) = self.mask_model(local_model, mask_config)?;
         let local_seed_dict = Self::create_local_seed_dict(sum_dict, &mask_seed);
 
-        Message {
+        Ok(Message {
             signature: None,
             participant_pk: self.state.keys.public,
             coordinator_pk,
@@ -52,13 +69,20 @@ impl Participant<Update> {
                 local_seed_dict,
             }
             .into(),
-        }
+        })
     }
 
-    /// Generate a mask seed and mask a local model.
-    fn mask_model(&self, local_model: Model) -> (MaskSeed, MaskObject, MaskObject) {
-        Masker::new(self.state.aggregation_config.mask)
-            .mask(self.state.aggregation_config.scalar, local_model)
+    /// Generate a mask seed and mask a local model, which is clipped
+    /// to the bounds of the masking configuration beforehand.
+    fn mask_model(
+        &self,
+        local_model: Model,
+        mask_config: MaskConfigPair,
+    ) -> Result<(MaskSeed, MaskObject, MaskObject), PetError> {
+        let scalar = self.state.aggregation_config.scalar;
+        let masker = Masker::new(mask_config);
+        let local_model = masker.clip(scalar, local_model);
+        masker.mask(scalar, local_model).map_err(PetError::Masking)
     }
 
     // Create a local seed dictionary from a sum dictionary.
@@ -73,10 +97,22 @@ impl Participant<Update> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mobile_client::participant::AggregationConfig;
     use sodiumoxide::randombytes::{randombytes, randombytes_uniform};
     use std::{collections::HashMap, iter};
     use xaynet_core::{
-        crypto::{ByteObject, EncryptKeyPair},
+        crypto::{ByteObject, EncryptKeyPair, SigningKeyPair},
+        mask::{
+            BoundType,
+            DataType,
+            DpConfig,
+            DpError,
+            FromPrimitives,
+            GroupType,
+            MaskConfig,
+            ModelType,
+            PrivacyAccountant,
+        },
         SumParticipantEphemeralPublicKey,
         SumParticipantEphemeralSecretKey,
         SumParticipantPublicKey,
@@ -109,4 +145,56 @@ mod tests {
             mask_seed == seed.decrypt(ephm_pk, ephm_sk).unwrap()
         }));
     }
+
+    #[test]
+    fn test_compose_update_message_spends_privacy_budget() {
+        sodiumoxide::init().unwrap();
+        let keys = SigningKeyPair::generate();
+        let dp = DpConfig {
+            clipping_bound: 1.,
+            noise_multiplier: 1.,
+            delta: 1e-5,
+            max_epsilon: Some(6.),
+        };
+        let state = ParticipantState {
+            aggregation_config: AggregationConfig {
+                scalar: 1_f64,
+                dp: Some(dp),
+            },
+            max_chunk_size: None,
+            privacy_accountant: Some(PrivacyAccountant::new(dp)),
+            keys: keys.clone(),
+        };
+        let mut part = Participant::<Update>::new(
+            state,
+            keys.secret.sign_detached(b"sum"),
+            keys.secret.sign_detached(b"update"),
+        );
+
+        let coordinator_pk = EncryptKeyPair::generate().public;
+        let sum_dict = iter::once((
+            SumParticipantPublicKey::from_slice(&randombytes(32)).unwrap(),
+            EncryptKeyPair::generate().public,
+        ))
+        .collect();
+        let model = Model::from_primitives(vec![0_f32; 10].into_iter()).unwrap();
+        let mask_config: MaskConfigPair = MaskConfig {
+            group_type: GroupType::Prime,
+            data_type: DataType::F32,
+            bound_type: BoundType::B0,
+            model_type: ModelType::M3,
+        }
+        .into();
+
+        // ε is about 5.3 after the first round and about 7.8 after the second round
+        assert!(part
+            .compose_update_message(coordinator_pk, &sum_dict, model.clone(), mask_config)
+            .is_ok());
+        assert_eq!(part.privacy_accountant().unwrap().rounds(), 1);
+        assert!(matches!(
+            part.compose_update_message(coordinator_pk, &sum_dict, model, mask_config),
+            Err(PetError::Privacy(DpError::BudgetExhausted))
+        ));
+        assert_eq!(part.privacy_accountant().unwrap().rounds(), 1);
+    }
 }
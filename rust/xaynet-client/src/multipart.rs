@@ -0,0 +1,138 @@
+//! Provides the splitting of large PET messages into chunks.
+//!
+//! See the [client module] documentation since this is a private module anyways.
+//!
+//! [client module]: ../index.html
+
+use sodiumoxide::randombytes::randombytes_uniform;
+use xaynet_core::{
+    message::{Chunk, Message},
+    CoordinatorPublicKey,
+    ParticipantSecretKey,
+};
+
+/// The maximum number of chunks a message can be split into.
+const MAX_CHUNKS: usize = u16::MAX as usize + 1;
+
+/// Serialize the given message and sign it with the participant
+/// secret key.
+pub(crate) fn serialize_message(message: &Message, sk: &ParticipantSecretKey) -> Vec<u8> {
+    let mut buf = vec![0; message.buffer_length()];
+    message.to_bytes(&mut buf, sk);
+    buf
+}
+
+/// Serialize, sign and encrypt the given message. If the signed
+/// message is larger than `max_chunk_size` bytes, it is split into
+/// chunks instead, each of which is signed and encrypted separately.
+///
+/// The sealed messages are returned in the order of their chunk IDs.
+/// A message is split into at most 65536 chunks, so the chunks of very
+/// large messages may be larger than `max_chunk_size`.
+pub(crate) fn seal_message_chunks(
+    message: &Message,
+    sk: &ParticipantSecretKey,
+    coordinator_pk: &CoordinatorPublicKey,
+    max_chunk_size: usize,
+) -> Vec<Vec<u8>> {
+    let data = serialize_message(message, sk);
+    if data.len() <= max_chunk_size {
+        return vec![coordinator_pk.encrypt(&data[..])];
+    }
+
+    let chunk_size = max_chunk_size.max((data.len() + MAX_CHUNKS - 1) / MAX_CHUNKS);
+    let message_id = randombytes_uniform(MAX_CHUNKS as u32) as u16;
+    let last_id = (data.len() - 1) / chunk_size;
+    data.chunks(chunk_size)
+        .enumerate()
+        .map(|(id, data)| {
+            let chunk = Message {
+                signature: None,
+                participant_pk: message.participant_pk,
+                coordinator_pk: *coordinator_pk,
+                payload: Chunk {
+                    id: id as u16,
+                    message_id,
+                    last: id == last_id,
+                    data: data.to_vec(),
+                }
+                .into(),
+            };
+            coordinator_pk.encrypt(&serialize_message(&chunk, sk)[..])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use xaynet_core::{
+        crypto::{EncryptKeyPair, SigningKeyPair},
+        message::{Payload, Sum},
+    };
+
+    use super::*;
+
+    fn sum_message(keys: &SigningKeyPair, coordinator_pk: CoordinatorPublicKey) -> Message {
+        Message {
+            signature: None,
+            participant_pk: keys.public,
+            coordinator_pk,
+            payload: Sum {
+                sum_signature: keys.secret.sign_detached(b"sum"),
+                ephm_pk: EncryptKeyPair::generate().public,
+            }
+            .into(),
+        }
+    }
+
+    fn open_message(sealed: &[u8], coordinator_keys: &EncryptKeyPair) -> Message {
+        let data = coordinator_keys
+            .secret
+            .decrypt(sealed, &coordinator_keys.public)
+            .unwrap();
+        Message::from_bytes(&data).unwrap()
+    }
+
+    #[test]
+    fn test_small_message() {
+        sodiumoxide::init().unwrap();
+        let keys = SigningKeyPair::generate();
+        let coordinator_keys = EncryptKeyPair::generate();
+        let message = sum_message(&keys, coordinator_keys.public);
+
+        let sealed = seal_message_chunks(&message, &keys.secret, &coordinator_keys.public, 1024);
+        assert_eq!(sealed.len(), 1);
+        let opened = open_message(&sealed[0], &coordinator_keys);
+        assert_eq!(opened.payload, message.payload);
+    }
+
+    #[test]
+    fn test_large_message() {
+        sodiumoxide::init().unwrap();
+        let keys = SigningKeyPair::generate();
+        let coordinator_keys = EncryptKeyPair::generate();
+        let message = sum_message(&keys, coordinator_keys.public);
+        let data = serialize_message(&message, &keys.secret);
+
+        let sealed = seal_message_chunks(&message, &keys.secret, &coordinator_keys.public, 50);
+        assert_eq!(sealed.len(), (data.len() + 49) / 50);
+
+        let mut reassembled = Vec::new();
+        let mut message_ids = Vec::new();
+        for (i, sealed_chunk) in sealed.iter().enumerate() {
+            let opened = open_message(sealed_chunk, &coordinator_keys);
+            assert_eq!(opened.participant_pk, keys.public);
+            match opened.payload {
+                Payload::Chunk(chunk) => {
+                    assert_eq!(chunk.id as usize, i);
+                    assert_eq!(chunk.last, i == sealed.len() - 1);
+                    message_ids.push(chunk.message_id);
+                    reassembled.extend(chunk.data);
+                }
+                _ => panic!("expected a chunk"),
+            }
+        }
+        assert!(message_ids.iter().all(|id| *id == message_ids[0]));
+        assert_eq!(reassembled, data);
+    }
+}
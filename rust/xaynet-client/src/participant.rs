@@ -8,18 +8,7 @@ use std::default::Default;
 
 use xaynet_core::{
     crypto::{ByteObject, EncryptKeyPair, SigningKeyPair},
-    mask::{
-        Aggregation,
-        BoundType,
-        DataType,
-        GroupType,
-        MaskConfig,
-        MaskObject,
-        MaskSeed,
-        Masker,
-        Model,
-        ModelType,
-    },
+    mask::{Aggregation, MaskConfigPair, MaskObject, MaskSeed, Masker, Model},
     message::{Message, Sum, Sum2, Update},
     CoordinatorPublicKey,
     InitError,
@@ -33,7 +22,7 @@ use xaynet_core::{
     UpdateSeedDict,
 };
 
-use crate::PetError;
+use crate::{multipart, PetError};
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 /// Tasks of a participant.
@@ -137,18 +126,29 @@ impl Participant {
     }
 
     /// Compose an update message given the coordinator public key, sum
-    /// dictionary, model scalar and local model update.
+    /// dictionary, model scalar, local model update and masking
+    /// configurations of the round.
+    ///
+    /// The local model is clipped to the bounds of the masking
+    /// configuration before it is masked.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PetError`] if the local model can't be masked, for
+    /// example because the model scalar is out of bounds.
     pub fn compose_update_message(
         &self,
         coordinator_pk: CoordinatorPublicKey,
         sum_dict: &SumDict,
         scalar: f64,
         local_model: Model,
-    ) -> Message {
-        let (mask_seed, masked_model, masked_scalar) = Self::mask_model(scalar, local_model);
+        mask_config: MaskConfigPair,
+    ) -> Result<Message, PetError> {
+        let (mask_seed, masked_model, masked_scalar) =
+            Self::mask_model(scalar, local_model, mask_config)?;
         let local_seed_dict = Self::create_local_seed_dict(sum_dict, &mask_seed);
 
-        Message {
+        Ok(Message {
             signature: None,
             participant_pk: self.pk,
             coordinator_pk,
@@ -160,11 +160,11 @@ impl Participant {
                 local_seed_dict,
             }
             .into(),
-        }
+        })
     }
 
-    /// Compose a sum2 message given the coordinator public key, seed dictionary
-    /// and mask length.
+    /// Compose a sum2 message given the coordinator public key, seed dictionary,
+    /// mask length and masking configurations of the round.
     ///
     /// # Errors
     ///
@@ -175,10 +175,11 @@ impl Participant {
         coordinator_pk: CoordinatorPublicKey,
         seed_dict: &UpdateSeedDict,
         mask_len: usize,
+        mask_config: MaskConfigPair,
     ) -> Result<Message, PetError> {
         let mask_seeds = self.get_seeds(seed_dict)?;
         let (model_mask, scalar_mask) =
-            self.compute_global_mask(mask_seeds, mask_len, dummy_config())?;
+            self.compute_global_mask(mask_seeds, mask_len, mask_config)?;
         Ok(Message {
             signature: None,
             participant_pk: self.pk,
@@ -195,9 +196,20 @@ impl Participant {
     /// Sign the given message with the participant secret key, and
     /// encrypt the signed message with the given public key.
     pub fn seal_message(&self, pk: &CoordinatorPublicKey, message: &Message) -> Vec<u8> {
-        let mut buf = vec![0; message.buffer_length()];
-        message.to_bytes(&mut buf, &self.sk);
-        pk.encrypt(&buf[..])
+        pk.encrypt(&multipart::serialize_message(message, &self.sk)[..])
+    }
+
+    /// Sign the given message with the participant secret key, and
+    /// encrypt the signed message with the given public key. If the
+    /// signed message is larger than `max_chunk_size` bytes, it is
+    /// split into chunks which are signed and encrypted separately.
+    pub fn seal_message_chunks(
+        &self,
+        pk: &CoordinatorPublicKey,
+        message: &Message,
+        max_chunk_size: usize,
+    ) -> Vec<Vec<u8>> {
+        multipart::seal_message_chunks(message, &self.sk, pk, max_chunk_size)
     }
 
     /// Generate an ephemeral encryption key pair.
@@ -207,10 +219,16 @@ impl Participant {
         self.ephm_sk = secret;
     }
 
-    /// Generate a mask seed and mask a local model.
-    fn mask_model(scalar: f64, local_model: Model) -> (MaskSeed, MaskObject, MaskObject) {
-        // TODO: use proper config
-        Masker::new(dummy_config()).mask(scalar, local_model)
+    /// Generate a mask seed and mask a local model, which is clipped
+    /// to the bounds of the masking configuration beforehand.
+    fn mask_model(
+        scalar: f64,
+        local_model: Model,
+        mask_config: MaskConfigPair,
+    ) -> Result<(MaskSeed, MaskObject, MaskObject), PetError> {
+        let masker = Masker::new(mask_config);
+        let local_model = masker.clip(scalar, local_model);
+        masker.mask(scalar, local_model).map_err(PetError::Masking)
     }
 
     // Create a local seed dictionary from a sum dictionary.
@@ -237,14 +255,14 @@ impl Participant {
         &self,
         mask_seeds: Vec<MaskSeed>,
         mask_len: usize,
-        mask_config: MaskConfig,
+        mask_config: MaskConfigPair,
     ) -> Result<(MaskObject, MaskObject), PetError> {
         if mask_seeds.is_empty() {
             return Err(PetError::InvalidMask);
         }
 
-        let mut model_mask_agg = Aggregation::new(mask_config, mask_len);
-        let mut scalar_mask_agg = Aggregation::new(mask_config, 1);
+        let mut model_mask_agg = Aggregation::new(mask_config.vect, mask_len);
+        let mut scalar_mask_agg = Aggregation::new(mask_config.unit, 1);
         for seed in mask_seeds.into_iter() {
             let (model_mask, scalar_mask) = seed.derive_mask(mask_len, mask_config);
 
@@ -396,12 +414,3 @@ mod tests {
         );
     }
 }
-
-fn dummy_config() -> MaskConfig {
-    MaskConfig {
-        group_type: GroupType::Prime,
-        data_type: DataType::F32,
-        bound_type: BoundType::B0,
-        model_type: ModelType::M3,
-    }
-}
@@ -0,0 +1,91 @@
+//! Benchmarks the aggregation of masked models of 1M weights.
+//!
+//! The aggregation picks fixed-width integers for the arithmetic if the group order of the masking
+//! configuration allows it. The benchmark compares it against the arbitrary precision arithmetic
+//! for configurations of each width. Run it with `cargo bench -p xaynet-core`.
+
+use std::time::{Duration, Instant};
+
+use num::bigint::BigUint;
+use xaynet_core::{
+    crypto::ByteObject,
+    mask::{
+        Aggregation,
+        BoundType,
+        DataType,
+        GroupType,
+        MaskConfig,
+        MaskObject,
+        MaskSeed,
+        ModelType,
+    },
+};
+
+const MODEL_SIZE: usize = 1_000_000;
+const NB_MODELS: usize = 5;
+
+/// Generates random mask objects, which are as good as masked models for the aggregation.
+fn objects(config: MaskConfig) -> Vec<MaskObject> {
+    (0..NB_MODELS)
+        .map(|_| {
+            MaskSeed::generate()
+                .derive_mask(MODEL_SIZE, config.into())
+                .0
+        })
+        .collect()
+}
+
+/// Aggregates the objects with the arbitrary precision arithmetic only.
+fn aggregate_biguint(config: MaskConfig, objects: Vec<MaskObject>) -> Duration {
+    let order = config.order();
+    let start = Instant::now();
+    let mut objects = objects.into_iter();
+    let mut aggregated: Vec<BigUint> = objects.next().unwrap().data;
+    for object in objects {
+        for (i, j) in aggregated.iter_mut().zip(object.data.into_iter()) {
+            *i = (&*i + j) % &order
+        }
+    }
+    start.elapsed()
+}
+
+/// Aggregates the objects with the arithmetic picked by the aggregation.
+fn aggregate(config: MaskConfig, objects: Vec<MaskObject>) -> Duration {
+    let start = Instant::now();
+    let mut aggregation = Aggregation::new(config, MODEL_SIZE);
+    for object in objects {
+        aggregation.aggregate(object);
+    }
+    start.elapsed()
+}
+
+/// Gets the aggregation throughput in million weights per second.
+fn throughput(duration: Duration) -> f64 {
+    (MODEL_SIZE * NB_MODELS) as f64 / duration.as_secs_f64() / 1e6
+}
+
+fn main() {
+    for &(data_type, bound_type) in &[
+        (DataType::F32, BoundType::B0),
+        (DataType::F64, BoundType::B6),
+        (DataType::F64, BoundType::Bmax),
+    ] {
+        let config = MaskConfig {
+            group_type: GroupType::Prime,
+            data_type,
+            bound_type,
+            model_type: ModelType::M3,
+        };
+        let biguint = throughput(aggregate_biguint(config, objects(config)));
+        let picked = throughput(aggregate(config, objects(config)));
+        println!(
+            "{:?}/{:?} ({} bits): {:.2} M weights/s with BigUint, {:.2} M weights/s picked ({:.1}x)",
+            data_type,
+            bound_type,
+            config.order().bits(),
+            biguint,
+            picked,
+            picked / biguint,
+        );
+    }
+}
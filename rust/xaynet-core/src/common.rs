@@ -1,6 +1,12 @@
 use sodiumoxide::{self, crypto::box_};
+use thiserror::Error;
 
-use crate::{crypto::ByteObject, CoordinatorPublicKey};
+use crate::{
+    crypto::{ByteObject, PublicSigningKey, SecretSigningKey, Signature},
+    mask::{BoundType, DataType, GroupType, MaskConfig, MaskConfigPair, ModelType},
+    message::ToBytes,
+    CoordinatorPublicKey,
+};
 
 /// The round parameters.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -13,6 +19,8 @@ pub struct RoundParameters {
     pub update: f64,
     /// The random round seed.
     pub seed: RoundSeed,
+    /// The masking configurations of the model weights and the scalar.
+    pub mask_config: MaskConfigPair,
 }
 
 impl Default for RoundParameters {
@@ -22,10 +30,74 @@ impl Default for RoundParameters {
             sum: 0.0,
             update: 0.0,
             seed: RoundSeed::zeroed(),
+            mask_config: MaskConfig {
+                group_type: GroupType::Prime,
+                data_type: DataType::F32,
+                bound_type: BoundType::B0,
+                model_type: ModelType::M3,
+            }
+            .into(),
         }
     }
 }
 
+/// The prefix of the signed round parameters, which separates their signatures from the ones of
+/// any other data signed with the same key.
+const ROUND_PARAMETERS_DOMAIN: &[u8] = b"xaynet-round-parameters-v1";
+
+impl RoundParameters {
+    /// Signs the round parameters with the long-term signing key of the coordinator.
+    pub fn sign(&self, sk: &SecretSigningKey) -> Signature {
+        sk.sign_detached(&self.signed_bytes())
+    }
+
+    /// Verifies the signature of the round parameters against the long-term public signing key of
+    /// the coordinator.
+    pub fn verify(&self, pk: &PublicSigningKey, signature: &Signature) -> bool {
+        pk.verify_detached(signature, &self.signed_bytes())
+    }
+
+    /// Gets the signed data, i.e. the domain separation prefix followed by the coordinator public
+    /// key, the little-endian fractions, the round seed and the serialized masking configurations
+    /// of the model weights and the scalar.
+    fn signed_bytes(&self) -> Vec<u8> {
+        let serialize = |config: &MaskConfig| {
+            let mut buffer = vec![0; config.buffer_length()];
+            config.to_bytes(&mut buffer);
+            buffer
+        };
+        [
+            ROUND_PARAMETERS_DOMAIN,
+            self.pk.as_slice(),
+            &self.sum.to_le_bytes(),
+            &self.update.to_le_bytes(),
+            self.seed.as_slice(),
+            &serialize(&self.mask_config.vect),
+            &serialize(&self.mask_config.unit),
+        ]
+        .concat()
+    }
+}
+
+/// The round parameters as served by the coordinator.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SignedRoundParameters {
+    /// The round parameters.
+    pub params: RoundParameters,
+    /// The signature of the round parameters by the long-term signing key of the coordinator, see
+    /// [`RoundParameters::sign()`]. It is missing if the coordinator has no signing key.
+    pub signature: Option<Signature>,
+}
+
+impl SignedRoundParameters {
+    /// Verifies that the round parameters are signed by the coordinator with the given long-term
+    /// public signing key.
+    pub fn verify(&self, pk: &PublicSigningKey) -> bool {
+        self.signature
+            .map_or(false, |signature| self.params.verify(pk, &signature))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 /// A seed for a round.
 pub struct RoundSeed(box_::Seed);
@@ -51,3 +123,139 @@ impl ByteObject for RoundSeed {
         self.0.as_ref()
     }
 }
+
+/// The reason why the coordinator rejected a PET message.
+///
+/// It is serialized as a stable `snake_case` code, e.g. `"not_update_eligible"`, which doesn't
+/// depend on the order of the variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Error)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageRejection {
+    #[error("the message could not be decrypted with the coordinator secret key")]
+    Decrypt,
+
+    #[error("the message could not be parsed")]
+    Parsing,
+
+    #[error("the message signature is invalid")]
+    InvalidSignature,
+
+    #[error("the message was not encrypted for the current round")]
+    InvalidCoordinatorPublicKey,
+
+    #[error("the message was not expected in the current phase")]
+    UnexpectedMessage,
+
+    #[error("the participant is not eligible for the sum task")]
+    NotSumEligible,
+
+    #[error("the participant is not eligible for the update task")]
+    NotUpdateEligible,
+
+    #[error("the multipart message exceeds the maximum message size")]
+    MessageTooLarge,
+
+    #[error("the coordinator cannot buffer more chunks at the moment")]
+    BufferFull,
+
+    #[error("the chunk does not belong to a valid multipart message")]
+    InvalidChunk,
+
+    #[error("the message was rejected by the coordinator")]
+    Rejected,
+
+    #[error("the model or scalar of the update could not be aggregated")]
+    AggregationFailed,
+
+    #[error("the local seed dictionary of the update is invalid")]
+    InvalidLocalSeedDict,
+
+    #[error("the coordinator cannot process more messages at the moment")]
+    Overloaded,
+
+    #[error("the message could not be processed due to an internal error")]
+    InternalError,
+
+    #[error("the participant is not registered at the coordinator")]
+    UnknownParticipant,
+
+    #[error("the message exceeds the rate limit")]
+    RateLimited,
+
+    #[error("the message has already been received in the current round")]
+    Replay,
+}
+
+/// The body of the error responses to a PET message.
+///
+/// The coordinator sends it as JSON, e.g. `{"error":"not_update_eligible"}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageRejectionBody {
+    /// The reason why the message was rejected.
+    pub error: MessageRejection,
+}
+
+impl MessageRejection {
+    /// Checks whether the message may be accepted when it is sent again.
+    ///
+    /// Only a coordinator which is temporarily overloaded may accept the message later, whereas
+    /// all other rejections are permanent for the message.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            MessageRejection::Overloaded
+                | MessageRejection::BufferFull
+                | MessageRejection::RateLimited
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{EncryptKeyPair, SigningKeyPair};
+
+    #[test]
+    fn test_signed_round_parameters() {
+        let keys = SigningKeyPair::generate();
+        let params = RoundParameters {
+            pk: EncryptKeyPair::generate().public,
+            sum: 0.01,
+            update: 0.1,
+            seed: RoundSeed::generate(),
+            ..RoundParameters::default()
+        };
+        let signed = SignedRoundParameters {
+            signature: Some(params.sign(&keys.secret)),
+            params: params.clone(),
+        };
+        assert!(signed.verify(&keys.public));
+        assert!(!signed.verify(&SigningKeyPair::generate().public));
+
+        let tampered = SignedRoundParameters {
+            params: RoundParameters {
+                sum: 1.,
+                ..params.clone()
+            },
+            ..signed
+        };
+        assert!(!tampered.verify(&keys.public));
+
+        let mut mask_config = params.mask_config;
+        mask_config.unit.data_type = DataType::F64;
+        let tampered = SignedRoundParameters {
+            params: RoundParameters {
+                mask_config,
+                ..params.clone()
+            },
+            ..signed
+        };
+        assert!(!tampered.verify(&keys.public));
+
+        let unsigned = SignedRoundParameters {
+            params,
+            signature: None,
+        };
+        assert!(!unsigned.verify(&keys.public));
+    }
+}
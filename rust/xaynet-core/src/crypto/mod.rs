@@ -75,4 +75,58 @@ pub trait ByteObject: Sized {
     fn fill_with(value: u8) -> Self {
         Self::from_slice_unchecked(&vec![value; Self::LENGTH])
     }
+
+    /// Gets the lowercase hex encoding of the object byte representation.
+    fn to_hex(&self) -> String {
+        self.as_slice()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Creates an object from its hex encoding. Both lowercase and uppercase digits are accepted.
+    ///
+    /// # Errors
+    /// Returns `None` if the string is not a valid hex encoding of an object.
+    fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != 2 * Self::LENGTH || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .ok()?;
+        Self::from_slice(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex() {
+        let pk = SigningKeyPair::generate().public;
+        let hex = pk.to_hex();
+        assert_eq!(hex.len(), 2 * PublicSigningKey::LENGTH);
+        assert_eq!(PublicSigningKey::from_hex(&hex), Some(pk));
+        assert_eq!(PublicSigningKey::from_hex(&hex.to_uppercase()), Some(pk));
+
+        let seed = SigningKeySeed::fill_with(0xab);
+        assert_eq!(seed.to_hex(), "ab".repeat(SigningKeySeed::LENGTH));
+    }
+
+    #[test]
+    fn test_hex_invalid() {
+        let hex = SigningKeyPair::generate().public.to_hex();
+        for invalid in &[
+            hex[1..].to_string(),
+            format!("+{}", &hex[1..]),
+            format!("{}00", hex),
+            format!("{}\u{e9}", &hex[2..]),
+        ] {
+            assert_eq!(PublicSigningKey::from_hex(invalid), None);
+        }
+    }
 }
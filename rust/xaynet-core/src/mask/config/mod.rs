@@ -632,3 +632,25 @@ impl MaskConfig {
         BigUint::from_str_radix(order_str, 10).unwrap()
     }
 }
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A pair of masking configurations.
+///
+/// The model weights are masked wrt the `vect` configuration and the scalar wrt the `unit`
+/// configuration, since the scalar usually has much tighter bounds than the scaled weights.
+pub struct MaskConfigPair {
+    /// The masking configuration of the model weights.
+    pub vect: MaskConfig,
+    /// The masking configuration of the scalar.
+    pub unit: MaskConfig,
+}
+
+impl From<MaskConfig> for MaskConfigPair {
+    /// Creates a pair which masks both the model weights and the scalar wrt the same `config`.
+    fn from(config: MaskConfig) -> Self {
+        Self {
+            vect: config,
+            unit: config,
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Differential privacy of local models.
+//!
+//! See the [mask module] documentation since this is a private module anyways.
+//!
+//! [mask module]: ../index.html
+
+use std::f64::consts::PI;
+
+use num::{bigint::BigInt, rational::Ratio, traits::Signed};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::mask::model::{float_to_ratio_bounded, ratio_to_float, Model};
+
+#[derive(Debug, Error, Eq, PartialEq)]
+/// Errors related to the differential privacy of local models.
+pub enum DpError {
+    #[error("the differential privacy configuration is invalid")]
+    InvalidConfig,
+
+    #[error("the privacy budget is exhausted")]
+    BudgetExhausted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// A configuration of the Gaussian mechanism for client-level differential privacy.
+///
+/// A local model is clipped to the L2 norm `clipping_bound` and Gaussian noise with the standard
+/// deviation `noise_multiplier * clipping_bound` is added to each of its weights.
+pub struct DpConfig {
+    /// The bound on the L2 norm of a local model. The value must be positive and finite.
+    pub clipping_bound: f64,
+    /// The ratio of the standard deviation of the noise to the clipping bound. The value must be
+    /// positive and finite.
+    pub noise_multiplier: f64,
+    /// The probability `δ` with which the `ε` of the privacy guarantee may be exceeded. The value
+    /// must be between `0` and `1` (i.e. `0 < delta < 1`).
+    pub delta: f64,
+    /// The maximum `ε` which may be spent over all rounds. The budget is unlimited if it is
+    /// missing.
+    pub max_epsilon: Option<f64>,
+}
+
+impl DpConfig {
+    /// Checks the validity of the configuration.
+    ///
+    /// # Errors
+    /// Fails if one of the values is out of its documented range.
+    pub fn validate(&self) -> Result<(), DpError> {
+        let is_positive = |value: f64| value > 0. && value.is_finite();
+        if is_positive(self.clipping_bound)
+            && is_positive(self.noise_multiplier)
+            && self.delta > 0.
+            && self.delta < 1.
+            && self.max_epsilon.map_or(true, |epsilon| epsilon >= 0.)
+        {
+            Ok(())
+        } else {
+            Err(DpError::InvalidConfig)
+        }
+    }
+
+    /// Gets the standard deviation of the Gaussian noise.
+    pub fn std_dev(&self) -> f64 {
+        self.noise_multiplier * self.clipping_bound
+    }
+
+    /// Clips the `model` to the L2 norm bound. Models within the bound are kept as is.
+    pub fn clip(&self, model: Model) -> Model {
+        let norm = l2_norm(&model);
+        if norm <= self.clipping_bound {
+            return model;
+        }
+        // safe unwrap: the factor is finite, since the norm exceeds the positive clipping bound
+        let factor = Ratio::<BigInt>::from_float(self.clipping_bound / norm).unwrap();
+        model.into_iter().map(|weight| weight * &factor).collect()
+    }
+
+    /// Clips the `model` to the L2 norm bound and adds Gaussian noise to its weights.
+    ///
+    /// The noise is derived from a PRNG seeded with fresh entropy.
+    ///
+    /// # Errors
+    /// Fails if the configuration is invalid, see [`validate()`].
+    ///
+    /// [`validate()`]: DpConfig::validate
+    pub fn privatize(&self, model: Model) -> Result<Model, DpError> {
+        self.validate()?;
+        let mut prng = ChaCha20Rng::from_entropy();
+        let std_dev = self.std_dev();
+        Ok(self
+            .clip(model)
+            .into_iter()
+            .map(|weight| weight + float_to_ratio_bounded(std_dev * standard_normal(&mut prng)))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// An accountant of the privacy budget, which is spent in each round a local model is privatized.
+///
+/// The privacy loss is tracked via zero-concentrated differential privacy: each round spends
+/// `ρ = 1 / (2 * noise_multiplier²)`, which composes additively over the rounds and translates
+/// into `ε = ρ + 2 * sqrt(ρ * ln(1 / δ))`. This is a conservative bound, since it doesn't account
+/// for the amplification by the random selection of the update participants.
+pub struct PrivacyAccountant {
+    config: DpConfig,
+    rounds: u32,
+}
+
+impl PrivacyAccountant {
+    /// Creates a new accountant for the given configuration without any spent budget.
+    pub fn new(config: DpConfig) -> Self {
+        Self::with_spent_rounds(config, 0)
+    }
+
+    /// Creates a new accountant for the given configuration, which has already spent the budget
+    /// of `rounds` rounds.
+    ///
+    /// This restores the accountant of a participant which has privatized its local models
+    /// before, e.g. from the number of [`rounds()`] recorded by a previous accountant.
+    ///
+    /// [`rounds()`]: PrivacyAccountant::rounds
+    pub fn with_spent_rounds(config: DpConfig, rounds: u32) -> Self {
+        Self { config, rounds }
+    }
+
+    /// Gets the differential privacy configuration.
+    pub fn config(&self) -> &DpConfig {
+        &self.config
+    }
+
+    /// Gets the number of rounds in which the budget has been spent.
+    pub fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    /// Gets the spent `ε`.
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon_after(self.rounds)
+    }
+
+    /// Gets the remaining `ε`, if the budget is limited.
+    pub fn remaining_epsilon(&self) -> Option<f64> {
+        self.config
+            .max_epsilon
+            .map(|max_epsilon| (max_epsilon - self.epsilon()).max(0.))
+    }
+
+    /// Checks whether the budget suffices for another round.
+    pub fn can_spend_round(&self) -> bool {
+        self.config.max_epsilon.map_or(true, |max_epsilon| {
+            self.epsilon_after(self.rounds.saturating_add(1)) <= max_epsilon
+        })
+    }
+
+    /// Spends the budget of a round and privatizes the `model`, see [`DpConfig::privatize()`].
+    ///
+    /// # Errors
+    /// Fails if the configuration is invalid or if the budget doesn't suffice for another round.
+    /// The budget is not spent in this case.
+    pub fn privatize(&mut self, model: Model) -> Result<Model, DpError> {
+        self.config.validate()?;
+        if !self.can_spend_round() {
+            return Err(DpError::BudgetExhausted);
+        }
+        let model = self.config.privatize(model)?;
+        self.rounds += 1;
+        Ok(model)
+    }
+
+    fn epsilon_after(&self, rounds: u32) -> f64 {
+        if rounds == 0 {
+            return 0.;
+        }
+        let rho = f64::from(rounds) / (2. * self.config.noise_multiplier.powi(2));
+        rho + 2. * (rho * (1. / self.config.delta).ln()).sqrt()
+    }
+}
+
+/// Computes the L2 norm of the `model`. Weights beyond the range of `f64` count as its extrema.
+fn l2_norm(model: &Model) -> f64 {
+    model.iter().fold(0., |norm, weight| {
+        let weight = ratio_to_float::<f64>(weight).unwrap_or_else(|| {
+            if weight.is_positive() {
+                f64::MAX
+            } else {
+                f64::MIN
+            }
+        });
+        norm.hypot(weight)
+    })
+}
+
+/// Samples from the standard normal distribution via the Box-Muller transform.
+fn standard_normal<R: Rng>(prng: &mut R) -> f64 {
+    // `gen()` samples from `[0, 1)`, hence the first sample is shifted to `(0, 1]`
+    let radius = (-2. * (1. - prng.gen::<f64>()).ln()).sqrt();
+    let angle = 2. * PI * prng.gen::<f64>();
+    radius * angle.cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mask::model::{FromPrimitives, IntoPrimitives};
+
+    fn config() -> DpConfig {
+        DpConfig {
+            clipping_bound: 1.,
+            noise_multiplier: 1.,
+            delta: 1e-5,
+            max_epsilon: None,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(config().validate().is_ok());
+        for invalid in &[
+            DpConfig {
+                clipping_bound: 0.,
+                ..config()
+            },
+            DpConfig {
+                noise_multiplier: f64::INFINITY,
+                ..config()
+            },
+            DpConfig {
+                delta: 1.,
+                ..config()
+            },
+            DpConfig {
+                max_epsilon: Some(-1.),
+                ..config()
+            },
+        ] {
+            assert_eq!(invalid.validate(), Err(DpError::InvalidConfig));
+        }
+    }
+
+    #[test]
+    fn test_clip() {
+        let config = DpConfig {
+            clipping_bound: 2.5,
+            ..config()
+        };
+
+        let model = Model::from_primitives(vec![3_f64, -4.].into_iter()).unwrap();
+        let clipped: Vec<f64> = config.clip(model).into_primitives_unchecked().collect();
+        assert_eq!(clipped, vec![1.5, -2.]);
+
+        let model = Model::from_primitives(vec![1_f64, -2.].into_iter()).unwrap();
+        assert_eq!(config.clip(model.clone()), model);
+    }
+
+    #[test]
+    fn test_privatize() {
+        let config = DpConfig {
+            noise_multiplier: 0.1,
+            ..config()
+        };
+        let model = Model::from_primitives(vec![0_f64; 10_000].into_iter()).unwrap();
+        let noisy: Vec<f64> = config
+            .privatize(model)
+            .unwrap()
+            .into_primitives_unchecked()
+            .collect();
+
+        let mean = noisy.iter().sum::<f64>() / noisy.len() as f64;
+        let std_dev = (noisy
+            .iter()
+            .map(|weight| (weight - mean).powi(2))
+            .sum::<f64>()
+            / noisy.len() as f64)
+            .sqrt();
+        assert!(mean.abs() < 0.01);
+        assert!((std_dev - 0.1).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_privatize_invalid_config() {
+        let model = Model::from_primitives(vec![1_f64, -2.].into_iter()).unwrap();
+        for invalid in &[
+            DpConfig {
+                clipping_bound: -1.,
+                ..config()
+            },
+            DpConfig {
+                noise_multiplier: 0.,
+                ..config()
+            },
+            DpConfig {
+                delta: 0.,
+                ..config()
+            },
+        ] {
+            assert_eq!(
+                invalid.privatize(model.clone()),
+                Err(DpError::InvalidConfig)
+            );
+        }
+    }
+
+    #[test]
+    fn test_accountant() {
+        let mut accountant = PrivacyAccountant::new(DpConfig {
+            max_epsilon: Some(9.),
+            ..config()
+        });
+        assert_eq!(accountant.rounds(), 0);
+        assert!(accountant.epsilon().abs() < 1e-9);
+        assert!((accountant.remaining_epsilon().unwrap() - 9.).abs() < 1e-9);
+
+        let model = Model::from_primitives(vec![0_f64; 10].into_iter()).unwrap();
+        assert!(accountant.privatize(model.clone()).is_ok());
+        assert_eq!(accountant.rounds(), 1);
+        // ρ = 0.5 and ε = ρ + 2 * sqrt(ρ * ln(1 / δ))
+        let epsilon = 0.5 + 2. * (0.5 * 1e5_f64.ln()).sqrt();
+        assert!((accountant.epsilon() - epsilon).abs() < 1e-9);
+        assert!((accountant.remaining_epsilon().unwrap() - (9. - epsilon)).abs() < 1e-9);
+
+        // ε after 2 rounds is about 7.8 and after 3 rounds about 9.8
+        assert!(accountant.privatize(model.clone()).is_ok());
+        assert!(!accountant.can_spend_round());
+        assert_eq!(accountant.privatize(model), Err(DpError::BudgetExhausted));
+        assert_eq!(accountant.rounds(), 2);
+    }
+
+    #[test]
+    fn test_accountant_with_spent_rounds() {
+        let config = DpConfig {
+            max_epsilon: Some(9.),
+            ..config()
+        };
+        let mut accountant = PrivacyAccountant::new(config);
+        let model = Model::from_primitives(vec![0_f64; 10].into_iter()).unwrap();
+        assert!(accountant.privatize(model.clone()).is_ok());
+
+        // a restored accountant keeps spending the budget of the previous one
+        let mut restored = PrivacyAccountant::with_spent_rounds(config, accountant.rounds());
+        assert_eq!(restored, accountant);
+        assert!(restored.privatize(model.clone()).is_ok());
+        assert_eq!(restored.privatize(model), Err(DpError::BudgetExhausted));
+        assert_eq!(restored.rounds(), 2);
+    }
+}
@@ -4,20 +4,26 @@
 //!
 //! [mask module]: ../index.html
 
-use std::iter::{self, Iterator};
+use std::ops::{Add, Rem, Sub};
 
 use num::{
     bigint::{BigInt, BigUint, ToBigInt},
-    clamp,
     rational::Ratio,
+    traits::{Signed, ToPrimitive, Zero},
 };
-use rand::SeedableRng;
+use rand::{RngCore, SeedableRng};
 use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
 use thiserror::Error;
 
 use crate::{
     crypto::{prng::generate_integer, ByteObject},
-    mask::{config::MaskConfig, model::Model, object::MaskObject, seed::MaskSeed},
+    mask::{
+        config::{MaskConfig, MaskConfigPair},
+        model::Model,
+        object::MaskObject,
+        seed::MaskSeed,
+    },
 };
 
 #[derive(Debug, Error, Eq, PartialEq)]
@@ -36,6 +42,16 @@ pub enum UnmaskingError {
     InvalidMask,
 }
 
+#[derive(Debug, Error, Eq, PartialEq)]
+/// Errors related to the masking of models.
+pub enum MaskingError {
+    #[error("the scalar is not within the bounds of the masking configuration")]
+    ScalarOutOfBounds,
+
+    #[error("the scaled weight {0} is not within the bounds of the masking configuration")]
+    WeightOutOfBounds(usize),
+}
+
 #[derive(Debug, Error)]
 /// Errors related to the aggregation of masks and models.
 pub enum AggregationError {
@@ -49,11 +65,273 @@ pub enum AggregationError {
     ModelMismatch,
 }
 
+/// The integer width for the arithmetic in a finite group.
+///
+/// The orders of many masking configurations are small enough for fixed-width integers, which
+/// avoid the heap allocations of the arbitrary precision arithmetic. The results are identical for
+/// all widths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Width {
+    U64,
+    U128,
+    BigUint,
+}
+
+impl Width {
+    /// Picks the smallest width in which the sum of two elements of the finite group of the given
+    /// `order` doesn't overflow.
+    fn from_order(order: &BigUint) -> Self {
+        match order.bits() {
+            0..=63 => Width::U64,
+            64..=127 => Width::U128,
+            _ => Width::BigUint,
+        }
+    }
+}
+
+/// A fixed-width unsigned integer for the arithmetic in a finite group.
+trait FixedWidth:
+    Copy
+    + Send
+    + Sync
+    + PartialOrd
+    + Zero
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Rem<Output = Self>
+{
+    /// Converts the integer if it fits into the width.
+    fn from_biguint(int: &BigUint) -> Option<Self>;
+
+    /// Converts the integer into an arbitrary precision integer.
+    fn into_biguint(self) -> BigUint;
+
+    /// Generates a secure pseudo-random integer between zero (included) and `max_int` (excluded).
+    ///
+    /// This consumes the PRNG exactly like [`generate_integer()`] and yields the same integers.
+    fn generate(prng: &mut ChaCha20Rng, max_int: Self) -> Self;
+}
+
+macro_rules! impl_fixed_width {
+    ($($int:ty => $to_int:ident),+ $(,)?) => {
+        $(
+            impl FixedWidth for $int {
+                fn from_biguint(int: &BigUint) -> Option<Self> {
+                    int.$to_int()
+                }
+
+                fn into_biguint(self) -> BigUint {
+                    BigUint::from(self)
+                }
+
+                fn generate(prng: &mut ChaCha20Rng, max_int: Self) -> Self {
+                    if max_int.is_zero() {
+                        return 0;
+                    }
+                    // the same number of bytes as the little-endian representation of a `BigUint`
+                    let nb_bytes = (8 * std::mem::size_of::<Self>() + 7
+                        - max_int.leading_zeros() as usize)
+                        / 8;
+                    let mut bytes = [0_u8; std::mem::size_of::<Self>()];
+                    let mut rand_int = max_int;
+                    while rand_int >= max_int {
+                        prng.fill_bytes(&mut bytes[..nb_bytes]);
+                        rand_int = Self::from_le_bytes(bytes);
+                    }
+                    rand_int
+                }
+            }
+        )+
+    };
+}
+
+impl_fixed_width! {
+    u64 => to_u64,
+    u128 => to_u128,
+}
+
+/// The minimal number of elements which are processed at once by a thread in the data-parallel
+/// operations.
+const CHUNK_SIZE: usize = 4096;
+
+/// Converts the integers if they are all elements of the finite group of the given `order`.
+fn to_fixed_width<T: FixedWidth>(ints: &[BigUint], order: T, parallel: bool) -> Option<Vec<T>> {
+    let convert = |int| T::from_biguint(int).filter(|int| *int < order);
+    if parallel {
+        ints.par_iter()
+            .with_min_len(CHUNK_SIZE)
+            .map(convert)
+            .collect()
+    } else {
+        ints.iter().map(convert).collect()
+    }
+}
+
+/// Applies the `add` function to the elements of the `data` and the `ints` pairwise.
+fn add_elements<T, F>(data: &mut [T], ints: Vec<T>, add: F, parallel: bool)
+where
+    T: Send,
+    F: Fn(&mut T, T) + Send + Sync,
+{
+    if parallel {
+        data.par_iter_mut()
+            .zip(ints.into_par_iter())
+            .with_min_len(CHUNK_SIZE)
+            .for_each(|(i, j)| add(i, j));
+    } else {
+        data.iter_mut()
+            .zip(ints.into_iter())
+            .for_each(|(i, j)| add(i, j));
+    }
+}
+
+/// Applies the `unmask` function to the elements of the `data` and the `mask` pairwise.
+fn unmask_elements<T, F>(data: Vec<T>, mask: Vec<T>, unmask: F, parallel: bool) -> Model
+where
+    T: Send,
+    F: Fn(T, T) -> Ratio<BigInt> + Send + Sync,
+{
+    if parallel {
+        data.into_par_iter()
+            .zip(mask.into_par_iter())
+            .with_min_len(CHUNK_SIZE)
+            .map(|(i, j)| unmask(i, j))
+            .collect::<Vec<_>>()
+            .into()
+    } else {
+        data.into_iter()
+            .zip(mask.into_iter())
+            .map(|(i, j)| unmask(i, j))
+            .collect()
+    }
+}
+
+/// Adds a random element to the `int` in the finite group of the given `order`.
+fn add_random_integer(
+    prng: &mut ChaCha20Rng,
+    int: BigUint,
+    order: &BigUint,
+    width: Width,
+) -> BigUint {
+    fn add<T: FixedWidth>(prng: &mut ChaCha20Rng, int: BigUint, order: &BigUint) -> BigUint {
+        // safe unwrap: the width has been chosen for the order
+        let order = T::from_biguint(order).unwrap();
+        let rand_int = T::generate(prng, order);
+        match T::from_biguint(&int) {
+            Some(int) if int < order => ((int + rand_int) % order).into_biguint(),
+            _ => (int + rand_int.into_biguint()) % order.into_biguint(),
+        }
+    }
+
+    match width {
+        Width::U64 => add::<u64>(prng, int, order),
+        Width::U128 => add::<u128>(prng, int, order),
+        Width::BigUint => (int + generate_integer(prng, order)) % order,
+    }
+}
+
+/// The elements of an aggregated mask object in the integer width of its finite group.
+#[derive(Debug, Clone)]
+enum AggregationData {
+    U64(Vec<u64>),
+    U128(Vec<u128>),
+    BigUint(Vec<BigUint>),
+}
+
+impl AggregationData {
+    /// Creates empty elements for the finite group of the given `order`.
+    fn new(order: &BigUint, capacity: usize) -> Self {
+        match Width::from_order(order) {
+            Width::U64 => AggregationData::U64(Vec::with_capacity(capacity)),
+            Width::U128 => AggregationData::U128(Vec::with_capacity(capacity)),
+            Width::BigUint => AggregationData::BigUint(Vec::with_capacity(capacity)),
+        }
+    }
+
+    /// Converts the elements of the finite group of the given `order` into its integer width.
+    ///
+    /// The elements are kept as they are if they aren't elements of the finite group, which can
+    /// only happen for invalid mask objects.
+    fn from_biguints(ints: Vec<BigUint>, order: &BigUint, parallel: bool) -> Self {
+        // safe unwraps: the width has been chosen for the order
+        let data =
+            match Width::from_order(order) {
+                Width::U64 => to_fixed_width(&ints, order.to_u64().unwrap(), parallel)
+                    .map(AggregationData::U64),
+                Width::U128 => to_fixed_width(&ints, order.to_u128().unwrap(), parallel)
+                    .map(AggregationData::U128),
+                Width::BigUint => None,
+            };
+        data.unwrap_or(AggregationData::BigUint(ints))
+    }
+
+    /// Converts the elements into arbitrary precision integers.
+    fn into_biguints(self) -> Vec<BigUint> {
+        match self {
+            AggregationData::U64(data) => data.into_iter().map(BigUint::from).collect(),
+            AggregationData::U128(data) => data.into_iter().map(BigUint::from).collect(),
+            AggregationData::BigUint(data) => data,
+        }
+    }
+
+    /// Adds the `other` elements to the elements in the finite group of the given `order`.
+    fn add(&mut self, other: AggregationData, order: &BigUint, parallel: bool) {
+        fn add<T: FixedWidth>(order: T) -> impl Fn(&mut T, T) + Send + Sync {
+            move |i, j| *i = (*i + j) % order
+        }
+
+        let other = match (&*self, other) {
+            (AggregationData::BigUint(_), other) => AggregationData::BigUint(other.into_biguints()),
+            (_, AggregationData::BigUint(ints)) => {
+                AggregationData::from_biguints(ints, order, parallel)
+            }
+            (_, other) => other,
+        };
+
+        // safe unwraps: the width has been chosen for the order
+        match self {
+            AggregationData::U64(data) => match other {
+                AggregationData::U64(ints) => {
+                    add_elements(data, ints, add(order.to_u64().unwrap()), parallel)
+                }
+                other => self.add_biguints(other, order, parallel),
+            },
+            AggregationData::U128(data) => match other {
+                AggregationData::U128(ints) => {
+                    add_elements(data, ints, add(order.to_u128().unwrap()), parallel)
+                }
+                other => self.add_biguints(other, order, parallel),
+            },
+            AggregationData::BigUint(data) => {
+                let add = |i: &mut BigUint, j| *i = (&*i + j) % order;
+                add_elements(data, other.into_biguints(), add, parallel)
+            }
+        }
+    }
+
+    /// Adds the `other` elements as arbitrary precision integers.
+    ///
+    /// This is only necessary if the `other` elements aren't elements of the finite group, which
+    /// can only happen for invalid objects.
+    fn add_biguints(&mut self, other: AggregationData, order: &BigUint, parallel: bool) {
+        let mut data = AggregationData::BigUint(std::mem::take(self).into_biguints());
+        data.add(other, order, parallel);
+        *self = data;
+    }
+}
+
+impl Default for AggregationData {
+    fn default() -> Self {
+        AggregationData::BigUint(Vec::new())
+    }
+}
+
 #[derive(Debug, Clone)]
 /// An aggregator for masks and masked models.
 pub struct Aggregation {
     nb_models: usize,
-    object: MaskObject,
+    config: MaskConfig,
+    data: AggregationData,
     object_size: usize,
 }
 
@@ -62,14 +340,15 @@ impl From<MaskObject> for Aggregation {
         Self {
             nb_models: 1,
             object_size: object.data.len(),
-            object,
+            data: AggregationData::from_biguints(object.data, &object.config.order(), false),
+            config: object.config,
         }
     }
 }
 
 impl Into<MaskObject> for Aggregation {
     fn into(self) -> MaskObject {
-        self.object
+        MaskObject::new(self.config, self.data.into_biguints())
     }
 }
 
@@ -79,11 +358,24 @@ impl Aggregation {
     pub fn new(config: MaskConfig, object_size: usize) -> Self {
         Self {
             nb_models: 0,
-            object: MaskObject::new(config, Vec::with_capacity(object_size)),
+            config,
+            data: AggregationData::new(&config.order(), object_size),
             object_size,
         }
     }
 
+    /// Creates an aggregator from a mask object which has already been aggregated from the given
+    /// number of masks or masked models, e.g. to restore a checkpointed aggregation.
+    ///
+    /// The number of masks or masked models should be positive, since an empty aggregator is
+    /// replaced by the first aggregated mask object.
+    pub fn from_aggregated(object: MaskObject, nb_models: usize) -> Self {
+        Self {
+            nb_models,
+            ..Self::from(object)
+        }
+    }
+
     /// Gets the length of the aggregated mask object.
     pub fn len(&self) -> usize {
         self.object_size
@@ -91,7 +383,12 @@ impl Aggregation {
 
     /// Gets the masking configuration of the aggregator.
     pub fn config(&self) -> MaskConfig {
-        self.object.config
+        self.config
+    }
+
+    /// Gets the number of aggregated masks or masked models.
+    pub fn nb_models(&self) -> usize {
+        self.nb_models
     }
 
     /// Validates if unmasking of the aggregated masked model with the given `mask` may be
@@ -122,11 +419,11 @@ impl Aggregation {
             return Err(UnmaskingError::NoModel);
         }
 
-        if self.nb_models > self.object.config.model_type.max_nb_models() {
+        if self.nb_models > self.config.model_type.max_nb_models() {
             return Err(UnmaskingError::TooManyModels);
         }
 
-        if self.object.config != mask.config || self.object_size != mask.data.len() {
+        if self.config != mask.config || self.object_size != mask.data.len() {
             return Err(UnmaskingError::MaskMismatch);
         }
 
@@ -156,31 +453,81 @@ impl Aggregation {
     ///
     /// [`validate_unmasking()`]: #method.validate_unmasking
     /// [`mask()`]: struct.Masker.html#method.mask
-    pub fn unmask(mut self, mask: MaskObject) -> Model {
-        let scaled_add_shift = self.object.config.add_shift() * BigInt::from(self.nb_models);
-        let exp_shift = self.object.config.exp_shift();
-        let order = self.object.config.order();
-        self.object
-            .data
-            .drain(..)
-            .zip(mask.data.into_iter())
-            .map(|(masked_weight, mask)| {
-                // PANIC_SAFE: The substraction panics if it
-                // underflows, which can only happen if:
-                //
-                //     mask > self.object.config.order()
-                //
-                // If the mask is valid, we are guaranteed that this
-                // cannot happen. Thus this method may panic only if
-                // given an invalid mask.
-                let n = (masked_weight + &order - mask) % &order;
-
-                // UNWRAP_SAFE: to_bigint never fails for BigUint
-                let ratio = Ratio::<BigInt>::from(n.to_bigint().unwrap());
-
-                ratio / &exp_shift - &scaled_add_shift
-            })
-            .collect()
+    pub fn unmask(self, mask: MaskObject) -> Model {
+        self.unmask_elements(mask, false)
+    }
+
+    /// Unmasks the aggregated masked model with the given `mask` in parallel.
+    ///
+    /// This is the data-parallel version of [`unmask()`], which splits the aggregated masked model
+    /// into chunks and unmasks them on the current `rayon` thread pool. The result is identical.
+    ///
+    /// # Panics
+    /// This may only panic if [`validate_unmasking()`] fails.
+    ///
+    /// [`unmask()`]: #method.unmask
+    /// [`validate_unmasking()`]: #method.validate_unmasking
+    pub fn par_unmask(self, mask: MaskObject) -> Model {
+        self.unmask_elements(mask, true)
+    }
+
+    fn unmask_elements(self, mask: MaskObject, parallel: bool) -> Model {
+        let scaled_add_shift = self.config.add_shift() * BigInt::from(self.nb_models);
+        let exp_shift = self.config.exp_shift();
+        let order = self.config.order();
+        let unshift = |n: BigInt| Ratio::<BigInt>::from(n) / &exp_shift - &scaled_add_shift;
+
+        fn unmask<T: FixedWidth>(
+            order: T,
+            unshift: impl Fn(BigInt) -> Ratio<BigInt> + Send + Sync,
+        ) -> impl Fn(T, T) -> Ratio<BigInt> + Send + Sync {
+            move |masked_weight, mask| {
+                // the mask is an element of the finite group, hence this neither underflows nor
+                // overflows
+                unshift(BigInt::from(
+                    ((masked_weight + (order - mask)) % order).into_biguint(),
+                ))
+            }
+        }
+
+        // safe unwraps: the width has been chosen for the order
+        let data = match self.data {
+            AggregationData::U64(data) => {
+                let fixed_order = order.to_u64().unwrap();
+                match to_fixed_width(&mask.data, fixed_order, parallel) {
+                    Some(mask) => {
+                        return unmask_elements(data, mask, unmask(fixed_order, unshift), parallel)
+                    }
+                    None => AggregationData::U64(data).into_biguints(),
+                }
+            }
+            AggregationData::U128(data) => {
+                let fixed_order = order.to_u128().unwrap();
+                match to_fixed_width(&mask.data, fixed_order, parallel) {
+                    Some(mask) => {
+                        return unmask_elements(data, mask, unmask(fixed_order, unshift), parallel)
+                    }
+                    None => AggregationData::U128(data).into_biguints(),
+                }
+            }
+            AggregationData::BigUint(data) => data,
+        };
+
+        let unmask = |masked_weight: BigUint, mask: BigUint| {
+            // PANIC_SAFE: The substraction panics if it
+            // underflows, which can only happen if:
+            //
+            //     mask > self.config.order()
+            //
+            // If the mask is valid, we are guaranteed that this
+            // cannot happen. Thus this method may panic only if
+            // given an invalid mask.
+            let n = (masked_weight + &order - mask) % &order;
+
+            // UNWRAP_SAFE: to_bigint never fails for BigUint
+            unshift(n.to_bigint().unwrap())
+        };
+        unmask_elements(data, mask.data, unmask, parallel)
     }
 
     /// Applies a correction to the given unmasked model based on the associated
@@ -220,7 +567,29 @@ impl Aggregation {
     ///
     /// [`aggregate()`]: #method.aggregate
     pub fn validate_aggregation(&self, object: &MaskObject) -> Result<(), AggregationError> {
-        if self.object.config != object.config {
+        self.validate_pending_aggregation(object, 0)
+    }
+
+    /// Validates if aggregation of the aggregated mask object with the given `object` may be safely
+    /// performed, after `nb_pending` further masks or masked models which have been validated
+    /// before are aggregated.
+    ///
+    /// This allows to aggregate the pending objects in a separate aggregator, for example on
+    /// another thread, which is [`merge()`]d afterwards. Otherwise, this is the same as
+    /// [`validate_aggregation()`].
+    ///
+    /// # Errors
+    /// Fails in the same cases as [`validate_aggregation()`], where the number of aggregated
+    /// masks or masked models includes the pending ones.
+    ///
+    /// [`merge()`]: #method.merge
+    /// [`validate_aggregation()`]: #method.validate_aggregation
+    pub fn validate_pending_aggregation(
+        &self,
+        object: &MaskObject,
+        nb_pending: usize,
+    ) -> Result<(), AggregationError> {
+        if self.config != object.config {
             return Err(AggregationError::ModelMismatch);
         }
 
@@ -228,7 +597,7 @@ impl Aggregation {
             return Err(AggregationError::ModelMismatch);
         }
 
-        if self.nb_models >= self.object.config.model_type.max_nb_models() {
+        if self.nb_models + nb_pending >= self.config.model_type.max_nb_models() {
             return Err(AggregationError::TooManyModels);
         }
 
@@ -251,104 +620,171 @@ impl Aggregation {
     ///
     /// [`validate_aggregation()`]: #method.validate_aggregation
     pub fn aggregate(&mut self, object: MaskObject) {
+        self.merge_elements(object.into(), false);
+    }
+
+    /// Aggregates the aggregated mask object with the given `object` in parallel.
+    ///
+    /// This is the data-parallel version of [`aggregate()`], which splits the mask objects into
+    /// chunks and aggregates them on the current `rayon` thread pool. The result is identical.
+    ///
+    /// [`aggregate()`]: #method.aggregate
+    pub fn par_aggregate(&mut self, object: MaskObject) {
+        self.merge_elements(object.into(), true);
+    }
+
+    /// Merges the `other` aggregator into this one, as if all masks or masked models of the
+    /// `other` aggregator were aggregated with this one.
+    ///
+    /// Each of the masks or masked models of the `other` aggregator should have been validated
+    /// via [`validate_pending_aggregation()`] of this aggregator, since merging may return garbage
+    /// values otherwise.
+    ///
+    /// [`validate_pending_aggregation()`]: #method.validate_pending_aggregation
+    pub fn merge(&mut self, other: Aggregation) {
+        self.merge_elements(other, false);
+    }
+
+    fn merge_elements(&mut self, other: Aggregation, parallel: bool) {
+        if other.nb_models == 0 {
+            return;
+        }
         if self.nb_models == 0 {
-            self.object = object;
-            self.nb_models = 1;
+            *self = other;
             return;
         }
 
-        let order = self.object.config.order();
-        for (i, j) in self.object.data.iter_mut().zip(object.data.into_iter()) {
-            *i = (&*i + j) % &order
-        }
-        self.nb_models += 1;
+        self.data.add(other.data, &self.config.order(), parallel);
+        self.nb_models += other.nb_models;
     }
 }
 
 /// A masker for models.
 pub struct Masker {
-    config: MaskConfig,
+    config: MaskConfigPair,
     seed: MaskSeed,
 }
 
 impl Masker {
-    /// Creates a new masker with the given masking `config`uration with a randomly generated seed.
-    pub fn new(config: MaskConfig) -> Self {
+    /// Creates a new masker with the given masking `config`urations with a randomly generated
+    /// seed.
+    pub fn new(config: MaskConfigPair) -> Self {
         Self {
             config,
             seed: MaskSeed::generate(),
         }
     }
 
-    /// Creates a new masker with the given masking `config`uration and `seed`.
-    pub fn with_seed(config: MaskConfig, seed: MaskSeed) -> Self {
+    /// Creates a new masker with the given masking `config`urations and `seed`.
+    pub fn with_seed(config: MaskConfigPair, seed: MaskSeed) -> Self {
         Self { config, seed }
     }
+
+    /// Clips the weights of the `model`, such that the weights scaled by the `scalar` are within
+    /// the bounds of the masking configuration of the weights.
+    ///
+    /// The `scalar` itself is not clipped. If it is not positive, the `model` is kept as is.
+    pub fn clip(&self, scalar: f64, model: Model) -> Model {
+        let scalar = match Ratio::<BigInt>::from_float(scalar) {
+            Some(scalar) if scalar > Ratio::zero() => scalar,
+            _ => return model,
+        };
+        let higher_bound = self.config.vect.add_shift() / scalar;
+        let lower_bound = -&higher_bound;
+        model
+            .into_iter()
+            .map(|weight| {
+                if weight > higher_bound {
+                    higher_bound.clone()
+                } else if weight < lower_bound {
+                    lower_bound.clone()
+                } else {
+                    weight
+                }
+            })
+            .collect()
+    }
 }
 
 impl Masker {
-    /// Masks the given `model` wrt the masking configuration. Enforces bounds on the scalar and
-    /// weights.
+    /// Masks the given `model` wrt the masking configuration of the weights and the `scalar` wrt
+    /// the masking configuration of the scalar. Enforces bounds on the scalar and weights.
     ///
     /// The masking proceeds in the following steps:
-    /// - Clamp the scalar and the weights according to the masking configuration.
+    /// - Check the scalar and the scaled weights against the masking configurations.
     /// - Scale the weights by the scalar.
-    /// - Shift the weights into the non-negative reals.
-    /// - Shift the weights into the non-negative integers.
-    /// - Shift the weights into the finite group.
-    /// - Mask the weights with random elements from the finite group.
+    /// - Shift the weights and the scalar into the non-negative reals.
+    /// - Shift the weights and the scalar into the non-negative integers.
+    /// - Shift the weights and the scalar into their finite groups.
+    /// - Mask the weights and the scalar with random elements from their finite groups.
     ///
     /// The random elements are derived from a seeded PRNG. Unmasking as performed in [`unmask()`]
     /// proceeds in reverse order.
     ///
+    /// # Errors
+    /// Fails if the scalar is not finite or out of the bounds of the masking configuration of the
+    /// scalar, or if a weight scaled by the scalar is out of the bounds of the masking
+    /// configuration of the weights. Weights can be brought into the bounds with [`clip()`]
+    /// beforehand.
+    ///
     /// [`unmask()`]: struct.Aggregation.html#method.unmask
-    pub fn mask(self, scalar: f64, model: Model) -> (MaskSeed, MaskObject, MaskObject) {
-        let mut random_ints = self.random_ints();
+    /// [`clip()`]: #method.clip
+    pub fn mask(
+        self,
+        scalar: f64,
+        model: Model,
+    ) -> Result<(MaskSeed, MaskObject, MaskObject), MaskingError> {
         let Self { seed, config } = self;
+        let mut prng = ChaCha20Rng::from_seed(seed.as_array());
 
-        let exp_shift = config.exp_shift();
-        let add_shift = config.add_shift();
-        let order = config.order();
+        let exp_shift = config.vect.exp_shift();
+        let add_shift = config.vect.add_shift();
+        let order = config.vect.order();
+        let width = Width::from_order(&order);
         let higher_bound = &add_shift;
         let lower_bound = -&add_shift;
 
-        let scalar_ratio = crate::mask::model::float_to_ratio_bounded(scalar);
-        // HACK reuse upper bound for scaled weights for now, really should be tighter
-        // TODO give scalar its own config in later refactoring
-        let zero = Ratio::<BigInt>::from_float(0_f64).unwrap();
-        let scalar_clamped = clamp(&scalar_ratio, &zero, higher_bound);
+        let scalar_exp_shift = config.unit.exp_shift();
+        let scalar_add_shift = config.unit.add_shift();
+        let scalar_ratio = Ratio::<BigInt>::from_float(scalar)
+            .filter(|scalar| !scalar.is_negative() && scalar <= &scalar_add_shift)
+            .ok_or(MaskingError::ScalarOutOfBounds)?;
 
         let masked_weights = model
             .into_iter()
-            .zip(&mut random_ints)
-            .map(|(weight, rand_int)| {
-                let scaled = scalar_clamped * &weight;
-                let scaled_clamped = clamp(&scaled, &lower_bound, higher_bound);
+            .enumerate()
+            .map(|(index, weight)| {
+                let scaled = &scalar_ratio * weight;
+                if scaled < lower_bound || &scaled > higher_bound {
+                    return Err(MaskingError::WeightOutOfBounds(index));
+                }
                 // PANIC_SAFE: shifted weight is guaranteed to be non-negative
-                let shifted = ((scaled_clamped + &add_shift) * &exp_shift)
+                let shifted = ((scaled + &add_shift) * &exp_shift)
                     .to_integer()
                     .to_biguint()
                     .unwrap();
-                (shifted + rand_int) % &order
+                Ok(add_random_integer(&mut prng, shifted, &order, width))
             })
-            .collect();
-        let masked_model = MaskObject::new(config, masked_weights);
+            .collect::<Result<Vec<_>, _>>()?;
+        let masked_model = MaskObject::new(config.vect, masked_weights);
 
-        let rand_int = random_ints.next().unwrap();
-        let shifted = ((scalar_clamped + &add_shift) * &exp_shift)
+        let scalar_order = config.unit.order();
+        // PANIC_SAFE: shifted scalar is guaranteed to be non-negative
+        let shifted = ((scalar_ratio + &scalar_add_shift) * &scalar_exp_shift)
             .to_integer()
             .to_biguint()
             .unwrap();
-        let masked_scalar = MaskObject::new(config, vec![(shifted + rand_int) % &order]);
-
-        (seed, masked_model, masked_scalar)
-    }
-
-    /// Creates an iterator that yields randomly generated integers wrt the masking configuration.
-    fn random_ints(&self) -> impl Iterator<Item = BigUint> {
-        let order = self.config.order();
-        let mut prng = ChaCha20Rng::from_seed(self.seed.as_array());
-        iter::from_fn(move || Some(generate_integer(&mut prng, &order)))
+        let masked_scalar = MaskObject::new(
+            config.unit,
+            vec![add_random_integer(
+                &mut prng,
+                shifted,
+                &scalar_order,
+                Width::from_order(&scalar_order),
+            )],
+        );
+
+        Ok((seed, masked_model, masked_scalar))
     }
 }
 
@@ -425,13 +861,13 @@ mod tests {
                     // b. derive the mask corresponding to the seed used
                     // c. unmask the model and check it against the original one.
                     let (mask_seed, masked_model, masked_scalar) =
-                        Masker::new(config.clone()).mask(1_f64, model.clone());
+                        Masker::new(config.into()).mask(1_f64, model.clone()).unwrap();
                     assert_eq!(masked_model.data.len(), model.len());
                     assert!(masked_model.is_valid());
                     assert_eq!(masked_scalar.data.len(), 1);
                     assert!(masked_scalar.is_valid());
 
-                    let (mask, _scalar_mask) = mask_seed.derive_mask(model.len(), config);
+                    let (mask, _scalar_mask) = mask_seed.derive_mask(model.len(), config.into());
                     let aggregation = Aggregation::from(masked_model);
                     let unmasked_model = aggregation.unmask(mask);
 
@@ -571,9 +1007,10 @@ mod tests {
                         aggregated_masked_model.aggregate(masked_model);
 
                         assert_eq!(aggregated_masked_model.nb_models, nb);
-                        assert_eq!(aggregated_masked_model.object.data.len(), $len as usize);
-                        assert_eq!(aggregated_masked_model.object.config, config);
-                        assert!(aggregated_masked_model.object.is_valid());
+                        let object: MaskObject = aggregated_masked_model.clone().into();
+                        assert_eq!(object.data.len(), $len as usize);
+                        assert_eq!(object.config, config);
+                        assert!(object.is_valid());
                     }
                 }
             }
@@ -685,6 +1122,16 @@ mod tests {
                         },
                         model_type: M3,
                     };
+                    let scalar_config = MaskConfig {
+                        group_type: $group,
+                        data_type: F64,
+                        bound_type: B0,
+                        model_type: M3,
+                    };
+                    let config = MaskConfigPair {
+                        vect: config,
+                        unit: scalar_config,
+                    };
                     let model_size = $len as usize;
 
                     // Step 2: Generate random models
@@ -713,10 +1160,10 @@ mod tests {
                         iter::repeat(paste::expr! { 0 as [<$data:lower>] }).take($len as usize)
                     )
                     .unwrap();
-                    let mut aggregated_masked_model = Aggregation::new(config, model_size);
-                    let mut aggregated_mask = Aggregation::new(config, model_size);
-                    let mut aggregated_masked_scalar = Aggregation::new(config, 1);
-                    let mut aggregated_scalar_mask = Aggregation::new(config, 1);
+                    let mut aggregated_masked_model = Aggregation::new(config.vect, model_size);
+                    let mut aggregated_mask = Aggregation::new(config.vect, model_size);
+                    let mut aggregated_masked_scalar = Aggregation::new(config.unit, 1);
+                    let mut aggregated_scalar_mask = Aggregation::new(config.unit, 1);
                     let scalar = 1_f64 / ($count as f64);
                     let scalar_ratio = Ratio::from_float(scalar).unwrap();
                     for _ in 0..$count as usize {
@@ -729,7 +1176,7 @@ mod tests {
                             });
 
                         let (mask_seed, masked_model, masked_scalar) =
-                            Masker::new(config).mask(scalar, model);
+                            Masker::new(config).mask(scalar, model).unwrap();
                         let (mask, scalar_mask) = mask_seed.derive_mask($len as usize, config);
 
                         assert!(
@@ -749,7 +1196,7 @@ mod tests {
 
                     let unmasked_model = aggregated_masked_model.unmask(aggregated_mask.into());
                     let tolerance = Ratio::from_integer(BigInt::from($count as usize))
-                        / Ratio::from_integer(config.exp_shift());
+                        / Ratio::from_integer(config.vect.exp_shift());
                     assert!(
                         averaged_model.iter()
                             .zip(unmasked_model.iter())
@@ -757,7 +1204,13 @@ mod tests {
                                 (averaged_weight - unmasked_weight).abs() <= tolerance
                             })
                     );
-                    // TODO check scalar as well, after future refactoring
+
+                    let unmasked_scalar = aggregated_masked_scalar
+                        .unmask(aggregated_scalar_mask.into());
+                    let scalar_sum = scalar_ratio * BigInt::from($count as usize);
+                    let tolerance = Ratio::from_integer(BigInt::from($count as usize))
+                        / Ratio::from_integer(config.unit.exp_shift());
+                    assert!((&unmasked_scalar[0] - scalar_sum).abs() <= tolerance);
                 }
             }
         };
@@ -837,4 +1290,310 @@ mod tests {
     test_masking_and_aggregation!(pow_i64_b4, Power2, i64, 10_000, 10, 5);
     test_masking_and_aggregation!(pow_i64_b6, Power2, i64, 1_000_000, 10, 5);
     test_masking_and_aggregation!(pow_i64_bmax, Power2, i64, 10, 5);
+
+    /// Gets all masking configurations of the catalogue for the model type `M3`.
+    fn configs() -> impl Iterator<Item = MaskConfig> {
+        [Integer, Prime, Power2].iter().flat_map(|&group_type| {
+            [F32, F64, I32, I64].iter().flat_map(move |&data_type| {
+                [B0, B2, B4, B6, Bmax]
+                    .iter()
+                    .map(move |&bound_type| MaskConfig {
+                        group_type,
+                        data_type,
+                        bound_type,
+                        model_type: M3,
+                    })
+            })
+        })
+    }
+
+    /// Creates an aggregator which performs the arithmetic with arbitrary precision integers.
+    fn biguint_aggregation(object: MaskObject) -> Aggregation {
+        Aggregation {
+            nb_models: 1,
+            config: object.config,
+            object_size: object.data.len(),
+            data: AggregationData::BigUint(object.data),
+        }
+    }
+
+    #[test]
+    fn test_width() {
+        assert_eq!(Width::from_order(&BigUint::from(u64::MAX >> 1)), Width::U64);
+        assert_eq!(Width::from_order(&BigUint::from(u64::MAX)), Width::U128);
+        assert_eq!(
+            Width::from_order(&BigUint::from(u128::MAX >> 1)),
+            Width::U128
+        );
+        assert_eq!(Width::from_order(&BigUint::from(u128::MAX)), Width::BigUint);
+
+        // the fast paths must be covered by the catalogue
+        assert!(configs().any(|config| Width::from_order(&config.order()) == Width::U64));
+        assert!(configs().any(|config| Width::from_order(&config.order()) == Width::U128));
+    }
+
+    #[test]
+    fn test_generate_fixed_width_integer() {
+        let mut prng = ChaCha20Rng::from_seed(MaskSeed::generate().as_array());
+        for _ in 0..100 {
+            let seed = MaskSeed::generate().as_array();
+            let max_u64 = prng.next_u64() >> (prng.next_u32() % 64);
+            let max_u128 = (u128::from(prng.next_u64()) << 64 | u128::from(prng.next_u64()))
+                >> (prng.next_u32() % 128);
+
+            let mut prng_1 = ChaCha20Rng::from_seed(seed);
+            let mut prng_2 = ChaCha20Rng::from_seed(seed);
+            for _ in 0..10 {
+                assert_eq!(
+                    BigUint::from(u64::generate(&mut prng_1, max_u64)),
+                    generate_integer(&mut prng_2, &BigUint::from(max_u64)),
+                );
+                assert_eq!(
+                    BigUint::from(u128::generate(&mut prng_1, max_u128)),
+                    generate_integer(&mut prng_2, &BigUint::from(max_u128)),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_random_integer_fixed_width() {
+        let mut prng = ChaCha20Rng::from_seed(MaskSeed::generate().as_array());
+        for config in configs() {
+            let order = config.order();
+            let seed = MaskSeed::generate().as_array();
+            let mut prng_1 = ChaCha20Rng::from_seed(seed);
+            let mut prng_2 = ChaCha20Rng::from_seed(seed);
+            for _ in 0..10 {
+                let int = generate_integer(&mut prng, &order);
+                assert_eq!(
+                    add_random_integer(&mut prng_1, int.clone(), &order, Width::from_order(&order)),
+                    add_random_integer(&mut prng_2, int, &order, Width::BigUint),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_aggregation_and_unmasking_fixed_width() {
+        let mut prng = ChaCha20Rng::from_seed(MaskSeed::generate().as_array());
+        for config in configs() {
+            let order = config.order();
+            let mut objects = iter::repeat_with(|| {
+                let integers = iter::repeat_with(|| generate_integer(&mut prng, &order))
+                    .take(10)
+                    .collect::<Vec<_>>();
+                MaskObject::new(config, integers)
+            });
+
+            let first = objects.next().unwrap();
+            let mut aggregation = Aggregation::from(first.clone());
+            let mut reference = biguint_aggregation(first);
+            for object in objects.by_ref().take(5) {
+                aggregation.aggregate(object.clone());
+                reference.aggregate(object);
+            }
+            let aggregated: MaskObject = aggregation.clone().into();
+            let expected: MaskObject = reference.clone().into();
+            assert_eq!(aggregated, expected);
+
+            let mask = objects.next().unwrap();
+            assert_eq!(aggregation.unmask(mask.clone()), reference.unmask(mask));
+        }
+    }
+
+    #[test]
+    fn test_parallel_aggregation_and_unmasking() {
+        let mut prng = ChaCha20Rng::from_seed(MaskSeed::generate().as_array());
+        for &(group_type, data_type, bound_type) in
+            &[(Prime, F32, B0), (Integer, F64, B6), (Power2, I64, Bmax)]
+        {
+            let config = MaskConfig {
+                group_type,
+                data_type,
+                bound_type,
+                model_type: M3,
+            };
+            let order = config.order();
+            // spans multiple chunks with a remainder
+            let mut objects = iter::repeat_with(|| {
+                let integers = iter::repeat_with(|| generate_integer(&mut prng, &order))
+                    .take(2 * CHUNK_SIZE + 3)
+                    .collect::<Vec<_>>();
+                MaskObject::new(config, integers)
+            });
+
+            let mut aggregation = Aggregation::new(config, 2 * CHUNK_SIZE + 3);
+            let mut reference = Aggregation::new(config, 2 * CHUNK_SIZE + 3);
+            for object in objects.by_ref().take(3) {
+                aggregation.par_aggregate(object.clone());
+                reference.aggregate(object);
+            }
+            let aggregated: MaskObject = aggregation.clone().into();
+            let expected: MaskObject = reference.clone().into();
+            assert_eq!(aggregated, expected);
+
+            let mask = objects.next().unwrap();
+            assert_eq!(aggregation.par_unmask(mask.clone()), reference.unmask(mask));
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut prng = ChaCha20Rng::from_seed(MaskSeed::generate().as_array());
+        for config in configs() {
+            let order = config.order();
+            let objects = iter::repeat_with(|| {
+                let integers = iter::repeat_with(|| generate_integer(&mut prng, &order))
+                    .take(10)
+                    .collect::<Vec<_>>();
+                MaskObject::new(config, integers)
+            })
+            .take(5)
+            .collect::<Vec<_>>();
+
+            let mut reference = Aggregation::new(config, 10);
+            for object in objects.iter().cloned() {
+                reference.aggregate(object);
+            }
+
+            let mut aggregation = Aggregation::new(config, 10);
+            aggregation.aggregate(objects[0].clone());
+            let mut pending = Aggregation::new(config, 10);
+            for (nb_pending, object) in objects.iter().skip(1).cloned().enumerate() {
+                assert!(aggregation
+                    .validate_pending_aggregation(&object, nb_pending)
+                    .is_ok());
+                pending.aggregate(object);
+            }
+            // merging an empty aggregation is a no-op
+            aggregation.merge(Aggregation::new(config, 10));
+            aggregation.merge(pending);
+
+            assert_eq!(aggregation.nb_models(), 5);
+            let aggregated: MaskObject = aggregation.into();
+            let expected: MaskObject = reference.into();
+            assert_eq!(aggregated, expected);
+        }
+    }
+
+    #[test]
+    fn test_from_aggregated() {
+        let mut prng = ChaCha20Rng::from_seed(MaskSeed::generate().as_array());
+        for config in configs() {
+            let order = config.order();
+            let mut objects = iter::repeat_with(|| {
+                let integers = iter::repeat_with(|| generate_integer(&mut prng, &order))
+                    .take(10)
+                    .collect::<Vec<_>>();
+                MaskObject::new(config, integers)
+            })
+            .take(3)
+            .collect::<Vec<_>>();
+
+            let mut reference = Aggregation::new(config, 10);
+            for object in objects.iter().cloned() {
+                reference.aggregate(object);
+            }
+
+            // restore the aggregation of the first two objects and aggregate the last one
+            let last = objects.pop().unwrap();
+            let mut checkpoint = Aggregation::new(config, 10);
+            for object in objects {
+                checkpoint.aggregate(object);
+            }
+            let mut aggregation = Aggregation::from_aggregated(checkpoint.into(), 2);
+            assert_eq!(aggregation.nb_models(), 2);
+            assert_eq!(aggregation.len(), 10);
+            assert!(aggregation.validate_aggregation(&last).is_ok());
+            aggregation.aggregate(last);
+
+            assert_eq!(aggregation.nb_models(), 3);
+            let aggregated: MaskObject = aggregation.into();
+            let expected: MaskObject = reference.into();
+            assert_eq!(aggregated, expected);
+        }
+    }
+
+    #[test]
+    fn test_validate_pending_aggregation() {
+        let config = MaskConfig {
+            group_type: Prime,
+            data_type: F32,
+            bound_type: B0,
+            model_type: M3,
+        };
+        let object = MaskObject::new(config, vec![BigUint::from(0_u8); 10]);
+        let mut aggregation = Aggregation::new(config, 10);
+        aggregation.aggregate(object.clone());
+
+        let max = config.model_type.max_nb_models();
+        assert!(aggregation
+            .validate_pending_aggregation(&object, max - 2)
+            .is_ok());
+        assert!(matches!(
+            aggregation.validate_pending_aggregation(&object, max - 1),
+            Err(AggregationError::TooManyModels)
+        ));
+    }
+
+    #[test]
+    fn test_mask_out_of_bounds() {
+        let config = MaskConfig {
+            group_type: Prime,
+            data_type: F32,
+            bound_type: B0,
+            model_type: M3,
+        };
+        let model = Model::from_primitives(vec![0_f32, 0.5, -0.5].into_iter()).unwrap();
+
+        assert!(Masker::new(config.into())
+            .mask(1_f64, model.clone())
+            .is_ok());
+        assert!(matches!(
+            Masker::new(config.into()).mask(-1_f64, model.clone()),
+            Err(MaskingError::ScalarOutOfBounds)
+        ));
+        assert!(matches!(
+            Masker::new(config.into()).mask(2_f64, model.clone()),
+            Err(MaskingError::ScalarOutOfBounds)
+        ));
+        assert!(matches!(
+            Masker::new(config.into()).mask(f64::NAN, model),
+            Err(MaskingError::ScalarOutOfBounds)
+        ));
+
+        let model = Model::from_primitives(vec![0_f32, 0.5, -1.5].into_iter()).unwrap();
+        assert!(matches!(
+            Masker::new(config.into()).mask(1_f64, model.clone()),
+            Err(MaskingError::WeightOutOfBounds(2))
+        ));
+        // the weights are within the bounds after scaling
+        assert!(Masker::new(config.into()).mask(0.5_f64, model).is_ok());
+    }
+
+    #[test]
+    fn test_clip() {
+        let config = MaskConfig {
+            group_type: Prime,
+            data_type: F32,
+            bound_type: B0,
+            model_type: M3,
+        };
+        let masker = Masker::new(config.into());
+        let model = Model::from_primitives(vec![0_f32, 1.5, -3.0].into_iter()).unwrap();
+
+        let clipped = masker.clip(1_f64, model.clone());
+        let expected = Model::from_primitives(vec![0_f32, 1.0, -1.0].into_iter()).unwrap();
+        assert_eq!(clipped, expected);
+        assert!(Masker::new(config.into()).mask(1_f64, clipped).is_ok());
+
+        let clipped = masker.clip(0.5_f64, model.clone());
+        let expected = Model::from_primitives(vec![0_f32, 1.5, -2.0].into_iter()).unwrap();
+        assert_eq!(clipped, expected);
+        assert!(Masker::new(config.into()).mask(0.5_f64, clipped).is_ok());
+
+        // an invalid scalar is rejected by the masking anyways
+        assert_eq!(masker.clip(-1_f64, model.clone()), model);
+    }
 }
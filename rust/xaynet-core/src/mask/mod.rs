@@ -81,12 +81,24 @@
 //! personal information if the model is generalized enough.
 //!
 //! ## Masking
-//! A [`Model`] can be masked with a [`Masker`], which requires a [`MaskConfig`]. During the
-//! masking, the model weights are scaled, then embedded as elements of the chosen finite group and
-//! finally masked by randomly generated elements from that very same finite group. The scalar
-//! provides the necessary means to perform different aggregation strategies, for example federated
-//! averaging. The masked model is returned as a [`MaskObject`] and the mask used to mask the model
-//! can be generated via the additionally returned [`MaskSeed`].
+//! A [`Model`] can be masked with a [`Masker`], which requires a [`MaskConfigPair`] of one
+//! [`MaskConfig`] for the model weights and one for the scalar. A pair which uses the same
+//! configuration for both can be created from a single [`MaskConfig`]. During the masking, the
+//! model weights are scaled, then embedded as elements of the chosen finite group and finally
+//! masked by randomly generated elements from that very same finite group. The scalar is masked
+//! likewise wrt its own configuration and provides the necessary means to perform different
+//! aggregation strategies, for example federated averaging. The masked model is returned as a
+//! [`MaskObject`] and the mask used to mask the model can be generated via the additionally
+//! returned [`MaskSeed`].
+//!
+//! Scaled weights beyond the bounds of the [`BoundType`] can't be embedded into the finite group,
+//! hence masking fails with a [`MaskingError`] instead of silently altering them. Local models can
+//! be clipped to the bounds via [`Masker::clip()`] beforehand.
+//!
+//! Optionally, local models can be privatized before they are masked for client-level
+//! differential privacy. The Gaussian mechanism configured by a [`DpConfig`] clips a local model
+//! to an L2 norm bound and adds calibrated Gaussian noise to its weights, while a
+//! [`PrivacyAccountant`] keeps track of the privacy budget spent over the rounds.
 //!
 //! ```
 //! # use xaynet_core::mask::{BoundType, DataType, FromPrimitives, GroupType, MaskConfig, Masker, Model, ModelType};
@@ -103,12 +115,12 @@
 //! };
 //!
 //! // mask the local models
-//! let (local_mask_seed_1, masked_local_model_1, masked_local_scalar_1) = Masker::new(config).mask(scalar, local_model_1);
-//! let (local_mask_seed_2, masked_local_model_2, masked_local_scalar_2) = Masker::new(config).mask(scalar, local_model_2);
+//! let (local_mask_seed_1, masked_local_model_1, masked_local_scalar_1) = Masker::new(config.into()).mask(scalar, local_model_1).unwrap();
+//! let (local_mask_seed_2, masked_local_model_2, masked_local_scalar_2) = Masker::new(config.into()).mask(scalar, local_model_2).unwrap();
 //!
 //! // derive the masks of the local masked models
-//! let local_mask_1 = local_mask_seed_1.derive_mask(number_weights, config);
-//! let local_mask_2 = local_mask_seed_2.derive_mask(number_weights, config);
+//! let local_mask_1 = local_mask_seed_1.derive_mask(number_weights, config.into());
+//! let local_mask_2 = local_mask_seed_2.derive_mask(number_weights, config.into());
 //! ```
 //!
 //! ## Aggregation
@@ -124,10 +136,10 @@
 //! # let local_model_1 = Model::from_primitives_bounded(vec![0_f32; number_weights].into_iter());
 //! # let local_model_2 = Model::from_primitives_bounded(vec![1_f32; number_weights].into_iter());
 //! # let config = MaskConfig { group_type: GroupType::Prime, data_type: DataType::F32, bound_type: BoundType::B0, model_type: ModelType::M3};
-//! # let (local_mask_seed_1, masked_local_model_1, masked_local_scalar_1) = Masker::new(config).mask(scalar, local_model_1);
-//! # let (local_mask_seed_2, masked_local_model_2, masked_local_scalar_2) = Masker::new(config).mask(scalar, local_model_2);
-//! # let (local_model_mask_1, local_scalar_mask_1) = local_mask_seed_1.derive_mask(number_weights, config);
-//! # let (local_model_mask_2, local_scalar_mask_2) = local_mask_seed_2.derive_mask(number_weights, config);
+//! # let (local_mask_seed_1, masked_local_model_1, masked_local_scalar_1) = Masker::new(config.into()).mask(scalar, local_model_1).unwrap();
+//! # let (local_mask_seed_2, masked_local_model_2, masked_local_scalar_2) = Masker::new(config.into()).mask(scalar, local_model_2).unwrap();
+//! # let (local_model_mask_1, local_scalar_mask_1) = local_mask_seed_1.derive_mask(number_weights, config.into());
+//! # let (local_model_mask_2, local_scalar_mask_2) = local_mask_seed_2.derive_mask(number_weights, config.into());
 //! // aggregate the local model masks (similarly for local scalar masks)
 //! let mut mask_aggregator = Aggregation::new(config, number_weights);
 //! if let Ok(_) = mask_aggregator.validate_aggregation(&local_model_mask_1) {
@@ -160,10 +172,10 @@
 //! # let local_model_1 = Model::from_primitives_bounded(vec![0_f32; number_weights].into_iter());
 //! # let local_model_2 = Model::from_primitives_bounded(vec![1_f32; number_weights].into_iter());
 //! # let config = MaskConfig { group_type: GroupType::Prime, data_type: DataType::F32, bound_type: BoundType::B0, model_type: ModelType::M3};
-//! # let (local_mask_seed_1, masked_local_model_1, masked_local_scalar_1) = Masker::new(config).mask(scalar, local_model_1);
-//! # let (local_mask_seed_2, masked_local_model_2, masked_local_scalar_2) = Masker::new(config).mask(scalar, local_model_2);
-//! # let (local_model_mask_1, local_scalar_mask_1) = local_mask_seed_1.derive_mask(number_weights, config);
-//! # let (local_model_mask_2, local_scalar_mask_2) = local_mask_seed_2.derive_mask(number_weights, config);
+//! # let (local_mask_seed_1, masked_local_model_1, masked_local_scalar_1) = Masker::new(config.into()).mask(scalar, local_model_1).unwrap();
+//! # let (local_mask_seed_2, masked_local_model_2, masked_local_scalar_2) = Masker::new(config.into()).mask(scalar, local_model_2).unwrap();
+//! # let (local_model_mask_1, local_scalar_mask_1) = local_mask_seed_1.derive_mask(number_weights, config.into());
+//! # let (local_model_mask_2, local_scalar_mask_2) = local_mask_seed_2.derive_mask(number_weights, config.into());
 //! # let mut mask_aggregator = Aggregation::new(config, number_weights);
 //! # if let Ok(_) = mask_aggregator.validate_aggregation(&local_model_mask_1) { mask_aggregator.aggregate(local_model_mask_1); };
 //! # if let Ok(_) = mask_aggregator.validate_aggregation(&local_model_mask_2) { mask_aggregator.aggregate(local_model_mask_2); };
@@ -182,6 +194,7 @@
 //! ```
 
 pub(crate) mod config;
+pub(crate) mod dp;
 pub(crate) mod masking;
 pub(crate) mod model;
 pub(crate) mod object;
@@ -195,10 +208,20 @@ pub use self::{
         GroupType,
         InvalidMaskConfigError,
         MaskConfig,
+        MaskConfigPair,
         ModelType,
     },
-    masking::{Aggregation, AggregationError, Masker, UnmaskingError},
-    model::{FromPrimitives, IntoPrimitives, Model, ModelCastError, PrimitiveCastError},
+    dp::{DpConfig, DpError, PrivacyAccountant},
+    masking::{Aggregation, AggregationError, Masker, MaskingError, UnmaskingError},
+    model::{
+        serialization::TypedModelBuffer,
+        FromPrimitives,
+        IntoPrimitives,
+        Model,
+        ModelCastError,
+        PrimitiveCastError,
+        TypedModel,
+    },
     object::{serialization::MaskObjectBuffer, InvalidMaskObjectError, MaskObject},
     seed::{EncryptedMaskSeed, MaskSeed},
 };
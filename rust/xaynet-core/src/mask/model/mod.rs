@@ -4,9 +4,13 @@
 //!
 //! [mask module]: ../index.html
 
+pub mod serialization;
+
 use std::{
+    convert::TryInto,
     fmt::Debug,
     iter::{FromIterator, IntoIterator},
+    mem,
     slice::{Iter, IterMut},
 };
 
@@ -19,6 +23,8 @@ use num::{
 };
 use thiserror::Error;
 
+use crate::mask::config::DataType;
+
 #[derive(Debug, Clone, PartialEq, Hash, From, Index, IndexMut, Into, Serialize, Deserialize)]
 /// A numerical representation of a machine learning model.
 pub struct Model(Vec<Ratio<BigInt>>);
@@ -57,6 +63,102 @@ impl IntoIterator for Model {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A model whose weights are converted into a primitive data type.
+///
+/// This is the compact representation of a [`Model`] for transferring it: each weight is stored
+/// as a little-endian value of the primitive data type instead of two arbitrarily large integers.
+pub struct TypedModel {
+    data_type: DataType,
+    /// The little-endian encoded weights.
+    weights: Vec<u8>,
+}
+
+macro_rules! impl_typed_model_conversions {
+    ($($data_type:ident => $primitive:ty),+ $(,)?) => {
+        impl TypedModel {
+            /// Converts the weights of the model into the given primitive data type.
+            ///
+            /// # Errors
+            /// Fails if a weight can't be converted into the primitive data type.
+            pub fn new(model: &Model, data_type: DataType) -> Result<Self, ModelCastError> {
+                let mut weights = Vec::with_capacity(model.len() * primitive_size(data_type));
+                match data_type {
+                    $(DataType::$data_type => {
+                        for weight in IntoPrimitives::<$primitive>::to_primitives(model) {
+                            weights.extend_from_slice(&weight?.to_le_bytes());
+                        }
+                    })+
+                }
+                Ok(Self { data_type, weights })
+            }
+
+            /// Converts the weights back into a [`Model`].
+            pub fn into_model(self) -> Model {
+                match self.data_type {
+                    $(DataType::$data_type => {
+                        let weights = self.weights.chunks_exact(primitive_size(self.data_type)).map(
+                            // safe unwrap: the chunk has exactly the size of the primitive
+                            |chunk| <$primitive>::from_le_bytes(chunk.try_into().unwrap()),
+                        );
+                        // the weights are finite because they either originate from a model or
+                        // have been checked during deserialization
+                        Model::from_primitives_bounded(weights)
+                    })+
+                }
+            }
+
+            /// Checks that the encoded weights can be converted into a [`Model`].
+            fn check_weights(data_type: DataType, weights: &[u8]) -> bool {
+                match data_type {
+                    $(DataType::$data_type => weights
+                        .chunks_exact(primitive_size(data_type))
+                        .all(|chunk| {
+                            // safe unwrap: the chunk has exactly the size of the primitive
+                            Model::from_primitives(std::iter::once(
+                                <$primitive>::from_le_bytes(chunk.try_into().unwrap()),
+                            ))
+                            .is_ok()
+                        }),)+
+                }
+            }
+        }
+    };
+}
+
+impl_typed_model_conversions! {
+    F32 => f32,
+    F64 => f64,
+    I32 => i32,
+    I64 => i64,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl TypedModel {
+    /// The media type of a serialized typed model, e.g. for HTTP content negotiation.
+    pub const MEDIA_TYPE: &'static str = "application/vnd.xaynet.typed-model";
+
+    /// Gets the primitive data type of the weights.
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    /// Gets the number of weights/parameters of this model.
+    pub fn len(&self) -> usize {
+        self.weights.len() / primitive_size(self.data_type)
+    }
+}
+
+/// Gets the number of bytes of a value of the primitive data type.
+pub(crate) fn primitive_size(data_type: DataType) -> usize {
+    match data_type {
+        DataType::F32 => mem::size_of::<f32>(),
+        DataType::F64 => mem::size_of::<f64>(),
+        DataType::I32 => mem::size_of::<i32>(),
+        DataType::I64 => mem::size_of::<i64>(),
+    }
+}
+
 #[derive(Debug, Display)]
 /// A primitive data type as a target for model conversion.
 enum PrimitiveType {
@@ -0,0 +1,233 @@
+//! Serialization of typed models.
+//!
+//! See the [mask module] documentation since this is a private module anyways.
+//!
+//! [mask module]: ../index.html
+
+use std::{
+    convert::{TryFrom, TryInto},
+    ops::Range,
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    mask::{
+        config::DataType,
+        model::{primitive_size, TypedModel},
+    },
+    message::{
+        traits::{FromBytes, ToBytes},
+        utils::range,
+        DecodeError,
+    },
+};
+
+const DATA_TYPE_FIELD: usize = 0;
+const NUMBERS_FIELD: Range<usize> = range(DATA_TYPE_FIELD + 1, 4);
+
+/// A buffer for serialized typed models.
+///
+/// The buffer consists of the data type (1 byte), the number of weights (4 bytes, big-endian) and
+/// the weights as little-endian values of the data type.
+pub struct TypedModelBuffer<T> {
+    inner: T,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<T: AsRef<[u8]>> TypedModelBuffer<T> {
+    /// Creates a new buffer from `bytes`.
+    ///
+    /// # Errors
+    /// Fails if the `bytes` don't conform to the required buffer length for typed models.
+    pub fn new(bytes: T) -> Result<Self, DecodeError> {
+        let buffer = Self { inner: bytes };
+        buffer
+            .check_buffer_length()
+            .context("not a valid TypedModel")?;
+        Ok(buffer)
+    }
+
+    /// Creates a new buffer from `bytes`.
+    pub fn new_unchecked(bytes: T) -> Self {
+        Self { inner: bytes }
+    }
+
+    /// Checks if this buffer conforms to the required buffer length for typed models.
+    ///
+    /// # Errors
+    /// Fails if the buffer is too small or if the data type is unknown.
+    pub fn check_buffer_length(&self) -> Result<(), DecodeError> {
+        let len = self.inner.as_ref().len();
+        if len < NUMBERS_FIELD.end {
+            return Err(anyhow!(
+                "invalid buffer length: {} < {}",
+                len,
+                NUMBERS_FIELD.end
+            ));
+        }
+
+        let data_type = DataType::try_from(self.data_type()).context("invalid data type")?;
+        let (data_length, overflows) = self.numbers().overflowing_mul(primitive_size(data_type));
+        if overflows {
+            return Err(anyhow!("invalid TypedModel buffer: invalid numbers field"));
+        }
+        let total_expected_length = NUMBERS_FIELD.end + data_length;
+        if len < total_expected_length {
+            return Err(anyhow!(
+                "invalid buffer length: expected {} bytes but buffer has only {} bytes",
+                total_expected_length,
+                len
+            ));
+        }
+        Ok(())
+    }
+
+    /// Gets the expected number of bytes of this buffer wrt to the data type.
+    ///
+    /// # Panics
+    /// Panics if the serialized data type is invalid.
+    pub fn len(&self) -> usize {
+        let data_type = DataType::try_from(self.data_type()).unwrap();
+        NUMBERS_FIELD.end + self.numbers() * primitive_size(data_type)
+    }
+
+    /// Gets the serialized data type.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn data_type(&self) -> u8 {
+        self.inner.as_ref()[DATA_TYPE_FIELD]
+    }
+
+    /// Gets the number of serialized weights.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn numbers(&self) -> usize {
+        // UNWRAP SAFE: the slice is exactly 4 bytes long
+        u32::from_be_bytes(self.inner.as_ref()[NUMBERS_FIELD].try_into().unwrap()) as usize
+    }
+
+    /// Gets the serialized weights.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn data(&self) -> &[u8] {
+        &self.inner.as_ref()[NUMBERS_FIELD.end..self.len()]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> TypedModelBuffer<T> {
+    /// Sets the serialized data type.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn set_data_type(&mut self, value: u8) {
+        self.inner.as_mut()[DATA_TYPE_FIELD] = value;
+    }
+
+    /// Sets the number of serialized weights.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn set_numbers(&mut self, value: u32) {
+        self.inner.as_mut()[NUMBERS_FIELD].copy_from_slice(&value.to_be_bytes());
+    }
+
+    /// Gets the serialized weights.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        let end = self.len();
+        &mut self.inner.as_mut()[NUMBERS_FIELD.end..end]
+    }
+}
+
+impl ToBytes for TypedModel {
+    fn buffer_length(&self) -> usize {
+        NUMBERS_FIELD.end + self.weights.len()
+    }
+
+    fn to_bytes<T: AsMut<[u8]> + AsRef<[u8]>>(&self, buffer: &mut T) {
+        let mut writer = TypedModelBuffer::new_unchecked(buffer.as_mut());
+        writer.set_data_type(self.data_type as u8);
+        writer.set_numbers(self.len() as u32);
+        writer.data_mut().copy_from_slice(&self.weights);
+    }
+}
+
+impl FromBytes for TypedModel {
+    fn from_bytes<T: AsRef<[u8]>>(buffer: &T) -> Result<Self, DecodeError> {
+        let reader = TypedModelBuffer::new(buffer.as_ref())?;
+        // safe unwrap: the data type has been checked by the buffer
+        let data_type = DataType::try_from(reader.data_type()).unwrap();
+        if !TypedModel::check_weights(data_type, reader.data()) {
+            return Err(anyhow!("invalid TypedModel: the weights must be finite"));
+        }
+        Ok(TypedModel {
+            data_type,
+            weights: reader.data().to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mask::{
+        model::{FromPrimitives, IntoPrimitives, Model},
+        DataType,
+    };
+
+    fn model() -> TypedModel {
+        let model = Model::from_primitives(vec![1_f32, -2_f32].into_iter()).unwrap();
+        TypedModel::new(&model, DataType::F32).unwrap()
+    }
+
+    fn bytes() -> Vec<u8> {
+        vec![
+            0x00, // data type
+            0x00, 0x00, 0x00, 0x02, // number of weights
+            0x00, 0x00, 0x80, 0x3f, // 1
+            0x00, 0x00, 0x00, 0xc0, // -2
+        ]
+    }
+
+    #[test]
+    fn serialize() {
+        let mut buf = vec![0xff; 13];
+        model().to_bytes(&mut buf);
+        assert_eq!(buf, bytes());
+    }
+
+    #[test]
+    fn deserialize() {
+        let typed_model = TypedModel::from_bytes(&bytes()).unwrap();
+        assert_eq!(typed_model, model());
+        assert_eq!(typed_model.len(), 2);
+
+        let weights: Vec<f32> = typed_model
+            .into_model()
+            .into_primitives_unchecked()
+            .collect();
+        assert_eq!(weights, vec![1_f32, -2_f32]);
+    }
+
+    #[test]
+    fn deserialize_invalid() {
+        // unknown data type
+        let mut invalid = bytes();
+        invalid[0] = 0x04;
+        assert!(TypedModel::from_bytes(&invalid).is_err());
+
+        // too few weights
+        assert!(TypedModel::from_bytes(&bytes()[..12].to_vec()).is_err());
+
+        // NaN weight
+        let mut invalid = bytes();
+        invalid[9..13].copy_from_slice(&f32::NAN.to_le_bytes());
+        assert!(TypedModel::from_bytes(&invalid).is_err());
+    }
+}
@@ -14,7 +14,7 @@ use thiserror::Error;
 
 use crate::{
     crypto::{encrypt::SEALBYTES, prng::generate_integer, ByteObject},
-    mask::{config::MaskConfig, object::MaskObject},
+    mask::{config::MaskConfigPair, object::MaskObject},
     SumParticipantEphemeralPublicKey,
     SumParticipantEphemeralSecretKey,
 };
@@ -53,17 +53,18 @@ impl MaskSeed {
         EncryptedMaskSeed::from_slice_unchecked(pk.encrypt(self.as_slice()).as_slice())
     }
 
-    // TODO separate config for scalar mask - future refactoring
-    /// Derives a mask of given length from this seed wrt the masking configuration.
-    pub fn derive_mask(&self, len: usize, config: MaskConfig) -> (MaskObject, MaskObject) {
+    /// Derives a mask of given length for the model weights and a mask for the scalar from this
+    /// seed wrt the masking configurations.
+    pub fn derive_mask(&self, len: usize, config: MaskConfigPair) -> (MaskObject, MaskObject) {
         let mut prng = ChaCha20Rng::from_seed(self.as_array());
-        let rand_ints = iter::repeat_with(|| generate_integer(&mut prng, &config.order()))
+        let order = config.vect.order();
+        let rand_ints = iter::repeat_with(|| generate_integer(&mut prng, &order))
             .take(len)
             .collect();
-        let model_mask = MaskObject::new(config, rand_ints);
+        let model_mask = MaskObject::new(config.vect, rand_ints);
 
-        let rand_int = generate_integer(&mut prng, &config.order());
-        let scalar_mask = MaskObject::new(config, vec![rand_int]);
+        let rand_int = generate_integer(&mut prng, &config.unit.order());
+        let scalar_mask = MaskObject::new(config.unit, vec![rand_int]);
 
         (model_mask, scalar_mask)
     }
@@ -131,7 +132,7 @@ mod tests {
     use super::*;
     use crate::{
         crypto::encrypt::EncryptKeyPair,
-        mask::config::{BoundType, DataType, GroupType, MaskConfig, ModelType},
+        mask::config::{BoundType, DataType, GroupType, MaskConfig, MaskConfigPair, ModelType},
     };
 
     #[test]
@@ -150,19 +151,32 @@ mod tests {
 
     #[test]
     fn test_derive_mask() {
-        let config = MaskConfig {
-            group_type: GroupType::Prime,
-            data_type: DataType::F32,
-            bound_type: BoundType::B0,
-            model_type: ModelType::M3,
+        let config = MaskConfigPair {
+            vect: MaskConfig {
+                group_type: GroupType::Prime,
+                data_type: DataType::F32,
+                bound_type: BoundType::B6,
+                model_type: ModelType::M3,
+            },
+            unit: MaskConfig {
+                group_type: GroupType::Prime,
+                data_type: DataType::F32,
+                bound_type: BoundType::B0,
+                model_type: ModelType::M3,
+            },
         };
         let seed = MaskSeed::generate();
         let (mask, scalar_mask) = seed.derive_mask(10, config);
+        assert_eq!(mask.config, config.vect);
         assert_eq!(mask.data.len(), 10);
-        assert!(mask.data.iter().all(|integer| integer < &config.order()));
+        assert!(mask
+            .data
+            .iter()
+            .all(|integer| integer < &config.vect.order()));
 
+        assert_eq!(scalar_mask.config, config.unit);
         assert_eq!(scalar_mask.data.len(), 1);
-        // TODO check size later after future refactoring
+        assert!(scalar_mask.data[0] < config.unit.order());
     }
 
     #[test]
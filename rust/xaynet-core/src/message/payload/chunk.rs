@@ -25,16 +25,24 @@ pub(crate) mod ranges {
 const HEADER_LENGTH: usize = ranges::RESERVED.end;
 
 /// A message chunk.
+///
+/// Messages that are too large to be sent at once can be split into
+/// chunks. The chunks of a message share the same `message_id` and are
+/// numbered from `0` to the ID of the chunk flagged as `last`. The
+/// data of all the chunks, concatenated in the order of their IDs,
+/// forms the complete serialized and signed [`Message`].
+///
+/// [`Message`]: crate::message::Message
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct Chunk {
     /// Chunk ID
-    id: u16,
+    pub id: u16,
     /// ID of the message this chunk belongs to
-    message_id: u16,
+    pub message_id: u16,
     /// `true` if this is the last chunk of the message, `false` otherwise
-    last: bool,
+    pub last: bool,
     /// Data contained in this chunk.
-    data: Vec<u8>,
+    pub data: Vec<u8>,
 }
 
 bitflags::bitflags! {
@@ -40,9 +40,10 @@ use std::{
     ffi::CStr,
     iter::{IntoIterator, Iterator},
     mem,
-    os::raw::{c_char, c_int, c_uint, c_ulonglong, c_void},
+    os::raw::{c_char, c_int, c_uchar, c_uint, c_ulonglong, c_void},
     panic,
     ptr,
+    slice,
 };
 
 use tokio::{
@@ -51,7 +52,10 @@ use tokio::{
 };
 
 use xaynet_client::{api::HttpApiClient, CachedModel, Client, ClientError, Task};
-use xaynet_core::mask::{FromPrimitives, IntoPrimitives, Model};
+use xaynet_core::{
+    crypto::{ByteObject, PublicSigningKey},
+    mask::{FromPrimitives, IntoPrimitives, Model},
+};
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
@@ -94,6 +98,10 @@ pub struct FFIClient {
 /// Takes a `period` in seconds after which the [`Client`] will try to poll the coordinator for new
 /// broadcasted FL round data again.
 ///
+/// Optionally takes a `coordinator_key`, which points to the 32 bytes of the long-term public
+/// signing key of the coordinator. If it is given, the [`Client`] rejects the round parameters
+/// unless they are signed with the corresponding signing key. A null pointer disables the check.
+///
 /// # Errors
 /// Ignores null pointer `address`es and zero `period`s and returns a null pointer immediately.
 ///
@@ -102,7 +110,11 @@ pub struct FFIClient {
 /// # Safety
 /// The method dereferences from the raw pointer arguments. Therefore, the behavior of the method is
 /// undefined if the arguments don't point to valid objects.
-pub unsafe extern "C" fn new_client(address: *const c_char, period: c_ulonglong) -> *mut FFIClient {
+pub unsafe extern "C" fn new_client(
+    address: *const c_char,
+    period: c_ulonglong,
+    coordinator_key: *const c_uchar,
+) -> *mut FFIClient {
     if address.is_null() || period == 0 {
         return ptr::null_mut() as *mut FFIClient;
     }
@@ -128,9 +140,17 @@ pub unsafe extern "C" fn new_client(address: *const c_char, period: c_ulonglong)
     } else {
         return ptr::null_mut() as *mut FFIClient;
     };
-    let client = if let Ok(client) =
-        runtime.enter(move || Client::new(period as u64, 0, HttpApiClient::new(address)))
-    {
+    let mut api = HttpApiClient::new(address);
+    if !coordinator_key.is_null() {
+        let coordinator_key = unsafe {
+            // safe if the raw pointer `coordinator_key` comes from a valid allocation of at least
+            // `PublicSigningKey::LENGTH` bytes
+            slice::from_raw_parts(coordinator_key, PublicSigningKey::LENGTH)
+        };
+        // safe unwrap: the slice has the length of a key
+        api = api.with_coordinator_key(PublicSigningKey::from_slice(coordinator_key).unwrap());
+    }
+    let client = if let Ok(client) = runtime.enter(move || Client::new(period as u64, 0, api)) {
         client
     } else {
         return ptr::null_mut() as *mut FFIClient;
@@ -156,6 +176,7 @@ pub unsafe extern "C" fn new_client(address: *const c_char, period: c_ulonglong)
 /// - `4`: client stopped due to error [`TooEarly`]
 /// - `5`: client stopped due to error [`RoundOutdated`]
 /// - `6`: client stopped due to error [`Api`]
+/// - `7`: client stopped due to error [`MessageRejected`]
 ///
 /// # Safety
 ///
@@ -170,6 +191,7 @@ pub unsafe extern "C" fn new_client(address: *const c_char, period: c_ulonglong)
 /// [`TooEarly`]: ../../client/enum.ClientError.html#variant.TooEarly
 /// [`RoundOutdated`]: ../../client/enum.ClientError.html#variant.RoundOutdated
 /// [`Api`]: ../../client/enum.ClientError.html#variant.Api
+/// [`MessageRejected`]: ../../client/enum.ClientError.html#variant.MessageRejected
 pub unsafe extern "C" fn run_client(client: *mut FFIClient) -> c_int {
     if client.is_null() {
         return -1_i32 as c_int;
@@ -200,6 +222,7 @@ pub unsafe extern "C" fn run_client(client: *mut FFIClient) -> c_int {
         Ok(Err(ClientError::TooEarly(_))) => 4_i32 as c_int,
         Ok(Err(ClientError::RoundOutdated)) => 5_i32 as c_int,
         Ok(Err(ClientError::Api(_))) => 6_i32 as c_int,
+        Ok(Err(ClientError::MessageRejected(_))) => 7_i32 as c_int,
     }
 }
 
@@ -686,7 +709,23 @@ mod tests {
 
     #[test]
     fn test_new_client() {
-        let client = unsafe { new_client(CString::new("0.0.0.0:0000").unwrap().as_ptr(), 10) };
+        let client = unsafe {
+            new_client(
+                CString::new("0.0.0.0:0000").unwrap().as_ptr(),
+                10,
+                ptr::null(),
+            )
+        };
+        assert!(!client.is_null());
+        unsafe { drop_client(client, 0) };
+    }
+
+    #[test]
+    fn test_new_client_with_coordinator_key() {
+        let address = CString::new("0.0.0.0:0000").unwrap();
+        let coordinator_key = PublicSigningKey::fill_with(1);
+        let client =
+            unsafe { new_client(address.as_ptr(), 10, coordinator_key.as_slice().as_ptr()) };
         assert!(!client.is_null());
         unsafe { drop_client(client, 0) };
     }
@@ -694,7 +733,13 @@ mod tests {
     #[test]
     fn test_run_client() {
         // check for network error when running client without a service
-        let client = unsafe { new_client(CString::new("0.0.0.0:0000").unwrap().as_ptr(), 10) };
+        let client = unsafe {
+            new_client(
+                CString::new("0.0.0.0:0000").unwrap().as_ptr(),
+                10,
+                ptr::null(),
+            )
+        };
         assert_eq!(unsafe { run_client(client) }, 6);
         unsafe { drop_client(client, 0) };
     }
@@ -710,7 +755,7 @@ mod tests {
                 #[allow(unused_unsafe)]
                 #[test]
                 fn [<test_new_model_ $prim>]() {
-                    let client = unsafe { new_client(CString::new("0.0.0.0:0000").unwrap().as_ptr(), 10) };
+                    let client = unsafe { new_client(CString::new("0.0.0.0:0000").unwrap().as_ptr(), 10, ptr::null()) };
 
                     // check that the new model is cached
                     let model = dummy_model(0., 10);
@@ -740,7 +785,7 @@ mod tests {
                 #[allow(unused_unsafe)]
                 #[test]
                 fn [<test_get_model_ $prim>]() {
-                    let client = unsafe { new_client(CString::new("0.0.0.0:0000").unwrap().as_ptr(), 10) };
+                    let client = unsafe { new_client(CString::new("0.0.0.0:0000").unwrap().as_ptr(), 10, ptr::null()) };
 
                     // check that the primitive model is null if the global model is unavailable
                     assert!(unsafe { &*client }.client.global_model.is_none());
@@ -778,7 +823,7 @@ mod tests {
             paste::item! {
                 #[test]
                 fn [<test_update_model_ $prim>]() {
-                    let client = unsafe { new_client(CString::new("0.0.0.0:0000").unwrap().as_ptr(), 10) };
+                    let client = unsafe { new_client(CString::new("0.0.0.0:0000").unwrap().as_ptr(), 10, ptr::null()) };
                     let model = dummy_model(0., 10);
                     unsafe { &mut *client }.client.global_model = Some(model.clone());
                     let prim_model = unsafe { get_model(client, $dtype as c_uint) };
@@ -93,17 +93,20 @@ impl Parse for Send {
 /// A `Sender` must implement the method `pub fn send(&self, metrics: T)` where `T`
 /// is the type of the metric.
 ///
+/// The metrics are backend-agnostic, it's up to the receiving end of the `Sender` to forward
+/// them to the actual metrics backends (e.g. InfluxDB or Prometheus).
+///
 /// ### Example of a `Sender` implementation
 ///
 /// ```ignore
-/// use influxdb::WriteQuery;
 /// use tokio::sync::mpsc::Sender;
+/// use xaynet_server::metrics::Metric;
 ///
-/// pub struct MetricsSender(Sender<WriteQuery>);
+/// pub struct MetricsSender(Sender<Metric>);
 ///
 /// impl MetricsSender {
-///     pub fn send(&mut self, query: WriteQuery) {
-///         let _ = self.0.try_send(query).map_err(|e| error!("{}", e));
+///     pub fn send(&mut self, metric: Metric) {
+///         let _ = self.0.try_send(metric).map_err(|e| error!("{}", e));
 ///     }
 /// }
 /// ```
@@ -1,11 +1,31 @@
 use std::{path::PathBuf, process};
 use structopt::StructOpt;
-use tokio::signal;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::{
+    signal,
+    sync::oneshot,
+    time::{timeout, Duration},
+};
 use tracing_subscriber::*;
-use xaynet_server::{rest, services, settings::Settings, state_machine::StateMachine};
+use xaynet_core::crypto::ByteObject;
+use xaynet_server::{
+    rest,
+    services,
+    settings::Settings,
+    state_machine::StateMachine,
+    storage::{redis, registry::ParticipantRegistry},
+};
 
 #[cfg(feature = "metrics")]
-use xaynet_server::metrics::{run_metric_service, MetricsService};
+use xaynet_server::metrics::{
+    prometheus,
+    run_metric_service,
+    InfluxDbBackend,
+    MetricsBackend,
+    MetricsService,
+    PrometheusExporter,
+};
 
 #[macro_use]
 extern crate tracing;
@@ -30,6 +50,11 @@ async fn main() {
         model: model_settings,
         metrics: metrics_settings,
         redis: redis_settings,
+        multipart: multipart_settings,
+        services: services_settings,
+        shutdown: shutdown_settings,
+        registry: registry_settings,
+        rate_limit: rate_limit_settings,
     } = Settings::new(opt.config_path).unwrap_or_else(|err| {
         eprintln!("{}", err);
         process::exit(1);
@@ -47,36 +72,141 @@ async fn main() {
 
     #[cfg(feature = "metrics")]
     let (metrics_sender, metrics_handle) = {
-        let (metrics_service, metrics_sender) = MetricsService::new(
-            &metrics_settings.influxdb.url,
-            &metrics_settings.influxdb.db,
-        );
+        let mut backends: Vec<Box<dyn MetricsBackend>> = Vec::new();
+        if let Some(influxdb) = metrics_settings.influxdb {
+            backends.push(Box::new(InfluxDbBackend::new(&influxdb.url, &influxdb.db)));
+        }
+        if let Some(prometheus) = metrics_settings.prometheus {
+            let exporter = PrometheusExporter::new();
+            backends.push(Box::new(exporter.clone()));
+            let server =
+                prometheus::serve(prometheus.bind_address, exporter).unwrap_or_else(|err| {
+                    error!("failed to serve the Prometheus metrics: {}", err);
+                    process::exit(1);
+                });
+            tokio::spawn(server);
+        }
+        let (metrics_service, metrics_sender) = MetricsService::new(backends);
         (
             metrics_sender,
             tokio::spawn(async { run_metric_service(metrics_service).await }),
         )
     };
 
-    let (state_machine, requests_tx, event_subscriber) = StateMachine::new(
+    let redis = redis::Client::new(redis_settings.url, redis_settings.max_connections)
+        .await
+        .unwrap_or_else(|err| {
+            error!("failed to connect to Redis: {}", err);
+            process::exit(1);
+        });
+
+    let registry =
+        ParticipantRegistry::new(registry_settings, redis.clone()).unwrap_or_else(|err| {
+            error!("{}", err);
+            process::exit(1);
+        });
+
+    let (state_machine, requests_tx, control_tx, event_subscriber) = StateMachine::new(
         pet_settings,
         mask_settings,
         model_settings,
+        redis.clone(),
         #[cfg(feature = "metrics")]
         metrics_sender,
     )
-    .unwrap();
-    let fetcher = services::fetchers::fetcher(&event_subscriber);
-    let message_handler =
-        services::messages::PetMessageHandler::new(&event_subscriber, requests_tx);
+    .await
+    .unwrap_or_else(|err| {
+        error!("{}", err);
+        process::exit(1);
+    });
+
+    let signing_key = api_settings
+        .load_signing_key()
+        .unwrap_or_else(|err| {
+            error!("{}", err);
+            process::exit(1);
+        })
+        .map(|keys| {
+            info!(
+                "signing the round parameters with the public key {}",
+                keys.public.to_hex()
+            );
+            keys.secret
+        });
+    let fetcher = services::fetchers::fetcher(&event_subscriber, signing_key);
+    let message_handler = services::messages::PetMessageHandler::new(
+        &event_subscriber,
+        requests_tx,
+        registry,
+        multipart_settings,
+        rate_limit_settings,
+        &services_settings,
+    )
+    .unwrap_or_else(|err| {
+        error!("{}", err);
+        process::exit(1);
+    });
 
-    tokio::select! {
-        _ = state_machine.run() => {
-            warn!("shutting down: Service terminated");
-        }
-        _ = rest::serve(api_settings.bind_address, fetcher, message_handler) => {
-            warn!("shutting down: REST server terminated");
+    // The futures are dropped at the end of this block, which drops the state machine even if
+    // the graceful shutdown timed out.
+    {
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let rest = rest::serve(
+            api_settings,
+            fetcher,
+            message_handler,
+            control_tx,
+            &event_subscriber,
+            redis,
+            async {
+                let _ = shutdown_rx.await;
+            },
+        )
+        .unwrap_or_else(|err| {
+            error!("{}", err);
+            process::exit(1);
+        });
+        let state_machine = state_machine.run();
+        tokio::pin!(state_machine);
+        tokio::pin!(rest);
+
+        // whether the REST server and the state machine are still running
+        let (rest_running, state_machine_running) = tokio::select! {
+            _ = &mut state_machine => {
+                warn!("shutting down: Service terminated");
+                (true, false)
+            }
+            _ = &mut rest => {
+                warn!("shutting down: REST server terminated");
+                (false, false)
+            }
+            _ = shutdown_signal() => (true, true),
+        };
+
+        if rest_running {
+            // The REST server stops accepting new messages and returns once the messages in flight
+            // have been handled, including an admin request which shut down the state machine.
+            // Then the message handler is dropped, which closes the request channel and moves a
+            // running state machine into the shutdown phase.
+            warn!("shutting down: waiting for the messages in flight");
+            let _ = shutdown_tx.send(());
+            let graceful_shutdown = async {
+                tokio::join!(&mut rest, async {
+                    if state_machine_running {
+                        (&mut state_machine).await;
+                    }
+                });
+            };
+            if timeout(
+                Duration::from_secs(shutdown_settings.timeout),
+                graceful_shutdown,
+            )
+            .await
+            .is_err()
+            {
+                warn!("shutting down: graceful shutdown timed out");
+            }
         }
-        _ =  signal::ctrl_c() => {}
     }
 
     #[cfg(feature = "metrics")]
@@ -88,3 +218,23 @@ async fn main() {
         let _ = metrics_handle.await;
     }
 }
+
+/// Waits for a `SIGINT` or `SIGTERM` signal.
+#[cfg(unix)]
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).unwrap_or_else(|err| {
+        error!("failed to listen for SIGTERM: {}", err);
+        process::exit(1);
+    });
+    tokio::select! {
+        _ = signal::ctrl_c() => info!("received SIGINT"),
+        _ = terminate.recv() => info!("received SIGTERM"),
+    }
+}
+
+/// Waits for a `SIGINT` signal.
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    let _ = signal::ctrl_c().await;
+    info!("received SIGINT");
+}
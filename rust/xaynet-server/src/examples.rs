@@ -110,7 +110,8 @@ selection above because they will be the most relevant for this guide.
 
 Going from the top, the [`ApiSettings`] include the
 address the coordinator should listen on for requests from participants. This
-address should be known to all participants.
+address should be known to all participants. Optionally, they also include the
+[`TlsSettings`] to serve the API over HTTPS.
 
 The [`PetSettings`] specify various parameters of the PET protocol:
 
@@ -212,6 +213,7 @@ $ cargo run --example test-drive-net -- -l 4 -n 20 -u http://127.0.0.1:8081
 ```
 
 [`ApiSettings`]: crate::settings::ApiSettings
+[`TlsSettings`]: crate::settings::TlsSettings
 [`PetSettings`]: crate::settings::PetSettings
 [`sum`]: crate::settings::PetSettings::sum
 [`update`]: crate::settings::PetSettings::update
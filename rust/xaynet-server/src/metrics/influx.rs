@@ -0,0 +1,100 @@
+//! The InfluxDB metrics backend.
+
+use chrono::{DateTime, Utc};
+use influxdb::{Client, InfluxDbWriteable, Timestamp, WriteQuery};
+
+use super::{
+    models::{DataPoint, Event, Measurement, Metric, Value},
+    MetricsBackend,
+};
+
+/// An influx `event` data point.
+#[derive(InfluxDbWriteable)]
+struct InfluxEvent {
+    time: DateTime<Utc>,
+    title: String,
+    text: Option<String>,
+    tags: Option<String>,
+}
+
+impl From<Metric> for WriteQuery {
+    fn from(metric: Metric) -> Self {
+        match metric {
+            Metric::DataPoint(DataPoint {
+                time,
+                measurement,
+                value,
+                round_id,
+                phase,
+            }) => {
+                let timestamp: Timestamp = time.into();
+                let query = timestamp.into_query(measurement.to_string());
+                let query = match value {
+                    Value::Float(value) => query.add_field("value", value),
+                    Value::Unsigned(value) => query.add_field("value", value),
+                };
+                query
+                    .add_tag("round_id", round_id)
+                    .add_tag("phase", phase.map(|phase| phase as u8))
+            }
+            Metric::Event(Event {
+                time,
+                title,
+                text,
+                tags,
+            }) => InfluxEvent {
+                time,
+                title,
+                text,
+                tags,
+            }
+            .into_query(Measurement::Event.to_string()),
+        }
+    }
+}
+
+/// A metrics backend that writes the metrics to InfluxDB.
+pub struct InfluxDbBackend {
+    /// The InfluxDB client.
+    client: Client,
+}
+
+impl InfluxDbBackend {
+    /// Creates a new InfluxDB backend.
+    ///
+    /// - `url`: The url where InfluxDB is running (e.g. `http://127.0.0.1:8086`).
+    /// - `database`: The name of the database in which the metrics are to be written.
+    ///
+    /// Note:
+    /// It is assumed that the database already exists. If this is not the case, no metrics are
+    /// written in InfluxDB.
+    pub fn new(url: &str, database: &str) -> Self {
+        Self {
+            client: Client::new(url, database),
+        }
+    }
+
+    /// Similar to the [`InfluxDbBackend::new`] but with additional InfluxDB user credentials.
+    ///
+    /// - `username`: The username for InfluxDB.
+    /// - `password`: The password for that username.
+    pub fn new_with_auth(url: &str, database: &str, username: &str, password: &str) -> Self {
+        Self {
+            client: Client::new(url, database).with_auth(username, password),
+        }
+    }
+}
+
+#[async_trait]
+impl MetricsBackend for InfluxDbBackend {
+    /// Sends the metric to InfluxDB.
+    ///
+    /// If an error occurs, the metric is discarded and the error is logged.
+    async fn record(&mut self, metric: &Metric) {
+        let _ = self
+            .client
+            .query(&WriteQuery::from(metric.clone()))
+            .await
+            .map_err(|e| error!("{}", e));
+    }
+}
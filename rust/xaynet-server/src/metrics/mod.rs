@@ -1,13 +1,22 @@
-//! Utilities for sending metrics to an InfluxDB instance.
+//! Utilities for sending metrics to InfluxDB and/or exposing them to Prometheus.
+//!
+//! The functions of this module create backend-agnostic [`Metric`]s which are sent to the
+//! [`MetricsService`]. The service hands them over to all its [`MetricsBackend`]s.
 //!
 //! ## Basic usage:
 //!
 //! ```compile_fail
 //! async fn main() {
-//!     let (metrics_service, metrics_sender) =
-//!         MetricsService::new("http://127.0.0.1:8086", "metrics");
+//!     let prometheus = PrometheusExporter::new();
+//!     let backends: Vec<Box<dyn MetricsBackend>> = vec![
+//!         Box::new(InfluxDbBackend::new("http://127.0.0.1:8086", "metrics")),
+//!         Box::new(prometheus.clone()),
+//!     ];
+//!     let (metrics_service, metrics_sender) = MetricsService::new(backends);
 //!     let metrics_service_handle =
 //!         tokio::spawn(async { run_metric_service(metrics_service).await });
+//!     let server = prometheus::serve(([127, 0, 0, 1], 9100), prometheus).unwrap();
+//!     tokio::spawn(server);
 //!
 //!     metrics_sender.send(metrics::phase::update(PhaseName::Idle));
 //!
@@ -18,7 +27,9 @@
 //! }
 //! ```
 
+mod influx;
 mod models;
+pub mod prometheus;
 
 #[cfg(not(test))]
 pub(crate) mod service;
@@ -28,17 +39,31 @@ pub use self::service::{run_metric_service, MetricsSender, MetricsService};
 pub mod tests;
 #[cfg(test)]
 pub use self::tests::MetricsSender;
+pub use self::{
+    influx::InfluxDbBackend,
+    models::{DataPoint, Event, Measurement, Metric, Value},
+    prometheus::PrometheusExporter,
+};
+
+/// A backend which records metrics.
+#[async_trait]
+pub trait MetricsBackend: Send {
+    /// Records a metric.
+    ///
+    /// Recording must not fail. A backend handles its errors itself, e.g. by logging them.
+    async fn record(&mut self, metric: &Metric);
+}
 
 pub mod round_parameters {
-    use super::models::{DataPoint, Measurement};
+    use super::models::{DataPoint, Measurement, Metric};
     use crate::state_machine::phases::PhaseName;
-    use influxdb::{InfluxDbWriteable, Timestamp, WriteQuery};
+    use chrono::Utc;
     pub mod sum {
         use super::*;
 
         /// Updates the measurement `round_param_sum` with the value of `sum`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
@@ -49,14 +74,15 @@ pub mod round_parameters {
         /// | tag_value   | value of `round_id`      |
         /// | tag_key     | `"phase"`                |
         /// | tag_value   | value of `phase` as `u8` |
-        pub fn update(sum: f64, round_id: u64, phase: PhaseName) -> WriteQuery {
+        pub fn update(sum: f64, round_id: u64, phase: PhaseName) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: sum,
+                time: Utc::now(),
+                measurement: Measurement::RoundParamSum,
+                value: sum.into(),
                 round_id: Some(round_id),
-                phase: Some(phase as u8),
+                phase: Some(phase),
             }
-            .into_query(Measurement::RoundParamSum.to_string())
+            .into()
         }
     }
 
@@ -65,7 +91,7 @@ pub mod round_parameters {
 
         /// Updates the measurement `round_param_update` with the value of `update`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
@@ -76,75 +102,80 @@ pub mod round_parameters {
         /// | tag_value   | value of `round_id`      |
         /// | tag_key     | `"phase"`                |
         /// | tag_value   | value of `phase` as `u8` |
-        pub fn update(update: f64, round_id: u64, phase: PhaseName) -> WriteQuery {
+        pub fn update(update: f64, round_id: u64, phase: PhaseName) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: update,
+                time: Utc::now(),
+                measurement: Measurement::RoundParamUpdate,
+                value: update.into(),
                 round_id: Some(round_id),
-                phase: Some(phase as u8),
+                phase: Some(phase),
             }
-            .into_query(Measurement::RoundParamUpdate.to_string())
+            .into()
         }
     }
 }
 
 pub mod phase {
-    use super::models::{DataPoint, Event, Measurement};
+    use super::models::{DataPoint, Event, Measurement, Metric};
     use crate::state_machine::phases::{PhaseName, StateError};
-    use influxdb::{InfluxDbWriteable, Timestamp, WriteQuery};
+    use chrono::Utc;
     pub mod error {
         use super::*;
 
         /// Emits the measurement `event` with the value of `error`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                       |
         /// |-------------|-----------------------------|
         /// | measurement | `event             `        |
         /// | field_key   | `title`                     |
         /// | field_value | value of `error.to_string()`|
-        pub fn emit(error: &StateError) -> WriteQuery {
+        /// | field_key   | `tags`                      |
+        /// | field_value | value of `error.kind()`     |
+        pub fn emit(error: &StateError) -> Metric {
             Event {
-                time: Timestamp::Now.into(),
+                time: Utc::now(),
                 title: error.to_string(),
                 text: None,
-                tags: None,
+                tags: Some(error.kind().to_string()),
             }
-            .into_query(Measurement::Event.to_string())
+            .into()
         }
     }
 
     /// Updates the measurement `phase` with the value of `phase`.
     ///
-    /// Creates an influx data point with the following properties:
+    /// Creates a data point with the following properties:
     ///
     /// | property    | value                    |
     /// |-------------|--------------------------|
     /// | measurement | `phase`                  |
     /// | field_key   | `value`                  |
     /// | field_value | value of `phase` as `u8` |
-    pub fn update(phase: PhaseName) -> WriteQuery {
+    pub fn update(phase: PhaseName) -> Metric {
         DataPoint {
-            time: Timestamp::Now.into(),
-            value: phase as u8,
+            time: Utc::now(),
+            measurement: Measurement::Phase,
+            value: u64::from(phase as u8).into(),
             round_id: None,
             phase: None,
         }
-        .into_query(Measurement::Phase.to_string())
+        .into()
     }
 }
 
 pub mod masks {
-    use super::models::{DataPoint, Measurement};
+    use super::models::{DataPoint, Event, Measurement, Metric};
     use crate::state_machine::phases::PhaseName;
-    use influxdb::{InfluxDbWriteable, Timestamp, WriteQuery};
+    use chrono::Utc;
+    use xaynet_core::{crypto::ByteObject, SumParticipantPublicKey};
     pub mod total_number {
         use super::*;
 
         /// Updates the measurement `masks_total_number` with the value of `total_number`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
@@ -155,42 +186,75 @@ pub mod masks {
         /// | tag_value   | value of `round_id`      |
         /// | tag_key     | `"phase"`                |
         /// | tag_value   | value of `phase` as `u8` |
-        pub fn update(total_number: usize, round_id: u64, phase: PhaseName) -> WriteQuery {
+        pub fn update(total_number: usize, round_id: u64, phase: PhaseName) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: total_number as u64,
+                time: Utc::now(),
+                measurement: Measurement::MasksTotalNumber,
+                value: (total_number as u64).into(),
                 round_id: Some(round_id),
-                phase: Some(phase as u8),
+                phase: Some(phase),
             }
-            .into_query(Measurement::MasksTotalNumber.to_string())
+            .into()
+        }
+    }
+
+    pub mod disagreement {
+        use super::*;
+
+        /// Emits the measurement `event` with the sum participants whose masks disagree with the
+        /// mask used for unmasking.
+        ///
+        /// Creates a data point with the following properties:
+        ///
+        /// | property    | value                                                   |
+        /// |-------------|---------------------------------------------------------|
+        /// | measurement | `event`                                                 |
+        /// | field_key   | `title`                                                 |
+        /// | field_value | `"sum participants disagreed on the mask"`              |
+        /// | field_key   | `text`                                                  |
+        /// | field_value | value of `round_id` and the hex encoded `participants`  |
+        pub fn emit(round_id: u64, participants: &[SumParticipantPublicKey]) -> Metric {
+            let participants = participants
+                .iter()
+                .map(|pk| pk.to_hex())
+                .collect::<Vec<_>>()
+                .join(", ");
+            Event {
+                time: Utc::now(),
+                title: "sum participants disagreed on the mask".to_string(),
+                text: Some(format!("round {}: {}", round_id, participants)),
+                tags: None,
+            }
+            .into()
         }
     }
 }
 
 pub mod round {
-    use super::models::{DataPoint, Measurement};
+    use super::models::{DataPoint, Measurement, Metric};
     use crate::state_machine::phases::PhaseName;
-    use influxdb::{InfluxDbWriteable, Timestamp, WriteQuery};
+    use chrono::Utc;
     pub mod total_number {
         use super::*;
 
         /// Updates the measurement `round_total_number` with the value of `total_number`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
         /// | measurement | `round_total_number`     |
         /// | field_key   | `value`                  |
         /// | field_value | value of `total_number`  |
-        pub fn update(total_number: u64) -> WriteQuery {
+        pub fn update(total_number: u64) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: total_number,
+                time: Utc::now(),
+                measurement: Measurement::RoundTotalNumber,
+                value: total_number.into(),
                 round_id: None,
                 phase: None,
             }
-            .into_query(Measurement::RoundTotalNumber.to_string())
+            .into()
         }
     }
 
@@ -199,7 +263,7 @@ pub mod round {
 
         /// Increments value of the measurement `round_successful` by `1`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
@@ -210,28 +274,29 @@ pub mod round {
         /// | tag_value   | value of `round_id`      |
         /// | tag_key     | `"phase"`                |
         /// | tag_value   | value of `phase` as `u8` |
-        pub fn increment(round_id: u64, phase: PhaseName) -> WriteQuery {
+        pub fn increment(round_id: u64, phase: PhaseName) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: 1,
+                time: Utc::now(),
+                measurement: Measurement::RoundSuccessful,
+                value: 1u64.into(),
                 round_id: Some(round_id),
-                phase: Some(phase as u8),
+                phase: Some(phase),
             }
-            .into_query(Measurement::RoundSuccessful.to_string())
+            .into()
         }
     }
 }
 
 pub mod message {
-    use super::models::{DataPoint, Measurement};
+    use super::models::{DataPoint, Measurement, Metric};
     use crate::state_machine::phases::PhaseName;
-    use influxdb::{InfluxDbWriteable, Timestamp, WriteQuery};
+    use chrono::Utc;
     pub mod sum {
         use super::*;
 
         /// Increments value of the measurement `message_sum` by `1`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
@@ -242,14 +307,15 @@ pub mod message {
         /// | tag_value   | value of `round_id`      |
         /// | tag_key     | `"phase"`                |
         /// | tag_value   | value of `phase` as `u8` |
-        pub fn increment(round_id: u64, phase: PhaseName) -> WriteQuery {
+        pub fn increment(round_id: u64, phase: PhaseName) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: 1,
+                time: Utc::now(),
+                measurement: Measurement::MessageSum,
+                value: 1u64.into(),
                 round_id: Some(round_id),
-                phase: Some(phase as u8),
+                phase: Some(phase),
             }
-            .into_query(Measurement::MessageSum.to_string())
+            .into()
         }
     }
 
@@ -258,7 +324,7 @@ pub mod message {
 
         /// Increments value of the measurement `message_update` by `1`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
@@ -269,14 +335,15 @@ pub mod message {
         /// | tag_value   | value of `round_id`      |
         /// | tag_key     | `"phase"`                |
         /// | tag_value   | value of `phase` as `u8` |
-        pub fn increment(round_id: u64, phase: PhaseName) -> WriteQuery {
+        pub fn increment(round_id: u64, phase: PhaseName) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: 1,
+                time: Utc::now(),
+                measurement: Measurement::MessageUpdate,
+                value: 1u64.into(),
                 round_id: Some(round_id),
-                phase: Some(phase as u8),
+                phase: Some(phase),
             }
-            .into_query(Measurement::MessageUpdate.to_string())
+            .into()
         }
     }
 
@@ -285,7 +352,7 @@ pub mod message {
 
         /// Increments value of the measurement `message_sum2` by `1`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
@@ -296,14 +363,15 @@ pub mod message {
         /// | tag_value   | value of `round_id`      |
         /// | tag_key     | `"phase"`                |
         /// | tag_value   | value of `phase` as `u8` |
-        pub fn increment(round_id: u64, phase: PhaseName) -> WriteQuery {
+        pub fn increment(round_id: u64, phase: PhaseName) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: 1,
+                time: Utc::now(),
+                measurement: Measurement::MessageSum2,
+                value: 1u64.into(),
                 round_id: Some(round_id),
-                phase: Some(phase as u8),
+                phase: Some(phase),
             }
-            .into_query(Measurement::MessageSum2.to_string())
+            .into()
         }
     }
 
@@ -312,7 +380,7 @@ pub mod message {
 
         /// Increments value of the measurement `message_discarded` by `1`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
@@ -323,14 +391,15 @@ pub mod message {
         /// | tag_value   | value of `round_id`      |
         /// | tag_key     | `"phase"`                |
         /// | tag_value   | value of `phase` as `u8` |
-        pub fn increment(round_id: u64, phase: PhaseName) -> WriteQuery {
+        pub fn increment(round_id: u64, phase: PhaseName) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: 1,
+                time: Utc::now(),
+                measurement: Measurement::MessageDiscarded,
+                value: 1u64.into(),
                 round_id: Some(round_id),
-                phase: Some(phase as u8),
+                phase: Some(phase),
             }
-            .into_query(Measurement::MessageDiscarded.to_string())
+            .into()
         }
     }
 
@@ -339,7 +408,7 @@ pub mod message {
 
         /// Increments value of the measurement `message_rejected` by `1`.
         ///
-        /// Creates an influx data point with the following properties:
+        /// Creates a data point with the following properties:
         ///
         /// | property    | value                    |
         /// |-------------|--------------------------|
@@ -350,14 +419,15 @@ pub mod message {
         /// | tag_value   | value of `round_id`      |
         /// | tag_key     | `"phase"`                |
         /// | tag_value   | value of `phase` as `u8` |
-        pub fn increment(round_id: u64, phase: PhaseName) -> WriteQuery {
+        pub fn increment(round_id: u64, phase: PhaseName) -> Metric {
             DataPoint {
-                time: Timestamp::Now.into(),
-                value: 1,
+                time: Utc::now(),
+                measurement: Measurement::MessageRejected,
+                value: 1u64.into(),
                 round_id: Some(round_id),
-                phase: Some(phase as u8),
+                phase: Some(phase),
             }
-            .into_query(Measurement::MessageRejected.to_string())
+            .into()
         }
     }
 }
@@ -368,7 +438,8 @@ mod test {
         phases::{PhaseName, StateError},
         RoundFailed,
     };
-    use influxdb::Query;
+    use influxdb::{Query, WriteQuery};
+    use xaynet_core::{crypto::ByteObject, SumParticipantPublicKey};
 
     // The fields of the WriteQuery are private and there are no kinds of getters for the fields.
     // One way to get something is via `build`.
@@ -377,45 +448,49 @@ mod test {
 
     #[test]
     fn test_round_parameters_sum() {
-        let query = round_parameters::sum::update(0.6, 1, PhaseName::Sum).build();
+        let query = WriteQuery::from(round_parameters::sum::update(0.6, 1, PhaseName::Sum)).build();
         assert!(format!("{:?}", query.unwrap())
             .contains("round_param_sum,round_id=\\\"1\\\",phase=\\\"1\\\" value=0.6"));
     }
 
     #[test]
     fn test_round_parameters_update() {
-        let query = round_parameters::update::update(0.8, 1, PhaseName::Sum).build();
+        let query =
+            WriteQuery::from(round_parameters::update::update(0.8, 1, PhaseName::Sum)).build();
         assert!(format!("{:?}", query.unwrap())
             .contains("round_param_update,round_id=\\\"1\\\",phase=\\\"1\\\" value=0.8"));
     }
 
     #[test]
     fn test_phase_name() {
-        let query = phase::update(PhaseName::Idle).build();
+        let query = WriteQuery::from(phase::update(PhaseName::Idle)).build();
         assert!(format!("{:?}", query.unwrap()).contains("phase value=0"));
 
-        let query = phase::update(PhaseName::Sum).build();
+        let query = WriteQuery::from(phase::update(PhaseName::Sum)).build();
         assert!(format!("{:?}", query.unwrap()).contains("phase value=1"));
 
-        let query = phase::update(PhaseName::Update).build();
+        let query = WriteQuery::from(phase::update(PhaseName::Update)).build();
         assert!(format!("{:?}", query.unwrap()).contains("phase value=2"));
 
-        let query = phase::update(PhaseName::Sum2).build();
+        let query = WriteQuery::from(phase::update(PhaseName::Sum2)).build();
         assert!(format!("{:?}", query.unwrap()).contains("phase value=3"));
 
-        let query = phase::update(PhaseName::Unmask).build();
+        let query = WriteQuery::from(phase::update(PhaseName::Unmask)).build();
         assert!(format!("{:?}", query.unwrap()).contains("phase value=4"));
 
-        let query = phase::update(PhaseName::Error).build();
+        let query = WriteQuery::from(phase::update(PhaseName::Error)).build();
         assert!(format!("{:?}", query.unwrap()).contains("phase value=5"));
 
-        let query = phase::update(PhaseName::Shutdown).build();
+        let query = WriteQuery::from(phase::update(PhaseName::Shutdown)).build();
         assert!(format!("{:?}", query.unwrap()).contains("phase value=6"));
     }
 
     #[test]
     fn test_phase_error() {
-        let query = phase::error::emit(&StateError::RoundError(RoundFailed::NoMask)).build();
+        let query = WriteQuery::from(phase::error::emit(&StateError::RoundError(
+            RoundFailed::NoMask,
+        )))
+        .build();
         assert!(format!("{:?}", query.unwrap()).contains(
             "event title=\\\"state\\\\ failed:\\\\ round\\\\ error:\\\\ no\\\\ mask\\\\ found\\\""
         ));
@@ -423,55 +498,64 @@ mod test {
 
     #[test]
     fn test_masks_total_number() {
-        let query = masks::total_number::update(12, 1, PhaseName::Sum).build();
+        let query = WriteQuery::from(masks::total_number::update(12, 1, PhaseName::Sum)).build();
         assert!(format!("{:?}", query.unwrap())
             .contains("masks_total_number,round_id=\\\"1\\\",phase=\\\"1\\\" value=12"));
     }
 
+    #[test]
+    fn test_masks_disagreement() {
+        let pk = SumParticipantPublicKey::fill_with(0xab);
+        let query = WriteQuery::from(masks::disagreement::emit(1, &[pk])).build();
+        let query = format!("{:?}", query.unwrap());
+        assert!(query.contains("event title=\\\"sum\\\\ participants\\\\ disagreed"));
+        assert!(query.contains(&format!("round\\\\ 1:\\\\ {}", "ab".repeat(32))));
+    }
+
     #[test]
     fn test_round_total_number() {
-        let query = round::total_number::update(2).build();
+        let query = WriteQuery::from(round::total_number::update(2)).build();
         assert!(format!("{:?}", query.unwrap()).contains("round_total_number value=2"));
     }
 
     #[test]
     fn test_round_successful() {
-        let query = round::successful::increment(1, PhaseName::Sum).build();
+        let query = WriteQuery::from(round::successful::increment(1, PhaseName::Sum)).build();
         assert!(format!("{:?}", query.unwrap())
             .contains("round_successful,round_id=\\\"1\\\",phase=\\\"1\\\" value=1"));
     }
 
     #[test]
     fn test_message_sum() {
-        let query = message::sum::increment(1, PhaseName::Sum).build();
+        let query = WriteQuery::from(message::sum::increment(1, PhaseName::Sum)).build();
         assert!(format!("{:?}", query.unwrap())
             .contains("message_sum,round_id=\\\"1\\\",phase=\\\"1\\\" value=1"));
     }
 
     #[test]
     fn test_message_update() {
-        let query = message::update::increment(1, PhaseName::Update).build();
+        let query = WriteQuery::from(message::update::increment(1, PhaseName::Update)).build();
         assert!(format!("{:?}", query.unwrap())
             .contains("message_update,round_id=\\\"1\\\",phase=\\\"2\\\" value=1"));
     }
 
     #[test]
     fn test_message_sum2() {
-        let query = message::sum2::increment(1, PhaseName::Sum2).build();
+        let query = WriteQuery::from(message::sum2::increment(1, PhaseName::Sum2)).build();
         assert!(format!("{:?}", query.unwrap())
             .contains("message_sum2,round_id=\\\"1\\\",phase=\\\"3\\\" value=1"));
     }
 
     #[test]
     fn test_message_discarded() {
-        let query = message::discarded::increment(1, PhaseName::Idle).build();
+        let query = WriteQuery::from(message::discarded::increment(1, PhaseName::Idle)).build();
         assert!(format!("{:?}", query.unwrap())
             .contains("message_discarded,round_id=\\\"1\\\",phase=\\\"0\\\" value=1"));
     }
 
     #[test]
     fn test_message_rejected() {
-        let query = message::rejected::increment(1, PhaseName::Sum).build();
+        let query = WriteQuery::from(message::rejected::increment(1, PhaseName::Sum)).build();
         assert!(format!("{:?}", query.unwrap())
             .contains("message_rejected,round_id=\\\"1\\\",phase=\\\"1\\\" value=1"));
     }
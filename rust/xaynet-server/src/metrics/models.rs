@@ -1,8 +1,10 @@
 use chrono::{DateTime, Utc};
-use influxdb::InfluxDbWriteable;
+
+use crate::state_machine::phases::PhaseName;
 
 /// An enum that contains all supported measurements.
-pub(in crate::metrics) enum Measurement {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Measurement {
     RoundParamSum,
     RoundParamUpdate,
     Phase,
@@ -17,6 +19,23 @@ pub(in crate::metrics) enum Measurement {
     Event,
 }
 
+impl Measurement {
+    /// Checks whether the measurement counts occurrences, i.e. whether its data points are
+    /// increments rather than absolute values.
+    pub fn is_counter(self) -> bool {
+        matches!(
+            self,
+            Measurement::RoundSuccessful
+                | Measurement::MessageSum
+                | Measurement::MessageUpdate
+                | Measurement::MessageSum2
+                | Measurement::MessageDiscarded
+                | Measurement::MessageRejected
+                | Measurement::Event
+        )
+    }
+}
+
 impl From<&Measurement> for &'static str {
     fn from(measurement: &Measurement) -> &'static str {
         match measurement {
@@ -42,30 +61,70 @@ impl ToString for Measurement {
     }
 }
 
-/// A generic influx data point.
-pub(in crate::metrics) struct DataPoint<T: Into<influxdb::Type>> {
+/// The value of a data point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Float(f64),
+    Unsigned(u64),
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::Unsigned(value)
+    }
+}
+
+impl From<Value> for f64 {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Float(value) => value,
+            Value::Unsigned(value) => value as f64,
+        }
+    }
+}
+
+/// A metric that can be recorded by any [`MetricsBackend`].
+///
+/// [`MetricsBackend`]: crate::metrics::MetricsBackend
+#[derive(Debug, Clone, PartialEq)]
+pub enum Metric {
+    DataPoint(DataPoint),
+    Event(Event),
+}
+
+/// A generic data point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataPoint {
     pub time: DateTime<Utc>,
-    pub value: T,
+    pub measurement: Measurement,
+    pub value: Value,
     pub round_id: Option<u64>,
-    pub phase: Option<u8>,
+    pub phase: Option<PhaseName>,
 }
 
-impl<T: Into<influxdb::Type>> InfluxDbWriteable for DataPoint<T> {
-    fn into_query<I: Into<String>>(self, name: I) -> influxdb::WriteQuery {
-        let timestamp: ::influxdb::Timestamp = self.time.into();
-        let mut query = timestamp.into_query(name);
-        query = query.add_field("value", self.value);
-        query = query.add_tag("round_id", self.round_id);
-        query = query.add_tag("phase", self.phase);
-        query
+impl From<DataPoint> for Metric {
+    fn from(data_point: DataPoint) -> Self {
+        Metric::DataPoint(data_point)
     }
 }
 
 /// An `event` data point.
-#[derive(InfluxDbWriteable)]
-pub(in crate::metrics) struct Event {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
     pub time: DateTime<Utc>,
     pub title: String,
     pub text: Option<String>,
     pub tags: Option<String>,
 }
+
+impl From<Event> for Metric {
+    fn from(event: Event) -> Self {
+        Metric::Event(event)
+    }
+}
@@ -0,0 +1,225 @@
+//! The Prometheus metrics backend.
+//!
+//! The metrics are kept in memory and exposed in the Prometheus text format on a `/metrics`
+//! scrape endpoint. The round ID of a data point is not exported as a label, since every round
+//! would otherwise create new time series. The current round is available via the
+//! `xaynet_round_total_number` gauge instead. For the same reason, events are counted by their
+//! tags if they have any, since the title of an event may contain the details of an error.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use futures::Future;
+use warp::Filter;
+
+use super::{
+    models::{DataPoint, Event, Measurement, Metric},
+    MetricsBackend,
+};
+use crate::state_machine::phases::PhaseName;
+
+/// The content type of the Prometheus text format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The recorded metrics.
+#[derive(Debug, Default)]
+struct Registry {
+    /// The values of the data points by measurement and phase.
+    data_points: BTreeMap<(Measurement, Option<&'static str>), f64>,
+    /// The number of events by tags or, for untagged events, by title.
+    events: BTreeMap<String, u64>,
+}
+
+/// A metrics backend that exposes the metrics to Prometheus.
+///
+/// Data points of counter measurements are added up, all other data points overwrite the
+/// previous value of their measurement.
+#[derive(Debug, Clone, Default)]
+pub struct PrometheusExporter(Arc<Mutex<Registry>>);
+
+impl PrometheusExporter {
+    /// Creates a new Prometheus exporter without any metrics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a metric.
+    fn record_metric(&self, metric: &Metric) {
+        let mut registry = self.0.lock().unwrap();
+        match metric {
+            Metric::DataPoint(DataPoint {
+                measurement,
+                value,
+                phase,
+                ..
+            }) => {
+                let key = (*measurement, phase.map(phase_label));
+                let value = f64::from(*value);
+                if measurement.is_counter() {
+                    *registry.data_points.entry(key).or_default() += value;
+                } else {
+                    registry.data_points.insert(key, value);
+                }
+            }
+            Metric::Event(Event { title, tags, .. }) => {
+                let key = tags.as_ref().unwrap_or(title);
+                *registry.events.entry(key.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Renders the recorded metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let registry = self.0.lock().unwrap();
+        let mut output = String::new();
+
+        let mut previous = None;
+        for ((measurement, phase), value) in registry.data_points.iter() {
+            let name = metric_name(*measurement);
+            if previous != Some(*measurement) {
+                write_type(&mut output, &name, *measurement);
+                previous = Some(*measurement);
+            }
+            match phase {
+                Some(phase) => writeln!(output, "{}{{phase=\"{}\"}} {}", name, phase, value),
+                None => writeln!(output, "{} {}", name, value),
+            }
+            .unwrap();
+        }
+
+        if !registry.events.is_empty() {
+            let name = metric_name(Measurement::Event);
+            write_type(&mut output, &name, Measurement::Event);
+            for (event, count) in registry.events.iter() {
+                writeln!(output, "{}{{event=\"{}\"}} {}", name, escape(event), count).unwrap();
+            }
+        }
+
+        output
+    }
+}
+
+#[async_trait]
+impl MetricsBackend for PrometheusExporter {
+    async fn record(&mut self, metric: &Metric) {
+        self.record_metric(metric);
+    }
+}
+
+/// Binds a HTTP server to the given address which serves the metrics of the exporter on the
+/// `/metrics` scrape endpoint.
+///
+/// # Errors
+/// Fails if the server can't be bound to the address.
+pub fn serve(
+    addr: impl Into<SocketAddr> + 'static,
+    exporter: PrometheusExporter,
+) -> Result<impl Future<Output = ()>, warp::Error> {
+    let metrics = warp::path!("metrics")
+        .and(warp::get())
+        .map(move || warp::reply::with_header(exporter.render(), "Content-Type", CONTENT_TYPE));
+    let (_, server) = warp::serve(metrics).try_bind_ephemeral(addr)?;
+    Ok(server)
+}
+
+/// Gets the Prometheus name of a measurement.
+fn metric_name(measurement: Measurement) -> String {
+    if measurement.is_counter() {
+        format!("xaynet_{}_total", measurement.to_string())
+    } else {
+        format!("xaynet_{}", measurement.to_string())
+    }
+}
+
+/// Writes the type line of a metric.
+fn write_type(output: &mut String, name: &str, measurement: Measurement) {
+    let kind = if measurement.is_counter() {
+        "counter"
+    } else {
+        "gauge"
+    };
+    writeln!(output, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Gets the label value of a phase.
+fn phase_label(phase: PhaseName) -> &'static str {
+    match phase {
+        PhaseName::Idle => "idle",
+        PhaseName::Sum => "sum",
+        PhaseName::Update => "update",
+        PhaseName::Sum2 => "sum2",
+        PhaseName::Unmask => "unmask",
+        PhaseName::Error => "error",
+        PhaseName::Shutdown => "shutdown",
+    }
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        metrics::{masks, message, phase, round},
+        state_machine::{phases::StateError, RoundFailed},
+    };
+
+    #[test]
+    fn test_render_gauges() {
+        let exporter = PrometheusExporter::new();
+        exporter.record_metric(&phase::update(PhaseName::Sum));
+        exporter.record_metric(&round::total_number::update(1));
+        exporter.record_metric(&round::total_number::update(2));
+        assert_eq!(
+            exporter.render(),
+            "# TYPE xaynet_phase gauge\n\
+             xaynet_phase 1\n\
+             # TYPE xaynet_round_total_number gauge\n\
+             xaynet_round_total_number 2\n"
+        );
+    }
+
+    #[test]
+    fn test_render_counters() {
+        let exporter = PrometheusExporter::new();
+        exporter.record_metric(&message::sum::increment(1, PhaseName::Sum));
+        exporter.record_metric(&message::sum::increment(2, PhaseName::Sum));
+        exporter.record_metric(&message::rejected::increment(1, PhaseName::Sum));
+        exporter.record_metric(&message::rejected::increment(1, PhaseName::Update));
+        exporter.record_metric(&phase::error::emit(&StateError::RoundError(
+            RoundFailed::NoMask,
+        )));
+        exporter.record_metric(&phase::error::emit(&StateError::RoundError(
+            RoundFailed::NoQuorum,
+        )));
+        exporter.record_metric(&masks::disagreement::emit(1, &[]));
+        assert_eq!(
+            exporter.render(),
+            "# TYPE xaynet_message_sum_total counter\n\
+             xaynet_message_sum_total{phase=\"sum\"} 2\n\
+             # TYPE xaynet_message_rejected_total counter\n\
+             xaynet_message_rejected_total{phase=\"sum\"} 1\n\
+             xaynet_message_rejected_total{phase=\"update\"} 1\n\
+             # TYPE xaynet_event_total counter\n\
+             xaynet_event_total{event=\"round_error\"} 2\n\
+             xaynet_event_total{event=\"sum participants disagreed on the mask\"} 1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_serve_address_in_use() {
+        let listener = std::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(serve(addr, PrometheusExporter::new()).is_err());
+    }
+}
@@ -1,22 +1,20 @@
-use influxdb::{Client, WriteQuery};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
+use super::{Metric, MetricsBackend};
+
 /// Runs the metrics service.
 ///
 /// The future is automatically resolved after all senders have been dropped and all remaining
 /// messages have been processed.
 ///
-/// If an error occurs when sending the metric to InfluxDB, the metric is discarded and the error
-/// is logged.
+/// Each metric is recorded by all backends of the service.
 pub async fn run_metric_service(mut metrics_service: MetricsService) {
     loop {
         match metrics_service.receiver.recv().await {
-            Some(write_query) => {
-                let _ = metrics_service
-                    .client
-                    .query(&write_query)
-                    .await
-                    .map_err(|e| error!("{}", e));
+            Some(metric) => {
+                for backend in metrics_service.backends.iter_mut() {
+                    backend.record(&metric).await;
+                }
             }
             None => {
                 warn!("All senders have been dropped!");
@@ -28,56 +26,30 @@ pub async fn run_metric_service(mut metrics_service: MetricsService) {
 
 /// A handle to send metrics to the [`MetricsService`] via a bounded channel.
 #[derive(Debug, Clone)]
-pub struct MetricsSender(Sender<WriteQuery>);
+pub struct MetricsSender(Sender<Metric>);
 
 impl MetricsSender {
     /// Sends a metric to the [`MetricsService`].
     /// If the channel is already full or closed, the metric is discarded and the error is logged.
-    pub fn send(&mut self, query: WriteQuery) {
-        let _ = self.0.try_send(query).map_err(|e| error!("{}", e));
+    pub fn send(&mut self, metric: Metric) {
+        let _ = self.0.try_send(metric).map_err(|e| error!("{}", e));
     }
 }
 
-/// A service that handles the transmission of metrics to InfluxDB.
+/// A service that handles the transmission of metrics to the metrics backends.
 pub struct MetricsService {
-    /// The InfluxDB client.
-    client: Client,
+    /// The backends which record the metrics.
+    backends: Vec<Box<dyn MetricsBackend>>,
     /// The receiver half of the bounded channel.
-    receiver: Receiver<WriteQuery>,
+    receiver: Receiver<Metric>,
 }
 
 impl MetricsService {
     /// Creates and returns a new [`MetricsService`] and the associated [`MetricsSender`].
     /// The [`MetricsSender`] can be used to send metrics to the [`MetricsService`].
-    /// The [`MetricsService`] handles the transmission of metrics to InfluxDB.
-    ///
-    /// - `url`: The url where InfluxDB is running (e.g. `http://127.0.0.1:8086`).
-    /// - `database`: The name of the database in which the metrics are to be written.
-    ///
-    /// Note:
-    /// It is assumed that the database already exists. If this is not the case, no metrics are
-    /// written in InfluxDB.
-    pub fn new(url: &str, database: &str) -> (MetricsService, MetricsSender) {
-        let client = Client::new(url, database);
-        Self::new_metrics_service(client)
-    }
-
-    /// Similar to the [`MetricsService::new`] but with additional InfluxDB user credentials.
-    ///
-    /// - `username`: The username for InfluxDB.
-    /// - `password`: The password for that username.
-    pub fn new_with_auth(
-        url: &str,
-        database: &str,
-        username: &str,
-        password: &str,
-    ) -> (MetricsService, MetricsSender) {
-        let client_auth = Client::new(url, database).with_auth(username, password);
-        Self::new_metrics_service(client_auth)
-    }
-
-    fn new_metrics_service(client: Client) -> (MetricsService, MetricsSender) {
+    /// The [`MetricsService`] hands the metrics over to the given backends.
+    pub fn new(backends: Vec<Box<dyn MetricsBackend>>) -> (MetricsService, MetricsSender) {
         let (sender, receiver) = channel(4096);
-        (MetricsService { client, receiver }, MetricsSender(sender))
+        (MetricsService { backends, receiver }, MetricsSender(sender))
     }
 }
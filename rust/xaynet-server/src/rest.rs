@@ -1,33 +1,190 @@
 //! A HTTP API for the PET protocol interactions.
 
-use crate::services::{fetchers::Fetcher, messages::PetMessageHandler};
+use crate::{
+    services::{fetchers::Fetcher, messages::PetMessageHandler},
+    settings::{ApiSettings, PetSettings, TlsSettings},
+    state_machine::{
+        control::{ControlError, ControlRequest, ControlSender},
+        coordinator::GlobalModelInfo,
+        events::{EventListener, EventSubscriber, MessageCounts},
+        phases::PhaseName,
+    },
+    storage::{
+        redis,
+        registry::{ParticipantRegistry, RegistryError},
+    },
+};
 use bytes::{Buf, Bytes};
-use std::{convert::Infallible, net::SocketAddr};
+use futures::{
+    future::{self, BoxFuture},
+    Future,
+    FutureExt,
+    Stream,
+    StreamExt,
+};
+use sodiumoxide::utils::memcmp;
+use std::{
+    convert::Infallible,
+    fs::File,
+    io::{self, BufReader},
+    net::{SocketAddr, TcpListener as StdTcpListener},
+    path::Path,
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::{timeout, Duration},
+};
+use tokio_rustls::{
+    rustls::{
+        internal::pemfile,
+        AllowAnyAuthenticatedClient,
+        Certificate,
+        NoClientAuth,
+        PrivateKey,
+        RootCertStore,
+        ServerConfig,
+        TLSError,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
 use warp::{
+    filters::{body::BodyDeserializeError, BoxedFilter},
     http::{Response, StatusCode},
+    hyper::{
+        server::accept,
+        service::{make_service_fn, service_fn, Service},
+        Server,
+    },
     Filter,
+    Reply,
+};
+use xaynet_core::{
+    common::{MessageRejection, MessageRejectionBody},
+    crypto::ByteObject,
+    mask::TypedModel,
+    message::ToBytes,
+    ParticipantPublicKey,
 };
-use xaynet_core::{crypto::ByteObject, ParticipantPublicKey};
 
-/// Starts a HTTP server at the given address, listening to GET requests for
-/// data and POST requests containing PET messages.
+/// Binds a HTTP server to the configured address and returns the future which serves the API.
+/// The server listens to GET requests for data and POST requests containing PET messages or
+/// admin commands.
 ///
-/// * `addr`: address of the server.
+/// * `api_settings`: address, TLS settings and admin token of the server. If the TLS settings
+///   are missing, the server uses plain HTTP. If the admin token is missing, the admin API is
+///   disabled.
 /// * `fetcher`: fetcher for responding to data requests.
-/// * `pet_message_handler`: handler for responding to PET messages.
-pub async fn serve<F>(
-    addr: impl Into<SocketAddr> + 'static,
+/// * `pet_message_handler`: handler for responding to PET messages. Its participant registry is
+///   modified via the admin API.
+/// * `control`: handle for sending admin commands to the state machine.
+/// * `event_subscriber`: subscriber for responding to status requests.
+/// * `redis`: Redis client for responding to readiness and model history requests.
+/// * `shutdown`: signal to shut down the server gracefully, i.e. to stop accepting new
+///   connections and to return once the requests in flight have been handled.
+///
+/// This must be called on the tokio runtime.
+///
+/// # Errors
+/// Fails if the TLS certificates or keys cannot be loaded or if the address cannot be bound.
+pub fn serve<F>(
+    api_settings: ApiSettings,
     fetcher: F,
     pet_message_handler: PetMessageHandler,
-) where
+    control: ControlSender,
+    event_subscriber: &EventSubscriber,
+    redis: redis::Client,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<BoxFuture<'static, ()>, ServeError>
+where
+    F: Fetcher + Sync + Send + 'static + Clone,
+{
+    let tls_config = api_settings.tls.as_ref().map(tls_config).transpose()?;
+    let routes = routes(
+        api_settings.admin_token,
+        fetcher,
+        pet_message_handler,
+        control,
+        event_subscriber,
+        redis,
+    );
+
+    let addr = api_settings.bind_address;
+    match tls_config {
+        None => {
+            let (_, server) = warp::serve(routes)
+                .try_bind_with_graceful_shutdown(addr, shutdown)
+                .map_err(|e| ServeError::Bind(e.into()))?;
+            Ok(server.boxed())
+        }
+        // warp's TLS server doesn't support client authentication and panics on invalid TLS
+        // settings, hence we do the TLS handshakes ourselves.
+        Some(tls_config) => {
+            let listener = StdTcpListener::bind(addr)
+                .and_then(TcpListener::from_std)
+                .map_err(|e| ServeError::Bind(e.into()))?;
+            let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+            let incoming = tls_incoming(listener, acceptor, HANDSHAKE_TIMEOUT);
+            Ok(serve_tls(incoming, routes, shutdown))
+        }
+    }
+}
+
+/// Serves the routes on the established TLS streams.
+///
+/// warp doesn't know the client addresses of such streams, hence they are attached to the
+/// requests as a [`PeerAddr`] extension.
+fn serve_tls<R: warp::Reply + 'static>(
+    incoming: impl Stream<Item = Result<TlsStream<TcpStream>, Infallible>> + Send + 'static,
+    routes: BoxedFilter<(R,)>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> BoxFuture<'static, ()> {
+    let service = warp::service(routes);
+    let make_service = make_service_fn(move |stream: &TlsStream<TcpStream>| {
+        let peer_addr = stream.get_ref().0.peer_addr().ok().map(PeerAddr);
+        let service = service.clone();
+        future::ok::<_, Infallible>(service_fn(move |mut req| {
+            if let Some(peer_addr) = peer_addr {
+                req.extensions_mut().insert(peer_addr);
+            }
+            service.clone().call(req)
+        }))
+    });
+    Server::builder(accept::from_stream(incoming))
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .map(|result| {
+            if let Err(e) = result {
+                error!("the REST API failed: {}", e);
+            }
+        })
+        .boxed()
+}
+
+/// Builds the routes of the HTTP API, see [`serve()`] for the arguments. If the `admin_token` is
+/// missing, the admin API is disabled.
+fn routes<F>(
+    admin_token: Option<String>,
+    fetcher: F,
+    pet_message_handler: PetMessageHandler,
+    control: ControlSender,
+    event_subscriber: &EventSubscriber,
+    redis: redis::Client,
+) -> BoxedFilter<(impl warp::Reply,)>
+where
     F: Fetcher + Sync + Send + 'static + Clone,
 {
     let message = warp::path!("message")
         .and(warp::post())
         .and(warp::body::bytes())
+        .and(remote_addr())
         .and(with_message_handler(pet_message_handler.clone()))
         .and_then(handle_message);
 
+    let registry = pet_message_handler.registry().clone();
+
     let sum_dict = warp::path!("sums")
         .and(warp::get())
         .and(with_fetcher(fetcher.clone()))
@@ -51,30 +208,248 @@ pub async fn serve<F>(
 
     let model = warp::path!("model")
         .and(warp::get())
-        .and(with_fetcher(fetcher.clone()))
+        .and(warp::header::optional::<String>("accept"))
+        .and(with_fetcher(fetcher))
         .and_then(handle_model);
 
-    let routes = message
+    let model_history = warp::path!("models")
+        .and(warp::get())
+        .and(with_redis(redis.clone()))
+        .and_then(handle_model_history);
+
+    let historic_model = warp::path!("model" / u64)
+        .and(warp::get())
+        .and(with_redis(redis.clone()))
+        .and_then(handle_historic_model);
+
+    let health = warp::path!("health").and(warp::get()).map(warp::reply);
+
+    let phase = event_subscriber.phase_listener();
+    let ready = warp::path!("ready")
+        .and(warp::get())
+        .and(with_listener(phase.clone()))
+        .and(with_redis(redis))
+        .and_then(handle_ready);
+
+    let status = warp::path!("status")
+        .and(warp::get())
+        .and(with_listener(phase))
+        .and(with_listener(event_subscriber.message_counts_listener()))
+        .and_then(handle_status);
+
+    let admin = warp::path!("admin" / String)
+        .and(warp::post())
+        .and(admin_auth(admin_token.clone()))
+        .and(with_control(control.clone()))
+        .and_then(handle_admin);
+
+    let pet_settings = warp::path!("admin" / "settings")
+        .and(warp::put())
+        .and(admin_auth(admin_token.clone()))
+        .and(warp::body::json())
+        .and(with_control(control))
+        .and_then(handle_pet_settings);
+
+    let register = warp::path!("admin" / "participants")
+        .and(warp::put())
+        .and(admin_auth(admin_token.clone()))
+        .and(part_pk())
+        .and(with_registry(registry.clone()))
+        .and_then(handle_register);
+
+    let unregister = warp::path!("admin" / "participants")
+        .and(warp::delete())
+        .and(admin_auth(admin_token))
+        .and(part_pk())
+        .and(with_registry(registry))
+        .and_then(handle_unregister);
+
+    message
         .or(round_params)
         .or(sum_dict)
         .or(seed_dict)
         .or(length)
         .or(model)
+        .or(model_history)
+        .or(historic_model)
+        .or(health)
+        .or(ready)
+        .or(status)
+        .or(admin)
+        .or(pet_settings)
+        .or(register)
+        .or(unregister)
         .recover(handle_reject)
-        .with(warp::log("http"));
+        .with(warp::log("http"))
+        // boxing keeps the type of the routes small enough for the compiler
+        .boxed()
+}
 
-    warp::serve(routes).run(addr).await
+/// The maximum number of TLS handshakes that are performed concurrently.
+const MAX_PENDING_HANDSHAKES: usize = 128;
+
+/// The time after which a pending TLS handshake is aborted, so that idle connections cannot
+/// occupy the handshake slots.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Errors which can occur when starting the HTTP server.
+#[derive(Debug, Error)]
+pub enum ServeError {
+    #[error("invalid TLS settings: {0}")]
+    Tls(#[from] TlsConfigError),
+    #[error("failed to bind the REST API: {0}")]
+    Bind(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Errors which can occur when loading the TLS settings.
+#[derive(Debug, Error)]
+pub enum TlsConfigError {
+    #[error("failed to read {0}: {1}")]
+    Io(String, io::Error),
+    #[error("no valid certificate found in {0}")]
+    Certificate(String),
+    #[error("no valid private key found in {0}")]
+    PrivateKey(String),
+    #[error("invalid certificate or private key: {0}")]
+    Tls(#[from] TLSError),
+}
+
+/// Builds the server configuration for TLS, with client authentication if the settings contain
+/// a client CA.
+fn tls_config(settings: &TlsSettings) -> Result<ServerConfig, TlsConfigError> {
+    let mut config = match settings.client_ca {
+        Some(ref client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for ca in load_certificates(client_ca)? {
+                roots
+                    .add(&ca)
+                    .map_err(|_| TlsConfigError::Certificate(client_ca.display().to_string()))?;
+            }
+            ServerConfig::new(AllowAnyAuthenticatedClient::new(roots))
+        }
+        None => ServerConfig::new(NoClientAuth::new()),
+    };
+    config.set_single_cert(
+        load_certificates(&settings.certificate)?,
+        load_private_key(&settings.key)?,
+    )?;
+    config.set_protocols(&["h2".into(), "http/1.1".into()]);
+    Ok(config)
+}
+
+/// Accepts the connections of the `listener` and performs their TLS handshakes. Handshakes which
+/// fail or don't complete within the `handshake_timeout` are dropped.
+fn tls_incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    handshake_timeout: Duration,
+) -> impl Stream<Item = Result<TlsStream<TcpStream>, Infallible>> {
+    listener
+        .filter_map(|stream| {
+            future::ready(
+                stream
+                    .map_err(|e| warn!("failed to accept a connection: {}", e))
+                    .ok(),
+            )
+        })
+        .map(move |stream| timeout(handshake_timeout, acceptor.accept(stream)))
+        .buffer_unordered(MAX_PENDING_HANDSHAKES)
+        .filter_map(|stream| {
+            future::ready(match stream {
+                Ok(Ok(stream)) => Some(Ok(stream)),
+                Ok(Err(e)) => {
+                    warn!("TLS handshake failed: {}", e);
+                    None
+                }
+                Err(_) => {
+                    warn!("TLS handshake timed out");
+                    None
+                }
+            })
+        })
+}
+
+/// Loads the PEM encoded certificates from a file.
+fn load_certificates(path: &Path) -> Result<Vec<Certificate>, TlsConfigError> {
+    let certificates = pemfile::certs(&mut open(path)?)
+        .map_err(|_| TlsConfigError::Certificate(path.display().to_string()))?;
+    if certificates.is_empty() {
+        return Err(TlsConfigError::Certificate(path.display().to_string()));
+    }
+    Ok(certificates)
+}
+
+/// Loads the first PEM encoded PKCS#8 or RSA private key from a file.
+fn load_private_key(path: &Path) -> Result<PrivateKey, TlsConfigError> {
+    let mut keys = pemfile::pkcs8_private_keys(&mut open(path)?).unwrap_or_default();
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut open(path)?).unwrap_or_default();
+    }
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| TlsConfigError::PrivateKey(path.display().to_string()))
+}
+
+/// Opens a file for buffered reading.
+fn open(path: &Path) -> Result<BufReader<File>, TlsConfigError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| TlsConfigError::Io(path.display().to_string(), e))
 }
 
 /// Handles and responds to a PET message.
+///
+/// If the message is rejected, the response body contains the JSON
+/// serialized [`MessageRejectionBody`].
 async fn handle_message(
     body: Bytes,
+    remote: Option<SocketAddr>,
     mut handler: PetMessageHandler,
 ) -> Result<impl warp::Reply, Infallible> {
-    let _ = handler.handle_message(body.to_vec()).await.map_err(|e| {
-        warn!("failed to handle message: {:?}", e);
-    });
-    Ok(warp::reply())
+    let client = remote.map(|addr| addr.ip());
+    Ok(match handler.handle_message(body.to_vec(), client).await {
+        Ok(()) => warp::reply().into_response(),
+        Err(e) => {
+            warn!("failed to handle message: {:?}", e);
+            rejection_response(MessageRejection::from(&e))
+        }
+    })
+}
+
+/// Builds the response to a rejected PET message.
+fn rejection_response(error: MessageRejection) -> warp::reply::Response {
+    warp::reply::with_status(
+        warp::reply::json(&MessageRejectionBody { error }),
+        rejection_status(error),
+    )
+    .into_response()
+}
+
+/// Maps the reason for rejecting a PET message to the status code of
+/// the response.
+fn rejection_status(rejection: MessageRejection) -> StatusCode {
+    match rejection {
+        MessageRejection::Decrypt | MessageRejection::Parsing | MessageRejection::InvalidChunk => {
+            StatusCode::BAD_REQUEST
+        }
+        MessageRejection::InvalidSignature => StatusCode::UNAUTHORIZED,
+        MessageRejection::NotSumEligible
+        | MessageRejection::NotUpdateEligible
+        | MessageRejection::UnknownParticipant => StatusCode::FORBIDDEN,
+        MessageRejection::InvalidCoordinatorPublicKey
+        | MessageRejection::UnexpectedMessage
+        | MessageRejection::Rejected
+        | MessageRejection::Replay => StatusCode::CONFLICT,
+        MessageRejection::MessageTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        MessageRejection::AggregationFailed | MessageRejection::InvalidLocalSeedDict => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        MessageRejection::BufferFull | MessageRejection::Overloaded => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        MessageRejection::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        MessageRejection::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+    }
 }
 
 /// Handles and responds to a request for the sum dictionary.
@@ -152,27 +527,158 @@ async fn handle_length<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, I
 }
 
 /// Handles and responds to a request for the global model.
-async fn handle_model<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, Infallible> {
-    Ok(match fetcher.model().await {
-        Ok(Some(model)) => Response::builder()
-            .status(StatusCode::OK)
-            .body(bincode::serialize(model.as_ref()).unwrap())
-            .unwrap(),
-        Ok(None) => Response::builder()
-            .status(StatusCode::NO_CONTENT)
-            .body(Vec::new())
-            .unwrap(),
+///
+/// The model is serialized as a [`TypedModel`] with the data type of the current masking
+/// configuration if the request prefers its media type over `application/octet-stream`, and
+/// with bincode otherwise. If the model can't be represented in the data type of the current
+/// masking configuration, it falls back to bincode as well.
+async fn handle_model<F: Fetcher>(
+    accept: Option<String>,
+    mut fetcher: F,
+) -> Result<impl warp::Reply, Infallible> {
+    let model = match fetcher.model().await {
+        Ok(Some(model)) => model,
+        Ok(None) => {
+            return Ok(Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Vec::new())
+                .unwrap())
+        }
         Err(e) => {
             warn!("failed to handle model request: {:?}", e);
-            Response::builder()
+            return Ok(Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
                 .body(Vec::new())
-                .unwrap()
+                .unwrap());
         }
-    })
+    };
+
+    let accept = accept.as_deref();
+    if accept_quality(accept, TypedModel::MEDIA_TYPE)
+        > accept_quality(accept, "application/octet-stream")
+    {
+        let typed_model = match fetcher.round_params().await {
+            Ok(signed) => TypedModel::new(&model, signed.params.mask_config.vect.data_type)
+                .map_err(|e| warn!("failed to serialize the model as a typed model: {}", e)),
+            Err(e) => {
+                warn!("failed to serialize the model as a typed model: {:?}", e);
+                Err(())
+            }
+        };
+        if let Ok(typed_model) = typed_model {
+            let mut body = vec![0; typed_model.buffer_length()];
+            typed_model.to_bytes(&mut body);
+            return Ok(Response::builder()
+                .header("Content-Type", TypedModel::MEDIA_TYPE)
+                .status(StatusCode::OK)
+                .body(body)
+                .unwrap());
+        }
+    }
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/octet-stream")
+        .status(StatusCode::OK)
+        .body(bincode::serialize(model.as_ref()).unwrap())
+        .unwrap())
+}
+
+/// Gets the quality value with which an `Accept` header value accepts the `media_type`.
+///
+/// The most specific matching media range determines the quality value, i.e. `type/subtype`
+/// takes precedence over `type/*`, which takes precedence over `*/*`. Media ranges with an
+/// invalid quality value are ignored. A missing header accepts every media type.
+fn accept_quality(accept: Option<&str>, media_type: &str) -> f32 {
+    let accept = match accept {
+        Some(accept) => accept,
+        None => return 1.0,
+    };
+    let type_range = format!("{}/*", media_type.split('/').next().unwrap_or_default());
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let mut params = range.split(';').map(str::trim);
+            let range = params.next()?;
+            let specificity = if range.eq_ignore_ascii_case(media_type) {
+                2
+            } else if range.eq_ignore_ascii_case(&type_range) {
+                1
+            } else if range == "*/*" {
+                0
+            } else {
+                return None;
+            };
+            let quality = params.find_map(|param| {
+                let mut param = param.splitn(2, '=');
+                match (param.next(), param.next()) {
+                    (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("q") => {
+                        Some(value.trim().parse::<f32>().ok())
+                    }
+                    _ => None,
+                }
+            });
+            match quality {
+                None => Some((specificity, 1.0)),
+                Some(Some(quality)) if (0.0..=1.0).contains(&quality) => {
+                    Some((specificity, quality))
+                }
+                Some(_) => None,
+            }
+        })
+        .max_by_key(|(specificity, _)| *specificity)
+        .map_or(0.0, |(_, quality)| quality)
+}
+
+/// Handles and responds to a request for the metadata of the global models in the model
+/// history.
+async fn handle_model_history(redis: redis::Client) -> Result<impl warp::Reply, Infallible> {
+    Ok(
+        match redis.connection().await.get_global_model_infos().await {
+            Ok(infos) => warp::reply::with_status(warp::reply::json(&infos), StatusCode::OK),
+            Err(e) => {
+                warn!("failed to handle model history request: {}", e);
+                warp::reply::with_status(
+                    warp::reply::json(&Vec::<GlobalModelInfo>::new()),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+            }
+        },
+    )
+}
+
+/// Handles and responds to a request for the global model of a past round.
+async fn handle_historic_model(
+    round_id: u64,
+    redis: redis::Client,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(
+        match redis.connection().await.get_global_model(round_id).await {
+            Ok(Some(model)) => Response::builder()
+                .header("Content-Type", "application/octet-stream")
+                .status(StatusCode::OK)
+                .body(bincode::serialize(&model).unwrap())
+                .unwrap(),
+            Ok(None) => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Vec::new())
+                .unwrap(),
+            Err(e) => {
+                warn!("failed to handle historic model request: {}", e);
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Vec::new())
+                    .unwrap()
+            }
+        },
+    )
 }
 
 /// Handles and responds to a request for the round parameters.
+///
+/// The response body contains the `bincode` serialized
+/// [`SignedRoundParameters`].
+///
+/// [`SignedRoundParameters`]: xaynet_core::common::SignedRoundParameters
 async fn handle_params<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, Infallible> {
     Ok(match fetcher.round_params().await {
         Ok(params) => Response::builder()
@@ -189,6 +695,59 @@ async fn handle_params<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, I
     })
 }
 
+/// Handles and responds to a readiness request.
+///
+/// The coordinator is ready if the state machine is running and Redis is reachable.
+async fn handle_ready(
+    phase: EventListener<PhaseName>,
+    redis: redis::Client,
+) -> Result<impl warp::Reply, Infallible> {
+    let status = match phase.get_latest().event {
+        PhaseName::Error | PhaseName::Shutdown => {
+            warn!("not ready: the state machine is not running");
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        _ => match redis.connection().await.ping().await {
+            Ok(()) => StatusCode::OK,
+            Err(e) => {
+                warn!("not ready: failed to ping Redis: {}", e);
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        },
+    };
+    Ok(warp::reply::with_status(warp::reply(), status))
+}
+
+/// The status of the coordinator.
+#[derive(Serialize)]
+struct Status {
+    /// The current round ID.
+    round_id: u64,
+    /// The current phase.
+    phase: PhaseName,
+    /// The number of messages accepted in the current round.
+    messages: MessageCounts,
+}
+
+/// Handles and responds to a status request.
+async fn handle_status(
+    phase: EventListener<PhaseName>,
+    message_counts: EventListener<MessageCounts>,
+) -> Result<impl warp::Reply, Infallible> {
+    let phase = phase.get_latest();
+    let message_counts = message_counts.get_latest();
+    let messages = if message_counts.round_id == phase.round_id {
+        message_counts.event
+    } else {
+        MessageCounts::default()
+    };
+    Ok(warp::reply::json(&Status {
+        round_id: phase.round_id,
+        phase: phase.event,
+        messages,
+    }))
+}
+
 /// Converts a PET message handler into a `warp` filter.
 fn with_message_handler(
     handler: PetMessageHandler,
@@ -203,6 +762,47 @@ fn with_fetcher<F: Fetcher + Sync + Send + 'static + Clone>(
     warp::any().map(move || fetcher.clone())
 }
 
+/// Converts an event listener into a `warp` filter.
+fn with_listener<E: Clone + Send + Sync + 'static>(
+    listener: EventListener<E>,
+) -> impl Filter<Extract = (EventListener<E>,), Error = Infallible> + Clone {
+    warp::any().map(move || listener.clone())
+}
+
+/// The address of the client of a TLS connection, which is attached to its requests.
+#[derive(Clone, Copy, Debug)]
+struct PeerAddr(SocketAddr);
+
+/// Extracts the address of the client, if it is known.
+fn remote_addr() -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Clone {
+    warp::addr::remote()
+        .and(warp::ext::optional::<PeerAddr>())
+        .map(|remote: Option<SocketAddr>, peer_addr: Option<PeerAddr>| {
+            remote.or_else(|| peer_addr.map(|PeerAddr(addr)| addr))
+        })
+}
+
+/// Converts a control sender into a `warp` filter.
+fn with_control(
+    control: ControlSender,
+) -> impl Filter<Extract = (ControlSender,), Error = Infallible> + Clone {
+    warp::any().map(move || control.clone())
+}
+
+/// Converts a participant registry into a `warp` filter.
+fn with_registry(
+    registry: ParticipantRegistry,
+) -> impl Filter<Extract = (ParticipantRegistry,), Error = Infallible> + Clone {
+    warp::any().map(move || registry.clone())
+}
+
+/// Converts a Redis client into a `warp` filter.
+fn with_redis(
+    redis: redis::Client,
+) -> impl Filter<Extract = (redis::Client,), Error = Infallible> + Clone {
+    warp::any().map(move || redis.clone())
+}
+
 /// Extracts a participant public key from a request body
 fn part_pk() -> impl Filter<Extract = (ParticipantPublicKey,), Error = warp::Rejection> + Clone {
     warp::body::bytes().and_then(|body: Bytes| async move {
@@ -219,12 +819,128 @@ struct InvalidPublicKey;
 
 impl warp::reject::Reject for InvalidPublicKey {}
 
+/// Authenticates a request to the admin API by its bearer token.
+///
+/// If no admin token is configured, the admin API is disabled and all requests are rejected as
+/// not found.
+fn admin_auth(token: Option<String>) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let token = token.clone();
+            async move {
+                let token = token.ok_or_else(warp::reject::not_found)?;
+                let authorized = header
+                    .as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "))
+                    .map_or(false, |bearer| memcmp(bearer.as_bytes(), token.as_bytes()));
+                if authorized {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(Unauthorized))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// Handles and responds to an admin command by sending the respective control request to the
+/// state machine.
+async fn handle_admin(
+    command: String,
+    control: ControlSender,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let req = match command.as_str() {
+        "pause" => ControlRequest::Pause,
+        "resume" => ControlRequest::Resume,
+        "skip" => ControlRequest::Skip,
+        "abort" => ControlRequest::Abort,
+        "shutdown" => ControlRequest::Shutdown,
+        _ => return Err(warp::reject::not_found()),
+    };
+    Ok(send_control(req, control).await)
+}
+
+/// Handles and responds to an update of the PET settings, which the state machine validates and
+/// applies at the beginning of the next round.
+async fn handle_pet_settings(
+    pet_settings: PetSettings,
+    control: ControlSender,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(send_control(ControlRequest::UpdatePetSettings(pet_settings), control).await)
+}
+
+/// Handles and responds to the registration of a participant in the participant registry.
+///
+/// Replies with `201 Created` if the participant is newly registered.
+async fn handle_register(
+    pk: ParticipantPublicKey,
+    registry: ParticipantRegistry,
+) -> Result<impl warp::Reply, Infallible> {
+    info!("received registration of participant with pk {:?}", pk);
+    Ok(match registry.register(&pk).await {
+        Ok(true) => warp::reply::with_status(String::new(), StatusCode::CREATED),
+        Ok(false) => warp::reply::with_status(String::new(), StatusCode::OK),
+        Err(e) => registry_error(e),
+    })
+}
+
+/// Handles and responds to the removal of a participant from the participant registry.
+///
+/// Replies with `404 Not Found` if the participant is not registered.
+async fn handle_unregister(
+    pk: ParticipantPublicKey,
+    registry: ParticipantRegistry,
+) -> Result<impl warp::Reply, Infallible> {
+    info!("received removal of participant with pk {:?}", pk);
+    Ok(match registry.unregister(&pk).await {
+        Ok(true) => warp::reply::with_status(String::new(), StatusCode::OK),
+        Ok(false) => warp::reply::with_status(String::new(), StatusCode::NOT_FOUND),
+        Err(e) => registry_error(e),
+    })
+}
+
+/// Replies with the error of a failed modification of the participant registry.
+fn registry_error(e: RegistryError) -> warp::reply::WithStatus<String> {
+    warn!("failed to modify the participant registry: {}", e);
+    let code = match e {
+        RegistryError::Immutable => StatusCode::CONFLICT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    warp::reply::with_status(e.to_string(), code)
+}
+
+/// Sends a control request to the state machine and replies with the outcome.
+async fn send_control(req: ControlRequest, control: ControlSender) -> impl warp::Reply {
+    info!("received admin command: {:?}", req);
+    match control.control(req).await {
+        Ok(()) => warp::reply::with_status(String::new(), StatusCode::OK),
+        Err(e) => {
+            warn!("failed to handle admin command {:?}: {}", req, e);
+            let code = match e {
+                ControlError::NotSkippable => StatusCode::CONFLICT,
+                ControlError::InvalidSettings(_) => StatusCode::BAD_REQUEST,
+                ControlError::ShutDown => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            warp::reply::with_status(e.to_string(), code)
+        }
+    }
+}
+
 /// Handles `warp` rejections of bad requests.
 async fn handle_reject(err: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
     let code = if err.is_not_found() {
         StatusCode::NOT_FOUND
     } else if let Some(InvalidPublicKey) = err.find() {
         StatusCode::BAD_REQUEST
+    } else if let Some(Unauthorized) = err.find() {
+        StatusCode::UNAUTHORIZED
+    } else if err.find::<BodyDeserializeError>().is_some() {
+        StatusCode::BAD_REQUEST
     } else {
         error!("unhandled rejection: {:?}", err);
         StatusCode::INTERNAL_SERVER_ERROR
@@ -232,3 +948,545 @@ async fn handle_reject(err: warp::Rejection) -> Result<impl warp::Reply, Infalli
     // reply with empty body; the status code is the interesting part
     Ok(warp::reply::with_status(Vec::new(), code))
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        sync::{mpsc, oneshot},
+    };
+    use tokio_rustls::{
+        client::TlsStream as ClientTlsStream,
+        rustls::ClientConfig,
+        webpki::DNSNameRef,
+        TlsConnector,
+    };
+    use xaynet_core::crypto::SigningKeyPair;
+
+    use super::*;
+    use crate::{
+        services::{fetchers::fetcher, tests::utils},
+        settings::{MultipartSettings, RateLimitSettings},
+        state_machine::{
+            control::ControlReceiver,
+            events::EventPublisher,
+            requests::RequestReceiver,
+        },
+    };
+
+    /// Starts a fake Redis server which replies to every command with `PONG` and connects a
+    /// Redis client to it. The server becomes unreachable once the returned sender fires or is
+    /// dropped.
+    async fn fake_redis() -> (redis::Client, oneshot::Sender<()>) {
+        fake_redis_replying(b"+PONG\r\n").await
+    }
+
+    /// Starts a fake Redis server which replies to every command with the raw `reply`, see
+    /// [`fake_redis()`].
+    async fn fake_redis_replying(reply: &'static [u8]) -> (redis::Client, oneshot::Sender<()>) {
+        let mut listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let serve = async {
+                let mut buf = [0; 1024];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 || stream.write_all(reply).await.is_err() {
+                        break;
+                    }
+                }
+            };
+            tokio::select! {
+                _ = serve => {}
+                _ = stop_rx => {}
+            }
+            // the connection and the listener are dropped here
+        });
+        let redis = redis::Client::new(format!("redis://{}/", addr), 10)
+            .await
+            .unwrap();
+        (redis, stop_tx)
+    }
+
+    /// Builds the routes with a disabled admin API.
+    fn test_routes(
+        redis: redis::Client,
+    ) -> (
+        EventPublisher,
+        RequestReceiver,
+        BoxedFilter<(impl warp::Reply,)>,
+    ) {
+        let (publisher, subscriber) = utils::new_event_channels();
+        let (requests_rx, requests_tx) = RequestReceiver::new();
+        let handler =
+            utils::new_message_handler(&subscriber, requests_tx, RateLimitSettings::default());
+        let (_, control) = ControlReceiver::new();
+        let routes = routes(
+            None,
+            fetcher(&subscriber, None),
+            handler,
+            control,
+            &subscriber,
+            redis,
+        );
+        (publisher, requests_rx, routes)
+    }
+
+    const ADMIN_TOKEN: &str = "secret";
+
+    /// Builds the routes with the admin API enabled by the [`ADMIN_TOKEN`] and the given
+    /// participant registry.
+    fn admin_routes(
+        registry: ParticipantRegistry,
+        redis: redis::Client,
+    ) -> BoxedFilter<(impl warp::Reply,)> {
+        let (_publisher, subscriber) = utils::new_event_channels();
+        let (_, requests_tx) = RequestReceiver::new();
+        let handler = PetMessageHandler::new(
+            &subscriber,
+            requests_tx,
+            registry,
+            MultipartSettings {
+                max_message_size: 1_048_576,
+                max_buffer_size: 1_048_576,
+                timeout: 300,
+            },
+            RateLimitSettings::default(),
+            &utils::services_settings(),
+        )
+        .unwrap();
+        let (_, control) = ControlReceiver::new();
+        routes(
+            Some(ADMIN_TOKEN.to_string()),
+            fetcher(&subscriber, None),
+            handler,
+            control,
+            &subscriber,
+            redis,
+        )
+    }
+
+    /// Sends a registry request for the participant to the admin API.
+    async fn registry_request(
+        method: &str,
+        pk: &[u8],
+        token: &str,
+        routes: &BoxedFilter<(impl warp::Reply + 'static,)>,
+    ) -> StatusCode {
+        warp::test::request()
+            .method(method)
+            .path("/admin/participants")
+            .header("authorization", format!("Bearer {}", token))
+            .body(pk.to_vec())
+            .reply(routes)
+            .await
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let (redis, _stop) = fake_redis().await;
+        let (_publisher, _requests, routes) = test_routes(redis);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/health")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_ready() {
+        let (redis, _stop) = fake_redis().await;
+        let (mut publisher, _requests, routes) = test_routes(redis);
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/ready")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // the coordinator is not ready once the state machine stopped running
+        publisher.broadcast_phase(PhaseName::Shutdown);
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/ready")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_ready_redis_unreachable() {
+        let (redis, stop) = fake_redis().await;
+        let (_publisher, _requests, routes) = test_routes(redis);
+        stop.send(()).unwrap();
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/ready")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_status() {
+        let (redis, _stop) = fake_redis().await;
+        let (mut publisher, _requests, routes) = test_routes(redis);
+        publisher.set_round_id(2);
+        publisher.broadcast_phase(PhaseName::Sum);
+        publisher.broadcast_message_counts(MessageCounts {
+            sum: 3,
+            update: 0,
+            sum2: 0,
+        });
+
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/status")
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.body(),
+            r#"{"round_id":2,"phase":"Sum","messages":{"sum":3,"update":0,"sum2":0}}"#
+        );
+
+        // the message counts of a past round are not reported
+        publisher.set_round_id(3);
+        publisher.broadcast_phase(PhaseName::Idle);
+        let resp = warp::test::request()
+            .method("GET")
+            .path("/status")
+            .reply(&routes)
+            .await;
+        assert_eq!(
+            resp.body(),
+            r#"{"round_id":3,"phase":"Idle","messages":{"sum":0,"update":0,"sum2":0}}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_participant() {
+        let pk = SigningKeyPair::generate().public;
+
+        // the participant is newly registered and unregistered
+        let (redis, _stop) = fake_redis_replying(b":1\r\n").await;
+        let routes = admin_routes(ParticipantRegistry::Redis(redis.clone()), redis);
+        let status = registry_request("PUT", pk.as_slice(), ADMIN_TOKEN, &routes).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let status = registry_request("DELETE", pk.as_slice(), ADMIN_TOKEN, &routes).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // the participant is already registered and not registered anymore
+        let (redis, _stop) = fake_redis_replying(b":0\r\n").await;
+        let routes = admin_routes(ParticipantRegistry::Redis(redis.clone()), redis);
+        let status = registry_request("PUT", pk.as_slice(), ADMIN_TOKEN, &routes).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = registry_request("DELETE", pk.as_slice(), ADMIN_TOKEN, &routes).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_register_participant_rejected() {
+        let pk = SigningKeyPair::generate().public;
+        let (redis, _stop) = fake_redis_replying(b":1\r\n").await;
+        let routes = admin_routes(ParticipantRegistry::Redis(redis.clone()), redis);
+        for method in &["PUT", "DELETE"] {
+            let status = registry_request(method, pk.as_slice(), "wrong", &routes).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            let status = registry_request(method, &pk.as_slice()[1..], ADMIN_TOKEN, &routes).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+
+        // a static registry cannot be modified at runtime
+        let (redis, _stop) = fake_redis().await;
+        let registry = ParticipantRegistry::File(Arc::new(vec![pk].into_iter().collect()));
+        let routes = admin_routes(registry, redis);
+        for method in &["PUT", "DELETE"] {
+            let status = registry_request(method, pk.as_slice(), ADMIN_TOKEN, &routes).await;
+            assert_eq!(status, StatusCode::CONFLICT);
+        }
+    }
+
+    /// Gets the TLS settings with the test certificates, optionally with client authentication.
+    fn tls_settings(client_auth: bool) -> TlsSettings {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/tls");
+        TlsSettings {
+            certificate: dir.join("server.pem"),
+            key: dir.join("server.key"),
+            client_ca: if client_auth {
+                Some(dir.join("ca.pem"))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Connects a TLS client, which trusts the test CA and authenticates with the test client
+    /// certificate, to the address.
+    async fn connect_tls(addr: SocketAddr) -> io::Result<ClientTlsStream<TcpStream>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/tls");
+        let mut config = ClientConfig::new();
+        config
+            .root_store
+            .add_pem_file(&mut open(&dir.join("ca.pem")).unwrap())
+            .unwrap();
+        config
+            .set_single_client_cert(
+                load_certificates(&dir.join("client.pem")).unwrap(),
+                load_private_key(&dir.join("client.key")).unwrap(),
+            )
+            .unwrap();
+        let domain = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+        let stream = TcpStream::connect(addr).await?;
+        TlsConnector::from(Arc::new(config))
+            .connect(domain, stream)
+            .await
+    }
+
+    #[test]
+    fn test_tls_config() {
+        assert!(tls_config(&tls_settings(false)).is_ok());
+        assert!(tls_config(&tls_settings(true)).is_ok());
+
+        let settings = TlsSettings {
+            certificate: "/nonexistent/server.pem".into(),
+            ..tls_settings(false)
+        };
+        assert!(matches!(tls_config(&settings), Err(TlsConfigError::Io(..))));
+
+        let settings = tls_settings(false);
+        let settings = TlsSettings {
+            certificate: settings.key.clone(),
+            ..settings
+        };
+        assert!(matches!(
+            tls_config(&settings),
+            Err(TlsConfigError::Certificate(_))
+        ));
+
+        let settings = tls_settings(true);
+        let settings = TlsSettings {
+            key: settings.certificate.clone(),
+            ..settings
+        };
+        assert!(matches!(
+            tls_config(&settings),
+            Err(TlsConfigError::PrivateKey(_))
+        ));
+
+        let settings = tls_settings(true);
+        let settings = TlsSettings {
+            client_ca: Some(settings.key.clone()),
+            ..settings
+        };
+        assert!(matches!(
+            tls_config(&settings),
+            Err(TlsConfigError::Certificate(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tls_incoming() {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(tls_config(&tls_settings(true)).unwrap()));
+        let mut incoming = Box::pin(tls_incoming(listener, acceptor, Duration::from_millis(100)));
+        let (accepted_tx, mut accepted_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(stream) = incoming.next().await {
+                let _ = accepted_tx.send(stream);
+            }
+        });
+
+        // an idle connection doesn't hold up the handshakes of other connections
+        let mut idle = TcpStream::connect(addr).await.unwrap();
+        let _client = connect_tls(addr).await.unwrap();
+        assert!(accepted_rx.recv().await.unwrap().is_ok());
+
+        // the idle connection is dropped once its handshake timed out
+        let mut buf = [0; 1];
+        let read = timeout(Duration::from_secs(5), idle.read(&mut buf))
+            .await
+            .unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)));
+    }
+
+    #[tokio::test]
+    async fn test_tls_remote_addr() {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(tls_config(&tls_settings(true)).unwrap()));
+        let incoming = tls_incoming(listener, acceptor, HANDSHAKE_TIMEOUT);
+        let routes = warp::path!("addr")
+            .and(remote_addr())
+            .map(|addr: Option<SocketAddr>| format!("{:?}", addr))
+            .boxed();
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve_tls(incoming, routes, async {
+            let _ = stop_rx.await;
+        }));
+
+        let mut client = connect_tls(addr).await.unwrap();
+        let client_addr = client.get_ref().0.local_addr().unwrap();
+        client
+            .write_all(b"GET /addr HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut resp = String::new();
+        timeout(Duration::from_secs(5), client.read_to_string(&mut resp))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK"), "{}", resp);
+        assert!(
+            resp.ends_with(&format!("{:?}", Some(client_addr))),
+            "{}",
+            resp
+        );
+
+        stop_tx.send(()).unwrap();
+        timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remote_addr() {
+        let remote = SocketAddr::from(([127, 0, 0, 1], 1));
+        let peer = SocketAddr::from(([127, 0, 0, 1], 2));
+
+        let addr = warp::test::request()
+            .remote_addr(remote)
+            .filter(&remote_addr())
+            .await
+            .unwrap();
+        assert_eq!(addr, Some(remote));
+
+        let addr = warp::test::request()
+            .extension(PeerAddr(peer))
+            .filter(&remote_addr())
+            .await
+            .unwrap();
+        assert_eq!(addr, Some(peer));
+
+        let addr = warp::test::request().filter(&remote_addr()).await.unwrap();
+        assert_eq!(addr, None);
+    }
+
+    #[test]
+    fn test_rejection_status() {
+        let cases = vec![
+            (MessageRejection::Decrypt, StatusCode::BAD_REQUEST),
+            (MessageRejection::Parsing, StatusCode::BAD_REQUEST),
+            (MessageRejection::InvalidChunk, StatusCode::BAD_REQUEST),
+            (MessageRejection::InvalidSignature, StatusCode::UNAUTHORIZED),
+            (MessageRejection::NotSumEligible, StatusCode::FORBIDDEN),
+            (MessageRejection::NotUpdateEligible, StatusCode::FORBIDDEN),
+            (MessageRejection::UnknownParticipant, StatusCode::FORBIDDEN),
+            (
+                MessageRejection::InvalidCoordinatorPublicKey,
+                StatusCode::CONFLICT,
+            ),
+            (MessageRejection::UnexpectedMessage, StatusCode::CONFLICT),
+            (MessageRejection::Rejected, StatusCode::CONFLICT),
+            (MessageRejection::Replay, StatusCode::CONFLICT),
+            (
+                MessageRejection::MessageTooLarge,
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ),
+            (
+                MessageRejection::AggregationFailed,
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                MessageRejection::InvalidLocalSeedDict,
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                MessageRejection::BufferFull,
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                MessageRejection::Overloaded,
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (MessageRejection::RateLimited, StatusCode::TOO_MANY_REQUESTS),
+            (
+                MessageRejection::InternalError,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+        for (rejection, status) in cases {
+            assert_eq!(rejection_status(rejection), status, "{:?}", rejection);
+        }
+    }
+
+    #[test]
+    fn test_accept_quality() {
+        let typed = TypedModel::MEDIA_TYPE;
+        let bincode = "application/octet-stream";
+        let cases = vec![
+            (None, 1.0, 1.0),
+            (Some(typed), 1.0, 0.0),
+            (Some("*/*"), 1.0, 1.0),
+            (Some("application/*;q=0.5"), 0.5, 0.5),
+            (
+                Some("application/vnd.xaynet.typed-model, */*;q=0.1"),
+                1.0,
+                0.1,
+            ),
+            (
+                Some("application/vnd.xaynet.typed-model;q=0.2, application/*;q=0.8"),
+                0.2,
+                0.8,
+            ),
+            (
+                Some("*/*, application/vnd.xaynet.typed-model;q=0"),
+                0.0,
+                1.0,
+            ),
+            (Some("APPLICATION/OCTET-STREAM ; Q=0.7"), 0.0, 0.7),
+            (Some("application/vnd.xaynet.typed-model;q=2"), 0.0, 0.0),
+            (Some("text/html"), 0.0, 0.0),
+        ];
+        for (accept, typed_quality, bincode_quality) in cases {
+            assert!(
+                (accept_quality(accept, typed) - typed_quality).abs() < f32::EPSILON,
+                "{:?}",
+                accept
+            );
+            assert!(
+                (accept_quality(accept, bincode) - bincode_quality).abs() < f32::EPSILON,
+                "{:?}",
+                accept
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejection_response() {
+        let response = rejection_response(MessageRejection::NotUpdateEligible);
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        assert_eq!(&body[..], br#"{"error":"not_update_eligible"}"#);
+    }
+}
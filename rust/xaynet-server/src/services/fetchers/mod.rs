@@ -21,6 +21,8 @@ use std::task::{Context, Poll};
 use futures::future::poll_fn;
 use tower::{layer::Layer, Service, ServiceBuilder};
 
+use xaynet_core::crypto::SecretSigningKey;
+
 use crate::state_machine::events::EventSubscriber;
 
 /// A single interface for retrieving data from the coordinator.
@@ -207,13 +209,17 @@ impl<RoundParams, SumDict, SeedDict, MaskLength, Model>
     }
 }
 
-/// Construct a [`Fetcher`] service
-pub fn fetcher(event_subscriber: &EventSubscriber) -> impl Fetcher + Sync + Send + Clone + 'static {
+/// Construct a [`Fetcher`] service. The round parameters are signed
+/// with the `signing_key` of the coordinator, if it is given.
+pub fn fetcher(
+    event_subscriber: &EventSubscriber,
+    signing_key: Option<SecretSigningKey>,
+) -> impl Fetcher + Sync + Send + Clone + 'static {
     let round_params = ServiceBuilder::new()
         .buffer(100)
         .concurrency_limit(100)
         .layer(FetcherLayer)
-        .service(RoundParamsService::new(event_subscriber));
+        .service(RoundParamsService::new(event_subscriber, signing_key));
 
     let mask_length = ServiceBuilder::new()
         .buffer(100)
@@ -3,7 +3,10 @@ use std::task::{Context, Poll};
 use futures::future::{self, Ready};
 use tower::Service;
 use tracing_futures::{Instrument, Instrumented};
-use xaynet_core::common::RoundParameters;
+use xaynet_core::{
+    common::{RoundParameters, SignedRoundParameters},
+    crypto::SecretSigningKey,
+};
 
 use crate::state_machine::events::{EventListener, EventSubscriber};
 
@@ -12,19 +15,27 @@ use crate::state_machine::events::{EventListener, EventSubscriber};
 pub struct RoundParamsRequest;
 
 /// [`RoundParamsService`]'s response type
-pub type RoundParamsResponse = RoundParameters;
-
-/// A service that serves the round parameters for the current round.
-pub struct RoundParamsService(EventListener<RoundParameters>);
+pub type RoundParamsResponse = SignedRoundParameters;
+
+/// A service that serves the round parameters for the current round,
+/// signed by the long-term signing key of the coordinator if it has
+/// one.
+pub struct RoundParamsService {
+    params_listener: EventListener<RoundParameters>,
+    signing_key: Option<SecretSigningKey>,
+}
 
 impl RoundParamsService {
-    pub fn new(events: &EventSubscriber) -> Self {
-        Self(events.params_listener())
+    pub fn new(events: &EventSubscriber, signing_key: Option<SecretSigningKey>) -> Self {
+        Self {
+            params_listener: events.params_listener(),
+            signing_key,
+        }
     }
 }
 
 impl Service<RoundParamsRequest> for RoundParamsService {
-    type Response = RoundParameters;
+    type Response = SignedRoundParameters;
     type Error = ::std::convert::Infallible;
     type Future = Instrumented<Ready<Result<Self::Response, Self::Error>>>;
 
@@ -33,7 +44,9 @@ impl Service<RoundParamsRequest> for RoundParamsService {
     }
 
     fn call(&mut self, _req: RoundParamsRequest) -> Self::Future {
-        future::ready(Ok(self.0.get_latest().event))
+        let params = self.params_listener.get_latest().event;
+        let signature = self.signing_key.as_ref().map(|sk| params.sign(sk));
+        future::ready(Ok(SignedRoundParameters { params, signature }))
             .instrument(error_span!("round_params_fetch_request"))
     }
 }
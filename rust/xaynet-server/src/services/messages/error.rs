@@ -1,7 +1,8 @@
 use thiserror::Error;
-use xaynet_core::message::DecodeError;
+use tower::load_shed::error::Overloaded;
+use xaynet_core::{common::MessageRejection, message::DecodeError};
 
-use crate::state_machine::StateMachineError;
+use crate::{services::messages::MultipartError, state_machine::StateMachineError};
 
 /// Error type for the message parsing service
 #[derive(Debug, Error)]
@@ -26,12 +27,172 @@ pub enum ServiceError {
     #[error("the state machine failed to process the request: {0:?}")]
     StateMachine(StateMachineError),
 
+    #[error("failed to reassemble the multipart message: {0}")]
+    Multipart(MultipartError),
+
     #[error("participant is not eligible for sum task")]
     NotSumEligible,
 
     #[error("participant is not eligible for update task")]
     NotUpdateEligible,
 
+    #[error("participant is not in the participant registry")]
+    UnknownParticipant,
+
+    #[error("the rate limit is exceeded")]
+    RateLimited,
+
+    #[error("the message is a replay")]
+    Replay,
+
+    #[error("the service is overloaded")]
+    Overloaded,
+
     #[error("Internal error: {0}")]
     InternalError(String),
 }
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for ServiceError {
+    /// Recovers the service error from the error of a `tower`
+    /// middleware.
+    fn from(error: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        match error.downcast::<ServiceError>() {
+            Ok(error) => *error,
+            Err(error) if error.is::<Overloaded>() => ServiceError::Overloaded,
+            Err(error) => ServiceError::InternalError(error.to_string()),
+        }
+    }
+}
+
+impl From<&ServiceError> for MessageRejection {
+    fn from(error: &ServiceError) -> Self {
+        match error {
+            ServiceError::Decrypt => MessageRejection::Decrypt,
+            ServiceError::Parsing(_) => MessageRejection::Parsing,
+            ServiceError::InvalidMessageSignature => MessageRejection::InvalidSignature,
+            ServiceError::InvalidCoordinatorPublicKey => {
+                MessageRejection::InvalidCoordinatorPublicKey
+            }
+            ServiceError::UnexpectedMessage => MessageRejection::UnexpectedMessage,
+            ServiceError::Multipart(MultipartError::MessageTooLarge) => {
+                MessageRejection::MessageTooLarge
+            }
+            ServiceError::Multipart(MultipartError::BufferFull) => MessageRejection::BufferFull,
+            ServiceError::Multipart(MultipartError::InvalidChunkId(_))
+            | ServiceError::Multipart(MultipartError::ParticipantMismatch) => {
+                MessageRejection::InvalidChunk
+            }
+            ServiceError::StateMachine(StateMachineError::MessageRejected) => {
+                MessageRejection::Rejected
+            }
+            ServiceError::StateMachine(StateMachineError::AggregationFailed) => {
+                MessageRejection::AggregationFailed
+            }
+            ServiceError::StateMachine(StateMachineError::InvalidLocalSeedDict) => {
+                MessageRejection::InvalidLocalSeedDict
+            }
+            ServiceError::Overloaded => MessageRejection::Overloaded,
+            ServiceError::StateMachine(StateMachineError::InternalError)
+            | ServiceError::InternalError(_) => MessageRejection::InternalError,
+            ServiceError::NotSumEligible => MessageRejection::NotSumEligible,
+            ServiceError::NotUpdateEligible => MessageRejection::NotUpdateEligible,
+            ServiceError::UnknownParticipant => MessageRejection::UnknownParticipant,
+            ServiceError::RateLimited => MessageRejection::RateLimited,
+            ServiceError::Replay => MessageRejection::Replay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_rejection() {
+        let cases = vec![
+            (ServiceError::Decrypt, MessageRejection::Decrypt),
+            (
+                ServiceError::Parsing(anyhow::anyhow!("invalid length")),
+                MessageRejection::Parsing,
+            ),
+            (
+                ServiceError::InvalidMessageSignature,
+                MessageRejection::InvalidSignature,
+            ),
+            (
+                ServiceError::InvalidCoordinatorPublicKey,
+                MessageRejection::InvalidCoordinatorPublicKey,
+            ),
+            (
+                ServiceError::UnexpectedMessage,
+                MessageRejection::UnexpectedMessage,
+            ),
+            (
+                ServiceError::StateMachine(StateMachineError::MessageRejected),
+                MessageRejection::Rejected,
+            ),
+            (
+                ServiceError::StateMachine(StateMachineError::AggregationFailed),
+                MessageRejection::AggregationFailed,
+            ),
+            (
+                ServiceError::StateMachine(StateMachineError::InvalidLocalSeedDict),
+                MessageRejection::InvalidLocalSeedDict,
+            ),
+            (
+                ServiceError::StateMachine(StateMachineError::InternalError),
+                MessageRejection::InternalError,
+            ),
+            (
+                ServiceError::Multipart(MultipartError::MessageTooLarge),
+                MessageRejection::MessageTooLarge,
+            ),
+            (
+                ServiceError::Multipart(MultipartError::BufferFull),
+                MessageRejection::BufferFull,
+            ),
+            (
+                ServiceError::Multipart(MultipartError::InvalidChunkId(3)),
+                MessageRejection::InvalidChunk,
+            ),
+            (
+                ServiceError::Multipart(MultipartError::ParticipantMismatch),
+                MessageRejection::InvalidChunk,
+            ),
+            (
+                ServiceError::NotSumEligible,
+                MessageRejection::NotSumEligible,
+            ),
+            (
+                ServiceError::NotUpdateEligible,
+                MessageRejection::NotUpdateEligible,
+            ),
+            (
+                ServiceError::UnknownParticipant,
+                MessageRejection::UnknownParticipant,
+            ),
+            (ServiceError::RateLimited, MessageRejection::RateLimited),
+            (ServiceError::Replay, MessageRejection::Replay),
+            (ServiceError::Overloaded, MessageRejection::Overloaded),
+            (
+                ServiceError::InternalError("failure".to_string()),
+                MessageRejection::InternalError,
+            ),
+        ];
+        for (error, rejection) in cases {
+            assert_eq!(MessageRejection::from(&error), rejection, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn test_recover_service_error() {
+        let error: Box<dyn std::error::Error + Send + Sync> = Box::new(ServiceError::Replay);
+        assert!(matches!(ServiceError::from(error), ServiceError::Replay));
+
+        let error: Box<dyn std::error::Error + Send + Sync> = "failure".into();
+        assert!(matches!(
+            ServiceError::from(error),
+            ServiceError::InternalError(e) if e == "failure"
+        ));
+    }
+}
@@ -113,7 +113,10 @@ where
             Ok(tag) => match (phase, tag) {
                 (PhaseName::Sum, Tag::Sum)
                 | (PhaseName::Update, Tag::Update)
-                | (PhaseName::Sum2, Tag::Sum2) => {
+                | (PhaseName::Sum2, Tag::Sum2)
+                | (PhaseName::Sum, Tag::Chunk)
+                | (PhaseName::Update, Tag::Chunk)
+                | (PhaseName::Sum2, Tag::Chunk) => {
                     let fut = self.next_svc.call(req);
                     Box::pin(async move { fut.await })
                 }
@@ -6,96 +6,330 @@
 mod decryptor;
 mod error;
 mod message_parser;
+mod multipart;
+mod participant_validator;
+mod rate_limiter;
+mod replay_guard;
 mod state_machine;
 mod task_validator;
 
-pub use self::error::ServiceError;
 use self::{
     decryptor::Decryptor,
     message_parser::MessageParser,
+    multipart::MultipartHandler,
+    participant_validator::ParticipantValidator,
+    rate_limiter::RateLimiter,
+    replay_guard::ReplayGuard,
     state_machine::StateMachine,
     task_validator::TaskValidator,
 };
+pub use self::{error::ServiceError, multipart::MultipartError};
 
-use std::sync::Arc;
+use std::{net::IpAddr, sync::Arc};
 
 use futures::future::poll_fn;
-use rayon::ThreadPoolBuilder;
-use tower::Service;
-use xaynet_core::message::Message;
+use tower::{buffer::Buffer, limit::concurrency::ConcurrencyLimit, load_shed::LoadShed, Service};
+use xaynet_core::{
+    message::{Message, Payload},
+    ParticipantPublicKey,
+};
+
+use crate::{
+    settings::{
+        MultipartSettings,
+        RateLimitSettings,
+        ServiceLimits,
+        ServicesSettings,
+        SettingsError,
+    },
+    state_machine::{events::EventSubscriber, requests::RequestSender},
+    storage::registry::ParticipantRegistry,
+};
+
+/// A service with a concurrency limit and a bounded queue of
+/// requests. Requests that don't fit into the queue are rejected.
+type Limited<S, R> = LoadShed<Buffer<ConcurrencyLimit<S>, R>>;
+
+/// Wraps a service into a [`Limited`] service.
+///
+/// This spawns the worker of the queue, hence it must be called on
+/// the tokio runtime.
+fn limit<S, R>(service: S, limits: ServiceLimits) -> Limited<S, R>
+where
+    S: Service<R> + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError> + Send + Sync,
+    R: Send + 'static,
+{
+    LoadShed::new(Buffer::new(
+        ConcurrencyLimit::new(service, limits.concurrency_limit),
+        limits.queue_limit,
+    ))
+}
 
-use crate::state_machine::{events::EventSubscriber, requests::RequestSender};
+/// The error type of the `tower` middlewares.
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
 impl PetMessageHandler {
-    pub fn new(event_subscriber: &EventSubscriber, requests_tx: RequestSender) -> Self {
-        // TODO: make this configurable. Users should be able to
-        // choose how many threads they want etc.
-        //
-        // TODO: don't unwrap
-        let thread_pool = Arc::new(ThreadPoolBuilder::new().build().unwrap());
+    /// Creates a new PET message handler, which rejects the messages of
+    /// participants that are not in the participant `registry`.
+    ///
+    /// This must be called on the tokio runtime.
+    ///
+    /// # Errors
+    /// Fails if the thread pool of the services cannot be built.
+    pub fn new(
+        event_subscriber: &EventSubscriber,
+        requests_tx: RequestSender,
+        registry: ParticipantRegistry,
+        multipart_settings: MultipartSettings,
+        rate_limit_settings: RateLimitSettings,
+        services_settings: &ServicesSettings,
+    ) -> Result<Self, SettingsError> {
+        let thread_pool = Arc::new(services_settings.thread_pool()?);
         let decryptor = Decryptor::new(event_subscriber, thread_pool.clone());
         let message_parser = MessageParser::new(event_subscriber, thread_pool);
+        let multipart_handler = MultipartHandler::new(event_subscriber, multipart_settings);
+        let participant_validator = ParticipantValidator::new(registry.clone());
         let task_validator = TaskValidator::new(event_subscriber);
         let state_machine = StateMachine::new(requests_tx);
 
-        Self {
-            decryptor,
-            message_parser,
-            task_validator,
-            state_machine,
-        }
+        Ok(Self {
+            client_limiter: RateLimiter::new(rate_limit_settings.client),
+            participant_limiter: RateLimiter::new(rate_limit_settings.participant),
+            replay_guard: ReplayGuard::new(event_subscriber),
+            decryptor: limit(decryptor, services_settings.decryptor),
+            message_parser: limit(message_parser, services_settings.message_parser),
+            multipart_handler: limit(multipart_handler, services_settings.multipart_handler),
+            participant_validator: limit(
+                participant_validator,
+                services_settings.participant_validator,
+            ),
+            task_validator: limit(task_validator, services_settings.task_validator),
+            state_machine: limit(state_machine, services_settings.state_machine),
+            registry,
+        })
+    }
+
+    /// Gets the participant registry.
+    pub fn registry(&self) -> &ParticipantRegistry {
+        &self.registry
     }
+
+    async fn limit_client(&mut self, client: IpAddr) -> Result<(), ServiceError> {
+        poll_fn(|cx| self.client_limiter.poll_ready(cx)).await?;
+        self.client_limiter.call(client).await
+    }
+
+    async fn limit_participant(&mut self, pk: ParticipantPublicKey) -> Result<(), ServiceError> {
+        poll_fn(|cx| self.participant_limiter.poll_ready(cx)).await?;
+        self.participant_limiter.call(pk).await
+    }
+
     async fn decrypt(&mut self, enc_data: Vec<u8>) -> Result<Vec<u8>, ServiceError> {
-        poll_fn(|cx| <Decryptor as Service<Vec<u8>>>::poll_ready(&mut self.decryptor, cx)).await?;
-        self.decryptor.call(enc_data).await
+        poll_fn(|cx| self.decryptor.poll_ready(cx)).await?;
+        Ok(self.decryptor.call(enc_data).await?)
     }
 
     async fn parse(&mut self, data: Vec<u8>) -> Result<Message, ServiceError> {
-        poll_fn(|cx| <MessageParser as Service<Vec<u8>>>::poll_ready(&mut self.message_parser, cx))
-            .await?;
-        self.message_parser.call(data).await
+        poll_fn(|cx| self.message_parser.poll_ready(cx)).await?;
+        Ok(self.message_parser.call(data).await?)
+    }
+
+    async fn validate_participant(&mut self, message: Message) -> Result<Message, ServiceError> {
+        poll_fn(|cx| self.participant_validator.poll_ready(cx)).await?;
+        Ok(self.participant_validator.call(message).await?)
+    }
+
+    async fn reassemble(&mut self, message: Message) -> Result<Option<Vec<u8>>, ServiceError> {
+        poll_fn(|cx| self.multipart_handler.poll_ready(cx)).await?;
+        Ok(self.multipart_handler.call(message).await?)
     }
 
     async fn validate_task(&mut self, message: Message) -> Result<Message, ServiceError> {
         poll_fn(|cx| self.task_validator.poll_ready(cx)).await?;
-        self.task_validator.call(message).await
+        Ok(self.task_validator.call(message).await?)
     }
 
     async fn process(&mut self, message: Message) -> Result<(), ServiceError> {
         poll_fn(|cx| self.state_machine.poll_ready(cx)).await?;
-        self.state_machine.call(message).await
+        Ok(self.state_machine.call(message).await?)
     }
 
-    pub async fn handle_message(&mut self, enc_data: Vec<u8>) -> Result<(), ServiceError> {
+    /// Handles an encrypted PET message which has been sent by the
+    /// client with the given IP address, if it is known.
+    pub async fn handle_message(
+        &mut self,
+        enc_data: Vec<u8>,
+        client: Option<IpAddr>,
+    ) -> Result<(), ServiceError> {
+        if let Some(client) = client {
+            self.limit_client(client).await?;
+        }
         let raw_message = self.decrypt(enc_data).await?;
         let message = self.parse(raw_message).await?;
+        // the participants are limited by their whole messages, hence
+        // the chunks are limited once they have been reassembled
+        let is_chunk = matches!(message.payload, Payload::Chunk(_));
+        if !is_chunk {
+            self.limit_participant(message.participant_pk).await?;
+        }
+        // chunks are validated as well, so that unknown participants
+        // cannot fill up the multipart buffers
+        let message = self.validate_participant(message).await?;
+        let message = if is_chunk {
+            let participant_pk = message.participant_pk;
+            let raw_message = match self.reassemble(message).await? {
+                Some(raw_message) => raw_message,
+                None => return Ok(()),
+            };
+            // the reassembled message goes through the same checks as
+            // any other message
+            let message = self.parse(raw_message).await?;
+            if message.participant_pk != participant_pk {
+                return Err(ServiceError::Multipart(MultipartError::ParticipantMismatch));
+            }
+            self.limit_participant(participant_pk).await?;
+            message
+        } else {
+            message
+        };
         let message = self.validate_task(message).await?;
-        self.process(message).await
+
+        // a message which failed to be processed may be sent again
+        let signature = message.signature;
+        if let Some(signature) = signature {
+            self.replay_guard.insert(signature)?;
+        }
+        let result = self.process(message).await;
+        if let (Err(_), Some(ref signature)) = (&result, signature) {
+            self.replay_guard.remove(signature);
+        }
+        result
     }
 }
 
 /// A service that processes requests from the beginning to the
 /// end.
 ///
-/// The processing is divided in three phases:
+/// The processing is divided in five phases:
 ///
 /// 1. The raw request (which is just a vector of bytes represented an
-///    encrypted message) goes through the `MessageParser` service,
-///    which decrypt the message, validates it, and parses it
+///    encrypted message) goes through the `RateLimiter` of its client
+///    IP address and the `MessageParser` service, which decrypt the
+///    message, validates it, and parses it
 ///
-/// 2. The message is passed to the `TaskValidator`, which depending on
+/// 2. Unless the message is a chunk of a multipart message, it goes
+///    through the `RateLimiter` of its participant. The message is
+///    passed to the `ParticipantValidator`, which rejects it if its
+///    participant is not in the participant registry
+///
+/// 3. If the message is a chunk of a multipart message, it is passed
+///    to the `MultipartHandler`, which buffers it until all the chunks
+///    of the message have been received. The reassembled message is
+///    then parsed and validated like any other message and goes
+///    through the `RateLimiter` of its participant
+///
+/// 4. The message is passed to the `TaskValidator`, which depending on
 ///    the message type performs some additional checks. The
 ///    `TaskValidator` may also discard the message
 ///
-/// 3. Finally, the message is handled by the `StateMachine` service.
+/// 5. Finally, the message is handled by the `StateMachine` service,
+///    unless the `ReplayGuard` rejects it as an exact replay of a
+///    message from the current round.
+///
+/// Each service has its own concurrency limit and queue of requests.
+/// If the queue of a service is full, the message is rejected.
 #[derive(Clone)]
 pub struct PetMessageHandler {
-    decryptor: Decryptor,
-    message_parser: MessageParser,
-    task_validator: TaskValidator,
-    state_machine: StateMachine,
+    client_limiter: RateLimiter<IpAddr>,
+    participant_limiter: RateLimiter<ParticipantPublicKey>,
+    replay_guard: ReplayGuard,
+    decryptor: Limited<Decryptor, Vec<u8>>,
+    message_parser: Limited<MessageParser, Vec<u8>>,
+    multipart_handler: Limited<MultipartHandler, Message>,
+    participant_validator: Limited<ParticipantValidator, Message>,
+    task_validator: Limited<TaskValidator, Message>,
+    state_machine: Limited<StateMachine, Message>,
+    registry: ParticipantRegistry,
 }
 
 pub type BoxedServiceFuture<Response, Error> = std::pin::Pin<
     Box<dyn futures::Future<Output = Result<Response, Error>> + 'static + Send + Sync>,
 >;
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use futures::StreamExt;
+    use xaynet_client::Participant;
+    use xaynet_core::{
+        crypto::{ByteObject, PublicEncryptKey, PublicSigningKey},
+        mask::{FromPrimitives, Model},
+        SumDict,
+    };
+
+    use super::*;
+    use crate::{
+        services::tests::utils,
+        settings::RateLimit,
+        state_machine::{
+            phases::PhaseName,
+            requests::{RequestReceiver, StateMachineRequest},
+        },
+    };
+
+    #[tokio::test]
+    async fn test_multipart_update_is_rate_limited_as_a_whole() {
+        // the rate limits of the example configuration
+        let rate_limit_settings = RateLimitSettings {
+            client: Some(RateLimit {
+                burst: 100,
+                rate: 10.,
+            }),
+            participant: Some(RateLimit {
+                burst: 50,
+                rate: 1.,
+            }),
+        };
+        let (mut publisher, subscriber) = utils::new_event_channels();
+        let (mut requests_rx, requests_tx) = RequestReceiver::new();
+        let mut handler = utils::new_message_handler(&subscriber, requests_tx, rate_limit_settings);
+
+        // every participant is eligible for the update task
+        let mut params = subscriber.params_listener().get_latest().event;
+        params.sum = 0.;
+        params.update = 1.;
+        publisher.broadcast_params(params.clone());
+        publisher.broadcast_phase(PhaseName::Update);
+
+        let mut updater = Participant::new().unwrap();
+        updater.compute_signatures(params.seed.as_slice());
+        let mut sum_dict = SumDict::new();
+        sum_dict.insert(PublicSigningKey::generate(), PublicEncryptKey::generate());
+        let model = Model::from_primitives(vec![0_f32; 1000].into_iter()).unwrap();
+        let message = updater
+            .compose_update_message(params.pk, &sum_dict, 1., model, params.mask_config)
+            .unwrap();
+        // more chunks than the burst of the participant limit, but less than the one of the
+        // client limit
+        let chunks =
+            updater.seal_message_chunks(&params.pk, &message, message.buffer_length() / 75);
+        assert!(chunks.len() > 50 && chunks.len() <= 100);
+
+        let state_machine = tokio::spawn(async move {
+            let (req, _span, resp_tx) = requests_rx.next().await.unwrap();
+            let _ = resp_tx.send(Ok(()));
+            req
+        });
+        let client = IpAddr::from(Ipv4Addr::LOCALHOST);
+        for chunk in chunks {
+            handler.handle_message(chunk, Some(client)).await.unwrap();
+        }
+        assert!(matches!(
+            state_machine.await.unwrap(),
+            StateMachineRequest::Update(_)
+        ));
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use futures::{future, task::Context};
+use thiserror::Error;
+use tower::Service;
+use xaynet_core::{
+    message::{Chunk, Message, Payload},
+    ParticipantPublicKey,
+};
+
+use crate::{
+    services::messages::ServiceError,
+    settings::MultipartSettings,
+    state_machine::{
+        events::{Event, EventListener, EventSubscriber},
+        phases::PhaseName,
+    },
+};
+
+/// Error that occurs when the chunks of a multipart message cannot be
+/// reassembled.
+#[derive(Debug, Error)]
+pub enum MultipartError {
+    #[error("the multipart message exceeds the maximum message size")]
+    MessageTooLarge,
+
+    #[error("the buffer for incomplete multipart messages is full")]
+    BufferFull,
+
+    #[error("invalid chunk ID {0}: the chunk is beyond the last chunk of the message")]
+    InvalidChunkId(u16),
+
+    #[error("the reassembled message was not sent by the participant who sent the chunks")]
+    ParticipantMismatch,
+}
+
+/// An incomplete multipart message.
+#[derive(Debug)]
+struct PartialMessage {
+    /// The data of the chunks received so far, ordered by chunk ID.
+    chunks: BTreeMap<u16, Vec<u8>>,
+    /// The ID of the last chunk, once it has been received.
+    last_id: Option<u16>,
+    /// The total size in bytes of the chunks received so far.
+    size: usize,
+    /// The time at which the latest chunk was received.
+    updated: Instant,
+}
+
+impl PartialMessage {
+    fn new() -> Self {
+        Self {
+            chunks: BTreeMap::new(),
+            last_id: None,
+            size: 0,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Checks whether the chunk `id` is consistent with the chunks
+    /// received so far.
+    fn check_chunk_id(&self, id: u16, last: bool) -> Result<(), MultipartError> {
+        let beyond_last_id = self.last_id.map_or(false, |last_id| id > last_id);
+        let before_received_ids =
+            last && matches!(self.chunks.keys().next_back(), Some(max_id) if *max_id > id);
+        if beyond_last_id || before_received_ids {
+            Err(MultipartError::InvalidChunkId(id))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks whether all the chunks of the message have been received.
+    fn is_complete(&self) -> bool {
+        self.last_id
+            .map_or(false, |last_id| self.chunks.len() == last_id as usize + 1)
+    }
+
+    /// Concatenates the data of all the chunks.
+    fn into_data(self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.size);
+        for chunk in self.chunks.into_iter().map(|(_, chunk)| chunk) {
+            data.extend(chunk);
+        }
+        data
+    }
+}
+
+/// The buffer that holds the incomplete multipart messages of the
+/// current phase.
+#[derive(Debug)]
+struct MultipartBuffer {
+    /// The phase in which the buffered chunks have been received.
+    phase: Event<PhaseName>,
+    /// The incomplete messages, identified by the participant who
+    /// sends them and their message ID.
+    messages: HashMap<(ParticipantPublicKey, u16), PartialMessage>,
+    /// The total size in bytes of the buffered chunks.
+    size: usize,
+}
+
+impl MultipartBuffer {
+    fn new(phase: Event<PhaseName>) -> Self {
+        Self {
+            phase,
+            messages: HashMap::new(),
+            size: 0,
+        }
+    }
+
+    /// Discards all the messages if the coordinator moved to another
+    /// phase and the messages that have not received any chunk for
+    /// longer than `timeout`.
+    fn evict(&mut self, phase: Event<PhaseName>, timeout: Duration) {
+        if self.phase != phase {
+            if !self.messages.is_empty() {
+                info!(
+                    "discarding {} incomplete multipart message(s) of the previous phase",
+                    self.messages.len()
+                );
+            }
+            *self = Self::new(phase);
+            return;
+        }
+
+        let now = Instant::now();
+        let size = &mut self.size;
+        self.messages.retain(|_, message| {
+            let expired = now.duration_since(message.updated) >= timeout;
+            if expired {
+                debug!("discarding an expired incomplete multipart message");
+                *size -= message.size;
+            }
+            !expired
+        });
+    }
+
+    /// Removes the message identified by `key` from the buffer.
+    fn remove(&mut self, key: &(ParticipantPublicKey, u16)) -> Option<PartialMessage> {
+        let message = self.messages.remove(key)?;
+        self.size -= message.size;
+        Some(message)
+    }
+
+    /// Adds a chunk to the buffer. If the chunk completes its message,
+    /// the message is removed from the buffer and its data is returned.
+    fn add(
+        &mut self,
+        participant_pk: ParticipantPublicKey,
+        chunk: Chunk,
+        settings: &MultipartSettings,
+    ) -> Result<Option<Vec<u8>>, MultipartError> {
+        let Chunk {
+            id,
+            message_id,
+            last,
+            data,
+        } = chunk;
+        let key = (participant_pk, message_id);
+
+        let (message_size, replaced_size) = match self.messages.get(&key) {
+            Some(message) => {
+                if let Err(e) = message.check_chunk_id(id, last) {
+                    self.remove(&key);
+                    return Err(e);
+                }
+                // a chunk that has been received already replaces the
+                // previous one, which makes retries idempotent
+                let replaced_size = message.chunks.get(&id).map_or(0, Vec::len);
+                (message.size - replaced_size + data.len(), replaced_size)
+            }
+            None => (data.len(), 0),
+        };
+
+        if message_size > settings.max_message_size {
+            self.remove(&key);
+            return Err(MultipartError::MessageTooLarge);
+        }
+        if self.size - replaced_size + data.len() > settings.max_buffer_size {
+            return Err(MultipartError::BufferFull);
+        }
+
+        self.size = self.size - replaced_size + data.len();
+        let message = self.messages.entry(key).or_insert_with(PartialMessage::new);
+        message.chunks.insert(id, data);
+        message.size = message_size;
+        message.updated = Instant::now();
+        if last {
+            message.last_id = Some(id);
+        }
+
+        if message.is_complete() {
+            // Unwrapping is fine because the message is in the buffer
+            Ok(Some(self.remove(&key).unwrap().into_data()))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// A service that buffers the chunks of multipart messages and
+/// reassembles them.
+///
+/// The service responds with the data of the complete message once
+/// all its chunks have been received, and with `None` otherwise. The
+/// memory used by incomplete messages is bounded by the
+/// [`MultipartSettings`]. Incomplete messages are discarded when they
+/// time out or when the coordinator moves to another phase.
+#[derive(Debug, Clone)]
+pub struct MultipartHandler {
+    /// A listener to retrieve the current phase
+    phase: EventListener<PhaseName>,
+    /// The incomplete messages
+    buffer: Arc<Mutex<MultipartBuffer>>,
+    /// The limits for buffering the messages
+    settings: MultipartSettings,
+}
+
+impl MultipartHandler {
+    pub fn new(events: &EventSubscriber, settings: MultipartSettings) -> Self {
+        let phase = events.phase_listener();
+        let buffer = Arc::new(Mutex::new(MultipartBuffer::new(phase.get_latest())));
+        Self {
+            phase,
+            buffer,
+            settings,
+        }
+    }
+}
+
+impl Service<Message> for MultipartHandler {
+    type Response = Option<Vec<u8>>;
+    type Error = ServiceError;
+    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, message: Message) -> Self::Future {
+        let chunk = match message.payload {
+            Payload::Chunk(chunk) => chunk,
+            _ => return future::ready(Err(ServiceError::UnexpectedMessage)),
+        };
+
+        let mut buffer = match self.buffer.lock() {
+            Ok(buffer) => buffer,
+            Err(_) => {
+                return future::ready(Err(ServiceError::InternalError(
+                    "the multipart buffer is poisoned".to_string(),
+                )))
+            }
+        };
+        buffer.evict(
+            self.phase.get_latest(),
+            Duration::from_secs(self.settings.timeout),
+        );
+        let res = buffer
+            .add(message.participant_pk, chunk, &self.settings)
+            .map_err(ServiceError::Multipart);
+        future::ready(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_test::assert_ready;
+    use tower_test::mock::Spawn;
+    use xaynet_core::crypto::{ByteObject, PublicSigningKey};
+
+    use crate::{services::tests::utils, state_machine::events::EventPublisher};
+
+    use super::*;
+
+    fn settings() -> MultipartSettings {
+        MultipartSettings {
+            max_message_size: 10,
+            max_buffer_size: 15,
+            timeout: 60,
+        }
+    }
+
+    fn spawn_svc(settings: MultipartSettings) -> (EventPublisher, Spawn<MultipartHandler>) {
+        let (mut publisher, subscriber) = utils::new_event_channels();
+        publisher.broadcast_phase(PhaseName::Update);
+        let task = Spawn::new(MultipartHandler::new(&subscriber, settings));
+        (publisher, task)
+    }
+
+    fn chunk_message(
+        participant: u8,
+        message_id: u16,
+        id: u16,
+        last: bool,
+        data: &[u8],
+    ) -> Message {
+        Message {
+            signature: None,
+            participant_pk: PublicSigningKey::fill_with(participant),
+            coordinator_pk: ByteObject::zeroed(),
+            payload: Chunk {
+                id,
+                message_id,
+                last,
+                data: data.to_vec(),
+            }
+            .into(),
+        }
+    }
+
+    async fn call(
+        task: &mut Spawn<MultipartHandler>,
+        message: Message,
+    ) -> Result<Option<Vec<u8>>, ServiceError> {
+        assert_ready!(task.poll_ready()).unwrap();
+        task.call(message).await
+    }
+
+    #[tokio::test]
+    async fn test_reassemble_out_of_order() {
+        let (_publisher, mut task) = spawn_svc(settings());
+
+        let resp = call(&mut task, chunk_message(1, 7, 2, true, &[5, 6])).await;
+        assert_eq!(resp.unwrap(), None);
+        let resp = call(&mut task, chunk_message(1, 7, 0, false, &[1, 2])).await;
+        assert_eq!(resp.unwrap(), None);
+        // chunks of other messages are buffered separately
+        let resp = call(&mut task, chunk_message(2, 7, 1, false, &[9])).await;
+        assert_eq!(resp.unwrap(), None);
+        let resp = call(&mut task, chunk_message(1, 8, 1, false, &[9])).await;
+        assert_eq!(resp.unwrap(), None);
+        // a resent chunk replaces the previous one
+        let resp = call(&mut task, chunk_message(1, 7, 0, false, &[1, 2])).await;
+        assert_eq!(resp.unwrap(), None);
+        let resp = call(&mut task, chunk_message(1, 7, 1, false, &[3, 4])).await;
+        assert_eq!(resp.unwrap(), Some(vec![1, 2, 3, 4, 5, 6]));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_chunk_id() {
+        let (_publisher, mut task) = spawn_svc(settings());
+
+        let resp = call(&mut task, chunk_message(1, 7, 1, true, &[1])).await;
+        assert_eq!(resp.unwrap(), None);
+        let err = call(&mut task, chunk_message(1, 7, 2, false, &[2]))
+            .await
+            .unwrap_err();
+        match err {
+            ServiceError::Multipart(MultipartError::InvalidChunkId(2)) => {}
+            _ => panic!("expected MultipartError::InvalidChunkId got {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let (_publisher, mut task) = spawn_svc(settings());
+
+        let resp = call(&mut task, chunk_message(1, 7, 0, false, &[0; 8])).await;
+        assert_eq!(resp.unwrap(), None);
+        let err = call(&mut task, chunk_message(1, 7, 1, false, &[0; 3]))
+            .await
+            .unwrap_err();
+        match err {
+            ServiceError::Multipart(MultipartError::MessageTooLarge) => {}
+            _ => panic!("expected MultipartError::MessageTooLarge got {:?}", err),
+        }
+
+        // the message that is too large has been discarded
+        let resp = call(&mut task, chunk_message(2, 7, 0, false, &[0; 10])).await;
+        assert_eq!(resp.unwrap(), None);
+        let err = call(&mut task, chunk_message(3, 7, 0, false, &[0; 6]))
+            .await
+            .unwrap_err();
+        match err {
+            ServiceError::Multipart(MultipartError::BufferFull) => {}
+            _ => panic!("expected MultipartError::BufferFull got {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evict_on_phase_change() {
+        let (mut publisher, mut task) = spawn_svc(settings());
+
+        let resp = call(&mut task, chunk_message(1, 7, 0, false, &[1])).await;
+        assert_eq!(resp.unwrap(), None);
+        publisher.broadcast_phase(PhaseName::Sum2);
+        let resp = call(&mut task, chunk_message(1, 7, 1, true, &[2])).await;
+        assert_eq!(resp.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_evict_on_timeout() {
+        let settings = MultipartSettings {
+            timeout: 0,
+            ..settings()
+        };
+        let (_publisher, mut task) = spawn_svc(settings);
+
+        let resp = call(&mut task, chunk_message(1, 7, 0, false, &[1])).await;
+        assert_eq!(resp.unwrap(), None);
+        let resp = call(&mut task, chunk_message(1, 7, 1, true, &[2])).await;
+        assert_eq!(resp.unwrap(), None);
+    }
+}
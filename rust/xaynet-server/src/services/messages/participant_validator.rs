@@ -0,0 +1,88 @@
+use std::task::Poll;
+
+use futures::{future::BoxFuture, task::Context};
+use tower::Service;
+use xaynet_core::message::Message;
+
+use crate::{services::messages::ServiceError, storage::registry::ParticipantRegistry};
+
+/// A service for rejecting the messages of participants which are not
+/// in the participant registry.
+#[derive(Clone, Debug)]
+pub struct ParticipantValidator {
+    registry: ParticipantRegistry,
+}
+
+impl ParticipantValidator {
+    pub fn new(registry: ParticipantRegistry) -> Self {
+        Self { registry }
+    }
+}
+
+impl Service<Message> for ParticipantValidator {
+    type Response = Message;
+    type Error = ServiceError;
+    // the future is not `Sync`, because it may hold a Redis connection
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, message: Message) -> Self::Future {
+        let registry = self.registry.clone();
+        Box::pin(async move {
+            match registry.is_allowed(&message.participant_pk).await {
+                Ok(true) => Ok(message),
+                Ok(false) => Err(ServiceError::UnknownParticipant),
+                Err(e) => Err(ServiceError::InternalError(e.to_string())),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use tokio_test::assert_ready;
+    use tower_test::mock::Spawn;
+
+    use crate::services::tests::utils;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_disabled_registry() {
+        let mut task = Spawn::new(ParticipantValidator::new(ParticipantRegistry::Disabled));
+        let (_, subscriber) = utils::new_event_channels();
+        let round_params = subscriber.params_listener().get_latest().event;
+        let (message, _) = utils::new_sum_message(&round_params);
+
+        assert_ready!(task.poll_ready()).unwrap();
+        let resp = task.call(message.clone()).await.unwrap();
+        assert_eq!(resp, message);
+    }
+
+    #[tokio::test]
+    async fn test_file_registry() {
+        let (_, subscriber) = utils::new_event_channels();
+        let round_params = subscriber.params_listener().get_latest().event;
+        let (known_message, _) = utils::new_sum_message(&round_params);
+        let (unknown_message, _) = utils::new_sum_message(&round_params);
+        let participants: HashSet<_> = vec![known_message.participant_pk].into_iter().collect();
+        let registry = ParticipantRegistry::File(Arc::new(participants));
+        let mut task = Spawn::new(ParticipantValidator::new(registry));
+
+        assert_ready!(task.poll_ready()).unwrap();
+        let resp = task.call(known_message.clone()).await.unwrap();
+        assert_eq!(resp, known_message);
+
+        assert_ready!(task.poll_ready()).unwrap();
+        let err = task.call(unknown_message).await.unwrap_err();
+        match err {
+            ServiceError::UnknownParticipant => {}
+            _ => panic!("expected ServiceError::UnknownParticipant got {:?}", err),
+        }
+    }
+}
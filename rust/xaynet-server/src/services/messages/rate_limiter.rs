@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::Instant,
+};
+
+use futures::{future, task::Context};
+use tower::Service;
+
+use crate::{services::messages::ServiceError, settings::RateLimit};
+
+/// The minimal number of buckets from which on full buckets are
+/// dropped.
+const MIN_CLEANUP_LEN: usize = 1024;
+
+/// A service for limiting the rate of the requests per key, e.g. per
+/// client IP address or per participant public key.
+///
+/// Each key has a token bucket according to the [`RateLimit`]. A
+/// request takes a token from the bucket of its key and is rejected if
+/// the bucket is empty. Without a rate limit, all requests pass.
+#[derive(Clone, Debug)]
+pub struct RateLimiter<K> {
+    limit: Option<RateLimit>,
+    buckets: Arc<Mutex<Buckets<K>>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new(limit: Option<RateLimit>) -> Self {
+        Self {
+            limit,
+            buckets: Arc::new(Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                cleanup_len: MIN_CLEANUP_LEN,
+            })),
+        }
+    }
+
+    /// Takes a token from the bucket of the key at the given time.
+    /// Returns `false` if the bucket is empty.
+    fn try_acquire(&self, key: K, now: Instant) -> bool {
+        match self.limit {
+            Some(limit) => self.buckets.lock().unwrap().try_acquire(key, now, limit),
+            None => true,
+        }
+    }
+}
+
+impl<K: Eq + Hash> Service<K> for RateLimiter<K> {
+    type Response = ();
+    type Error = ServiceError;
+    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, key: K) -> Self::Future {
+        if self.try_acquire(key, Instant::now()) {
+            future::ready(Ok(()))
+        } else {
+            future::ready(Err(ServiceError::RateLimited))
+        }
+    }
+}
+
+/// The token buckets of the keys.
+#[derive(Debug)]
+struct Buckets<K> {
+    buckets: HashMap<K, Bucket>,
+    /// The number of buckets from which on full buckets are dropped.
+    cleanup_len: usize,
+}
+
+impl<K: Eq + Hash> Buckets<K> {
+    fn try_acquire(&mut self, key: K, now: Instant, limit: RateLimit) -> bool {
+        if self.buckets.len() >= self.cleanup_len {
+            // a full bucket behaves like a new one, hence it can be
+            // dropped without affecting the rate limit
+            let burst = f64::from(limit.burst);
+            self.buckets
+                .retain(|_, bucket| bucket.refill(now, limit) < burst);
+            self.cleanup_len = (2 * self.buckets.len()).max(MIN_CLEANUP_LEN);
+        }
+
+        let bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens: f64::from(limit.burst),
+            refilled_at: now,
+        });
+        if bucket.refill(now, limit) >= 1. {
+            bucket.tokens -= 1.;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A token bucket.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    /// Refills the tokens which accrued since the last refill and
+    /// returns the number of tokens.
+    fn refill(&mut self, now: Instant, limit: RateLimit) -> f64 {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * limit.rate).min(f64::from(limit.burst));
+        self.refilled_at = now;
+        self.tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_test::assert_ready;
+    use tower_test::mock::Spawn;
+
+    use super::*;
+
+    fn limit() -> Option<RateLimit> {
+        Some(RateLimit {
+            burst: 2,
+            rate: 0.5,
+        })
+    }
+
+    #[test]
+    fn test_burst_and_refill() {
+        let limiter = RateLimiter::new(limit());
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire(1, start));
+        assert!(limiter.try_acquire(1, start));
+        assert!(!limiter.try_acquire(1, start));
+        // the other keys have their own buckets
+        assert!(limiter.try_acquire(2, start));
+
+        // one token is refilled every two seconds
+        assert!(!limiter.try_acquire(1, start + Duration::from_secs(1)));
+        assert!(limiter.try_acquire(1, start + Duration::from_secs(2)));
+        assert!(!limiter.try_acquire(1, start + Duration::from_secs(2)));
+
+        // the bucket holds at most `burst` tokens
+        let later = start + Duration::from_secs(60);
+        assert!(limiter.try_acquire(1, later));
+        assert!(limiter.try_acquire(1, later));
+        assert!(!limiter.try_acquire(1, later));
+    }
+
+    #[test]
+    fn test_cleanup() {
+        let limiter = RateLimiter::new(limit());
+        let start = Instant::now();
+        assert!(limiter.try_acquire(0, start));
+        for key in 0..MIN_CLEANUP_LEN {
+            assert!(limiter.try_acquire(key, start));
+        }
+
+        // the buckets of all keys but the first one are full again and
+        // get dropped once a new key exceeds the cleanup length
+        let later = start + Duration::from_secs(2);
+        assert!(limiter.try_acquire(MIN_CLEANUP_LEN, later));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.buckets.len(), 2);
+        assert!((buckets.buckets[&0].tokens - 1.).abs() < 1e-9);
+        assert_eq!(buckets.cleanup_len, MIN_CLEANUP_LEN);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited() {
+        let mut task = Spawn::new(RateLimiter::new(limit()));
+        for _ in 0..2 {
+            assert_ready!(task.poll_ready()).unwrap();
+            task.call("participant").await.unwrap();
+        }
+
+        assert_ready!(task.poll_ready()).unwrap();
+        let err = task.call("participant").await.unwrap_err();
+        match err {
+            ServiceError::RateLimited => {}
+            _ => panic!("expected ServiceError::RateLimited got {:?}", err),
+        }
+
+        let mut task = Spawn::new(RateLimiter::new(None));
+        for _ in 0..10 {
+            assert_ready!(task.poll_ready()).unwrap();
+            task.call("participant").await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use xaynet_core::crypto::Signature;
+
+use crate::{
+    services::messages::ServiceError,
+    state_machine::{
+        events::{EventListener, EventSubscriber},
+        phases::PhaseName,
+    },
+};
+
+/// A per-round cache of the signatures of the messages which are
+/// handed over to the state machine, for rejecting exact replays of
+/// messages.
+///
+/// The cache is cleared at the beginning of each round.
+#[derive(Clone, Debug)]
+pub struct ReplayGuard {
+    phase_listener: EventListener<PhaseName>,
+    signatures: Arc<Mutex<RoundSignatures>>,
+}
+
+/// The signatures of a round.
+#[derive(Debug, Default)]
+struct RoundSignatures {
+    round_id: u64,
+    signatures: HashSet<Signature>,
+}
+
+impl ReplayGuard {
+    pub fn new(subscriber: &EventSubscriber) -> Self {
+        let phase_listener = subscriber.phase_listener();
+        let round_id = phase_listener.get_latest().round_id;
+        Self {
+            phase_listener,
+            signatures: Arc::new(Mutex::new(RoundSignatures {
+                round_id,
+                signatures: HashSet::new(),
+            })),
+        }
+    }
+
+    /// Records the signature of a message in the current round.
+    ///
+    /// # Errors
+    /// Fails with [`ServiceError::Replay`] if the signature has already
+    /// been recorded in the current round.
+    pub fn insert(&self, signature: Signature) -> Result<(), ServiceError> {
+        let round_id = self.phase_listener.get_latest().round_id;
+        let mut signatures = self.signatures.lock().unwrap();
+        if signatures.round_id != round_id {
+            signatures.round_id = round_id;
+            signatures.signatures.clear();
+        }
+        if signatures.signatures.insert(signature) {
+            Ok(())
+        } else {
+            Err(ServiceError::Replay)
+        }
+    }
+
+    /// Forgets the signature of a message, e.g. because the message
+    /// failed to be processed and may be sent again.
+    pub fn remove(&self, signature: &Signature) {
+        self.signatures.lock().unwrap().signatures.remove(signature);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xaynet_core::crypto::SigningKeyPair;
+
+    use crate::services::tests::utils;
+
+    use super::*;
+
+    #[test]
+    fn test_replay_guard() {
+        let (mut publisher, subscriber) = utils::new_event_channels();
+        let guard = ReplayGuard::new(&subscriber);
+        let signature = SigningKeyPair::generate().secret.sign_detached(b"message");
+
+        guard.insert(signature).unwrap();
+        assert!(matches!(guard.insert(signature), Err(ServiceError::Replay)));
+
+        guard.remove(&signature);
+        guard.insert(signature).unwrap();
+
+        // the signatures are forgotten in the next round
+        publisher.set_round_id(1);
+        publisher.broadcast_phase(PhaseName::Sum);
+        guard.insert(signature).unwrap();
+        assert!(matches!(guard.insert(signature), Err(ServiceError::Replay)));
+    }
+}
@@ -10,6 +10,8 @@
 //!   - [`SumDictService`]: for fetching the sum dictionary
 //! - the services for handling PET messages from the participant:
 //!   - [`MessageParserService`]: decrypt and parses incoming message
+//!   - [`MultipartHandler`]: reassembles messages that have been split
+//!     into chunks
 //!   - [`TaskValidator`]: performs sanity checks on the messages
 //!     (verify the task signatures, etc.)
 //!   - [`StateMachineService`]: pass the messages down to the state machine
@@ -23,4 +25,4 @@ pub mod fetchers;
 pub mod messages;
 
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
@@ -3,8 +3,8 @@ use std::{collections::HashMap, sync::Arc};
 use tokio_test::assert_ready;
 use tower_test::mock::Spawn;
 use xaynet_core::{
-    common::{RoundParameters, RoundSeed},
-    crypto::{ByteObject, PublicEncryptKey, PublicSigningKey},
+    common::{RoundParameters, RoundSeed, SignedRoundParameters},
+    crypto::{ByteObject, PublicEncryptKey, PublicSigningKey, SigningKeyPair},
     mask::{EncryptedMaskSeed, Model},
     SeedDict,
     SumDict,
@@ -78,22 +78,48 @@ async fn test_round_params_svc() {
     let (mut publisher, subscriber) = new_event_channels();
     let initial_params = subscriber.params_listener().get_latest().event;
 
-    let mut task = Spawn::new(RoundParamsService::new(&subscriber));
+    let mut task = Spawn::new(RoundParamsService::new(&subscriber, None));
     assert_ready!(task.poll_ready()).unwrap();
 
     let resp = task.call(RoundParamsRequest).await;
-    assert_eq!(resp, Ok(initial_params));
+    assert_eq!(
+        resp,
+        Ok(SignedRoundParameters {
+            params: initial_params.clone(),
+            signature: None,
+        })
+    );
 
     let params = RoundParameters {
         pk: PublicEncryptKey::fill_with(0x11),
         sum: 0.42,
         update: 0.42,
         seed: RoundSeed::fill_with(0x11),
+        mask_config: initial_params.mask_config,
     };
     publisher.broadcast_params(params.clone());
     assert_ready!(task.poll_ready()).unwrap();
     let resp = task.call(RoundParamsRequest).await;
-    assert_eq!(resp, Ok(params));
+    assert_eq!(
+        resp,
+        Ok(SignedRoundParameters {
+            params,
+            signature: None,
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_signed_round_params_svc() {
+    let (_, subscriber) = new_event_channels();
+    let params = subscriber.params_listener().get_latest().event;
+    let keys = SigningKeyPair::generate();
+
+    let mut task = Spawn::new(RoundParamsService::new(&subscriber, Some(keys.secret)));
+    assert_ready!(task.poll_ready()).unwrap();
+    let resp = task.call(RoundParamsRequest).await.unwrap();
+    assert_eq!(resp.params, params);
+    assert!(resp.verify(&keys.public));
 }
 
 fn dummy_seed_dict() -> SeedDict {
@@ -4,9 +4,16 @@ use xaynet_core::{
     message::{Message, Sum},
 };
 
-use crate::state_machine::{
-    events::{EventPublisher, EventSubscriber},
-    phases::PhaseName,
+use crate::{
+    services::messages::PetMessageHandler,
+    settings::{MultipartSettings, RateLimitSettings, ServiceLimits, ServicesSettings},
+    state_machine::{
+        events::{EventPublisher, EventSubscriber},
+        phases::PhaseName,
+        requests::RequestSender,
+        tests::utils::mask_settings,
+    },
+    storage::registry::ParticipantRegistry,
 };
 
 /// Create an [`EventPublisher`]/[`EventSubscriber`] pair with default
@@ -19,6 +26,7 @@ pub fn new_event_channels() -> (EventPublisher, EventSubscriber) {
         sum: 0.0,
         update: 0.0,
         seed: RoundSeed::generate(),
+        mask_config: mask_settings().into(),
     };
     let phase = PhaseName::Idle;
     let round_id = 0;
@@ -62,3 +70,47 @@ pub fn serialize_message(message: &Message, participant_signing_keys: &SigningKe
     message.to_bytes(&mut buf, &participant_signing_keys.secret);
     buf
 }
+
+/// Create the settings of the PET message services with limits that
+/// are not reached in the tests.
+pub fn services_settings() -> ServicesSettings {
+    let limits = ServiceLimits {
+        concurrency_limit: 100,
+        queue_limit: 100,
+    };
+    ServicesSettings {
+        threads: 2,
+        thread_name_prefix: "xaynet-services-test".to_string(),
+        thread_stack_size: None,
+        decryptor: limits,
+        message_parser: limits,
+        multipart_handler: limits,
+        participant_validator: limits,
+        task_validator: limits,
+        state_machine: limits,
+    }
+}
+
+/// Create a [`PetMessageHandler`] which hands the messages over to the
+/// state machine via `requests_tx`. It accepts all participants and
+/// messages up to 1 MB.
+pub fn new_message_handler(
+    subscriber: &EventSubscriber,
+    requests_tx: RequestSender,
+    rate_limit_settings: RateLimitSettings,
+) -> PetMessageHandler {
+    let multipart_settings = MultipartSettings {
+        max_message_size: 1_048_576,
+        max_buffer_size: 1_048_576,
+        timeout: 300,
+    };
+    PetMessageHandler::new(
+        subscriber,
+        requests_tx,
+        ParticipantRegistry::Disabled,
+        multipart_settings,
+        rate_limit_settings,
+        &services_settings(),
+    )
+    .unwrap()
+}
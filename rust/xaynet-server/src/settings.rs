@@ -3,16 +3,29 @@
 //! Values defined in the configuration file can be overridden by environment variables. Examples of
 //! configuration files can be found in the `configs/` directory located in the repository root.
 
-use std::{fmt, path::PathBuf};
+use std::{convert::TryInto, fmt, fs, io, path::PathBuf};
 
 use config::{Config, ConfigError, Environment};
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
 use redis::{ConnectionInfo, IntoConnectionInfo};
 use serde::de::{self, Deserializer, Visitor};
 use thiserror::Error;
 use tracing_subscriber::filter::EnvFilter;
 use validator::{Validate, ValidationError, ValidationErrors};
 
-use xaynet_core::mask::{BoundType, DataType, GroupType, MaskConfig, ModelType};
+use xaynet_core::{
+    crypto::{ByteObject, SigningKeyPair, SigningKeySeed},
+    mask::{
+        BoundType,
+        DataType,
+        FromPrimitives,
+        GroupType,
+        MaskConfig,
+        MaskConfigPair,
+        Model,
+        ModelType,
+    },
+};
 
 #[derive(Error, Debug)]
 /// An error related to loading and validation of settings.
@@ -21,6 +34,8 @@ pub enum SettingsError {
     Loading(#[from] ConfigError),
     #[error("validation failed: {0}")]
     Validation(#[from] ValidationErrors),
+    #[error("failed to build the thread pool: {0}")]
+    ThreadPool(#[from] ThreadPoolBuildError),
 }
 
 #[derive(Debug, Validate, Deserialize)]
@@ -32,12 +47,24 @@ pub struct Settings {
     pub api: ApiSettings,
     #[validate]
     pub pet: PetSettings,
+    #[validate]
     pub mask: MaskSettings,
     pub log: LoggingSettings,
     pub model: ModelSettings,
     #[validate]
     pub metrics: MetricsSettings,
+    #[validate]
     pub redis: RedisSettings,
+    #[validate]
+    pub multipart: MultipartSettings,
+    #[validate]
+    pub services: ServicesSettings,
+    #[serde(default)]
+    pub shutdown: ShutdownSettings,
+    pub registry: Option<RegistrySettings>,
+    #[validate]
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
 }
 
 impl Settings {
@@ -59,9 +86,12 @@ impl Settings {
     }
 }
 
-#[derive(Debug, Validate, Deserialize, Clone, Copy)]
+#[derive(Debug, Validate, Deserialize, Clone, Copy, PartialEq)]
 #[validate(schema(function = "validate_pet"))]
 /// PET protocol settings.
+///
+/// The PET settings can be updated at runtime by sending them as JSON to the `PUT /admin/settings`
+/// endpoint of the admin API. They are validated and take effect from the next round.
 pub struct PetSettings {
     #[validate(range(min = 1))]
     /// The minimal number of participants selected for computing the unmasking sum. The value must
@@ -195,6 +225,25 @@ pub struct PetSettings {
     /// ```
     pub max_update_time: u64,
 
+    /// The minimum amount of time the coordinator stays in the `idle` phase, in seconds.
+    ///
+    /// Defaults to 0 i.e. a new round starts right after the previous one. Set this higher to
+    /// give participants more time between two rounds.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [pet]
+    /// min_idle_time = 10
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_PET__MIN_IDLE_TIME=10
+    /// ```
+    pub min_idle_time: u64,
+
     /// The expected fraction of participants selected for computing the unmasking sum. The value
     /// must be between `0` and `1` (i.e. `0 < sum < 1`).
     ///
@@ -234,6 +283,220 @@ pub struct PetSettings {
     /// XAYNET_PET__UPDATE=0.01
     /// ```
     pub update: f64,
+
+    /// The policy which recomputes the [`PetSettings::sum`] and [`PetSettings::update`]
+    /// fractions at the start of each round.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [pet.fractions_policy]
+    /// policy = "Fixed"
+    /// # or
+    /// policy = "Target"
+    /// sum_count = 10
+    /// update_count = 100
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_PET__FRACTIONS_POLICY__POLICY=Target
+    /// XAYNET_PET__FRACTIONS_POLICY__SUM_COUNT=10
+    /// XAYNET_PET__FRACTIONS_POLICY__UPDATE_COUNT=100
+    /// ```
+    pub fractions_policy: FractionsPolicy,
+
+    #[serde(default)]
+    /// The quorum which the masks of the sum participants must reach to unmask the global model.
+    ///
+    /// Defaults to a simple plurality of the votes without a retry of the `sum2` phase.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [pet.mask_quorum]
+    /// min_share = 0.6
+    /// majority = true
+    /// retry_sum2 = true
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_PET__MASK_QUORUM__MIN_SHARE=0.6
+    /// XAYNET_PET__MASK_QUORUM__MAJORITY=true
+    /// XAYNET_PET__MASK_QUORUM__RETRY_SUM2=true
+    /// ```
+    pub mask_quorum: MaskQuorum,
+
+    #[serde(default)]
+    /// The policy which restarts failed rounds.
+    ///
+    /// Defaults to a backoff from `1` to `60` seconds between the rounds without a limit on the
+    /// number of consecutive failed rounds.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [pet.error_policy]
+    /// min_backoff = 1
+    /// max_backoff = 60
+    /// max_failed_rounds = 10
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_PET__ERROR_POLICY__MIN_BACKOFF=1
+    /// XAYNET_PET__ERROR_POLICY__MAX_BACKOFF=60
+    /// XAYNET_PET__ERROR_POLICY__MAX_FAILED_ROUNDS=10
+    /// ```
+    pub error_policy: ErrorPolicy,
+
+    #[serde(default)]
+    /// The sanity checks of the unmasked global model, which is discarded if it fails them.
+    ///
+    /// Defaults to no checks.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [pet.sanity_checks]
+    /// min_scalar = 0.001
+    /// max_scalar = 1.0
+    /// max_weight = 1.0
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_PET__SANITY_CHECKS__MIN_SCALAR=0.001
+    /// XAYNET_PET__SANITY_CHECKS__MAX_SCALAR=1.0
+    /// XAYNET_PET__SANITY_CHECKS__MAX_WEIGHT=1.0
+    /// ```
+    pub sanity_checks: SanityChecks,
+}
+
+/// A policy which recomputes the `sum` and `update` fractions of a round.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "policy")]
+pub enum FractionsPolicy {
+    /// Keeps the configured fractions for all rounds.
+    Fixed,
+    /// Adapts the fractions to the participation in the previous round, such that the expected
+    /// numbers of accepted sum and update messages approach the targets. The fractions change
+    /// by at most a factor of `2` per round and a fraction stays the same if no message of its
+    /// kind has been accepted in the previous round. The targets must not be less than
+    /// [`PetSettings::min_sum_count`] and [`PetSettings::min_update_count`] respectively.
+    Target {
+        /// The targeted number of sum participants.
+        sum_count: usize,
+        /// The targeted number of update participants.
+        update_count: usize,
+    },
+}
+
+/// A quorum for the masks submitted by the sum participants in the `sum2` phase.
+///
+/// The mask with the most votes is used for unmasking, if it is the unique mask with the most
+/// votes and it reaches the quorum.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct MaskQuorum {
+    /// The minimal share of the votes the winning mask needs. The value must be between `0` and
+    /// `1` (i.e. `0 <= min_share <= 1`).
+    #[serde(default)]
+    pub min_share: f64,
+    /// Whether the winning mask needs more than half of the votes.
+    #[serde(default)]
+    pub majority: bool,
+    /// Whether the `sum2` phase is retried once if no mask reaches the quorum. The retry waits
+    /// for the sum participants which haven't submitted their masks yet, but at most for
+    /// [`PetSettings::max_sum_time`].
+    #[serde(default)]
+    pub retry_sum2: bool,
+}
+
+impl Default for MaskQuorum {
+    fn default() -> Self {
+        Self {
+            min_share: 0_f64,
+            majority: false,
+            retry_sum2: false,
+        }
+    }
+}
+
+/// A policy which restarts failed rounds.
+///
+/// Retryable errors restart the round after a backoff, which doubles with each consecutive failed
+/// round. Fatal errors and too many consecutive failed rounds shut the coordinator down.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorPolicy {
+    /// The backoff after the first failed round, in seconds.
+    #[serde(default = "default_min_backoff")]
+    pub min_backoff: u64,
+    /// The maximum backoff, in seconds. The value must not be less than the minimum backoff.
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: u64,
+    /// The maximum number of consecutive failed rounds before the coordinator shuts down. The
+    /// value `0` means that there is no limit.
+    #[serde(default)]
+    pub max_failed_rounds: u32,
+}
+
+impl ErrorPolicy {
+    /// Gets the backoff after the given number of consecutive failed rounds.
+    pub fn backoff(&self, failed_rounds: u32) -> u64 {
+        let exponent = failed_rounds.saturating_sub(1).min(63);
+        self.min_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff)
+    }
+
+    /// Checks whether the given number of consecutive failed rounds exceeds the limit.
+    pub fn is_exceeded(&self, failed_rounds: u32) -> bool {
+        self.max_failed_rounds != 0 && failed_rounds >= self.max_failed_rounds
+    }
+}
+
+/// Sanity checks of the unmasked global model before it is published.
+///
+/// The masked scalars and local models of the update participants can't be inspected
+/// individually, hence the checks apply to their aggregation. A check is disabled if its
+/// threshold is missing.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+pub struct SanityChecks {
+    /// The minimum of the unmasked scalar per aggregated model, i.e. the mean scalar of the
+    /// update participants.
+    #[serde(default)]
+    pub min_scalar: Option<f64>,
+    /// The maximum of the unmasked scalar per aggregated model, i.e. the mean scalar of the
+    /// update participants.
+    #[serde(default)]
+    pub max_scalar: Option<f64>,
+    /// The maximum absolute value of the weights of the global model. The value must not be
+    /// negative.
+    #[serde(default)]
+    pub max_weight: Option<f64>,
+}
+
+fn default_min_backoff() -> u64 {
+    1
+}
+
+fn default_max_backoff() -> u64 {
+    60
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        Self {
+            min_backoff: default_min_backoff(),
+            max_backoff: default_max_backoff(),
+            max_failed_rounds: 0,
+        }
+    }
 }
 
 impl Default for PetSettings {
@@ -245,8 +508,13 @@ impl Default for PetSettings {
             min_update_time: 0_u64,
             max_sum_time: 604800_u64,
             max_update_time: 604800_u64,
+            min_idle_time: 0_u64,
             sum: 0.01_f64,
             update: 0.1_f64,
+            fractions_policy: FractionsPolicy::Fixed,
+            mask_quorum: MaskQuorum::default(),
+            error_policy: ErrorPolicy::default(),
+            sanity_checks: SanityChecks::default(),
         }
     }
 }
@@ -254,7 +522,11 @@ impl Default for PetSettings {
 /// Checks PET settings.
 fn validate_pet(s: &PetSettings) -> Result<(), ValidationError> {
     validate_phase_times(s)?;
-    validate_fractions(s)
+    validate_fractions(s.sum, s.update)?;
+    validate_fractions_policy(s)?;
+    validate_mask_quorum(s)?;
+    validate_error_policy(s)?;
+    validate_sanity_checks(s)
 }
 
 /// Checks validity of phase time ranges.
@@ -267,13 +539,13 @@ fn validate_phase_times(s: &PetSettings) -> Result<(), ValidationError> {
 }
 
 /// Checks pathological cases of deadlocks.
-fn validate_fractions(s: &PetSettings) -> Result<(), ValidationError> {
-    if 0. < s.sum
-        && s.sum < 1.
-        && 0. < s.update
-        && s.update < 1.
-        && 0. < s.sum + s.update - s.sum * s.update
-        && s.sum + s.update - s.sum * s.update < 1.
+pub(crate) fn validate_fractions(sum: f64, update: f64) -> Result<(), ValidationError> {
+    if 0. < sum
+        && sum < 1.
+        && 0. < update
+        && update < 1.
+        && 0. < sum + update - sum * update
+        && sum + update - sum * update < 1.
     {
         Ok(())
     } else {
@@ -281,7 +553,55 @@ fn validate_fractions(s: &PetSettings) -> Result<(), ValidationError> {
     }
 }
 
-#[derive(Debug, Validate, Deserialize, Clone, Copy)]
+/// Checks that the targets of the fractions policy satisfy the minimum message counts.
+fn validate_fractions_policy(s: &PetSettings) -> Result<(), ValidationError> {
+    match s.fractions_policy {
+        FractionsPolicy::Target {
+            sum_count,
+            update_count,
+        } if sum_count < s.min_sum_count || update_count < s.min_update_count => Err(
+            ValidationError::new("fractions policy targets below minimum counts"),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the minimal share of the mask quorum is a valid fraction.
+fn validate_mask_quorum(s: &PetSettings) -> Result<(), ValidationError> {
+    if (0. ..=1.).contains(&s.mask_quorum.min_share) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid mask quorum share"))
+    }
+}
+
+/// Checks that the backoff range of the error policy is valid.
+fn validate_error_policy(s: &PetSettings) -> Result<(), ValidationError> {
+    if s.error_policy.min_backoff <= s.error_policy.max_backoff {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid error policy backoff range"))
+    }
+}
+
+/// Checks that the thresholds of the sanity checks are valid.
+fn validate_sanity_checks(s: &PetSettings) -> Result<(), ValidationError> {
+    let SanityChecks {
+        min_scalar,
+        max_scalar,
+        max_weight,
+    } = s.sanity_checks;
+    let valid_scalars = min_scalar
+        .zip(max_scalar)
+        .map_or(true, |(min, max)| min <= max);
+    if valid_scalars && max_weight.map_or(true, |weight| weight >= 0.) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid sanity check thresholds"))
+    }
+}
+
+#[derive(Debug, Validate, Deserialize, Clone)]
 /// REST API settings.
 pub struct ApiSettings {
     /// The address to which the REST API should be bound.
@@ -301,9 +621,139 @@ pub struct ApiSettings {
     /// XAYNET_API__BIND_ADDRESS=127.0.0.1:8081
     /// ```
     pub bind_address: std::net::SocketAddr,
+
+    /// The TLS settings. If they are missing, the REST API is served over plain HTTP.
+    pub tls: Option<TlsSettings>,
+
+    /// The bearer token that authenticates requests to the admin API, which pauses, resumes,
+    /// skips or aborts rounds and updates the PET settings. If it is missing, the admin API is
+    /// disabled.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [api]
+    /// admin_token = "change-me"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_API__ADMIN_TOKEN=change-me
+    /// ```
+    pub admin_token: Option<String>,
+
+    /// The path to a file with the long-term signing key of the coordinator. If it is set, the
+    /// coordinator signs the round parameters, so that participants can verify them against the
+    /// pinned public key of the coordinator. Otherwise, the round parameters are not signed.
+    ///
+    /// The file contains the raw 32 bytes seed of the Ed25519 signing key, which can be generated
+    /// e.g. via `head -c 32 /dev/urandom > signing_key.bin`. The coordinator logs its public key
+    /// at startup.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [api]
+    /// signing_key = "/app/ssl/signing_key.bin"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_API__SIGNING_KEY=/app/ssl/signing_key.bin
+    /// ```
+    #[serde(default)]
+    pub signing_key: Option<PathBuf>,
+}
+
+/// An error related to loading the signing key of the coordinator.
+#[derive(Debug, Error)]
+pub enum SigningKeyError {
+    #[error("failed to read the signing key: {0}")]
+    Io(#[from] io::Error),
+    #[error(
+        "the signing key seed has {0} bytes but it must have {}",
+        SigningKeySeed::LENGTH
+    )]
+    InvalidLength(usize),
+}
+
+impl ApiSettings {
+    /// Loads the long-term signing key of the coordinator, if one is configured.
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or if it doesn't contain a seed of the expected length.
+    pub fn load_signing_key(&self) -> Result<Option<SigningKeyPair>, SigningKeyError> {
+        let path = match self.signing_key {
+            Some(ref path) => path,
+            None => return Ok(None),
+        };
+        let bytes = fs::read(path)?;
+        let seed = SigningKeySeed::from_slice(&bytes)
+            .ok_or_else(|| SigningKeyError::InvalidLength(bytes.len()))?;
+        let (public, secret) = seed.derive_signing_key_pair();
+        Ok(Some(SigningKeyPair { public, secret }))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+/// TLS settings of the REST API.
+pub struct TlsSettings {
+    /// The path to the PEM encoded server certificate chain.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [api.tls]
+    /// certificate = "/app/ssl/tls.pem"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_API__TLS__CERTIFICATE=/app/ssl/tls.pem
+    /// ```
+    pub certificate: PathBuf,
+
+    /// The path to the PEM encoded private key of the server certificate. Both PKCS#8 and RSA
+    /// keys are supported.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [api.tls]
+    /// key = "/app/ssl/tls.key"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_API__TLS__KEY=/app/ssl/tls.key
+    /// ```
+    pub key: PathBuf,
+
+    /// The path to the PEM encoded certificates of the trusted client certificate authorities.
+    /// If it is set, clients must authenticate themselves with a certificate signed by one of
+    /// these authorities (mutual TLS).
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [api.tls]
+    /// client_ca = "/app/ssl/client_ca.pem"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_API__TLS__CLIENT_CA=/app/ssl/client_ca.pem
+    /// ```
+    pub client_ca: Option<PathBuf>,
 }
 
 #[derive(Debug, Validate, Deserialize, Clone, Copy)]
+#[validate(schema(function = "validate_mask"))]
 /// Masking settings.
 pub struct MaskSettings {
     /// The order of the finite group.
@@ -369,6 +819,31 @@ pub struct MaskSettings {
     /// XAYNET_MASK__MODEL_TYPE=M3
     /// ```
     pub model_type: ModelType,
+
+    #[serde(default)]
+    /// The masking settings of the scalar. If they are not set, the scalar is masked with the
+    /// same settings as the model weights. The maximum number of models to be aggregated must
+    /// coincide with the one of the model weights.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [mask.scalar]
+    /// group_type = "Prime"
+    /// data_type = "F64"
+    /// bound_type = "B0"
+    /// model_type = "M3"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_MASK__SCALAR__GROUP_TYPE=Prime
+    /// XAYNET_MASK__SCALAR__DATA_TYPE=F64
+    /// XAYNET_MASK__SCALAR__BOUND_TYPE=B0
+    /// XAYNET_MASK__SCALAR__MODEL_TYPE=M3
+    /// ```
+    pub scalar: Option<ScalarMaskSettings>,
 }
 
 impl Default for MaskSettings {
@@ -378,18 +853,65 @@ impl Default for MaskSettings {
             data_type: DataType::F32,
             bound_type: BoundType::B0,
             model_type: ModelType::M3,
+            scalar: None,
         }
     }
 }
 
-impl From<MaskSettings> for MaskConfig {
+impl From<MaskSettings> for MaskConfigPair {
     fn from(
         MaskSettings {
             group_type,
             data_type,
             bound_type,
             model_type,
+            scalar,
         }: MaskSettings,
+    ) -> MaskConfigPair {
+        let vect = MaskConfig {
+            group_type,
+            data_type,
+            bound_type,
+            model_type,
+        };
+        let unit = scalar.map(MaskConfig::from).unwrap_or(vect);
+        MaskConfigPair { vect, unit }
+    }
+}
+
+/// Checks that the scalar may be aggregated as often as the model weights.
+fn validate_mask(s: &MaskSettings) -> Result<(), ValidationError> {
+    match s.scalar {
+        Some(scalar) if scalar.model_type != s.model_type => {
+            Err(ValidationError::new("invalid scalar model type"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+/// Masking settings of the scalar.
+///
+/// See [`MaskSettings`] for the meaning of the fields.
+pub struct ScalarMaskSettings {
+    /// The order of the finite group.
+    pub group_type: GroupType,
+    /// The data type of the scalar.
+    pub data_type: DataType,
+    /// The bounds of the scalar.
+    pub bound_type: BoundType,
+    /// The maximum number of scalars to be aggregated.
+    pub model_type: ModelType,
+}
+
+impl From<ScalarMaskSettings> for MaskConfig {
+    fn from(
+        ScalarMaskSettings {
+            group_type,
+            data_type,
+            bound_type,
+            model_type,
+        }: ScalarMaskSettings,
     ) -> MaskConfig {
         MaskConfig {
             group_type,
@@ -419,59 +941,187 @@ pub struct ModelSettings {
     /// XAYNET_MODEL__SIZE=100
     /// ```
     pub size: usize,
-}
 
-#[derive(Debug, Deserialize, Validate)]
-/// Metrics settings.
-pub struct MetricsSettings {
-    #[validate]
-    /// Settings for the InfluxDB backend.
-    pub influxdb: InfluxSettings,
-}
-
-#[derive(Debug, Deserialize, Validate)]
-/// InfluxDB settings.
-pub struct InfluxSettings {
-    #[validate(url)]
-    /// The URL where InfluxDB is running.
+    /// The maximum number of unmasked global models which are kept in the model history
+    /// together with their round ID, round seed and message counts. The oldest models are
+    /// deleted when the limit is exceeded. The value `0` disables the model history.
     ///
     /// # Examples
     ///
     /// **TOML**
     /// ```text
-    /// [metrics.influxdb]
-    /// url = "http://localhost:8086"
+    /// [model]
+    /// history_limit = 10
     /// ```
     ///
     /// **Environment variable**
     /// ```text
-    /// XAYNET_METRICS__INFLUXDB__URL=http://localhost:8086
+    /// XAYNET_MODEL__HISTORY_LIMIT=10
     /// ```
-    pub url: String,
+    pub history_limit: usize,
 
-    /// The InfluxDB database name.
+    /// The path to a file with an initial global model. If it is set, the coordinator publishes
+    /// the model as the global model of round 0, so that participants can fetch it before the
+    /// first round is finished. Otherwise, no global model is available until then.
+    ///
+    /// The file contains the raw weights of the model as consecutive little-endian values of the
+    /// data type configured in [`MaskSettings`], e.g. 4 bytes per weight for `F32`. The number
+    /// of weights must match the model [`size`].
     ///
     /// # Examples
     ///
     /// **TOML**
     /// ```text
-    /// [metrics.influxdb]
-    /// db = "test"
+    /// [model]
+    /// initial_model = "/opt/xaynet/model.bin"
     /// ```
     ///
     /// **Environment variable**
     /// ```text
-    /// XAYNET_METRICS__INFLUXDB__DB=test
+    /// XAYNET_MODEL__INITIAL_MODEL=/opt/xaynet/model.bin
     /// ```
-    pub db: String,
+    ///
+    /// [`size`]: #structfield.size
+    #[serde(default)]
+    pub initial_model: Option<PathBuf>,
 }
 
-#[derive(Debug, Deserialize)]
-/// Redis settings.
-pub struct RedisSettings {
-    /// The URL where Redis is running.
-    ///
-    /// The format of the URL is `redis://[<username>][:<passwd>@]<hostname>[:port][/<db>]`.
+/// An error related to loading the initial global model.
+#[derive(Debug, Error)]
+pub enum InitialModelError {
+    #[error("failed to read the initial model: {0}")]
+    Io(#[from] io::Error),
+    #[error("the initial model has {0} bytes which is not a multiple of the data type size")]
+    InvalidLength(usize),
+    #[error("the initial model has {actual} weights but the model size is {expected}")]
+    InvalidSize { expected: usize, actual: usize },
+    #[error("the initial model contains a weight which is not a finite number")]
+    InvalidWeight,
+}
+
+impl ModelSettings {
+    /// Loads the initial global model, if one is configured.
+    ///
+    /// # Errors
+    /// Fails if the file cannot be read or if its content doesn't match the model size and the
+    /// given data type.
+    pub fn load_initial_model(
+        &self,
+        data_type: DataType,
+    ) -> Result<Option<Model>, InitialModelError> {
+        let path = match self.initial_model {
+            Some(ref path) => path,
+            None => return Ok(None),
+        };
+        let model = model_from_le_bytes(&fs::read(path)?, data_type)?;
+        if model.len() != self.size {
+            return Err(InitialModelError::InvalidSize {
+                expected: self.size,
+                actual: model.len(),
+            });
+        }
+        Ok(Some(model))
+    }
+}
+
+/// Decodes a model from consecutive little-endian values of the given data type.
+fn model_from_le_bytes(bytes: &[u8], data_type: DataType) -> Result<Model, InitialModelError> {
+    macro_rules! decode {
+        ($primitive:ty) => {{
+            const SIZE: usize = std::mem::size_of::<$primitive>();
+            if bytes.len() % SIZE != 0 {
+                return Err(InitialModelError::InvalidLength(bytes.len()));
+            }
+            let weights = bytes.chunks_exact(SIZE).map(|chunk| {
+                // safe unwrap: the chunk has exactly `SIZE` bytes
+                <$primitive>::from_le_bytes(chunk.try_into().unwrap())
+            });
+            Model::from_primitives(weights).map_err(|_| InitialModelError::InvalidWeight)
+        }};
+    }
+
+    match data_type {
+        DataType::F32 => decode!(f32),
+        DataType::F64 => decode!(f64),
+        DataType::I32 => decode!(i32),
+        DataType::I64 => decode!(i64),
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+/// Metrics settings.
+pub struct MetricsSettings {
+    #[validate]
+    /// Settings for the InfluxDB backend. If they are missing, no metrics are sent to InfluxDB.
+    pub influxdb: Option<InfluxSettings>,
+    /// Settings for the Prometheus backend. If they are missing, no metrics are exposed to
+    /// Prometheus.
+    pub prometheus: Option<PrometheusSettings>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+/// InfluxDB settings.
+pub struct InfluxSettings {
+    #[validate(url)]
+    /// The URL where InfluxDB is running.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [metrics.influxdb]
+    /// url = "http://localhost:8086"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_METRICS__INFLUXDB__URL=http://localhost:8086
+    /// ```
+    pub url: String,
+
+    /// The InfluxDB database name.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [metrics.influxdb]
+    /// db = "test"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_METRICS__INFLUXDB__DB=test
+    /// ```
+    pub db: String,
+}
+
+#[derive(Debug, Deserialize)]
+/// Prometheus settings.
+pub struct PrometheusSettings {
+    /// The address to which the Prometheus scrape endpoint `/metrics` should be bound.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [metrics.prometheus]
+    /// bind_address = "0.0.0.0:9100"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_METRICS__PROMETHEUS__BIND_ADDRESS=0.0.0.0:9100
+    /// ```
+    pub bind_address: std::net::SocketAddr,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+/// Redis settings.
+pub struct RedisSettings {
+    /// The URL where Redis is running.
+    ///
+    /// The format of the URL is `redis://[<username>][:<passwd>@]<hostname>[:port][/<db>]`.
     ///
     /// # Examples
     ///
@@ -487,6 +1137,31 @@ pub struct RedisSettings {
     /// ```
     #[serde(deserialize_with = "deserialize_redis_url")]
     pub url: ConnectionInfo,
+
+    #[validate(range(min = 1))]
+    #[serde(default = "default_redis_max_connections")]
+    /// The maximum number of concurrent uses of the shared Redis connection. Further uses wait
+    /// until a pending one has completed. The state machine uses the connection sequentially,
+    /// hence the limit mostly bounds the concurrent requests of the REST API and the participant
+    /// registry. Defaults to `10`.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [redis]
+    /// max_connections = 10
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_REDIS__MAX_CONNECTIONS=10
+    /// ```
+    pub max_connections: usize,
+}
+
+fn default_redis_max_connections() -> usize {
+    10
 }
 
 fn deserialize_redis_url<'de, D>(deserializer: D) -> Result<ConnectionInfo, D::Error>
@@ -518,6 +1193,224 @@ where
     deserializer.deserialize_str(ConnectionInfoVisitor)
 }
 
+#[derive(Debug, Validate, Deserialize, Clone, Copy)]
+#[validate(schema(function = "validate_multipart"))]
+/// Multipart message settings.
+///
+/// Participants can split messages that are too large into chunks. The coordinator buffers the
+/// chunks until the message is complete.
+pub struct MultipartSettings {
+    #[validate(range(min = 1))]
+    /// The maximum size in bytes of a reassembled multipart message. A message that exceeds
+    /// this size is discarded.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// max_message_size = 104857600
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_MULTIPART__MAX_MESSAGE_SIZE=104857600
+    /// ```
+    pub max_message_size: usize,
+
+    #[validate(range(min = 1))]
+    /// The maximum size in bytes of all the chunks that are buffered at the same time. Chunks
+    /// that would exceed this size are rejected. Must be greater than or equal to
+    /// `max_message_size`.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// max_buffer_size = 1073741824
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_MULTIPART__MAX_BUFFER_SIZE=1073741824
+    /// ```
+    pub max_buffer_size: usize,
+
+    #[validate(range(min = 1))]
+    /// The time in seconds after which an incomplete multipart message is discarded if no new
+    /// chunk of it has been received. Incomplete messages are also discarded when the
+    /// coordinator moves to a new phase.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// timeout = 300
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_MULTIPART__TIMEOUT=300
+    /// ```
+    pub timeout: u64,
+}
+
+/// Checks multipart settings.
+fn validate_multipart(s: &MultipartSettings) -> Result<(), ValidationError> {
+    if s.max_message_size <= s.max_buffer_size {
+        Ok(())
+    } else {
+        Err(ValidationError::new(
+            "max_message_size exceeds max_buffer_size",
+        ))
+    }
+}
+
+#[derive(Debug, Validate, Deserialize, Clone, Copy, Default)]
+/// Rate limits of the PET messages.
+///
+/// Messages which exceed a rate limit are rejected with `429 Too Many Requests`. Defaults to no
+/// rate limits.
+pub struct RateLimitSettings {
+    #[validate]
+    /// The rate limit per client IP address, which is checked before a message is decrypted. If
+    /// it is missing, the clients are not limited.
+    ///
+    /// The chunks of a multipart message count as separate messages, hence the burst must cover
+    /// the number of chunks of the largest message. Behind a reverse proxy, all clients share the
+    /// address of the proxy.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [rate_limit.client]
+    /// burst = 100
+    /// rate = 10.0
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_RATE_LIMIT__CLIENT__BURST=100
+    /// XAYNET_RATE_LIMIT__CLIENT__RATE=10.0
+    /// ```
+    pub client: Option<RateLimit>,
+
+    #[validate]
+    /// The rate limit per participant public key, which is checked after a message is parsed.
+    /// If it is missing, the participants are not limited.
+    ///
+    /// A multipart message counts as a single message once its chunks have been reassembled.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [rate_limit.participant]
+    /// burst = 50
+    /// rate = 1.0
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_RATE_LIMIT__PARTICIPANT__BURST=50
+    /// XAYNET_RATE_LIMIT__PARTICIPANT__RATE=1.0
+    /// ```
+    pub participant: Option<RateLimit>,
+}
+
+#[derive(Debug, Validate, Deserialize, Clone, Copy, PartialEq)]
+#[validate(schema(function = "validate_rate_limit"))]
+/// A rate limit in terms of a token bucket.
+///
+/// A message takes a token from the bucket and it is rejected if the bucket is empty.
+pub struct RateLimit {
+    #[validate(range(min = 1))]
+    /// The number of tokens which the bucket holds, i.e. the maximum number of messages which
+    /// may be sent in a burst.
+    pub burst: u32,
+
+    /// The number of tokens per second which are refilled into the bucket, i.e. the sustained
+    /// number of messages per second. The value must be positive and finite.
+    pub rate: f64,
+}
+
+fn validate_rate_limit(s: &RateLimit) -> Result<(), ValidationError> {
+    if s.rate > 0. && s.rate.is_finite() {
+        Ok(())
+    } else {
+        Err(ValidationError::new("rate must be positive and finite"))
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+/// Shutdown settings.
+///
+/// On a `SIGINT` or `SIGTERM` signal, the coordinator stops accepting new messages, handles the
+/// messages in flight and checkpoints the coordinator state before it shuts down.
+pub struct ShutdownSettings {
+    /// The maximum amount of time for a graceful shutdown, in seconds. The coordinator shuts
+    /// down immediately once the time is up.
+    ///
+    /// Defaults to 30 seconds.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [shutdown]
+    /// timeout = 30
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SHUTDOWN__TIMEOUT=30
+    /// ```
+    pub timeout: u64,
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        Self { timeout: 30 }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "source")]
+/// Participant registry settings.
+///
+/// The participant registry is an allow-list of participant public keys. Messages of participants
+/// which are not registered are rejected before their task is validated. If the settings are
+/// missing, there is no registry and all participants are allowed.
+///
+/// # Examples
+///
+/// **TOML**
+/// ```text
+/// [registry]
+/// source = "File"
+/// path = "/app/participants.txt"
+/// ```
+///
+/// **Environment variable**
+/// ```text
+/// XAYNET_REGISTRY__SOURCE=File
+/// XAYNET_REGISTRY__PATH=/app/participants.txt
+/// ```
+pub enum RegistrySettings {
+    /// Loads the registry once at startup from a file. The file contains one hex encoded
+    /// participant public key per line. Empty lines and lines starting with `#` are ignored.
+    File {
+        /// The path to the file.
+        path: PathBuf,
+    },
+    /// Keeps the registry in Redis. Participants are registered and unregistered at runtime via
+    /// the `PUT /admin/participants` and `DELETE /admin/participants` endpoints of the admin API.
+    Redis,
+}
+
 #[derive(Debug, Deserialize)]
 /// Logging settings.
 pub struct LoggingSettings {
@@ -566,3 +1459,264 @@ where
 
     deserializer.deserialize_str(EnvFilterVisitor)
 }
+
+#[derive(Debug, Validate, Deserialize, Clone)]
+/// Settings of the services which process PET messages.
+pub struct ServicesSettings {
+    #[validate(range(min = 1))]
+    /// The number of threads of the thread pool to which the decryptor and the message parser
+    /// offload their CPU-intensive tasks.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [services]
+    /// threads = 4
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SERVICES__THREADS=4
+    /// ```
+    pub threads: usize,
+
+    /// The prefix of the names of the threads. The threads are named `<prefix>-<index>`.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [services]
+    /// thread_name_prefix = "xaynet-services"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SERVICES__THREAD_NAME_PREFIX=xaynet-services
+    /// ```
+    pub thread_name_prefix: String,
+
+    #[validate(range(min = 1))]
+    /// The stack size in bytes of the threads. If it is missing, the default stack size of the
+    /// standard library is used.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [services]
+    /// thread_stack_size = 2097152
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SERVICES__THREAD_STACK_SIZE=2097152
+    /// ```
+    pub thread_stack_size: Option<usize>,
+
+    #[validate]
+    /// The limits of the service which decrypts the messages.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [services.decryptor]
+    /// concurrency_limit = 100
+    /// queue_limit = 1000
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SERVICES__DECRYPTOR__CONCURRENCY_LIMIT=100
+    /// XAYNET_SERVICES__DECRYPTOR__QUEUE_LIMIT=1000
+    /// ```
+    pub decryptor: ServiceLimits,
+
+    #[validate]
+    /// The limits of the service which parses the messages.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [services.message_parser]
+    /// concurrency_limit = 100
+    /// queue_limit = 1000
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SERVICES__MESSAGE_PARSER__CONCURRENCY_LIMIT=100
+    /// XAYNET_SERVICES__MESSAGE_PARSER__QUEUE_LIMIT=1000
+    /// ```
+    pub message_parser: ServiceLimits,
+
+    #[validate]
+    /// The limits of the service which reassembles multipart messages.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [services.multipart_handler]
+    /// concurrency_limit = 100
+    /// queue_limit = 1000
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SERVICES__MULTIPART_HANDLER__CONCURRENCY_LIMIT=100
+    /// XAYNET_SERVICES__MULTIPART_HANDLER__QUEUE_LIMIT=1000
+    /// ```
+    pub multipart_handler: ServiceLimits,
+
+    #[validate]
+    /// The limits of the service which checks the participants of the messages against the
+    /// participant registry.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [services.participant_validator]
+    /// concurrency_limit = 100
+    /// queue_limit = 1000
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SERVICES__PARTICIPANT_VALIDATOR__CONCURRENCY_LIMIT=100
+    /// XAYNET_SERVICES__PARTICIPANT_VALIDATOR__QUEUE_LIMIT=1000
+    /// ```
+    pub participant_validator: ServiceLimits,
+
+    #[validate]
+    /// The limits of the service which validates the tasks of the messages.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [services.task_validator]
+    /// concurrency_limit = 100
+    /// queue_limit = 1000
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SERVICES__TASK_VALIDATOR__CONCURRENCY_LIMIT=100
+    /// XAYNET_SERVICES__TASK_VALIDATOR__QUEUE_LIMIT=1000
+    /// ```
+    pub task_validator: ServiceLimits,
+
+    #[validate]
+    /// The limits of the service which hands the messages over to the state machine.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [services.state_machine]
+    /// concurrency_limit = 100
+    /// queue_limit = 1000
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// XAYNET_SERVICES__STATE_MACHINE__CONCURRENCY_LIMIT=100
+    /// XAYNET_SERVICES__STATE_MACHINE__QUEUE_LIMIT=1000
+    /// ```
+    pub state_machine: ServiceLimits,
+}
+
+impl ServicesSettings {
+    /// Builds the thread pool of the services.
+    ///
+    /// # Errors
+    /// Fails when the thread pool cannot be built, e.g. because the threads cannot be spawned.
+    pub fn thread_pool(&self) -> Result<ThreadPool, SettingsError> {
+        let prefix = self.thread_name_prefix.clone();
+        let mut builder = ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .thread_name(move |index| format!("{}-{}", prefix, index));
+        if let Some(stack_size) = self.thread_stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        builder.build().map_err(SettingsError::from)
+    }
+}
+
+#[derive(Debug, Validate, Deserialize, Clone, Copy)]
+/// The limits of a service.
+///
+/// Requests which exceed the concurrency limit are queued. Requests which exceed the queue limit
+/// are rejected.
+pub struct ServiceLimits {
+    #[validate(range(min = 1))]
+    /// The maximum number of requests which the service processes concurrently. For the
+    /// decryptor and the message parser, it is additionally bounded by the number of threads.
+    pub concurrency_limit: usize,
+
+    #[validate(range(min = 1))]
+    /// The maximum number of requests which wait to be processed by the service.
+    pub queue_limit: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xaynet_core::mask::IntoPrimitives;
+
+    #[test]
+    fn test_error_policy_backoff() {
+        let policy = ErrorPolicy {
+            min_backoff: 2,
+            max_backoff: 20,
+            max_failed_rounds: 0,
+        };
+        let backoffs: Vec<u64> = (1..=6).map(|rounds| policy.backoff(rounds)).collect();
+        assert_eq!(backoffs, vec![2, 4, 8, 16, 20, 20]);
+        assert_eq!(policy.backoff(u32::MAX), 20);
+        assert!(!policy.is_exceeded(u32::MAX));
+
+        let policy = ErrorPolicy {
+            max_failed_rounds: 3,
+            ..policy
+        };
+        assert!(!policy.is_exceeded(2));
+        assert!(policy.is_exceeded(3));
+    }
+
+    #[test]
+    fn test_model_from_le_bytes() {
+        let bytes: Vec<u8> = [1_f32, -0.5, 2.25]
+            .iter()
+            .flat_map(|weight| weight.to_le_bytes().to_vec())
+            .collect();
+        let model = model_from_le_bytes(&bytes, DataType::F32).unwrap();
+        let weights: Vec<f32> = model.into_primitives_unchecked().collect();
+        assert_eq!(weights, vec![1_f32, -0.5, 2.25]);
+
+        let bytes: Vec<u8> = [3_i64, -4]
+            .iter()
+            .flat_map(|weight| weight.to_le_bytes().to_vec())
+            .collect();
+        let model = model_from_le_bytes(&bytes, DataType::I64).unwrap();
+        let weights: Vec<i64> = model.into_primitives_unchecked().collect();
+        assert_eq!(weights, vec![3_i64, -4]);
+    }
+
+    #[test]
+    fn test_model_from_le_bytes_invalid() {
+        assert!(matches!(
+            model_from_le_bytes(&[0; 7], DataType::F64),
+            Err(InitialModelError::InvalidLength(7))
+        ));
+        assert!(matches!(
+            model_from_le_bytes(&f32::NAN.to_le_bytes(), DataType::F32),
+            Err(InitialModelError::InvalidWeight)
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+//! This module provides the [`StateMachine`]'s `ControlRequest`, `ControlSender` and
+//! `ControlReceiver` types.
+//!
+//! Control requests travel on a channel next to the `Request` channel and allow an administrator
+//! to steer the rounds of the [`StateMachine`] at runtime.
+//!
+//! [`StateMachine`]: crate::state_machine::StateMachine
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use derive_more::From;
+use futures::Stream;
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::settings::PetSettings;
+
+/// A request to control the [`StateMachine`].
+///
+/// [`StateMachine`]: crate::state_machine::StateMachine
+#[derive(Debug, Clone, Copy)]
+pub enum ControlRequest {
+    /// Pauses the state machine after the current phase.
+    Pause,
+    /// Resumes a paused state machine.
+    Resume,
+    /// Ends the current phase early if the minimum number of messages has been accepted.
+    Skip,
+    /// Aborts the current round and moves to the `Error` phase.
+    Abort,
+    /// Shuts the state machine down.
+    Shutdown,
+    /// Updates the PET settings, which are applied at the beginning of the next round.
+    UpdatePetSettings(PetSettings),
+}
+
+/// Error returned when the state machine fails to apply a control request.
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum ControlError {
+    #[error("the current phase cannot be ended before the minimum number of messages is reached")]
+    NotSkippable,
+    #[error("invalid PET settings: {0}")]
+    InvalidSettings(String),
+    #[error("the control request cannot be processed because the state machine shut down")]
+    ShutDown,
+}
+
+pub type ControlResult = Result<(), ControlError>;
+
+/// A channel for the state machine to send the response to a [`ControlRequest`].
+pub(in crate::state_machine) type ControlResponseSender = oneshot::Sender<ControlResult>;
+
+/// A handle to send control requests to the [`StateMachine`].
+///
+/// [`StateMachine`]: crate::state_machine::StateMachine
+#[derive(Clone, From, Debug)]
+pub struct ControlSender(mpsc::UnboundedSender<(ControlRequest, ControlResponseSender)>);
+
+impl ControlSender {
+    /// Sends a control request to the [`StateMachine`] and waits until it has been applied.
+    ///
+    /// # Errors
+    /// Fails if the control request cannot be applied in the current phase or if the
+    /// [`StateMachine`] has already shut down.
+    ///
+    /// [`StateMachine`]: crate::state_machine::StateMachine
+    pub async fn control(&self, req: ControlRequest) -> ControlResult {
+        let (resp_tx, resp_rx) = oneshot::channel::<ControlResult>();
+        self.0.send((req, resp_tx)).map_err(|_| {
+            warn!("failed to send control request to the state machine: state machine is shutting down");
+            ControlError::ShutDown
+        })?;
+        resp_rx.await.map_err(|_| {
+            warn!(
+                "failed to receive response from the state machine: state machine is shutting down"
+            );
+            ControlError::ShutDown
+        })?
+    }
+}
+
+/// The receiver half of the `Control` channel that is used by the [`StateMachine`] to receive
+/// control requests.
+///
+/// [`StateMachine`]: crate::state_machine::StateMachine
+#[derive(Debug)]
+pub struct ControlReceiver(
+    Option<mpsc::UnboundedReceiver<(ControlRequest, ControlResponseSender)>>,
+);
+
+/// Unlike the `Request` channel, the state machine keeps running without any control requests.
+/// Hence, the stream stays pending instead of terminating once all [`ControlSender`]s have been
+/// dropped.
+impl Stream for ControlReceiver {
+    type Item = (ControlRequest, ControlResponseSender);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        trace!("ControlReceiver: polling");
+        let this = self.get_mut();
+        if let Some(ref mut rx) = this.0 {
+            if let Poll::Ready(item) = Pin::new(rx).poll_next(cx) {
+                if item.is_some() {
+                    return Poll::Ready(item);
+                }
+                debug!("control receiver closed: senders have been dropped");
+                this.0 = None;
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl ControlReceiver {
+    /// Creates a new `Control` channel and returns the [`ControlReceiver`] as well as the
+    /// [`ControlSender`] half.
+    pub fn new() -> (Self, ControlSender) {
+        let (tx, rx) = mpsc::unbounded_channel::<(ControlRequest, ControlResponseSender)>();
+        (Self(Some(rx)), ControlSender::from(tx))
+    }
+
+    /// Closes the `Control` channel.
+    pub fn close(&mut self) {
+        if let Some(ref mut rx) = self.0 {
+            rx.close();
+        }
+    }
+
+    /// Tries to retrieve the next control request without blocking.
+    pub fn try_recv(&mut self) -> Option<(ControlRequest, ControlResponseSender)> {
+        self.0.as_mut().and_then(|rx| rx.try_recv().ok())
+    }
+}
@@ -1,13 +1,28 @@
 //! Coordinator state and round parameter types.
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
 
 use xaynet_core::{
     common::{RoundParameters, RoundSeed},
     crypto::{ByteObject, EncryptKeyPair},
-    mask::{MaskConfig, MaskObject},
+    mask::MaskObject,
+    SumParticipantPublicKey,
 };
 
-use crate::settings::{MaskSettings, ModelSettings, PetSettings};
+use crate::{
+    settings::{
+        ErrorPolicy,
+        FractionsPolicy,
+        MaskQuorum,
+        MaskSettings,
+        ModelSettings,
+        PetSettings,
+        SanityChecks,
+    },
+    state_machine::{events::MessageCounts, phases::PhaseName},
+};
 
 /// The coordinator state.
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -16,6 +31,8 @@ pub struct CoordinatorState {
     pub keys: EncryptKeyPair,
     /// Internal ID used to identify a round
     pub round_id: u64,
+    /// The phase the coordinator was in when the state was stored.
+    pub phase: PhaseName,
     /// The round parameters.
     pub round_params: RoundParameters,
     /// The minimum of required sum/sum2 messages.
@@ -30,10 +47,28 @@ pub struct CoordinatorState {
     pub max_sum_time: u64,
     /// The maximum time (in seconds) permitted for processing update messages.
     pub max_update_time: u64,
-    /// The masking configuration.
-    pub mask_config: MaskConfig,
+    /// The minimum time (in seconds) spent in the idle phase.
+    pub min_idle_time: u64,
+    /// The policy which recomputes the round fractions.
+    #[serde(with = "fractions_policy")]
+    pub fractions_policy: FractionsPolicy,
+    /// The quorum which the masks of the sum participants must reach.
+    pub mask_quorum: MaskQuorum,
+    /// The policy which restarts failed rounds.
+    pub error_policy: ErrorPolicy,
+    /// The sanity checks of the unmasked global model.
+    pub sanity_checks: SanityChecks,
+    /// The number of consecutive failed rounds.
+    pub failed_rounds: u32,
+    /// Whether the sum2 phase of the current round is retried.
+    pub sum2_retried: bool,
     /// The size of the model.
     pub model_size: usize,
+    /// The maximum number of global models kept in the model history.
+    pub model_history_limit: usize,
+    /// The updated PET settings which are applied at the beginning of the next round.
+    #[serde(with = "pending_pet_settings")]
+    pub pending_pet_settings: Option<PetSettings>,
 }
 
 impl CoordinatorState {
@@ -48,24 +83,287 @@ impl CoordinatorState {
             sum: pet_settings.sum,
             update: pet_settings.update,
             seed: RoundSeed::zeroed(),
+            mask_config: mask_settings.into(),
         };
         let round_id = 0;
         Self {
             keys,
             round_params,
             round_id,
+            phase: PhaseName::Idle,
             min_sum_count: pet_settings.min_sum_count,
             min_update_count: pet_settings.min_update_count,
             min_sum_time: pet_settings.min_sum_time,
             min_update_time: pet_settings.min_update_time,
             max_sum_time: pet_settings.max_sum_time,
             max_update_time: pet_settings.max_update_time,
-            mask_config: mask_settings.into(),
+            min_idle_time: pet_settings.min_idle_time,
+            fractions_policy: pet_settings.fractions_policy,
+            mask_quorum: pet_settings.mask_quorum,
+            error_policy: pet_settings.error_policy,
+            sanity_checks: pet_settings.sanity_checks,
+            failed_rounds: 0,
+            sum2_retried: false,
             model_size: model_settings.size,
+            model_history_limit: model_settings.history_limit,
+            pending_pet_settings: None,
         }
     }
+
+    /// Replaces the round parameters and thresholds which are derived from the PET settings.
+    pub fn apply_pet_settings(&mut self, pet_settings: PetSettings) {
+        self.round_params.sum = pet_settings.sum;
+        self.round_params.update = pet_settings.update;
+        self.min_sum_count = pet_settings.min_sum_count;
+        self.min_update_count = pet_settings.min_update_count;
+        self.min_sum_time = pet_settings.min_sum_time;
+        self.min_update_time = pet_settings.min_update_time;
+        self.max_sum_time = pet_settings.max_sum_time;
+        self.max_update_time = pet_settings.max_update_time;
+        self.min_idle_time = pet_settings.min_idle_time;
+        self.fractions_policy = pet_settings.fractions_policy;
+        self.mask_quorum = pet_settings.mask_quorum;
+        self.error_policy = pet_settings.error_policy;
+        self.sanity_checks = pet_settings.sanity_checks;
+    }
+
+    /// Checks whether the round of a stored coordinator state can be resumed with this state.
+    ///
+    /// The masked models of the stored round are incompatible with a changed masking
+    /// configuration or model size.
+    pub fn can_resume(&self, stored_state: &CoordinatorState) -> bool {
+        self.round_params.mask_config == stored_state.round_params.mask_config
+            && self.model_size == stored_state.model_size
+    }
+
+    /// Takes over the round-scoped fields of a stored coordinator state.
+    ///
+    /// The thresholds, policies and model settings of this state are kept, such that the
+    /// configured settings apply to the resumed round as well. Pending PET settings are taken over
+    /// too, since they have been updated after the coordinator was started.
+    pub fn resume(&mut self, stored_state: CoordinatorState) {
+        self.pending_pet_settings = stored_state.pending_pet_settings;
+        self.keys = stored_state.keys;
+        self.round_params = stored_state.round_params;
+        self.round_id = stored_state.round_id;
+        self.phase = stored_state.phase;
+        self.failed_rounds = stored_state.failed_rounds;
+        self.sum2_retried = stored_state.sum2_retried;
+    }
+}
+
+/// The metadata of a global model in the model history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalModelInfo {
+    /// The ID of the round in which the model was computed.
+    pub round_id: u64,
+    /// The seed of the round in which the model was computed.
+    pub seed: RoundSeed,
+    /// The number of messages accepted in the round in which the model was computed.
+    pub messages: MessageCounts,
 }
 
 /// A dictionary created during the sum2 phase of the protocol. It counts the model masks
 /// represented by their hashes.
 pub type MaskDict = HashMap<MaskObject, usize>;
+
+/// A dictionary created during the sum2 phase of the protocol. It maps the sum participants to
+/// the digests of their model masks, see [`mask_digest()`].
+pub type MaskVotes = HashMap<SumParticipantPublicKey, u64>;
+
+/// Computes a digest of a mask to tell which sum participants voted for the same mask.
+pub fn mask_digest(mask: &MaskObject) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    mask.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// De/serializes the [`FractionsPolicy`] of the [`CoordinatorState`].
+///
+/// The policy is internally tagged for the settings, which isn't supported by bincode.
+mod fractions_policy {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::settings::FractionsPolicy;
+
+    #[derive(Serialize, Deserialize)]
+    enum Policy {
+        Fixed,
+        Target {
+            sum_count: usize,
+            update_count: usize,
+        },
+    }
+
+    pub fn serialize<S>(policy: &FractionsPolicy, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *policy {
+            FractionsPolicy::Fixed => Policy::Fixed,
+            FractionsPolicy::Target {
+                sum_count,
+                update_count,
+            } => Policy::Target {
+                sum_count,
+                update_count,
+            },
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<FractionsPolicy, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match Policy::deserialize(deserializer)? {
+            Policy::Fixed => FractionsPolicy::Fixed,
+            Policy::Target {
+                sum_count,
+                update_count,
+            } => FractionsPolicy::Target {
+                sum_count,
+                update_count,
+            },
+        })
+    }
+}
+
+/// De/serializes the pending [`PetSettings`] of the [`CoordinatorState`].
+///
+/// The settings contain a [`FractionsPolicy`], see [`fractions_policy`].
+mod pending_pet_settings {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::settings::{ErrorPolicy, FractionsPolicy, MaskQuorum, PetSettings, SanityChecks};
+
+    #[derive(Serialize, Deserialize)]
+    struct Settings {
+        min_sum_count: usize,
+        min_update_count: usize,
+        min_sum_time: u64,
+        min_update_time: u64,
+        max_sum_time: u64,
+        max_update_time: u64,
+        min_idle_time: u64,
+        sum: f64,
+        update: f64,
+        #[serde(with = "super::fractions_policy")]
+        fractions_policy: FractionsPolicy,
+        mask_quorum: MaskQuorum,
+        error_policy: ErrorPolicy,
+        sanity_checks: SanityChecks,
+    }
+
+    pub fn serialize<S>(settings: &Option<PetSettings>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        settings
+            .map(|settings| Settings {
+                min_sum_count: settings.min_sum_count,
+                min_update_count: settings.min_update_count,
+                min_sum_time: settings.min_sum_time,
+                min_update_time: settings.min_update_time,
+                max_sum_time: settings.max_sum_time,
+                max_update_time: settings.max_update_time,
+                min_idle_time: settings.min_idle_time,
+                sum: settings.sum,
+                update: settings.update,
+                fractions_policy: settings.fractions_policy,
+                mask_quorum: settings.mask_quorum,
+                error_policy: settings.error_policy,
+                sanity_checks: settings.sanity_checks,
+            })
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<PetSettings>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(
+            Option::<Settings>::deserialize(deserializer)?.map(|settings| PetSettings {
+                min_sum_count: settings.min_sum_count,
+                min_update_count: settings.min_update_count,
+                min_sum_time: settings.min_sum_time,
+                min_update_time: settings.min_update_time,
+                max_sum_time: settings.max_sum_time,
+                max_update_time: settings.max_update_time,
+                min_idle_time: settings.min_idle_time,
+                sum: settings.sum,
+                update: settings.update,
+                fractions_policy: settings.fractions_policy,
+                mask_quorum: settings.mask_quorum,
+                error_policy: settings.error_policy,
+                sanity_checks: settings.sanity_checks,
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_machine::tests::utils::{mask_settings, model_settings, pet_settings};
+
+    #[test]
+    fn test_coordinator_state_bincode() {
+        for &fractions_policy in [
+            FractionsPolicy::Fixed,
+            FractionsPolicy::Target {
+                sum_count: 10,
+                update_count: 100,
+            },
+        ]
+        .iter()
+        {
+            let mut state =
+                CoordinatorState::new(pet_settings(), mask_settings(), model_settings());
+            state.fractions_policy = fractions_policy;
+            state.pending_pet_settings = Some(PetSettings {
+                fractions_policy,
+                ..pet_settings()
+            });
+            let bytes = bincode::serialize(&state).unwrap();
+            assert_eq!(
+                bincode::deserialize::<CoordinatorState>(&bytes).unwrap(),
+                state
+            );
+        }
+    }
+
+    #[test]
+    fn test_resume_keeps_the_configured_settings() {
+        let mut stored_state =
+            CoordinatorState::new(pet_settings(), mask_settings(), model_settings());
+        stored_state.round_id = 7;
+        stored_state.phase = PhaseName::Update;
+        stored_state.failed_rounds = 2;
+        stored_state.min_sum_count = 100;
+        stored_state.model_history_limit = 0;
+
+        let keys = stored_state.keys.clone();
+        let round_params = stored_state.round_params.clone();
+
+        let mut state = CoordinatorState::new(pet_settings(), mask_settings(), model_settings());
+        assert!(state.can_resume(&stored_state));
+        state.resume(stored_state);
+        assert_eq!(state.keys, keys);
+        assert_eq!(state.round_params, round_params);
+        assert_eq!(state.round_id, 7);
+        assert_eq!(state.phase, PhaseName::Update);
+        assert_eq!(state.failed_rounds, 2);
+        assert_eq!(state.min_sum_count, pet_settings().min_sum_count);
+        assert_eq!(state.model_history_limit, model_settings().history_limit);
+    }
+
+    #[test]
+    fn test_cannot_resume_with_changed_model_size() {
+        let stored_state = CoordinatorState::new(pet_settings(), mask_settings(), model_settings());
+        let mut model_settings = model_settings();
+        model_settings.size += 1;
+        let state = CoordinatorState::new(pet_settings(), mask_settings(), model_settings);
+        assert!(!state.can_resume(&stored_state));
+    }
+}
@@ -53,6 +53,17 @@ pub enum DictionaryUpdate<D> {
     New(Arc<D>),
 }
 
+/// Number of messages accepted in the current round.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageCounts {
+    /// Number of accepted sum messages.
+    pub sum: u64,
+    /// Number of accepted update messages.
+    pub update: u64,
+    /// Number of accepted sum2 messages.
+    pub sum2: u64,
+}
+
 /// A convenience type to emit any coordinator event.
 #[derive(Debug)]
 pub struct EventPublisher {
@@ -65,6 +76,7 @@ pub struct EventPublisher {
     mask_length_tx: EventBroadcaster<MaskLengthUpdate>,
     sum_dict_tx: EventBroadcaster<DictionaryUpdate<SumDict>>,
     seed_dict_tx: EventBroadcaster<DictionaryUpdate<SeedDict>>,
+    message_counts_tx: EventBroadcaster<MessageCounts>,
 }
 
 /// The `EventSubscriber` hands out `EventListener`s for any
@@ -78,6 +90,7 @@ pub struct EventSubscriber {
     mask_length_rx: EventListener<MaskLengthUpdate>,
     sum_dict_rx: EventListener<DictionaryUpdate<SumDict>>,
     seed_dict_rx: EventListener<DictionaryUpdate<SeedDict>>,
+    message_counts_rx: EventListener<MessageCounts>,
 }
 
 impl EventPublisher {
@@ -125,6 +138,12 @@ impl EventPublisher {
             event: params,
         });
 
+        let (message_counts_tx, message_counts_rx) =
+            watch::channel::<Event<MessageCounts>>(Event {
+                round_id,
+                event: MessageCounts::default(),
+            });
+
         let publisher = EventPublisher {
             round_id,
             keys_tx: keys_tx.into(),
@@ -134,6 +153,7 @@ impl EventPublisher {
             mask_length_tx: mask_length_tx.into(),
             sum_dict_tx: sum_dict_tx.into(),
             seed_dict_tx: seed_dict_tx.into(),
+            message_counts_tx: message_counts_tx.into(),
         };
 
         let subscriber = EventSubscriber {
@@ -144,6 +164,7 @@ impl EventPublisher {
             mask_length_rx: mask_length_rx.into(),
             sum_dict_rx: sum_dict_rx.into(),
             seed_dict_rx: seed_dict_rx.into(),
+            message_counts_rx: message_counts_rx.into(),
         };
 
         (publisher, subscriber)
@@ -195,6 +216,11 @@ impl EventPublisher {
     pub fn broadcast_seed_dict(&mut self, update: DictionaryUpdate<SeedDict>) {
         let _ = self.seed_dict_tx.broadcast(self.event(update));
     }
+
+    /// Emit a message counts update
+    pub fn broadcast_message_counts(&mut self, counts: MessageCounts) {
+        let _ = self.message_counts_tx.broadcast(self.event(counts));
+    }
 }
 
 impl EventSubscriber {
@@ -233,6 +259,11 @@ impl EventSubscriber {
     pub fn seed_dict_listener(&self) -> EventListener<DictionaryUpdate<SeedDict>> {
         self.seed_dict_rx.clone()
     }
+
+    /// Get a listener for message counts updates
+    pub fn message_counts_listener(&self) -> EventListener<MessageCounts> {
+        self.message_counts_rx.clone()
+    }
 }
 
 /// A listener for coordinator events. It can be used to either
@@ -42,13 +42,17 @@
 //! **Unmask**
 //!
 //! Publishes [`PhaseName::Unmask`], unmasks the global masked model and publishes the global
-//! model.
+//! model. If no mask reaches the configured quorum, the [`PhaseName::Sum2`] phase may be retried
+//! once to wait for the remaining sum participants. The global model is only published if the
+//! unmasked scalar and weights pass the configured [`SanityChecks`].
 //!
 //! **Error**
 //!
 //! Publishes [`PhaseName::Error`] and handles [`StateError`]s that can occur during the
-//! execution of the [`StateMachine`]. In most cases, the error is handled by restarting the round.
-//! However, if a [`StateError::ChannelError`] occurs, the [`StateMachine`] will shut down.
+//! execution of the [`StateMachine`]. In most cases, the error is handled by restarting the round
+//! after a backoff according to the [`ErrorPolicy`]. However, if a [`StateError::ChannelError`]
+//! or a round error which would recur in every round occurs, or if too many consecutive rounds
+//! failed, the [`StateMachine`] will shut down.
 //!
 //! **Shutdown**
 //!
@@ -78,6 +82,17 @@
 //!
 //! See [here][requests] for more details.
 //!
+//! # Control requests
+//!
+//! Next to the [Request][requests_idx] channel, [`StateMachine::new()`] creates a
+//! [Control][control_idx] channel whose sender half ([`ControlSender`]) is returned to the
+//! caller as well. It is used to send [`ControlRequest`]s to pause the [`StateMachine`] after the
+//! current phase, resume it, end the current phase early once the minimum number of messages has
+//! been accepted, abort the current round or shut the [`StateMachine`] down. Aborted rounds are
+//! restarted right away and don't count as failed rounds of the [`ErrorPolicy`]. Furthermore,
+//! updated PET settings can be sent to the [`StateMachine`], which validates them and applies
+//! them in the next [`PhaseName::Idle`] phase.
+//!
 //! # Events
 //!
 //! During the execution of the PET protocol, the [`StateMachine`] will publish various events
@@ -87,12 +102,21 @@
 //!
 //! See [here][events] for more details.
 //!
+//! # Persistence
+//!
+//! The [`StateMachine`] stores the coordinator state at the beginning of each phase and writes the
+//! sum dictionary, the seed dictionary, the masked models and the masks through to Redis while
+//! handling the [`Request`]s. When a new [`StateMachine`] is created, it resumes an interrupted
+//! round from the stored data. See [`StateMachine::new()`] for more details.
+//!
 //! [settings]: ../settings/index.html
+//! [`ErrorPolicy`]: crate::settings::ErrorPolicy
 //! [`PhaseName::Idle`]: crate::state_machine::phases::PhaseName::Idle
 //! [`PhaseName::Sum`]: crate::state_machine::phases::PhaseName::Sum
 //! [`PhaseName::Update`]: crate::state_machine::phases::PhaseName::Update
 //! [`PhaseName::Sum2`]: crate::state_machine::phases::PhaseName::Sum2
 //! [`PhaseName::Unmask`]: crate::state_machine::phases::PhaseName::Unmask
+//! [`SanityChecks`]: crate::settings::SanityChecks
 //! [`PhaseName::Error`]: crate::state_machine::phases::PhaseName::Error
 //! [`PhaseName::Shutdown`]: crate::state_machine::phases::PhaseName::Shutdown
 //! [`SumDict`]: xaynet_core::SumDict
@@ -102,16 +126,20 @@
 //! [`MaskDict`]: crate::state_machine::coordinator::MaskDict
 //! [`Request`]: crate::state_machine::requests::Request
 //! [requests_idx]: ./requests/index.html
+//! [control_idx]: ./control/index.html
+//! [`ControlRequest`]: crate::state_machine::control::ControlRequest
 //! [events]: ./events/index.html
 
+pub mod control;
 pub mod coordinator;
 pub mod events;
 pub mod phases;
 pub mod requests;
 
 use self::{
-    coordinator::CoordinatorState,
-    events::{EventPublisher, EventSubscriber},
+    control::{ControlReceiver, ControlSender},
+    coordinator::{CoordinatorState, GlobalModelInfo},
+    events::{EventPublisher, EventSubscriber, MessageCounts, ModelUpdate},
     phases::{
         Idle,
         Phase,
@@ -128,11 +156,20 @@ use self::{
     requests::{RequestReceiver, RequestSender},
 };
 
+use std::sync::Arc;
+
 use derive_more::From;
+use redis::RedisError;
 use thiserror::Error;
-use xaynet_core::{mask::UnmaskingError, InitError};
+use xaynet_core::{
+    mask::{Model, UnmaskingError},
+    InitError,
+};
 
-use crate::settings::{MaskSettings, ModelSettings, PetSettings};
+use crate::{
+    settings::{InitialModelError, MaskSettings, ModelSettings, PetSettings},
+    storage::redis::Client,
+};
 
 #[cfg(feature = "metrics")]
 use crate::metrics::MetricsSender;
@@ -162,8 +199,51 @@ pub enum RoundFailed {
     AmbiguousMasks,
     #[error("no mask found")]
     NoMask,
+    #[error("no mask reached the quorum")]
+    NoQuorum,
     #[error("unmasking error: {0}")]
     Unmasking(#[from] UnmaskingError),
+    #[error("the aggregation of the masked models failed")]
+    Aggregation,
+    #[error("the unmasked scalar is out of the configured range")]
+    InvalidScalar,
+    #[error("the unmasked global model failed the sanity checks")]
+    InvalidModel,
+}
+
+impl RoundFailed {
+    /// Checks whether a round which failed with this error may succeed if it is restarted.
+    ///
+    /// Missing, ambiguous or invalid masks, an invalid scalar and an invalid model depend on the
+    /// participants of the round. Too many aggregated models and a failed aggregation can't be
+    /// caused by the participants, since their models are validated beforehand. These errors
+    /// point to a misconfiguration or a bug instead and would fail the restarted rounds as well.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            RoundFailed::AmbiguousMasks
+            | RoundFailed::NoMask
+            | RoundFailed::NoQuorum
+            | RoundFailed::Unmasking(UnmaskingError::NoModel)
+            | RoundFailed::Unmasking(UnmaskingError::MaskMismatch)
+            | RoundFailed::Unmasking(UnmaskingError::InvalidMask)
+            | RoundFailed::InvalidScalar
+            | RoundFailed::InvalidModel => true,
+            RoundFailed::Unmasking(UnmaskingError::TooManyModels) | RoundFailed::Aggregation => {
+                false
+            }
+        }
+    }
+}
+
+/// Error that occurs when the [`StateMachine`] cannot be initialized.
+#[derive(Debug, Error)]
+pub enum StateMachineInitError {
+    #[error("{0}")]
+    Crypto(#[from] InitError),
+    #[error("failed to restore the coordinator state: {0}")]
+    Restore(#[from] RedisError),
+    #[error("{0}")]
+    InitialModel(#[from] InitialModelError),
 }
 
 /// The state machine with all its states.
@@ -188,11 +268,27 @@ where
     PhaseState<StateError>: Phase,
     PhaseState<Shutdown>: Phase,
 {
-    /// Creates a new state machine with the initial state [`Idle`].
+    /// Creates a new state machine.
+    ///
+    /// If a [`CoordinatorState`] has been stored in Redis, the state machine resumes the
+    /// interrupted round: the [`Sum`], [`Update`], [`Sum2`] and [`Unmask`] states are rebuilt from
+    /// the stored dictionaries, masked models and masks and the timers of the resumed phase start
+    /// anew. The resumed round keeps its keys and round parameters, whereas the configured
+    /// settings apply right away, except for the round fractions which apply from the next round
+    /// on. PET settings which have been updated via the admin API but haven't taken effect yet
+    /// are kept. A round can't be resumed if the masking configuration or the model size has
+    /// changed. In any other case, the state machine starts a new round with the initial state
+    /// [`Idle`] and continues with the stored round id if there is one.
+    ///
+    /// If an initial model is configured and no round has been started yet, the initial model is
+    /// published as the global model of round 0 and added to the model history.
+    ///
+    /// All subsequent changes of the coordinator state and the dictionaries are written to Redis.
     ///
     /// # Errors
     ///
-    /// Fails if there is insufficient system entropy to generate secrets.
+    /// Fails if there is insufficient system entropy to generate secrets, if the initial model
+    /// cannot be loaded or if the stored state cannot be retrieved from Redis.
     ///
     /// <div class="information">
     ///     <div class="tooltip ignore" style="">ⓘ<span class="tooltiptext">Note</span></div>
@@ -211,34 +307,117 @@ where
     /// let state_machine =
     ///     StateMachine::from(PhaseState::<Idle>::new(coordinator_state, req_receiver));
     /// ```
-    pub fn new(
+    pub async fn new(
         pet_settings: PetSettings,
         mask_settings: MaskSettings,
         model_settings: ModelSettings,
+        redis: Client,
         #[cfg(feature = "metrics")] metrics_tx: MetricsSender,
-    ) -> Result<(Self, RequestSender, EventSubscriber), InitError> {
+    ) -> Result<(Self, RequestSender, ControlSender, EventSubscriber), StateMachineInitError> {
         // crucial: init must be called before anything else in this module
         sodiumoxide::init().or(Err(InitError))?;
 
-        let coordinator_state = CoordinatorState::new(pet_settings, mask_settings, model_settings);
-        let (event_publisher, event_subscriber) = EventPublisher::init(
+        let initial_model = model_settings.load_initial_model(mask_settings.data_type)?;
+        let mut coordinator_state =
+            CoordinatorState::new(pet_settings, mask_settings, model_settings);
+        let phase = match redis.connection().await.get_coordinator_state().await? {
+            Some(stored_state) => match stored_state.phase {
+                PhaseName::Sum | PhaseName::Update | PhaseName::Sum2 | PhaseName::Unmask
+                    if coordinator_state.can_resume(&stored_state) =>
+                {
+                    info!(
+                        "resuming {:?} phase of round {}",
+                        stored_state.phase, stored_state.round_id
+                    );
+                    coordinator_state.resume(stored_state);
+                    // the resumed round keeps its round parameters, the configured ones apply
+                    // from the next round on unless they have been updated in the meantime
+                    coordinator_state
+                        .pending_pet_settings
+                        .get_or_insert(pet_settings);
+                    coordinator_state.phase
+                }
+                PhaseName::Sum | PhaseName::Update | PhaseName::Sum2 | PhaseName::Unmask => {
+                    warn!(
+                        "cannot resume round {} with a changed masking configuration or model size",
+                        stored_state.round_id
+                    );
+                    coordinator_state.round_id = stored_state.round_id;
+                    coordinator_state.failed_rounds = stored_state.failed_rounds;
+                    coordinator_state.pending_pet_settings = stored_state.pending_pet_settings;
+                    PhaseName::Idle
+                }
+                _ => {
+                    info!(
+                        "no round to resume, continuing after round {}",
+                        stored_state.round_id
+                    );
+                    coordinator_state.round_id = stored_state.round_id;
+                    coordinator_state.failed_rounds = stored_state.failed_rounds;
+                    coordinator_state.pending_pet_settings = stored_state.pending_pet_settings;
+                    PhaseName::Idle
+                }
+            },
+            None => PhaseName::Idle,
+        };
+
+        let (mut event_publisher, event_subscriber) = EventPublisher::init(
             coordinator_state.round_id,
             coordinator_state.keys.clone(),
             coordinator_state.round_params.clone(),
-            PhaseName::Idle,
+            phase,
         );
+        if let Some(model) = initial_model {
+            // only a fresh coordinator starts with round 0, otherwise the initial model is outdated
+            if coordinator_state.round_id == 0 {
+                info!("publishing the initial model as global model of round 0");
+                Self::store_initial_model(&coordinator_state, &redis, &model).await?;
+                event_publisher.broadcast_model(ModelUpdate::New(Arc::new(model)));
+            }
+        }
         let (req_receiver, handle) = RequestReceiver::new();
+        let (control_receiver, control_handle) = ControlReceiver::new();
 
         let shared = Shared::new(
             coordinator_state,
             event_publisher,
             req_receiver,
+            control_receiver,
+            Some(redis.clone()),
             #[cfg(feature = "metrics")]
             metrics_tx,
         );
 
-        let state_machine = StateMachine::from(PhaseState::<Idle>::new(shared));
-        Ok((state_machine, handle, event_subscriber))
+        let state_machine = match phase {
+            PhaseName::Sum => PhaseState::<Sum>::restore(shared, &redis).await?.into(),
+            PhaseName::Update => PhaseState::<Update>::restore(shared, &redis).await?.into(),
+            PhaseName::Sum2 => PhaseState::<Sum2>::restore(shared, &redis).await?.into(),
+            PhaseName::Unmask => PhaseState::<Unmask>::restore(shared, &redis).await?.into(),
+            _ => PhaseState::<Idle>::new(shared).into(),
+        };
+        Ok((state_machine, handle, control_handle, event_subscriber))
+    }
+
+    /// Adds the initial model to the model history, unless the model history is disabled.
+    async fn store_initial_model(
+        coordinator_state: &CoordinatorState,
+        redis: &Client,
+        model: &Model,
+    ) -> Result<(), RedisError> {
+        let limit = coordinator_state.model_history_limit;
+        if limit == 0 {
+            return Ok(());
+        }
+        let info = GlobalModelInfo {
+            round_id: coordinator_state.round_id,
+            seed: coordinator_state.round_params.seed.clone(),
+            messages: MessageCounts::default(),
+        };
+        redis
+            .connection()
+            .await
+            .add_global_model(&info, model, limit)
+            .await
     }
 
     /// Moves the [`StateMachine`] to the next state and consumes the current one.
@@ -256,7 +435,8 @@ where
     }
 
     /// Runs the state machine until it shuts down.
-    /// The [`StateMachine`] shuts down once all [`RequestSender`] have been dropped.
+    /// The [`StateMachine`] shuts down once all [`RequestSender`] have been dropped or a shutdown
+    /// has been requested via a [`ControlSender`].
     pub async fn run(mut self) -> Option<()> {
         loop {
             self = self.next().await?;
@@ -7,7 +7,9 @@ use crate::state_machine::{
 #[cfg(feature = "metrics")]
 use crate::metrics;
 
+use redis::RedisError;
 use thiserror::Error;
+use tokio::time::Duration;
 
 /// Error that can occur during the execution of the [`StateMachine`].
 #[derive(Error, Debug)]
@@ -16,8 +18,45 @@ pub enum StateError {
     ChannelError(&'static str),
     #[error("state failed: round error: {0}")]
     RoundError(#[from] RoundFailed),
-    #[error("state failed: phase timeout: {0}")]
-    TimeoutError(#[from] tokio::time::Elapsed),
+    #[error("state failed: phase timeout")]
+    TimeoutError,
+    #[error("state failed: storage error: {0}")]
+    StorageError(#[from] RedisError),
+    #[error("state failed: round aborted")]
+    Aborted,
+    #[error("state failed: shutdown requested")]
+    ShutdownRequested,
+}
+
+impl StateError {
+    /// Checks whether the round may be restarted after this error.
+    ///
+    /// A closed request channel and a requested shutdown are fatal, whereas timeouts and storage
+    /// errors may be transient. Failed rounds are classified by [`RoundFailed::is_retryable()`].
+    /// Aborted rounds are restarted as well.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            StateError::ChannelError(_) | StateError::ShutdownRequested => false,
+            StateError::RoundError(err) => err.is_retryable(),
+            StateError::TimeoutError | StateError::StorageError(_) | StateError::Aborted => true,
+        }
+    }
+}
+
+impl StateError {
+    /// Gets the name of the kind of this error.
+    ///
+    /// Unlike the error message, the name doesn't contain any details of the error.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StateError::ChannelError(_) => "channel_error",
+            StateError::RoundError(_) => "round_error",
+            StateError::TimeoutError => "timeout_error",
+            StateError::StorageError(_) => "storage_error",
+            StateError::Aborted => "aborted",
+            StateError::ShutdownRequested => "shutdown_requested",
+        }
+    }
 }
 
 impl PhaseState<StateError> {
@@ -46,16 +85,168 @@ impl Phase for PhaseState<StateError> {
         info!("broadcasting error phase event");
         self.shared.io.events.broadcast_phase(PhaseName::Error);
 
-        Ok(())
+        if !self.inner.is_retryable() {
+            return Ok(());
+        }
+        if let StateError::Aborted = self.inner {
+            info!("round aborted, restarting the round");
+            return Ok(());
+        }
+
+        let policy = self.shared.state.error_policy;
+        self.shared.state.failed_rounds = self.shared.state.failed_rounds.saturating_add(1);
+        let failed_rounds = self.shared.state.failed_rounds;
+        if policy.is_exceeded(failed_rounds) {
+            error!("{} consecutive rounds failed", failed_rounds);
+            return Ok(());
+        }
+
+        let backoff = policy.backoff(failed_rounds);
+        info!(
+            "{} consecutive rounds failed, restarting the round in {} seconds",
+            failed_rounds, backoff
+        );
+        self.delay(Duration::from_secs(backoff)).await
     }
 
     /// Moves from the error state to the next state.
     ///
     /// See the [module level documentation](../index.html) for more details.
     fn next(self) -> Option<StateMachine> {
-        Some(match self.inner {
-            StateError::ChannelError(_) => PhaseState::<Shutdown>::new(self.shared).into(),
-            _ => PhaseState::<Idle>::new(self.shared).into(),
+        let policy = self.shared.state.error_policy;
+        Some(
+            if self.inner.is_retryable() && !policy.is_exceeded(self.shared.state.failed_rounds) {
+                PhaseState::<Idle>::new(self.shared).into()
+            } else {
+                PhaseState::<Shutdown>::new(self.shared).into()
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        settings::ErrorPolicy,
+        state_machine::{
+            control::{ControlRequest, ControlSender},
+            requests::RequestSender,
+            tests::utils,
+        },
+    };
+    use tokio::time::timeout;
+
+    fn error_state(
+        failed_rounds: u32,
+        error: StateError,
+    ) -> (PhaseState<StateError>, RequestSender, ControlSender) {
+        let (mut shared, _, request_tx, control_tx) = utils::init_shared();
+        shared.state.error_policy = ErrorPolicy {
+            min_backoff: 0,
+            max_backoff: 0,
+            max_failed_rounds: 2,
+        };
+        shared.state.failed_rounds = failed_rounds;
+        (
+            PhaseState::<StateError>::new(shared, error),
+            request_tx,
+            control_tx,
+        )
+    }
+
+    #[tokio::test]
+    async fn retryable_error_restarts_round() {
+        let (mut state, _request_tx, _control_tx) =
+            error_state(0, StateError::RoundError(RoundFailed::NoMask));
+        state.run().await.unwrap();
+        assert_eq!(state.shared.state.failed_rounds, 1);
+        assert!(state.next().unwrap().is_idle());
+    }
+
+    #[tokio::test]
+    async fn too_many_failed_rounds_shut_down() {
+        let (mut state, _request_tx, _control_tx) =
+            error_state(1, StateError::RoundError(RoundFailed::NoMask));
+        state.run().await.unwrap();
+        assert_eq!(state.shared.state.failed_rounds, 2);
+        assert!(state.next().unwrap().is_shutdown());
+    }
+
+    #[tokio::test]
+    async fn aborted_round_restarts_without_counting() {
+        let (mut state, _request_tx, _control_tx) = error_state(1, StateError::Aborted);
+        state.run().await.unwrap();
+        assert_eq!(state.shared.state.failed_rounds, 1);
+        assert!(state.next().unwrap().is_idle());
+    }
+
+    #[tokio::test]
+    async fn fatal_error_shuts_down() {
+        let (mut state, _request_tx, _control_tx) =
+            error_state(0, StateError::ChannelError("closed"));
+        state.run().await.unwrap();
+        assert_eq!(state.shared.state.failed_rounds, 0);
+        assert!(state.next().unwrap().is_shutdown());
+    }
+
+    #[tokio::test]
+    async fn deterministic_round_error_shuts_down() {
+        let (mut state, _request_tx, _control_tx) =
+            error_state(0, StateError::RoundError(RoundFailed::Aggregation));
+        state.run().await.unwrap();
+        assert_eq!(state.shared.state.failed_rounds, 0);
+        assert!(state.next().unwrap().is_shutdown());
+    }
+
+    #[test]
+    fn round_error_is_retryable() {
+        use xaynet_core::mask::UnmaskingError;
+
+        let retryable = [
+            RoundFailed::AmbiguousMasks,
+            RoundFailed::NoMask,
+            RoundFailed::NoQuorum,
+            RoundFailed::Unmasking(UnmaskingError::NoModel),
+            RoundFailed::Unmasking(UnmaskingError::MaskMismatch),
+            RoundFailed::Unmasking(UnmaskingError::InvalidMask),
+            RoundFailed::InvalidScalar,
+            RoundFailed::InvalidModel,
+        ];
+        for err in retryable.iter() {
+            assert!(err.is_retryable(), "{:?} is not retryable", err);
+        }
+
+        let fatal = [
+            RoundFailed::Unmasking(UnmaskingError::TooManyModels),
+            RoundFailed::Aggregation,
+        ];
+        for err in fatal.iter() {
+            assert!(!err.is_retryable(), "{:?} is retryable", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn requested_shutdown_shuts_down() {
+        let (mut state, _request_tx, _control_tx) = error_state(0, StateError::ShutdownRequested);
+        state.run().await.unwrap();
+        assert_eq!(state.shared.state.failed_rounds, 0);
+        assert!(state.next().unwrap().is_shutdown());
+    }
+
+    #[tokio::test]
+    async fn backoff_is_interrupted_by_shutdown() {
+        let (mut state, _request_tx, control_tx) =
+            error_state(0, StateError::RoundError(RoundFailed::NoMask));
+        state.shared.state.error_policy.min_backoff = 3600;
+        state.shared.state.error_policy.max_backoff = 3600;
+
+        let (res, control_res) = timeout(Duration::from_secs(5), async {
+            tokio::join!(state.run(), control_tx.control(ControlRequest::Shutdown))
         })
+        .await
+        .expect("the backoff was not interrupted");
+        assert!(matches!(res, Err(StateError::ShutdownRequested)));
+        assert!(control_res.is_ok());
     }
 }
@@ -3,27 +3,35 @@ use xaynet_core::{
     crypto::{ByteObject, EncryptKeyPair, SigningKeySeed},
 };
 
-use crate::state_machine::{
-    events::{DictionaryUpdate, MaskLengthUpdate},
-    phases::{Handler, Phase, PhaseName, PhaseState, Shared, Sum},
-    requests::StateMachineRequest,
-    StateError,
-    StateMachine,
-    StateMachineError,
+use crate::{
+    settings::{validate_fractions, FractionsPolicy},
+    state_machine::{
+        events::{DictionaryUpdate, MaskLengthUpdate, MessageCounts},
+        phases::{Handler, Phase, PhaseName, PhaseState, Shared, Sum},
+        requests::StateMachineRequest,
+        StateError,
+        StateMachine,
+        StateMachineError,
+    },
 };
 
 #[cfg(feature = "metrics")]
 use crate::metrics;
 
 use sodiumoxide::crypto::hash::sha256;
+use std::time::Duration;
+
+/// The maximum factor by which a fraction changes from one round to the next.
+const MAX_FRACTION_ADJUSTMENT: f64 = 2.;
 
 /// Idle state
 #[derive(Debug)]
 pub struct Idle;
 
+#[async_trait]
 impl Handler for PhaseState<Idle> {
     /// Reject the request with a [`StateMachineError::MessageRejected`]
-    fn handle_request(&mut self, _req: StateMachineRequest) -> Result<(), StateMachineError> {
+    async fn handle_request(&mut self, _req: StateMachineRequest) -> Result<(), StateMachineError> {
         Err(StateMachineError::MessageRejected)
     }
 }
@@ -36,11 +44,25 @@ impl Phase for PhaseState<Idle> {
     ///
     /// See the [module level documentation](../index.html) for more details.
     async fn run(&mut self) -> Result<(), StateError> {
+        if let Some(connection) = self.shared.redis_connection().await {
+            info!("flushing the dictionaries of the previous round");
+            connection.flush_dicts().await?;
+        }
+
         info!("updating the keys");
         self.gen_round_keypair();
 
-        info!("updating round thresholds");
-        self.update_round_thresholds();
+        if let Some(pet_settings) = self.shared.state.pending_pet_settings.take() {
+            info!(
+                "applying the updated PET settings from round {}: {:?}",
+                self.shared.state.round_id, pet_settings
+            );
+            self.shared.state.apply_pet_settings(pet_settings);
+        } else {
+            info!("updating round thresholds");
+            self.update_round_thresholds();
+        }
+        self.shared.set_message_counts(MessageCounts::default());
 
         info!("updating round seeds");
         self.update_round_seed();
@@ -77,8 +99,9 @@ impl Phase for PhaseState<Idle> {
             )
         );
 
-        // TODO: add a delay to prolongate the idle phase
-        Ok(())
+        let min_time = self.shared.state.min_idle_time;
+        debug!("in idle phase for a minimum of {} seconds", min_time);
+        self.delay(Duration::from_secs(min_time)).await
     }
 
     fn next(self) -> Option<StateMachine> {
@@ -101,7 +124,45 @@ impl PhaseState<Idle> {
         }
     }
 
-    fn update_round_thresholds(&mut self) {}
+    /// Updates the sum and update fractions of the round parameters
+    /// according to the fractions policy and the message counts of the
+    /// previous round.
+    fn update_round_thresholds(&mut self) {
+        let (target_sum, target_update) = match self.shared.state.fractions_policy {
+            FractionsPolicy::Fixed => return,
+            FractionsPolicy::Target {
+                sum_count,
+                update_count,
+            } => (sum_count, update_count),
+        };
+        // there is no previous round to learn from
+        if self.shared.state.round_id <= 1 {
+            return;
+        }
+
+        let MessageCounts { sum, update, .. } = self.shared.message_counts;
+        let round_params = &mut self.shared.state.round_params;
+        let sum_fraction = adjust_fraction(round_params.sum, target_sum, sum);
+        // the update participants are selected among the participants
+        // which have not been selected for the sum task
+        let update_fraction = adjust_fraction(round_params.update, target_update, update)
+            * (1. - round_params.sum)
+            / (1. - sum_fraction);
+
+        if let Err(e) = validate_fractions(sum_fraction, update_fraction) {
+            warn!(
+                "keeping the fractions of the previous round, the new fractions sum = {} and update = {} are invalid: {}",
+                sum_fraction, update_fraction, e
+            );
+            return;
+        }
+        debug!(
+            "new fractions: sum = {}, update = {}",
+            sum_fraction, update_fraction
+        );
+        round_params.sum = sum_fraction;
+        round_params.update = update_fraction;
+    }
 
     /// Updates the seed round parameter.
     fn update_round_seed(&mut self) {
@@ -129,17 +190,34 @@ impl PhaseState<Idle> {
     }
 }
 
+/// Scales a fraction such that the expected number of accepted messages
+/// approaches the target. The fraction is kept if no message has been
+/// accepted.
+fn adjust_fraction(fraction: f64, target: usize, count: u64) -> f64 {
+    if count == 0 {
+        return fraction;
+    }
+    let factor = (target as f64 / count as f64)
+        .max(1. / MAX_FRACTION_ADJUSTMENT)
+        .min(MAX_FRACTION_ADJUSTMENT);
+    fraction * factor
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::state_machine::{
-        events::Event,
-        tests::{builder::StateMachineBuilder, utils},
+    use crate::{
+        settings::PetSettings,
+        state_machine::{
+            control::ControlRequest,
+            events::Event,
+            tests::{builder::StateMachineBuilder, utils},
+        },
     };
 
     #[tokio::test]
     async fn round_id_is_updated_when_idle_phase_runs() {
-        let (shared, event_subscriber, ..) = utils::init_shared();
+        let (shared, event_subscriber, _request_tx, _control_tx) = utils::init_shared();
 
         let keys = event_subscriber.keys_listener();
         let id = keys.get_latest().round_id;
@@ -152,6 +230,116 @@ mod test {
         assert_eq!(id, 1);
     }
 
+    #[tokio::test]
+    async fn pending_pet_settings_are_applied() {
+        let (mut shared, event_subscriber, _request_tx, _control_tx) = utils::init_shared();
+        shared.state.pending_pet_settings = Some(PetSettings {
+            sum: 0.2,
+            min_sum_count: 5,
+            max_sum_time: 60,
+            ..utils::pet_settings()
+        });
+
+        let mut idle_phase = PhaseState::<Idle>::new(shared);
+        idle_phase.run().await.unwrap();
+
+        let state = &idle_phase.shared.state;
+        assert!(idle_phase.shared.state.pending_pet_settings.is_none());
+        assert_eq!(state.min_sum_count, 5);
+        assert_eq!(state.max_sum_time, 60);
+        let params = event_subscriber.params_listener().get_latest();
+        assert_eq!(params.round_id, 1);
+        assert_close(params.event.sum, 0.2);
+    }
+
+    #[tokio::test]
+    async fn idle_time_is_interrupted_by_shutdown() {
+        let (mut shared, _event_subscriber, _request_tx, control_tx) = utils::init_shared();
+        shared.state.min_idle_time = 3600;
+
+        let mut idle_phase = PhaseState::<Idle>::new(shared);
+        let (res, control_res) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(
+                idle_phase.run(),
+                control_tx.control(ControlRequest::Shutdown)
+            )
+        })
+        .await
+        .expect("the idle time was not interrupted");
+        assert!(matches!(res, Err(StateError::ShutdownRequested)));
+        assert!(control_res.is_ok());
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn adjust_fraction_is_bounded() {
+        assert_close(adjust_fraction(0.2, 10, 5), 0.4);
+        assert_close(adjust_fraction(0.2, 10, 40), 0.1);
+        assert_close(adjust_fraction(0.2, 100, 5), 0.4);
+        assert_close(adjust_fraction(0.2, 1, 100), 0.1);
+        assert_close(adjust_fraction(0.2, 10, 0), 0.2);
+    }
+
+    #[tokio::test]
+    async fn fractions_are_adapted_to_participation() {
+        let (mut shared, event_subscriber, _request_tx, _control_tx) = utils::init_shared();
+        shared.state.fractions_policy = FractionsPolicy::Target {
+            sum_count: 2,
+            update_count: 10,
+        };
+        shared.set_round_id(1);
+        shared.set_message_counts(MessageCounts {
+            sum: 1,
+            update: 20,
+            sum2: 1,
+        });
+
+        let mut idle_phase = PhaseState::<Idle>::new(shared);
+        idle_phase.run().await.unwrap();
+
+        // sum: 0.4 * 2, update: 0.5 * 0.5 * (1 - 0.4) / (1 - 0.8)
+        let round_params = event_subscriber.params_listener().get_latest().event;
+        assert_close(round_params.sum, 0.8);
+        assert_close(round_params.update, 0.75);
+        assert_eq!(
+            event_subscriber.message_counts_listener().get_latest(),
+            Event {
+                round_id: 2,
+                event: MessageCounts::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_fractions_are_not_adapted() {
+        let (mut shared, event_subscriber, _request_tx, _control_tx) = utils::init_shared();
+        shared.state.fractions_policy = FractionsPolicy::Target {
+            sum_count: 2,
+            update_count: 10,
+        };
+        shared.set_round_id(1);
+        shared.set_message_counts(MessageCounts {
+            sum: 1,
+            update: 1,
+            sum2: 1,
+        });
+
+        let mut idle_phase = PhaseState::<Idle>::new(shared);
+        idle_phase.run().await.unwrap();
+
+        let round_params = event_subscriber.params_listener().get_latest().event;
+        assert_close(round_params.sum, 0.4);
+        assert_close(round_params.update, 0.5);
+    }
+
     #[tokio::test]
     async fn idle_to_sum() {
         let (state_machine, _request_tx, events) =
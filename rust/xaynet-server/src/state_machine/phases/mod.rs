@@ -18,23 +18,32 @@ pub use self::{
     update::Update,
 };
 
-use crate::state_machine::{
-    coordinator::CoordinatorState,
-    events::EventPublisher,
-    requests::{RequestReceiver, ResponseSender, StateMachineRequest},
-    StateMachine,
-    StateMachineError,
+use crate::{
+    state_machine::{
+        control::{ControlError, ControlReceiver, ControlRequest, ControlResponseSender},
+        coordinator::CoordinatorState,
+        events::{EventPublisher, MessageCounts},
+        requests::{RequestReceiver, ResponseSender, StateMachineRequest},
+        StateMachine,
+        StateMachineError,
+    },
+    storage::redis,
 };
 
 #[cfg(feature = "metrics")]
 use crate::{metrics, metrics::MetricsSender};
 
-use futures::StreamExt;
+use ::redis::RedisResult;
+use futures::{future, StreamExt};
+use std::task::Poll;
+use tokio::time::{timeout_at, Duration, Instant};
 use tracing::Span;
 use tracing_futures::Instrument;
+use validator::Validate;
+use xaynet_core::{mask::Aggregation, SeedDict};
 
 /// Name of the current phase
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum PhaseName {
     Idle,
     Sum,
@@ -59,9 +68,17 @@ pub trait Phase {
 }
 
 /// A trait that must be implemented by a state to handle a request.
+#[async_trait]
 pub trait Handler {
     /// Handles a request.
-    fn handle_request(&mut self, req: StateMachineRequest) -> Result<(), StateMachineError>;
+    async fn handle_request(&mut self, req: StateMachineRequest) -> Result<(), StateMachineError>;
+
+    /// Checks whether the minimum number of messages has been accepted, such that the phase may
+    /// be ended early by a [`ControlRequest::Skip`]. Phases without a minimum message count can't
+    /// be ended early.
+    fn has_enough_messages(&self) -> bool {
+        false
+    }
 }
 
 /// I/O interfaces.
@@ -69,8 +86,13 @@ pub trait Handler {
 pub struct IO {
     /// The request receiver half.
     pub(in crate::state_machine) request_rx: RequestReceiver,
+    /// The control request receiver half.
+    pub(in crate::state_machine) control_rx: ControlReceiver,
     /// The event publisher.
     pub(in crate::state_machine) events: EventPublisher,
+    /// The Redis client that persists the coordinator state and the dictionaries. Persistence
+    /// is disabled if no client is available.
+    pub(in crate::state_machine) redis: Option<redis::Client>,
     #[cfg(feature = "metrics")]
     /// The metrics sender half.
     pub(in crate::state_machine) metrics_tx: MetricsSender,
@@ -84,6 +106,10 @@ pub struct Shared {
     pub(in crate::state_machine) state: CoordinatorState,
     /// I/O interfaces.
     pub(in crate::state_machine) io: IO,
+    /// The number of messages accepted in the current round.
+    pub(in crate::state_machine) message_counts: MessageCounts,
+    /// Whether the state machine pauses after the current phase.
+    pub(in crate::state_machine) paused: bool,
 }
 
 impl Shared {
@@ -91,29 +117,150 @@ impl Shared {
         coordinator_state: CoordinatorState,
         publisher: EventPublisher,
         request_rx: RequestReceiver,
+        control_rx: ControlReceiver,
+        redis: Option<redis::Client>,
         #[cfg(feature = "metrics")] metrics_tx: MetricsSender,
     ) -> Self {
         Self {
             state: coordinator_state,
             io: IO {
                 request_rx,
+                control_rx,
                 events: publisher,
+                redis,
                 #[cfg(feature = "metrics")]
                 metrics_tx,
             },
+            message_counts: MessageCounts::default(),
+            paused: false,
         }
     }
 
-    /// Set the round ID to the given value
+    /// Set the round ID to the given value.
     pub fn set_round_id(&mut self, id: u64) {
         self.state.round_id = id;
         self.io.events.set_round_id(id);
     }
 
+    /// Sets and broadcasts the number of messages accepted in the current round.
+    pub(in crate::state_machine) fn set_message_counts(&mut self, counts: MessageCounts) {
+        self.message_counts = counts;
+        self.io.events.broadcast_message_counts(counts);
+    }
+
+    /// Counts a message that has been accepted in the given phase.
+    fn count_accepted_message(&mut self, phase: PhaseName) {
+        let mut counts = self.message_counts;
+        match phase {
+            PhaseName::Sum => counts.sum += 1,
+            PhaseName::Update => counts.update += 1,
+            PhaseName::Sum2 => counts.sum2 += 1,
+            _ => return,
+        }
+        self.set_message_counts(counts);
+    }
+
     /// Return the current round ID
     pub fn round_id(&self) -> u64 {
         self.state.round_id
     }
+
+    /// Acquires a connection to Redis or returns `None` if persistence is disabled.
+    pub(in crate::state_machine) async fn redis_connection(&self) -> Option<redis::Connection> {
+        match self.io.redis {
+            Some(ref client) => Some(client.connection().await),
+            None => None,
+        }
+    }
+
+    /// Stores the coordinator state as it is, e.g. after it has been changed in the middle of a
+    /// phase.
+    ///
+    /// A failure is only logged, since the state is stored again at the beginning of the next
+    /// phase.
+    async fn persist_state(&mut self) {
+        if let Some(connection) = self.redis_connection().await {
+            debug!("storing the coordinator state");
+            if let Err(err) = connection.set_coordinator_state(&self.state).await {
+                warn!("failed to store the coordinator state: {}", err);
+            }
+        }
+    }
+
+    /// Stores the coordinator state together with the given phase.
+    async fn store_state(&mut self, phase: PhaseName) -> RedisResult<()> {
+        self.state.phase = phase;
+        if let Some(connection) = self.redis_connection().await {
+            debug!("storing the coordinator state");
+            connection.set_coordinator_state(&self.state).await?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the model and scalar aggregations from their checkpoints and the remaining masked
+    /// models and scalars in Redis.
+    ///
+    /// The remaining masked models and scalars are checkpointed as well, such that they aren't
+    /// aggregated twice once the update phase is resumed.
+    async fn restore_aggregations(
+        &self,
+        redis: &redis::Client,
+    ) -> RedisResult<(Aggregation, Aggregation)> {
+        let mask_config = self.state.round_params.mask_config;
+        let (mut model_agg, mut scalar_agg) =
+            match redis.connection().await.get_aggregations().await? {
+                Some((model_agg, scalar_agg, nb_models)) => (
+                    Aggregation::from_aggregated(model_agg, nb_models),
+                    Aggregation::from_aggregated(scalar_agg, nb_models),
+                ),
+                None => (
+                    Aggregation::new(mask_config.vect, self.state.model_size),
+                    Aggregation::new(mask_config.unit, 1),
+                ),
+            };
+
+        let masked_models = redis.connection().await.get_masked_models().await?;
+        let nb_remaining = masked_models.len();
+        for masked_model in masked_models {
+            model_agg.aggregate(masked_model);
+        }
+        for masked_scalar in redis.connection().await.get_masked_scalars().await? {
+            scalar_agg.aggregate(masked_scalar);
+        }
+
+        if nb_remaining > 0 {
+            redis
+                .connection()
+                .await
+                .checkpoint_aggregations(
+                    &model_agg.clone().into(),
+                    &scalar_agg.clone().into(),
+                    model_agg.nb_models(),
+                    nb_remaining,
+                )
+                .await?;
+        }
+        Ok((model_agg, scalar_agg))
+    }
+}
+
+/// Returns the number of update participants whose seeds are in the seed dictionary.
+fn count_updates(seed_dict: &SeedDict) -> u64 {
+    seed_dict
+        .values()
+        .next()
+        .map_or(0, |seeds| seeds.len() as u64)
+}
+
+/// The outcome of [`PhaseState::process_single()`].
+#[derive(Debug, PartialEq, Eq)]
+enum Processed {
+    /// A request or control request has been processed.
+    Incoming,
+    /// The phase has been ended early by a [`ControlRequest::Skip`].
+    Skipped,
+    /// The deadline has been reached before anything arrived.
+    Elapsed,
 }
 
 /// The state corresponding to a phase of the PET protocol.
@@ -132,34 +279,64 @@ impl<S> PhaseState<S>
 where
     Self: Handler + Phase,
 {
-    /// Processes requests for as long as the given duration.
-    async fn process_during(&mut self, dur: tokio::time::Duration) -> Result<(), StateError> {
-        tokio::select! {
-            err = self.process_loop() => {
-                error!("processing loop terminated before duration elapsed");
-                err
-            }
-            _ = tokio::time::delay_for(dur) => {
-                debug!("duration elapsed");
-                Ok(())
+    /// Processes requests for as long as the given duration or until the phase is ended early.
+    async fn process_during(&mut self, dur: Duration) -> Result<(), StateError> {
+        let deadline = Instant::now() + dur;
+        loop {
+            match self.process_single(deadline).await? {
+                Processed::Incoming => {}
+                Processed::Skipped => {
+                    info!("phase ended early");
+                    return Ok(());
+                }
+                Processed::Elapsed => {
+                    debug!("duration elapsed");
+                    return Ok(());
+                }
             }
         }
     }
 
-    /// Processes requests indefinitely.
-    async fn process_loop(&mut self) -> Result<(), StateError> {
-        loop {
-            self.process_single().await?;
+    /// Processes the next request or control request which arrives before the deadline.
+    ///
+    /// The deadline only applies to waiting for the next request. A request which has been
+    /// received is always handled to completion, because cancelling a handler halfway through
+    /// could leave the dictionaries in Redis out of sync with the ones in memory.
+    async fn process_single(&mut self, deadline: Instant) -> Result<Processed, StateError> {
+        if Instant::now() >= deadline {
+            return Ok(Processed::Elapsed);
         }
-    }
-
-    /// Processes the next available request.
-    async fn process_single(&mut self) -> Result<(), StateError> {
-        let (req, span, resp_tx) = self.next_request().await?;
+        let incoming = match timeout_at(deadline, self.next_incoming()).await {
+            Ok(incoming) => incoming?,
+            Err(_) => return Ok(Processed::Elapsed),
+        };
+        let (req, span, resp_tx) = match incoming {
+            Incoming::Request(req, span, resp_tx) => (req, span, resp_tx),
+            Incoming::Control(ControlRequest::Skip, resp_tx) => {
+                let res = if self.has_enough_messages() {
+                    Ok(())
+                } else {
+                    Err(ControlError::NotSkippable)
+                };
+                let processed = if res.is_ok() {
+                    Processed::Skipped
+                } else {
+                    Processed::Incoming
+                };
+                let _ = resp_tx.send(res);
+                return Ok(processed);
+            }
+            Incoming::Control(req, resp_tx) => {
+                self.apply_control(req, resp_tx).await?;
+                return Ok(Processed::Incoming);
+            }
+        };
         let _span_guard = span.enter();
-        let res = self.handle_request(req);
+        let res = self.handle_request(req).await;
 
-        if res.is_err() {
+        if res.is_ok() {
+            self.shared.count_accepted_message(Self::NAME);
+        } else {
             metrics!(
                 self.shared.io.metrics_tx,
                 metrics::message::rejected::increment(self.shared.state.round_id, Self::NAME)
@@ -169,7 +346,7 @@ where
         // This may error out if the receiver has already be dropped but
         // it doesn't matter for us.
         let _ = resp_tx.send(res.map_err(Into::into));
-        Ok(())
+        Ok(Processed::Incoming)
     }
 }
 
@@ -192,6 +369,18 @@ where
 
             metrics!(self.shared.io.metrics_tx, metrics::phase::update(phase));
 
+            match phase {
+                PhaseName::Error | PhaseName::Shutdown => {
+                    debug!("in error/shutdown state: not storing the coordinator state");
+                }
+                _ => {
+                    if let Err(err) = self.shared.store_state(phase).await {
+                        warn!("failed to store the coordinator state: {}", err);
+                        return Some(self.into_error_state(err.into()));
+                    }
+                }
+            }
+
             if let Err(err) = self.run().await {
                 warn!("phase failed: {:?}", err);
                 return Some(self.into_error_state(err));
@@ -212,6 +401,12 @@ where
                 }
             }
 
+            if phase != PhaseName::Shutdown {
+                if let Err(err) = self.process_controls().await {
+                    return Some(self.into_error_state(err));
+                }
+            }
+
             info!("transitioning to the next phase");
             self.next()
         }.instrument(span).await
@@ -221,43 +416,134 @@ where
     /// outdated. This happens at the end of each phase, before
     /// transitioning to the next phase.
     fn purge_outdated_requests(&mut self) -> Result<(), StateError> {
+        while let Some((_req, span, resp_tx)) = self.try_next_request()? {
+            self.discard_request(span, resp_tx);
+        }
+        Ok(())
+    }
+
+    /// Applies the pending control requests at the end of a phase and waits for as long as the
+    /// state machine is paused. Requests which arrive in the meantime are rejected.
+    async fn process_controls(&mut self) -> Result<(), StateError> {
+        while let Some((req, resp_tx)) = self.shared.io.control_rx.try_recv() {
+            self.apply_control(req, resp_tx).await?;
+        }
+
+        if self.shared.paused {
+            info!("state machine paused");
+        }
+        while self.shared.paused {
+            match self.next_incoming().await? {
+                Incoming::Request(_req, span, resp_tx) => self.discard_request(span, resp_tx),
+                Incoming::Control(req, resp_tx) => self.apply_control(req, resp_tx).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the given duration. Requests which arrive in the meantime are rejected and
+    /// control requests are applied.
+    ///
+    /// # Errors
+    /// Fails early if the request channel is closed, the round is aborted or the state machine
+    /// is requested to shut down.
+    async fn delay(&mut self, dur: Duration) -> Result<(), StateError> {
+        let mut delay = tokio::time::delay_for(dur);
         loop {
-            match self.try_next_request()? {
-                Some((_req, span, resp_tx)) => {
-                    let _span_guard = span.enter();
-                    info!("rejecting request");
-                    let _ = resp_tx.send(Err(StateMachineError::MessageRejected));
-
-                    metrics!(
-                        self.shared.io.metrics_tx,
-                        metrics::message::discarded::increment(
-                            self.shared.state.round_id,
-                            Self::NAME
-                        )
-                    );
-                }
-                None => return Ok(()),
+            tokio::select! {
+                _ = &mut delay => return Ok(()),
+                incoming = self.next_incoming() => match incoming? {
+                    Incoming::Request(_req, span, resp_tx) => self.discard_request(span, resp_tx),
+                    Incoming::Control(req, resp_tx) => self.apply_control(req, resp_tx).await?,
+                },
             }
         }
     }
+
+    /// Rejects a request which can't be processed in the current phase anymore.
+    fn discard_request(&mut self, span: Span, resp_tx: ResponseSender) {
+        let _span_guard = span.enter();
+        info!("rejecting request");
+        let _ = resp_tx.send(Err(StateMachineError::MessageRejected));
+
+        metrics!(
+            self.shared.io.metrics_tx,
+            metrics::message::discarded::increment(self.shared.state.round_id, Self::NAME)
+        );
+    }
+}
+
+/// An incoming request or control request.
+enum Incoming {
+    Request(StateMachineRequest, Span, ResponseSender),
+    Control(ControlRequest, ControlResponseSender),
 }
 
 // Functions that are available to all states
 impl<S> PhaseState<S> {
-    /// Receives the next [`Request`].
+    /// Receives the next [`Request`] or [`ControlRequest`], whichever arrives first.
     ///
     /// # Errors
-    /// Returns [`StateError::ChannelError`] when all sender halves have been dropped.
-    async fn next_request(
-        &mut self,
-    ) -> Result<(StateMachineRequest, Span, ResponseSender), StateError> {
+    /// Returns [`StateError::ChannelError`] when all request sender halves have been dropped.
+    async fn next_incoming(&mut self) -> Result<Incoming, StateError> {
         debug!("waiting for the next incoming request");
-        self.shared.io.request_rx.next().await.ok_or_else(|| {
+        // control requests take precedence over requests
+        let io = &mut self.shared.io;
+        let incoming = future::poll_fn(|cx| match io.control_rx.poll_next_unpin(cx) {
+            Poll::Ready(Some((req, resp_tx))) => Poll::Ready(Some(Incoming::Control(req, resp_tx))),
+            _ => io
+                .request_rx
+                .poll_next_unpin(cx)
+                .map(|item| item.map(|(req, span, resp_tx)| Incoming::Request(req, span, resp_tx))),
+        })
+        .await;
+        incoming.ok_or_else(|| {
             error!("request receiver broken: senders have been dropped");
             StateError::ChannelError("all message senders have been dropped!")
         })
     }
 
+    /// Applies a [`ControlRequest`] which doesn't end the current phase early.
+    ///
+    /// # Errors
+    /// Returns [`StateError::Aborted`] if the round is aborted and
+    /// [`StateError::ShutdownRequested`] if the state machine is requested to shut down.
+    async fn apply_control(
+        &mut self,
+        req: ControlRequest,
+        resp_tx: ControlResponseSender,
+    ) -> Result<(), StateError> {
+        info!("applying control request: {:?}", req);
+        let (res, outcome) = match req {
+            ControlRequest::Pause => {
+                self.shared.paused = true;
+                (Ok(()), Ok(()))
+            }
+            ControlRequest::Resume => {
+                self.shared.paused = false;
+                (Ok(()), Ok(()))
+            }
+            ControlRequest::Skip => (Err(ControlError::NotSkippable), Ok(())),
+            ControlRequest::Abort => (Ok(()), Err(StateError::Aborted)),
+            ControlRequest::Shutdown => (Ok(()), Err(StateError::ShutdownRequested)),
+            ControlRequest::UpdatePetSettings(pet_settings) => match pet_settings.validate() {
+                Ok(()) => {
+                    info!(
+                        "PET settings updated, applying them from round {}: {:?}",
+                        self.shared.state.round_id + 1,
+                        pet_settings
+                    );
+                    self.shared.state.pending_pet_settings = Some(pet_settings);
+                    self.shared.persist_state().await;
+                    (Ok(()), Ok(()))
+                }
+                Err(err) => (Err(ControlError::InvalidSettings(err.to_string())), Ok(())),
+            },
+        };
+        let _ = resp_tx.send(res);
+        outcome
+    }
+
     fn try_next_request(
         &mut self,
     ) -> Result<Option<(StateMachineRequest, Span, ResponseSender)>, StateError> {
@@ -284,7 +570,51 @@ impl<S> PhaseState<S> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::state_machine::tests::utils;
+    use crate::{
+        settings::PetSettings,
+        state_machine::{
+            requests::SumRequest,
+            tests::{builder::StateMachineBuilder, utils},
+        },
+    };
+    use tokio::{
+        sync::oneshot,
+        time::{timeout, Duration},
+    };
+    use xaynet_core::crypto::ByteObject;
+
+    /// A phase whose handler blocks until it is released.
+    struct Blocking {
+        release: Option<oneshot::Receiver<()>>,
+        handled: bool,
+    }
+
+    #[async_trait]
+    impl Handler for PhaseState<Blocking> {
+        async fn handle_request(
+            &mut self,
+            _: StateMachineRequest,
+        ) -> Result<(), StateMachineError> {
+            if let Some(release) = self.inner.release.take() {
+                let _ = release.await;
+            }
+            self.inner.handled = true;
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Phase for PhaseState<Blocking> {
+        const NAME: PhaseName = PhaseName::Sum;
+
+        async fn run(&mut self) -> Result<(), StateError> {
+            Ok(())
+        }
+
+        fn next(self) -> Option<StateMachine> {
+            None
+        }
+    }
 
     #[test]
     fn update_round_id() {
@@ -307,4 +637,122 @@ mod tests {
         let id = phases.get_latest().round_id;
         assert_eq!(id, 1);
     }
+
+    #[tokio::test]
+    async fn paused_until_resumed() {
+        let builder = StateMachineBuilder::new();
+        let control_tx = builder.control_sender();
+        let (state_machine, _request_tx, _events) = builder.build();
+        assert!(state_machine.is_idle());
+
+        let transition_fut = state_machine.next();
+        tokio::pin!(transition_fut);
+
+        // The idle phase pauses once it has finished.
+        let (paused, transition) = tokio::join!(
+            control_tx.control(ControlRequest::Pause),
+            timeout(Duration::from_millis(100), &mut transition_fut),
+        );
+        assert_eq!(paused, Ok(()));
+        assert!(transition.is_err());
+
+        let (resumed, state_machine) = tokio::join!(
+            control_tx.control(ControlRequest::Resume),
+            &mut transition_fut,
+        );
+        assert_eq!(resumed, Ok(()));
+        assert!(state_machine.unwrap().is_sum());
+    }
+
+    #[tokio::test]
+    async fn idle_not_skipped() {
+        let builder = StateMachineBuilder::new();
+        let control_tx = builder.control_sender();
+        let (state_machine, _request_tx, _events) = builder.build();
+
+        let (skipped, state_machine) = tokio::join!(
+            control_tx.control(ControlRequest::Skip),
+            state_machine.next(),
+        );
+        assert_eq!(skipped, Err(ControlError::NotSkippable));
+        assert!(state_machine.unwrap().is_sum());
+    }
+
+    #[tokio::test]
+    async fn invalid_pet_settings_rejected() {
+        let builder = StateMachineBuilder::new();
+        let control_tx = builder.control_sender();
+        let (state_machine, _request_tx, _events) = builder.build();
+
+        let pet_settings = PetSettings {
+            min_sum_time: 10,
+            max_sum_time: 5,
+            ..utils::pet_settings()
+        };
+        let (updated, state_machine) = tokio::join!(
+            control_tx.control(ControlRequest::UpdatePetSettings(pet_settings)),
+            state_machine.next(),
+        );
+        assert!(matches!(updated, Err(ControlError::InvalidSettings(_))));
+        let sum_state = state_machine.unwrap().into_sum_phase_state();
+        assert!(sum_state.shared.state.pending_pet_settings.is_none());
+    }
+
+    #[tokio::test]
+    async fn pet_settings_are_persisted() {
+        let (url, commands_rx) = utils::fake_redis();
+        let (mut shared, _events, _request_tx, control_tx) = utils::init_shared();
+        shared.io.redis = Some(redis::Client::new(url, 1).await.unwrap());
+        let mut state = PhaseState {
+            inner: Blocking {
+                release: None,
+                handled: false,
+            },
+            shared,
+        };
+
+        let pet_settings = PetSettings {
+            min_sum_count: 5,
+            ..utils::pet_settings()
+        };
+        let (updated, processed) = tokio::join!(
+            control_tx.control(ControlRequest::UpdatePetSettings(pet_settings)),
+            state.process_single(Instant::now() + Duration::from_secs(1)),
+        );
+        assert_eq!(updated, Ok(()));
+        assert_eq!(processed.unwrap(), Processed::Incoming);
+
+        let commands = commands_rx.try_iter().flatten().collect::<Vec<u8>>();
+        let stored_state = utils::stored_state(&commands);
+        assert_eq!(stored_state.pending_pet_settings, Some(pet_settings));
+    }
+
+    #[tokio::test]
+    async fn handler_not_cancelled_when_duration_elapses() {
+        let (shared, _events, request_tx, _control_tx) = utils::init_shared();
+        let (release_tx, release_rx) = oneshot::channel();
+        let mut state = PhaseState {
+            inner: Blocking {
+                release: Some(release_rx),
+                handled: false,
+            },
+            shared,
+        };
+
+        let req = StateMachineRequest::Sum(SumRequest {
+            participant_pk: ByteObject::zeroed(),
+            ephm_pk: ByteObject::zeroed(),
+        });
+        let response = tokio::spawn(async move { request_tx.request(req, Span::none()).await });
+
+        // The handler is still blocked when the duration elapses.
+        let release = async {
+            tokio::time::delay_for(Duration::from_millis(200)).await;
+            release_tx.send(()).unwrap();
+        };
+        let (processed, _) = tokio::join!(state.process_during(Duration::from_millis(50)), release);
+        assert!(processed.is_ok());
+        assert!(state.inner.handled);
+        assert!(response.await.unwrap().is_ok());
+    }
 }
@@ -14,11 +14,24 @@ impl Phase for PhaseState<Shutdown> {
 
     /// Shuts down the [`StateMachine`].
     ///
+    /// The coordinator state is checkpointed together with the phase of the interrupted round,
+    /// such that a new [`StateMachine`] resumes the round. The dictionaries, masked models and
+    /// masks are already written through to Redis while handling the requests.
+    ///
     /// See the [module level documentation](../index.html) for more details.
     async fn run(&mut self) -> Result<(), StateError> {
         // clear the request channel
         self.shared.io.request_rx.close();
         while self.shared.io.request_rx.recv().await.is_some() {}
+        // dropping the pending control requests notifies their senders about the shutdown
+        self.shared.io.control_rx.close();
+        while self.shared.io.control_rx.try_recv().is_some() {}
+
+        let phase = self.shared.state.phase;
+        info!("checkpointing the coordinator state in phase {:?}", phase);
+        if let Err(err) = self.shared.store_state(phase).await {
+            warn!("failed to checkpoint the coordinator state: {}", err);
+        }
         Ok(())
     }
 
@@ -37,3 +50,26 @@ impl PhaseState<Shutdown> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{state_machine::tests::utils, storage::redis};
+
+    #[tokio::test]
+    async fn shutdown_checkpoints_the_coordinator_state() {
+        let (url, commands_rx) = utils::fake_redis();
+        let (mut shared, _event_subscriber, _request_tx, _control_tx) = utils::init_shared();
+        shared.io.redis = Some(redis::Client::new(url, 1).await.unwrap());
+        shared.state.phase = PhaseName::Sum2;
+        shared.set_round_id(3);
+
+        let mut shutdown_phase = PhaseState::<Shutdown>::new(shared);
+        shutdown_phase.run().await.unwrap();
+
+        let commands = commands_rx.try_iter().flatten().collect::<Vec<u8>>();
+        let state = utils::stored_state(&commands);
+        assert_eq!(state.phase, PhaseName::Sum2);
+        assert_eq!(state.round_id, 3);
+    }
+}
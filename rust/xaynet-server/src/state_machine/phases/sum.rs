@@ -2,18 +2,22 @@ use std::sync::Arc;
 
 use xaynet_core::{LocalSeedDict, SeedDict, SumDict};
 
-use crate::state_machine::{
-    events::DictionaryUpdate,
-    phases::{Handler, Phase, PhaseName, PhaseState, Shared, StateError, Update},
-    requests::{StateMachineRequest, SumRequest},
-    StateMachine,
-    StateMachineError,
+use crate::{
+    state_machine::{
+        events::{DictionaryUpdate, MessageCounts},
+        phases::{Handler, Phase, PhaseName, PhaseState, Processed, Shared, StateError, Update},
+        requests::{StateMachineRequest, SumRequest},
+        StateMachine,
+        StateMachineError,
+    },
+    storage::{redis, AddSumParticipant},
 };
 
 #[cfg(feature = "metrics")]
 use crate::metrics;
 
-use tokio::time::{timeout, Duration};
+use ::redis::RedisResult;
+use tokio::time::{Duration, Instant};
 
 /// Sum state
 #[derive(Debug)]
@@ -31,25 +35,29 @@ impl Sum {
     }
 }
 
+#[async_trait]
 impl Handler for PhaseState<Sum> {
     /// Handles a [`StateMachineRequest`].
     ///
     /// If the request is a [`StateMachineRequest::Update`] or
     /// [`StateMachineRequest::Sum2`] request, the request sender will receive a
     /// [`StateMachineError::MessageRejected`].
-    fn handle_request(&mut self, req: StateMachineRequest) -> Result<(), StateMachineError> {
+    async fn handle_request(&mut self, req: StateMachineRequest) -> Result<(), StateMachineError> {
         match req {
             StateMachineRequest::Sum(sum_req) => {
                 metrics!(
                     self.shared.io.metrics_tx,
                     metrics::message::sum::increment(self.shared.state.round_id, Self::NAME)
                 );
-                self.handle_sum(sum_req);
-                Ok(())
+                self.handle_sum(sum_req).await
             }
             _ => Err(StateMachineError::MessageRejected),
         }
     }
+
+    fn has_enough_messages(&self) -> bool {
+        self.has_enough_sums()
+    }
 }
 
 #[async_trait]
@@ -68,7 +76,8 @@ where
         self.process_during(Duration::from_secs(min_time)).await?;
 
         let time_left = self.shared.state.max_sum_time - min_time;
-        timeout(Duration::from_secs(time_left), self.process_until_enough()).await??;
+        self.process_until_enough(Duration::from_secs(time_left))
+            .await?;
 
         info!(
             "{} sum messages handled (min {} required)",
@@ -107,14 +116,21 @@ where
     Self: Handler + Phase,
 {
     /// Processes requests until there are enough.
-    async fn process_until_enough(&mut self) -> Result<(), StateError> {
+    ///
+    /// # Errors
+    /// Fails with [`StateError::TimeoutError`] if there aren't enough requests within the given
+    /// duration.
+    async fn process_until_enough(&mut self, dur: Duration) -> Result<(), StateError> {
+        let deadline = Instant::now() + dur;
         while !self.has_enough_sums() {
             debug!(
                 "{} sum messages handled (min {} required)",
                 self.inner.sum_dict.len(),
                 self.shared.state.min_sum_count,
             );
-            self.process_single().await?;
+            if self.process_single(deadline).await? == Processed::Elapsed {
+                return Err(StateError::TimeoutError);
+            }
         }
         Ok(())
     }
@@ -133,13 +149,58 @@ impl PhaseState<Sum> {
         }
     }
 
+    /// Restores the sum state from the sum dictionary in Redis.
+    pub(in crate::state_machine) async fn restore(
+        mut shared: Shared,
+        redis: &redis::Client,
+    ) -> RedisResult<Self> {
+        info!("restoring sum phase");
+        let sum_dict = redis.connection().await.get_sum_dict().await?;
+        shared.set_message_counts(MessageCounts {
+            sum: sum_dict.len() as u64,
+            ..MessageCounts::default()
+        });
+        Ok(Self {
+            inner: Sum {
+                sum_dict,
+                seed_dict: None,
+            },
+            shared,
+        })
+    }
+
     /// Handles a sum request.
-    fn handle_sum(&mut self, req: SumRequest) {
+    ///
+    /// # Errors
+    /// Fails if the participant already submitted a sum message or if the sum dictionary entry
+    /// could not be stored.
+    async fn handle_sum(&mut self, req: SumRequest) -> Result<(), StateMachineError> {
         let SumRequest {
             participant_pk,
             ephm_pk,
         } = req;
+
+        if self.inner.sum_dict.contains_key(&participant_pk) {
+            warn!("sum participant already submitted a sum message");
+            return Err(StateMachineError::MessageRejected);
+        }
+
+        if let Some(connection) = self.shared.redis_connection().await {
+            let added = connection
+                .add_sum_participant(&participant_pk, &ephm_pk)
+                .await
+                .map_err(|err| {
+                    warn!("failed to store sum participant: {}", err);
+                    StateMachineError::InternalError
+                })?;
+            if added == AddSumParticipant::AlreadyExists {
+                warn!("sum participant already exists in the stored sum dictionary");
+                return Err(StateMachineError::MessageRejected);
+            }
+        }
+
         self.inner.sum_dict.insert(participant_pk, ephm_pk);
+        Ok(())
     }
 
     /// Freezes the sum dictionary.
@@ -171,6 +232,7 @@ impl PhaseState<Sum> {
 mod test {
     use super::*;
     use crate::state_machine::{
+        control::{ControlError, ControlRequest},
         events::Event,
         tests::{builder::StateMachineBuilder, utils},
     };
@@ -242,4 +304,61 @@ mod test {
             }
         );
     }
+
+    #[tokio::test]
+    pub async fn sum_skipped_after_enough_sums() {
+        let sum = Sum {
+            sum_dict: SumDict::new(),
+            seed_dict: None,
+        };
+        let builder = StateMachineBuilder::new()
+            .with_phase(sum)
+            .with_sum_ratio(1.0)
+            .with_update_ratio(0.0)
+            .with_min_sum(1)
+            // Without skipping, the sum phase would last for an hour.
+            .with_min_sum_time(3600)
+            .with_model_size(4);
+        let control_tx = builder.control_sender();
+        let (state_machine, request_tx, events) = builder.build();
+
+        let round_params = events.params_listener().get_latest().event;
+        let mut summer = utils::generate_summer(&round_params.seed, 1.0, 0.0);
+        let sum_msg = summer.compose_sum_message(round_params.pk);
+        let request_fut = async {
+            request_tx.msg(&sum_msg).await.unwrap();
+            control_tx.control(ControlRequest::Skip).await
+        };
+        let transition_fut = async { state_machine.next().await.unwrap() };
+
+        let (skipped, state_machine) = tokio::join!(request_fut, transition_fut);
+        assert_eq!(skipped, Ok(()));
+        assert!(state_machine.is_update());
+    }
+
+    #[tokio::test]
+    pub async fn sum_not_skipped_before_enough_sums() {
+        let sum = Sum {
+            sum_dict: SumDict::new(),
+            seed_dict: None,
+        };
+        let builder = StateMachineBuilder::new().with_phase(sum).with_min_sum(1);
+        let control_tx = builder.control_sender();
+        let (state_machine, _request_tx, _events) = builder.build();
+
+        let control_fut = async {
+            let skipped = control_tx.control(ControlRequest::Skip).await;
+            let aborted = control_tx.control(ControlRequest::Abort).await;
+            (skipped, aborted)
+        };
+        let transition_fut = async { state_machine.next().await.unwrap() };
+
+        let ((skipped, aborted), state_machine) = tokio::join!(control_fut, transition_fut);
+        assert_eq!(skipped, Err(ControlError::NotSkippable));
+        assert_eq!(aborted, Ok(()));
+        assert!(matches!(
+            state_machine.into_error_phase_state().inner,
+            StateError::Aborted
+        ));
+    }
 }
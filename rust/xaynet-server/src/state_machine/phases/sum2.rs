@@ -1,21 +1,38 @@
+use std::sync::Arc;
+
 use xaynet_core::{
     mask::{Aggregation, MaskObject},
     SumDict,
     SumParticipantPublicKey,
 };
 
-use crate::state_machine::{
-    coordinator::MaskDict,
-    phases::{Handler, Phase, PhaseName, PhaseState, Shared, StateError, Unmask},
-    requests::{StateMachineRequest, Sum2Request},
-    StateMachine,
-    StateMachineError,
+use crate::{
+    state_machine::{
+        coordinator::{mask_digest, MaskDict, MaskVotes},
+        events::{DictionaryUpdate, MaskLengthUpdate, MessageCounts},
+        phases::{
+            count_updates,
+            Handler,
+            Phase,
+            PhaseName,
+            PhaseState,
+            Processed,
+            Shared,
+            StateError,
+            Unmask,
+        },
+        requests::{StateMachineRequest, Sum2Request},
+        StateMachine,
+        StateMachineError,
+    },
+    storage::redis,
 };
 
 #[cfg(feature = "metrics")]
 use crate::metrics;
 
-use tokio::time::{timeout, Duration};
+use ::redis::RedisResult;
+use tokio::time::{Duration, Instant};
 
 /// Sum2 state
 #[derive(Debug)]
@@ -34,6 +51,9 @@ pub struct Sum2 {
 
     /// The scalar mask dictionary built during the sum2 phase.
     scalar_mask_dict: MaskDict,
+
+    /// The model mask votes of the sum participants built during the sum2 phase.
+    mask_votes: MaskVotes,
 }
 
 #[cfg(test)]
@@ -70,12 +90,25 @@ where
     ///
     /// See the [module level documentation](../index.html) for more details.
     async fn run(&mut self) -> Result<(), StateError> {
+        if self.shared.state.sum2_retried {
+            let max_time = self.shared.state.max_sum_time;
+            debug!("retrying sum2 phase for a maximum of {} seconds", max_time);
+            // The retry only waits for the sum participants which haven't submitted their masks
+            // yet, hence running out of time is not an error.
+            self.process_until_all(Duration::from_secs(max_time))
+                .await?;
+
+            info!("{} sum2 messages handled after retrying", self.mask_count());
+            return Ok(());
+        }
+
         let min_time = self.shared.state.min_sum_time;
         debug!("in sum2 phase for a minimum of {} seconds", min_time);
         self.process_during(Duration::from_secs(min_time)).await?;
 
         let time_left = self.shared.state.max_sum_time - min_time;
-        timeout(Duration::from_secs(time_left), self.process_until_enough()).await??;
+        self.process_until_enough(Duration::from_secs(time_left))
+            .await?;
 
         info!(
             "{} sum2 messages handled (min {} required)",
@@ -89,13 +122,29 @@ where
     ///
     /// See the [module level documentation](../index.html) for more details.
     fn next(self) -> Option<StateMachine> {
+        let Sum2 {
+            sum_dict,
+            model_agg,
+            scalar_agg,
+            model_mask_dict,
+            scalar_mask_dict,
+            mask_votes,
+        } = self.inner;
+        // the sum2 phase is retried at most once
+        let remaining_sum_dict = if self.shared.state.sum2_retried {
+            None
+        } else {
+            Some(sum_dict)
+        };
         Some(
             PhaseState::<Unmask>::new(
                 self.shared,
-                self.inner.model_agg,
-                self.inner.scalar_agg,
-                self.inner.model_mask_dict,
-                self.inner.scalar_mask_dict,
+                model_agg,
+                scalar_agg,
+                model_mask_dict,
+                scalar_mask_dict,
+                mask_votes,
+                remaining_sum_dict,
             )
             .into(),
         )
@@ -107,48 +156,77 @@ where
     Self: Handler + Phase,
 {
     /// Processes requests until there are enough.
-    async fn process_until_enough(&mut self) -> Result<(), StateError> {
+    ///
+    /// # Errors
+    /// Fails with [`StateError::TimeoutError`] if there aren't enough requests within the given
+    /// duration.
+    async fn process_until_enough(&mut self, dur: Duration) -> Result<(), StateError> {
+        let deadline = Instant::now() + dur;
         while !self.has_enough_sum2s() {
             debug!(
                 "{} sum2 messages handled (min {} required)",
                 self.mask_count(),
                 self.shared.state.min_sum_count
             );
-            self.process_single().await?;
+            if self.process_single(deadline).await? == Processed::Elapsed {
+                return Err(StateError::TimeoutError);
+            }
+        }
+        Ok(())
+    }
+
+    /// Processes requests until all sum participants submitted their masks or the given duration
+    /// has elapsed.
+    async fn process_until_all(&mut self, dur: Duration) -> Result<(), StateError> {
+        let deadline = Instant::now() + dur;
+        while !self.inner.sum_dict.is_empty() {
+            debug!(
+                "{} sum participants haven't submitted their masks yet",
+                self.inner.sum_dict.len()
+            );
+            if self.process_single(deadline).await? != Processed::Incoming {
+                break;
+            }
         }
         Ok(())
     }
 }
 
+#[async_trait]
 impl Handler for PhaseState<Sum2> {
     /// Handles a [`StateMachineRequest`],
     ///
     /// If the request is a [`StateMachineRequest::Sum`] or
     /// [`StateMachineRequest::Update`] request, the request sender
     /// will receive a [`StateMachineError::MessageRejected`].
-    fn handle_request(&mut self, req: StateMachineRequest) -> Result<(), StateMachineError> {
+    async fn handle_request(&mut self, req: StateMachineRequest) -> Result<(), StateMachineError> {
         match req {
             StateMachineRequest::Sum2(sum2_req) => {
                 metrics!(
                     self.shared.io.metrics_tx,
                     metrics::message::sum2::increment(self.shared.state.round_id, Self::NAME)
                 );
-                self.handle_sum2(sum2_req)
+                self.handle_sum2(sum2_req).await
             }
             _ => Err(StateMachineError::MessageRejected),
         }
     }
+
+    fn has_enough_messages(&self) -> bool {
+        self.has_enough_sum2s()
+    }
 }
 
 impl PhaseState<Sum2> {
     /// Creates a new sum2 state.
     pub fn new(
-        shared: Shared,
+        mut shared: Shared,
         sum_dict: SumDict,
         model_agg: Aggregation,
         scalar_agg: Aggregation,
     ) -> Self {
         info!("state transition");
+        shared.state.sum2_retried = false;
         Self {
             inner: Sum2 {
                 sum_dict,
@@ -156,38 +234,135 @@ impl PhaseState<Sum2> {
                 scalar_agg,
                 model_mask_dict: MaskDict::new(),
                 scalar_mask_dict: MaskDict::new(),
+                mask_votes: MaskVotes::new(),
+            },
+            shared,
+        }
+    }
+
+    /// Creates a new sum2 state to retry the sum2 phase with the remaining sum participants.
+    ///
+    /// The masks submitted so far are kept.
+    pub fn retry(
+        mut shared: Shared,
+        sum_dict: SumDict,
+        model_agg: Aggregation,
+        scalar_agg: Aggregation,
+        model_mask_dict: MaskDict,
+        scalar_mask_dict: MaskDict,
+        mask_votes: MaskVotes,
+    ) -> Self {
+        info!("state transition");
+        shared.state.sum2_retried = true;
+        Self {
+            inner: Sum2 {
+                sum_dict,
+                model_agg,
+                scalar_agg,
+                model_mask_dict,
+                scalar_mask_dict,
+                mask_votes,
             },
             shared,
         }
     }
 
+    /// Restores the sum2 state from the dictionaries as well as the masked models and scalars in
+    /// Redis.
+    ///
+    /// A retried sum2 phase is restored as a retry, since the coordinator state keeps track of it.
+    pub(in crate::state_machine) async fn restore(
+        mut shared: Shared,
+        redis: &redis::Client,
+    ) -> RedisResult<Self> {
+        info!("restoring sum2 phase");
+        let frozen_sum_dict = redis.connection().await.get_sum_dict().await?;
+        let seed_dict = redis.connection().await.get_seed_dict().await?;
+        let (model_agg, scalar_agg) = shared.restore_aggregations(redis).await?;
+        let model_mask_dict = redis.connection().await.get_mask_dict().await?;
+        let scalar_mask_dict = redis.connection().await.get_scalar_mask_dict().await?;
+
+        // Participants that already submitted their masks must not be able to do it again
+        let sum2_participants = redis.connection().await.get_sum2_participants().await?;
+        let sum_dict = frozen_sum_dict
+            .iter()
+            .filter(|(pk, _)| !sum2_participants.contains(pk))
+            .map(|(pk, ephm_pk)| (*pk, *ephm_pk))
+            .collect();
+        shared.set_message_counts(MessageCounts {
+            sum: frozen_sum_dict.len() as u64,
+            update: count_updates(&seed_dict),
+            sum2: sum2_participants.len() as u64,
+        });
+
+        let events = &mut shared.io.events;
+
+        info!("broadcasting sum dictionary");
+        events.broadcast_sum_dict(DictionaryUpdate::New(Arc::new(frozen_sum_dict)));
+
+        info!("broadcasting mask length");
+        events.broadcast_mask_length(MaskLengthUpdate::New(model_agg.len()));
+
+        info!("broadcasting the global seed dictionary");
+        events.broadcast_seed_dict(DictionaryUpdate::New(Arc::new(seed_dict)));
+
+        Ok(Self {
+            inner: Sum2 {
+                sum_dict,
+                model_agg,
+                scalar_agg,
+                model_mask_dict,
+                scalar_mask_dict,
+                // the votes are not persisted, hence disagreeing sum participants of the restored
+                // phase can't be named
+                mask_votes: MaskVotes::new(),
+            },
+            shared,
+        })
+    }
+
     /// Handles a sum2 request.
     /// If the handling of the sum2 message fails, an error is returned to the request sender.
-    fn handle_sum2(&mut self, req: Sum2Request) -> Result<(), StateMachineError> {
+    async fn handle_sum2(&mut self, req: Sum2Request) -> Result<(), StateMachineError> {
         let Sum2Request {
             participant_pk,
             model_mask,
             scalar_mask,
         } = req;
         self.add_mask(&participant_pk, model_mask, scalar_mask)
+            .await
     }
 
     /// Adds a mask to the mask dictionary.
     ///
     /// # Errors
-    /// Fails if the sum participant didn't register in the sum phase or it is a repetition.
-    fn add_mask(
+    /// Fails if the sum participant didn't register in the sum phase, it is a repetition or the
+    /// masks could not be stored.
+    async fn add_mask(
         &mut self,
         pk: &SumParticipantPublicKey,
         model_mask: MaskObject,
         scalar_mask: MaskObject,
     ) -> Result<(), StateMachineError> {
-        // We remove the participant key here to make sure a participant
-        // cannot submit a mask multiple times
-        if self.inner.sum_dict.remove(pk).is_none() {
+        if !self.inner.sum_dict.contains_key(pk) {
             return Err(StateMachineError::MessageRejected);
         }
 
+        if let Some(connection) = self.shared.redis_connection().await {
+            connection
+                .add_masks(pk, &model_mask, &scalar_mask)
+                .await
+                .map_err(|err| {
+                    warn!("failed to store the masks: {}", err);
+                    StateMachineError::InternalError
+                })?;
+        }
+
+        // We remove the participant key here to make sure a participant
+        // cannot submit a mask multiple times
+        self.inner.sum_dict.remove(pk);
+        self.inner.mask_votes.insert(*pk, mask_digest(&model_mask));
+
         if let Some(count) = self.inner.model_mask_dict.get_mut(&model_mask) {
             *count += 1;
         } else {
@@ -231,7 +406,7 @@ mod test {
     use xaynet_core::{
         common::RoundSeed,
         crypto::{ByteObject, EncryptKeyPair},
-        mask::{FromPrimitives, Model},
+        mask::{FromPrimitives, MaskConfigPair, Model},
         SumDict,
     };
 
@@ -255,14 +430,22 @@ mod test {
         let updater = utils::generate_updater(&seed, sum_ratio, update_ratio);
         let scalar = 1.0 / (n_updaters as f64 * update_ratio);
         let model = Model::from_primitives(vec![0; model_size].into_iter()).unwrap();
-        let msg =
-            updater.compose_update_message(coord_keys.public, &sum_dict, scalar, model.clone());
+        let mask_config: MaskConfigPair = utils::mask_settings().into();
+        let msg = updater
+            .compose_update_message(
+                coord_keys.public,
+                &sum_dict,
+                scalar,
+                model.clone(),
+                mask_config,
+            )
+            .unwrap();
         let masked_model = utils::masked_model(&msg);
         let masked_scalar = utils::masked_scalar(&msg);
         let local_seed_dict = utils::local_seed_dict(&msg);
-        let mut aggregation = Aggregation::new(utils::mask_settings().into(), model_size);
+        let mut aggregation = Aggregation::new(mask_config.vect, model_size);
         aggregation.aggregate(masked_model.clone());
-        let mut scalar_agg = Aggregation::new(utils::mask_settings().into(), 1);
+        let mut scalar_agg = Aggregation::new(mask_config.unit, 1);
         scalar_agg.aggregate(masked_scalar.clone());
 
         // Create the state machine
@@ -272,6 +455,7 @@ mod test {
             scalar_agg,
             model_mask_dict: MaskDict::new(),
             scalar_mask_dict: MaskDict::new(),
+            mask_votes: MaskVotes::new(),
         };
 
         let (state_machine, request_tx, events) = StateMachineBuilder::new()
@@ -281,13 +465,18 @@ mod test {
             .with_update_ratio(update_ratio)
             .with_min_sum(n_summers)
             .with_min_update(n_updaters)
-            .with_mask_config(utils::mask_settings().into())
+            .with_mask_config(mask_config)
             .build();
         assert!(state_machine.is_sum2());
 
         // Create a sum2 request.
         let msg = summer
-            .compose_sum2_message(coord_keys.public, &local_seed_dict, masked_model.data.len())
+            .compose_sum2_message(
+                coord_keys.public,
+                &local_seed_dict,
+                masked_model.data.len(),
+                mask_config,
+            )
             .unwrap();
 
         // Have the state machine process the request
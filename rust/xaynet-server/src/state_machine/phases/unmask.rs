@@ -1,15 +1,30 @@
 use std::{cmp::Ordering, sync::Arc};
 
-use xaynet_core::mask::{Aggregation, MaskObject, Model};
-
-use crate::state_machine::{
-    coordinator::MaskDict,
-    events::ModelUpdate,
-    phases::{Idle, Phase, PhaseName, PhaseState, Shared, StateError},
-    RoundFailed,
-    StateMachine,
+use num::{
+    bigint::BigInt,
+    rational::Ratio,
+    traits::{Signed, Zero},
+};
+use xaynet_core::{
+    mask::{Aggregation, MaskObject, Model},
+    SumDict,
+    SumParticipantPublicKey,
+};
+
+use crate::{
+    settings::{MaskQuorum, SanityChecks},
+    state_machine::{
+        coordinator::{mask_digest, GlobalModelInfo, MaskDict, MaskVotes},
+        events::ModelUpdate,
+        phases::{Idle, Phase, PhaseName, PhaseState, Shared, StateError, Sum2},
+        RoundFailed,
+        StateMachine,
+    },
+    storage::redis,
 };
 
+use ::redis::RedisResult;
+
 #[cfg(feature = "metrics")]
 use crate::metrics;
 
@@ -27,6 +42,16 @@ pub struct Unmask {
 
     /// The scalar mask dictionary built during the sum2 phase.
     scalar_mask_dict: MaskDict,
+
+    /// The model mask votes of the sum participants built during the sum2 phase.
+    mask_votes: MaskVotes,
+
+    /// The sum participants which haven't submitted their masks yet, if the sum2 phase may still
+    /// be retried.
+    remaining_sum_dict: Option<SumDict>,
+
+    /// Whether the sum2 phase is retried.
+    retry: bool,
 }
 
 #[cfg(test)]
@@ -54,7 +79,26 @@ impl Phase for PhaseState<Unmask> {
             )
         );
 
-        let global_model = self.end_round()?;
+        let (model_mask, scalar_mask) = match self.freeze_mask_dict() {
+            Ok(masks) => masks,
+            Err(err) if self.may_retry(&err) => {
+                warn!("{}, retrying the sum2 phase", err);
+                self.inner.retry = true;
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        self.report_disagreement(&model_mask);
+
+        let global_model = self.end_round(model_mask, scalar_mask)?;
+        self.shared.state.failed_rounds = 0;
+
+        if let Err(err) = self.store_global_model(&global_model).await {
+            warn!(
+                "failed to store the global model in the model history: {}",
+                err
+            );
+        }
 
         info!("broadcasting the new global model");
         self.shared
@@ -69,19 +113,56 @@ impl Phase for PhaseState<Unmask> {
     ///
     /// See the [module level documentation](../index.html) for more details.
     fn next(self) -> Option<StateMachine> {
+        let PhaseState {
+            inner:
+                Unmask {
+                    model_agg,
+                    scalar_agg,
+                    model_mask_dict,
+                    scalar_mask_dict,
+                    mask_votes,
+                    remaining_sum_dict,
+                    retry,
+                },
+            shared,
+        } = self;
+
+        if retry {
+            info!("going back to sum2 phase");
+            // Safe unwraps: the aggregations are only taken at the end of the round and a retry
+            // requires the remaining sum participants
+            return Some(
+                PhaseState::<Sum2>::retry(
+                    shared,
+                    remaining_sum_dict.unwrap(),
+                    model_agg.unwrap(),
+                    scalar_agg.unwrap(),
+                    model_mask_dict,
+                    scalar_mask_dict,
+                    mask_votes,
+                )
+                .into(),
+            );
+        }
+
         info!("going back to idle phase");
-        Some(PhaseState::<Idle>::new(self.shared).into())
+        Some(PhaseState::<Idle>::new(shared).into())
     }
 }
 
 impl PhaseState<Unmask> {
     /// Creates a new unmask state.
+    ///
+    /// The sum2 phase may be retried with the sum participants in the `remaining_sum_dict`, if
+    /// the masks don't reach the quorum.
     pub fn new(
         shared: Shared,
         model_agg: Aggregation,
         scalar_agg: Aggregation,
         model_mask_dict: MaskDict,
         scalar_mask_dict: MaskDict,
+        mask_votes: MaskVotes,
+        remaining_sum_dict: Option<SumDict>,
     ) -> Self {
         info!("state transition");
         Self {
@@ -90,54 +171,114 @@ impl PhaseState<Unmask> {
                 scalar_agg: Some(scalar_agg),
                 model_mask_dict,
                 scalar_mask_dict,
+                mask_votes,
+                remaining_sum_dict,
+                retry: false,
             },
             shared,
         }
     }
 
-    /// Freezes the mask dictionary.
-    fn freeze_mask_dict(&mut self) -> Result<(MaskObject, MaskObject), RoundFailed> {
-        if self.inner.model_mask_dict.is_empty() {
-            return Err(RoundFailed::NoMask);
-        }
-
-        let model_mask = self
-            .inner
-            .model_mask_dict
-            .drain()
-            .fold(
-                (None, 0_usize),
-                |(unique_mask, unique_count), (mask, count)| match unique_count.cmp(&count) {
-                    Ordering::Less => (Some(mask), count),
-                    Ordering::Greater => (unique_mask, unique_count),
-                    Ordering::Equal => (None, unique_count),
-                },
-            )
-            .0
-            .ok_or(RoundFailed::AmbiguousMasks)?;
-
-        // TODO remove duplication
-        let scalar_mask = self
-            .inner
-            .scalar_mask_dict
-            .drain()
-            .fold(
-                (None, 0_usize),
-                |(unique_mask, unique_count), (mask, count)| match unique_count.cmp(&count) {
-                    Ordering::Less => (Some(mask), count),
-                    Ordering::Greater => (unique_mask, unique_count),
-                    Ordering::Equal => (None, unique_count),
-                },
+    /// Restores the unmask state from the masked models and scalars as well as the mask
+    /// dictionaries in Redis.
+    pub(in crate::state_machine) async fn restore(
+        shared: Shared,
+        redis: &redis::Client,
+    ) -> RedisResult<Self> {
+        info!("restoring unmask phase");
+        let (model_agg, scalar_agg) = shared.restore_aggregations(redis).await?;
+        let model_mask_dict = redis.connection().await.get_mask_dict().await?;
+        let scalar_mask_dict = redis.connection().await.get_scalar_mask_dict().await?;
+        let frozen_sum_dict = redis.connection().await.get_sum_dict().await?;
+        let sum2_participants = redis.connection().await.get_sum2_participants().await?;
+        // the sum2 phase is retried at most once
+        let remaining_sum_dict = if shared.state.sum2_retried {
+            None
+        } else {
+            Some(
+                frozen_sum_dict
+                    .into_iter()
+                    .filter(|(pk, _)| !sum2_participants.contains(pk))
+                    .collect(),
             )
-            .0
-            .ok_or(RoundFailed::AmbiguousMasks)?;
+        };
+        // the votes are not persisted, hence disagreeing sum participants of the restored phase
+        // can't be named
+        Ok(Self::new(
+            shared,
+            model_agg,
+            scalar_agg,
+            model_mask_dict,
+            scalar_mask_dict,
+            MaskVotes::new(),
+            remaining_sum_dict,
+        ))
+    }
 
+    /// Freezes the mask dictionaries by selecting the model mask and scalar mask which reach the
+    /// quorum.
+    fn freeze_mask_dict(&self) -> Result<(MaskObject, MaskObject), RoundFailed> {
+        let quorum = &self.shared.state.mask_quorum;
+        let model_mask = select_mask(&self.inner.model_mask_dict, quorum)?;
+        let scalar_mask = select_mask(&self.inner.scalar_mask_dict, quorum)?;
         Ok((model_mask, scalar_mask))
     }
 
-    fn end_round(&mut self) -> Result<Model, RoundFailed> {
-        let (model_mask, scalar_mask) = self.freeze_mask_dict()?;
+    /// Checks whether the sum2 phase may be retried after the given error.
+    fn may_retry(&self, err: &RoundFailed) -> bool {
+        self.shared.state.mask_quorum.retry_sum2
+            && matches!(err, RoundFailed::AmbiguousMasks | RoundFailed::NoQuorum)
+            && self
+                .inner
+                .remaining_sum_dict
+                .as_ref()
+                .map_or(false, |sum_dict| !sum_dict.is_empty())
+    }
+
+    /// Reports the sum participants whose model masks disagree with the given model mask.
+    fn report_disagreement(&mut self, model_mask: &MaskObject) {
+        let participants = disagreeing_participants(&self.inner.mask_votes, model_mask);
+        if participants.is_empty() {
+            return;
+        }
+
+        warn!(
+            "{} sum participants disagreed on the model mask",
+            participants.len()
+        );
+        metrics!(
+            self.shared.io.metrics_tx,
+            metrics::masks::disagreement::emit(self.shared.state.round_id, &participants)
+        );
+    }
+
+    /// Stores the global model in the model history, unless persistence or the model history is
+    /// disabled.
+    async fn store_global_model(&self, global_model: &Model) -> RedisResult<()> {
+        let limit = self.shared.state.model_history_limit;
+        if limit == 0 {
+            return Ok(());
+        }
+
+        if let Some(connection) = self.shared.redis_connection().await {
+            info!("storing the global model in the model history");
+            let info = GlobalModelInfo {
+                round_id: self.shared.state.round_id,
+                seed: self.shared.state.round_params.seed.clone(),
+                messages: self.shared.message_counts,
+            };
+            connection
+                .add_global_model(&info, global_model, limit)
+                .await?;
+        }
+        Ok(())
+    }
 
+    fn end_round(
+        &mut self,
+        model_mask: MaskObject,
+        scalar_mask: MaskObject,
+    ) -> Result<Model, RoundFailed> {
         // Safe unwrap: State::<Unmask>::new always creates Some(aggregation)
         let model_agg = self.inner.model_agg.take().unwrap();
         let scalar_agg = self.inner.scalar_agg.take().unwrap();
@@ -149,9 +290,285 @@ impl PhaseState<Unmask> {
             .validate_unmasking(&scalar_mask)
             .map_err(RoundFailed::from)?;
 
-        let model = model_agg.unmask(model_mask);
+        let nb_models = scalar_agg.nb_models();
+        let model = model_agg.par_unmask(model_mask);
         let scalar = scalar_agg.unmask(scalar_mask);
 
-        Ok(Aggregation::correct(model, scalar))
+        let checks = &self.shared.state.sanity_checks;
+        // Safe unwrap: the scalar aggregation always contains exactly one scalar
+        check_scalar(scalar.iter().next().unwrap(), nb_models, checks)?;
+        let global_model = Aggregation::correct(model, scalar);
+        check_model(&global_model, checks)?;
+
+        Ok(global_model)
+    }
+}
+
+/// Selects the mask with the most votes, if it is unique and reaches the quorum.
+///
+/// # Errors
+/// Fails if there are no masks, if the mask with the most votes is ambiguous or if it doesn't
+/// reach the quorum.
+fn select_mask(mask_dict: &MaskDict, quorum: &MaskQuorum) -> Result<MaskObject, RoundFailed> {
+    if mask_dict.is_empty() {
+        return Err(RoundFailed::NoMask);
+    }
+
+    let (mask, count) = mask_dict.iter().fold(
+        (None, 0_usize),
+        |(unique_mask, unique_count), (mask, &count)| match unique_count.cmp(&count) {
+            Ordering::Less => (Some(mask), count),
+            Ordering::Greater => (unique_mask, unique_count),
+            Ordering::Equal => (None, unique_count),
+        },
+    );
+    let mask = mask.ok_or(RoundFailed::AmbiguousMasks)?;
+
+    let total = mask_dict.values().sum::<usize>();
+    if (count as f64) < quorum.min_share * total as f64 || (quorum.majority && 2 * count <= total) {
+        return Err(RoundFailed::NoQuorum);
+    }
+    Ok(mask.clone())
+}
+
+/// Gets the sum participants whose votes disagree with the given model mask.
+fn disagreeing_participants(
+    mask_votes: &MaskVotes,
+    model_mask: &MaskObject,
+) -> Vec<SumParticipantPublicKey> {
+    let digest = mask_digest(model_mask);
+    mask_votes
+        .iter()
+        .filter(|(_, vote)| **vote != digest)
+        .map(|(pk, _)| *pk)
+        .collect()
+}
+
+/// Checks that the unmasked scalar sum of the aggregated models is within the configured range
+/// per model.
+///
+/// # Errors
+/// Fails if the scalar sum is zero, since the global model can't be corrected then, or if the
+/// mean scalar of the `nb_models` aggregated models is out of the configured range.
+fn check_scalar(
+    scalar_sum: &Ratio<BigInt>,
+    nb_models: usize,
+    checks: &SanityChecks,
+) -> Result<(), RoundFailed> {
+    if scalar_sum.is_zero() {
+        return Err(RoundFailed::InvalidScalar);
+    }
+
+    let nb_models = BigInt::from(nb_models);
+    let too_small = checks
+        .min_scalar
+        .and_then(Ratio::<BigInt>::from_float)
+        .map_or(false, |min| scalar_sum < &(min * &nb_models));
+    let too_large = checks
+        .max_scalar
+        .and_then(Ratio::<BigInt>::from_float)
+        .map_or(false, |max| scalar_sum > &(max * &nb_models));
+    if too_small || too_large {
+        Err(RoundFailed::InvalidScalar)
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks that the weights of the unmasked global model are within the configured bounds.
+///
+/// # Errors
+/// Fails if the absolute value of a weight exceeds the configured maximum.
+fn check_model(global_model: &Model, checks: &SanityChecks) -> Result<(), RoundFailed> {
+    match checks.max_weight.and_then(Ratio::<BigInt>::from_float) {
+        Some(max) if global_model.iter().any(|weight| weight.abs() > max) => {
+            Err(RoundFailed::InvalidModel)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state_machine::tests::utils;
+    use num::BigUint;
+    use xaynet_core::{
+        crypto::{ByteObject, EncryptKeyPair},
+        mask::{
+            BoundType,
+            DataType,
+            FromPrimitives,
+            GroupType,
+            MaskConfig,
+            MaskConfigPair,
+            ModelType,
+        },
+    };
+
+    fn mask(value: u8) -> MaskObject {
+        let config = MaskConfig {
+            group_type: GroupType::Prime,
+            data_type: DataType::F32,
+            bound_type: BoundType::B0,
+            model_type: ModelType::M3,
+        };
+        MaskObject::new(config, vec![BigUint::from(value)])
+    }
+
+    fn mask_dict(counts: &[usize]) -> MaskDict {
+        counts
+            .iter()
+            .enumerate()
+            .map(|(value, &count)| (mask(value as u8), count))
+            .collect()
+    }
+
+    #[test]
+    fn test_select_mask_plurality() {
+        let quorum = MaskQuorum::default();
+        assert_eq!(
+            select_mask(&mask_dict(&[3, 2, 2]), &quorum).unwrap(),
+            mask(0)
+        );
+        assert!(matches!(
+            select_mask(&mask_dict(&[2, 2, 1]), &quorum),
+            Err(RoundFailed::AmbiguousMasks)
+        ));
+        assert!(matches!(
+            select_mask(&MaskDict::new(), &quorum),
+            Err(RoundFailed::NoMask)
+        ));
+    }
+
+    #[test]
+    fn test_select_mask_majority() {
+        let quorum = MaskQuorum {
+            majority: true,
+            ..MaskQuorum::default()
+        };
+        assert_eq!(select_mask(&mask_dict(&[3, 2]), &quorum).unwrap(), mask(0));
+        assert!(matches!(
+            select_mask(&mask_dict(&[3, 2, 1]), &quorum),
+            Err(RoundFailed::NoQuorum)
+        ));
+    }
+
+    #[test]
+    fn test_select_mask_min_share() {
+        let quorum = MaskQuorum {
+            min_share: 0.75,
+            ..MaskQuorum::default()
+        };
+        assert_eq!(select_mask(&mask_dict(&[3, 1]), &quorum).unwrap(), mask(0));
+        assert!(matches!(
+            select_mask(&mask_dict(&[5, 2]), &quorum),
+            Err(RoundFailed::NoQuorum)
+        ));
+    }
+
+    #[test]
+    fn test_check_scalar() {
+        let scalar_sum = Ratio::from_integer(BigInt::from(3));
+        assert!(check_scalar(&scalar_sum, 4, &SanityChecks::default()).is_ok());
+        assert!(matches!(
+            check_scalar(&Ratio::zero(), 4, &SanityChecks::default()),
+            Err(RoundFailed::InvalidScalar)
+        ));
+
+        let checks = SanityChecks {
+            min_scalar: Some(0.5),
+            max_scalar: Some(1.0),
+            ..SanityChecks::default()
+        };
+        assert!(check_scalar(&scalar_sum, 3, &checks).is_ok());
+        assert!(check_scalar(&scalar_sum, 6, &checks).is_ok());
+        assert!(matches!(
+            check_scalar(&scalar_sum, 2, &checks),
+            Err(RoundFailed::InvalidScalar)
+        ));
+        assert!(matches!(
+            check_scalar(&scalar_sum, 7, &checks),
+            Err(RoundFailed::InvalidScalar)
+        ));
+    }
+
+    #[test]
+    fn test_check_model() {
+        let model = Model::from_primitives(vec![0.5_f32, -2.0, 1.0].into_iter()).unwrap();
+        assert!(check_model(&model, &SanityChecks::default()).is_ok());
+
+        let checks = SanityChecks {
+            max_weight: Some(2.0),
+            ..SanityChecks::default()
+        };
+        assert!(check_model(&model, &checks).is_ok());
+
+        let checks = SanityChecks {
+            max_weight: Some(1.5),
+            ..SanityChecks::default()
+        };
+        assert!(matches!(
+            check_model(&model, &checks),
+            Err(RoundFailed::InvalidModel)
+        ));
+    }
+
+    #[test]
+    fn test_disagreeing_participants() {
+        let pk_a = SumParticipantPublicKey::fill_with(1);
+        let pk_b = SumParticipantPublicKey::fill_with(2);
+        let pk_c = SumParticipantPublicKey::fill_with(3);
+        let mask_votes: MaskVotes = vec![
+            (pk_a, mask_digest(&mask(0))),
+            (pk_b, mask_digest(&mask(1))),
+            (pk_c, mask_digest(&mask(0))),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(disagreeing_participants(&mask_votes, &mask(0)), vec![pk_b]);
+        let mut participants = disagreeing_participants(&mask_votes, &mask(1));
+        participants.sort();
+        assert_eq!(participants, vec![pk_a, pk_c]);
+        assert!(disagreeing_participants(&MaskVotes::new(), &mask(0)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn unmask_to_sum2_retry() {
+        let mask_config: MaskConfigPair = utils::mask_settings().into();
+        let sum_pk = SumParticipantPublicKey::fill_with(1);
+        let remaining_sum_dict: SumDict = vec![(sum_pk, EncryptKeyPair::generate().public)]
+            .into_iter()
+            .collect();
+        let model_mask_dict = mask_dict(&[1, 1]);
+        let unmask = Unmask {
+            model_agg: Some(Aggregation::new(mask_config.vect, 1)),
+            scalar_agg: Some(Aggregation::new(mask_config.unit, 1)),
+            model_mask_dict: model_mask_dict.clone(),
+            scalar_mask_dict: MaskDict::new(),
+            mask_votes: MaskVotes::new(),
+            remaining_sum_dict: Some(remaining_sum_dict.clone()),
+            retry: false,
+        };
+        let (mut shared, _events, _request_tx, _control_tx) = utils::init_shared();
+        shared.state.mask_quorum.retry_sum2 = true;
+        let unmask_state = PhaseState {
+            inner: unmask,
+            shared,
+        };
+
+        // the ambiguous masks make the sum2 phase retry with the remaining sum participants
+        let state_machine = StateMachine::from(unmask_state).next().await.unwrap();
+        assert!(state_machine.is_sum2());
+        let sum2_state = state_machine.into_sum2_phase_state();
+        assert!(sum2_state.shared.state.sum2_retried);
+        assert_eq!(sum2_state.inner.sum_dict(), &remaining_sum_dict);
+        assert_eq!(sum2_state.inner.mask_dict(), &model_mask_dict);
+
+        // the sum2 phase is retried at most once
+        let unmask_state = sum2_state.next().unwrap().into_unmask_phase_state();
+        assert!(unmask_state.inner.remaining_sum_dict.is_none());
+        assert!(!unmask_state.may_retry(&RoundFailed::AmbiguousMasks));
     }
 }
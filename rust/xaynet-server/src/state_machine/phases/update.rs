@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
 
 use xaynet_core::{
     mask::{Aggregation, MaskObject},
@@ -8,18 +11,38 @@ use xaynet_core::{
     UpdateParticipantPublicKey,
 };
 
-use crate::state_machine::{
-    events::{DictionaryUpdate, MaskLengthUpdate},
-    phases::{Handler, Phase, PhaseName, PhaseState, Shared, StateError, Sum2},
-    requests::{StateMachineRequest, UpdateRequest},
-    StateMachine,
-    StateMachineError,
+use crate::{
+    state_machine::{
+        events::{DictionaryUpdate, MaskLengthUpdate, MessageCounts},
+        phases::{
+            count_updates,
+            Handler,
+            Phase,
+            PhaseName,
+            PhaseState,
+            Processed,
+            Shared,
+            StateError,
+            Sum2,
+        },
+        requests::{StateMachineRequest, UpdateRequest},
+        RoundFailed,
+        StateMachine,
+        StateMachineError,
+    },
+    storage::redis,
 };
 
 #[cfg(feature = "metrics")]
 use crate::metrics;
 
-use tokio::time::{timeout, Duration};
+use ::redis::RedisResult;
+use futures::future;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time::{Duration, Instant},
+};
 
 /// Update state
 #[derive(Debug)]
@@ -35,6 +58,182 @@ pub struct Update {
 
     /// The aggregator for masked scalars.
     scalar_agg: Aggregation,
+
+    /// The worker which aggregates the masked models and scalars in the background.
+    worker: Option<AggregationWorker>,
+}
+
+/// The maximum number of masked models and scalars which wait for their aggregation.
+const AGGREGATION_QUEUE_SIZE: usize = 4;
+
+/// The number of masked models and scalars after which the aggregations are checkpointed.
+///
+/// The stored masked models and scalars are removed once they are contained in a checkpoint,
+/// hence at most `AGGREGATION_CHECKPOINT_INTERVAL + AGGREGATION_QUEUE_SIZE + 1` of them are kept
+/// in Redis at any time, provided that the checkpoints succeed.
+const AGGREGATION_CHECKPOINT_INTERVAL: usize = 16;
+
+/// A worker which aggregates masked models and scalars in the background.
+///
+/// The aggregation is performed chunk-wise in parallel on the global `rayon` thread-pool, so that
+/// the state machine can keep handling requests in the meantime. The worker starts from the
+/// aggregations of the update phase, which must be replaced by the aggregations of the worker
+/// once all requests have been handled.
+///
+/// At most [`AGGREGATION_QUEUE_SIZE`] masked models and scalars are queued, hence the state
+/// machine waits for the worker if the aggregation can't keep up with the update messages.
+///
+/// Unless persistence is disabled, the aggregations are checkpointed in Redis every
+/// [`AGGREGATION_CHECKPOINT_INTERVAL`] masked models and scalars and once the worker finishes.
+#[derive(Debug)]
+struct AggregationWorker {
+    /// The number of masked models and scalars sent to the worker.
+    nb_pending: usize,
+
+    /// A sender for the masked models and scalars to be aggregated.
+    tx: mpsc::Sender<(MaskObject, MaskObject)>,
+
+    /// A handle to the worker which resolves to the aggregations.
+    handle: JoinHandle<Option<(Aggregation, Aggregation)>>,
+}
+
+impl AggregationWorker {
+    /// Spawns a new worker which starts from the given aggregations.
+    ///
+    /// The masked models and scalars must be sent to the worker in the order in which they have
+    /// been stored in Redis, since the checkpoints remove them from the front of the lists.
+    fn spawn(
+        model_agg: &Aggregation,
+        scalar_agg: &Aggregation,
+        redis: Option<redis::Client>,
+    ) -> Self {
+        let aggregations = (model_agg.clone(), scalar_agg.clone());
+        let (tx, mut rx) = mpsc::channel::<(MaskObject, MaskObject)>(AGGREGATION_QUEUE_SIZE);
+
+        let handle = tokio::spawn(async move {
+            let mut aggregations = aggregations;
+            let mut nb_unchecked = 0;
+            while let Some((masked_model, masked_scalar)) = rx.recv().await {
+                aggregations = on_thread_pool(move || {
+                    let (mut model_agg, mut scalar_agg) = aggregations;
+                    model_agg.par_aggregate(masked_model);
+                    scalar_agg.aggregate(masked_scalar);
+                    (model_agg, scalar_agg)
+                })
+                .await?;
+                nb_unchecked += 1;
+                if nb_unchecked >= AGGREGATION_CHECKPOINT_INTERVAL
+                    && checkpoint(redis.as_ref(), &aggregations, nb_unchecked).await
+                {
+                    nb_unchecked = 0;
+                }
+            }
+            if nb_unchecked > 0 {
+                checkpoint(redis.as_ref(), &aggregations, nb_unchecked).await;
+            }
+            Some(aggregations)
+        });
+
+        Self {
+            nb_pending: 0,
+            tx,
+            handle,
+        }
+    }
+
+    /// Waits until the worker can take another masked model and scalar and reserves a place in
+    /// its queue for them.
+    ///
+    /// # Errors
+    /// Fails if the worker failed.
+    async fn reserve(&mut self) -> Result<(), StateMachineError> {
+        let tx = &mut self.tx;
+        future::poll_fn(|cx| tx.poll_ready(cx)).await.map_err(|_| {
+            warn!("the aggregation worker failed");
+            StateMachineError::InternalError
+        })
+    }
+
+    /// Sends a masked model and scalar to the worker for aggregation.
+    ///
+    /// The masked model and scalar must have been validated beforehand and a place in the queue
+    /// must have been reserved via [`reserve()`]. The masked model and scalar are dropped if the
+    /// worker failed in the meantime, in which case [`finish()`] fails as well.
+    ///
+    /// [`reserve()`]: AggregationWorker::reserve
+    /// [`finish()`]: AggregationWorker::finish
+    fn aggregate(&mut self, masked_model: MaskObject, masked_scalar: MaskObject) {
+        if self.tx.try_send((masked_model, masked_scalar)).is_err() {
+            warn!("failed to send the masked model to the aggregation worker");
+        }
+        self.nb_pending += 1;
+    }
+
+    /// Waits until the worker aggregated all masked models and scalars sent to it and returns the
+    /// aggregations.
+    async fn finish(self) -> Result<(Aggregation, Aggregation), StateError> {
+        let Self { tx, handle, .. } = self;
+        drop(tx);
+        handle.await.ok().flatten().ok_or_else(|| {
+            error!("the aggregation worker failed");
+            RoundFailed::Aggregation.into()
+        })
+    }
+}
+
+/// Runs `f` on the global `rayon` thread-pool.
+///
+/// A panic in a `rayon` task aborts the process, hence it is caught instead and `None` is
+/// returned.
+async fn on_thread_pool<F, T>(f: F) -> Option<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    trace!("spawning task on thread-pool");
+    rayon::spawn(move || {
+        if let Ok(result) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            let _ = tx.send(result);
+        }
+    });
+    rx.await.ok()
+}
+
+/// Checkpoints the aggregations in Redis and removes the given number of masked models and
+/// scalars contained in them, unless persistence is disabled.
+///
+/// Returns whether the checkpoint succeeded. A failed checkpoint is not fatal, since the masked
+/// models and scalars are kept until the next checkpoint succeeds.
+async fn checkpoint(
+    redis: Option<&redis::Client>,
+    aggregations: &(Aggregation, Aggregation),
+    nb_removed: usize,
+) -> bool {
+    let client = match redis {
+        Some(client) => client,
+        None => return true,
+    };
+    let (model_agg, scalar_agg) = aggregations.clone();
+    let nb_models = model_agg.nb_models();
+    let objects = on_thread_pool(move || -> (MaskObject, MaskObject) {
+        (model_agg.into(), scalar_agg.into())
+    });
+    let (model_agg, scalar_agg) = match objects.await {
+        Some(objects) => objects,
+        None => return false,
+    };
+    debug!(
+        "checkpointing the aggregations of {} masked models",
+        nb_models
+    );
+    client
+        .connection()
+        .await
+        .checkpoint_aggregations(&model_agg, &scalar_agg, nb_models, nb_removed)
+        .await
+        .map_err(|err| warn!("failed to checkpoint the aggregations: {}", err))
+        .is_ok()
 }
 
 #[cfg(test)]
@@ -66,7 +265,9 @@ where
         self.process_during(Duration::from_secs(min_time)).await?;
 
         let time_left = self.shared.state.max_update_time - min_time;
-        timeout(Duration::from_secs(time_left), self.process_until_enough()).await??;
+        self.process_until_enough(Duration::from_secs(time_left))
+            .await?;
+        self.finish_aggregation().await?;
 
         info!(
             "{} update messages handled (min {} required)",
@@ -84,6 +285,7 @@ where
                     seed_dict,
                     model_agg,
                     scalar_agg,
+                    ..
                 },
             mut shared,
         } = self;
@@ -109,58 +311,116 @@ where
     Self: Handler + Phase,
 {
     /// Processes requests until there are enough.
-    async fn process_until_enough(&mut self) -> Result<(), StateError> {
+    ///
+    /// # Errors
+    /// Fails with [`StateError::TimeoutError`] if there aren't enough requests within the given
+    /// duration.
+    async fn process_until_enough(&mut self, dur: Duration) -> Result<(), StateError> {
+        let deadline = Instant::now() + dur;
         while !self.has_enough_updates() {
             debug!(
                 "{} update messages handled (min {} required)",
                 self.updater_count(),
                 self.shared.state.min_update_count
             );
-            self.process_single().await?;
+            if self.process_single(deadline).await? == Processed::Elapsed {
+                return Err(StateError::TimeoutError);
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the aggregation worker and takes over its aggregations.
+    async fn finish_aggregation(&mut self) -> Result<(), StateError> {
+        if let Some(worker) = self.inner.worker.take() {
+            debug!("waiting for {} pending aggregations", worker.nb_pending);
+            let (model_agg, scalar_agg) = worker.finish().await?;
+            self.inner.model_agg = model_agg;
+            self.inner.scalar_agg = scalar_agg;
         }
         Ok(())
     }
 }
 
+#[async_trait]
 impl Handler for PhaseState<Update> {
     /// Handles a [`StateMachineRequest`].
     ///
     /// If the request is a [`StateMachineRequest::Sum`] or
     /// [`StateMachineRequest::Sum2`] request, the request sender will
     /// receive a [`StateMachineError::MessageRejected`].
-    fn handle_request(&mut self, req: StateMachineRequest) -> Result<(), StateMachineError> {
+    async fn handle_request(&mut self, req: StateMachineRequest) -> Result<(), StateMachineError> {
         match req {
             StateMachineRequest::Update(update_req) => {
                 metrics!(
                     self.shared.io.metrics_tx,
                     metrics::message::update::increment(self.shared.state.round_id, Self::NAME)
                 );
-                self.handle_update(update_req)
+                self.handle_update(update_req).await
             }
             _ => Err(StateMachineError::MessageRejected),
         }
     }
+
+    fn has_enough_messages(&self) -> bool {
+        self.has_enough_updates()
+    }
 }
 
 impl PhaseState<Update> {
     /// Creates a new update state.
     pub fn new(shared: Shared, frozen_sum_dict: SumDict, seed_dict: SeedDict) -> Self {
         info!("state transition");
+        let mask_config = shared.state.round_params.mask_config;
         Self {
             inner: Update {
                 frozen_sum_dict,
                 seed_dict,
-                model_agg: Aggregation::new(shared.state.mask_config, shared.state.model_size),
-                // TODO separate config for scalars
-                scalar_agg: Aggregation::new(shared.state.mask_config, 1),
+                model_agg: Aggregation::new(mask_config.vect, shared.state.model_size),
+                scalar_agg: Aggregation::new(mask_config.unit, 1),
+                worker: None,
             },
             shared,
         }
     }
 
+    /// Restores the update state from the sum and seed dictionaries as well as the masked models
+    /// and scalars in Redis.
+    pub(in crate::state_machine) async fn restore(
+        mut shared: Shared,
+        redis: &redis::Client,
+    ) -> RedisResult<Self> {
+        info!("restoring update phase");
+        let frozen_sum_dict = redis.connection().await.get_sum_dict().await?;
+        let seed_dict = redis.connection().await.get_seed_dict().await?;
+        let (model_agg, scalar_agg) = shared.restore_aggregations(redis).await?;
+        shared.set_message_counts(MessageCounts {
+            sum: frozen_sum_dict.len() as u64,
+            update: count_updates(&seed_dict),
+            sum2: 0,
+        });
+
+        info!("broadcasting sum dictionary");
+        shared
+            .io
+            .events
+            .broadcast_sum_dict(DictionaryUpdate::New(Arc::new(frozen_sum_dict.clone())));
+
+        Ok(Self {
+            inner: Update {
+                frozen_sum_dict,
+                seed_dict,
+                model_agg,
+                scalar_agg,
+                worker: None,
+            },
+            shared,
+        })
+    }
+
     /// Handles an update request.
     /// If the handling of the update message fails, an error is returned to the request sender.
-    fn handle_update(&mut self, req: UpdateRequest) -> Result<(), StateMachineError> {
+    async fn handle_update(&mut self, req: UpdateRequest) -> Result<(), StateMachineError> {
         let UpdateRequest {
             participant_pk,
             local_seed_dict,
@@ -173,10 +433,11 @@ impl PhaseState<Update> {
             masked_model,
             masked_scalar,
         )
+        .await
     }
 
     /// Updates the local seed dict and aggregates the masked model.
-    fn update_seed_dict_and_aggregate_mask(
+    async fn update_seed_dict_and_aggregate_mask(
         &mut self,
         pk: &UpdateParticipantPublicKey,
         local_seed_dict: &LocalSeedDict,
@@ -186,11 +447,13 @@ impl PhaseState<Update> {
         // Check if aggregation can be performed. It is important to
         // do that _before_ updating the seed dictionary, because we
         // don't want to add the local seed dict if the corresponding
-        // masked model is invalid
+        // masked model is invalid. The masked models which are still
+        // pending in the aggregation worker must be accounted for.
+        let nb_pending = self.inner.worker.as_ref().map_or(0, |w| w.nb_pending);
         debug!("checking whether the masked model can be aggregated");
         self.inner
             .model_agg
-            .validate_aggregation(&masked_model)
+            .validate_pending_aggregation(&masked_model, nb_pending)
             .map_err(|e| {
                 warn!("model aggregation error: {}", e);
                 StateMachineError::AggregationFailed
@@ -199,33 +462,66 @@ impl PhaseState<Update> {
         debug!("checking whether the masked scalar can be aggregated");
         self.inner
             .scalar_agg
-            .validate_aggregation(&masked_scalar)
+            .validate_pending_aggregation(&masked_scalar, nb_pending)
             .map_err(|e| {
                 warn!("scalar aggregation error: {}", e);
                 StateMachineError::AggregationFailed
             })?;
 
-        // Try to update local seed dict first. If this fail, we do
-        // not want to aggregate the model.
-        info!("updating the global seed dictionary");
-        self.add_local_seed_dict(pk, local_seed_dict)
+        // Check the local seed dict first. If it is invalid, we do
+        // not want to store the update or aggregate the model.
+        debug!("checking whether the local seed dictionary is valid");
+        self.validate_local_seed_dict(pk, local_seed_dict)
             .map_err(|err| {
                 warn!("invalid local seed dictionary, ignoring update message");
                 err
             })?;
 
+        // Reserve a place in the queue of the aggregation worker before
+        // the update is stored, such that handing the masked model
+        // over to the worker can't fail afterwards.
+        let Update {
+            model_agg,
+            scalar_agg,
+            worker,
+            ..
+        } = &mut self.inner;
+        let redis = &self.shared.io.redis;
+        worker
+            .get_or_insert_with(|| AggregationWorker::spawn(model_agg, scalar_agg, redis.clone()))
+            .reserve()
+            .await?;
+
+        if let Some(connection) = self.shared.redis_connection().await {
+            info!("storing the update");
+            connection
+                .add_update(pk, local_seed_dict, &masked_model, &masked_scalar)
+                .await
+                .map_err(|err| {
+                    warn!("failed to store the update: {}", err);
+                    StateMachineError::InternalError
+                })?;
+        }
+
+        info!("updating the global seed dictionary");
+        self.add_local_seed_dict(pk, local_seed_dict)?;
+
         info!("aggregating the masked model and scalar");
-        self.inner.model_agg.aggregate(masked_model);
-        self.inner.scalar_agg.aggregate(masked_scalar);
+        // safe unwrap: the worker has been spawned above
+        self.inner
+            .worker
+            .as_mut()
+            .unwrap()
+            .aggregate(masked_model, masked_scalar);
         Ok(())
     }
 
-    /// Adds a local seed dictionary to the seed dictionary.
+    /// Checks whether a local seed dictionary can be added to the seed dictionary.
     ///
     /// # Error
     /// Fails if it contains invalid keys or it is a repetition.
-    fn add_local_seed_dict(
-        &mut self,
+    fn validate_local_seed_dict(
+        &self,
         pk: &UpdateParticipantPublicKey,
         local_seed_dict: &LocalSeedDict,
     ) -> Result<(), StateMachineError> {
@@ -240,17 +536,6 @@ impl PhaseState<Update> {
                 .next()
                 .map_or(true, |dict| !dict.contains_key(pk))
         {
-            debug!("adding local seed dictionary");
-            for (sum_pk, seed) in local_seed_dict {
-                self.inner
-                    .seed_dict
-                    .get_mut(sum_pk)
-                    // FIXME: the error is not very adapted here, it's
-                    // more an internal error. Could we not unwrap
-                    // here per the checks above?
-                    .ok_or(StateMachineError::InvalidLocalSeedDict)?
-                    .insert(*pk, seed.clone());
-            }
             Ok(())
         } else {
             warn!("invalid seed dictionary");
@@ -258,6 +543,31 @@ impl PhaseState<Update> {
         }
     }
 
+    /// Adds a local seed dictionary to the seed dictionary.
+    ///
+    /// The local seed dictionary must have been validated beforehand via
+    /// [`validate_local_seed_dict()`].
+    ///
+    /// [`validate_local_seed_dict()`]: PhaseState::<Update>::validate_local_seed_dict
+    fn add_local_seed_dict(
+        &mut self,
+        pk: &UpdateParticipantPublicKey,
+        local_seed_dict: &LocalSeedDict,
+    ) -> Result<(), StateMachineError> {
+        debug!("adding local seed dictionary");
+        for (sum_pk, seed) in local_seed_dict {
+            self.inner
+                .seed_dict
+                .get_mut(sum_pk)
+                // FIXME: the error is not very adapted here, it's
+                // more an internal error. Could we not unwrap
+                // here per the checks above?
+                .ok_or(StateMachineError::InvalidLocalSeedDict)?
+                .insert(*pk, seed.clone());
+        }
+        Ok(())
+    }
+
     /// Returns the number of update participants that sent a valid update message.
     fn updater_count(&self) -> usize {
         self.inner
@@ -282,14 +592,142 @@ mod test {
         events::Event,
         tests::{builder::StateMachineBuilder, utils},
     };
+    use num::BigUint;
+    use serial_test::serial;
+    use tokio::runtime;
     use xaynet_core::{
         common::RoundSeed,
-        crypto::{ByteObject, EncryptKeyPair},
-        mask::{FromPrimitives, MaskObject, Model},
+        crypto::{ByteObject, EncryptKeyPair, SigningKeyPair},
+        mask::{FromPrimitives, MaskConfigPair, MaskObject, Model},
         SumDict,
         UpdateSeedDict,
     };
 
+    /// Creates a masked model and scalar with the given elements.
+    fn masked_model_and_scalar(
+        config: MaskConfigPair,
+        elements: &[u32],
+        scalar: u32,
+    ) -> (MaskObject, MaskObject) {
+        (
+            MaskObject::new(
+                config.vect,
+                elements.iter().copied().map(BigUint::from).collect(),
+            ),
+            MaskObject::new(config.unit, vec![BigUint::from(scalar)]),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_aggregation_worker() {
+        let config: MaskConfigPair = utils::mask_settings().into();
+        let model_agg = Aggregation::new(config.vect, 3);
+        let scalar_agg = Aggregation::new(config.unit, 1);
+        let mut worker = AggregationWorker::spawn(&model_agg, &scalar_agg, None);
+
+        // more masked models than fit into the queue of the worker
+        let mut expected_model_agg = model_agg;
+        let mut expected_scalar_agg = scalar_agg;
+        for i in 0..(2 * AGGREGATION_QUEUE_SIZE as u32) {
+            let (masked_model, masked_scalar) = masked_model_and_scalar(config, &[i, 1, 2], i);
+            expected_model_agg.aggregate(masked_model.clone());
+            expected_scalar_agg.aggregate(masked_scalar.clone());
+            worker.reserve().await.unwrap();
+            worker.aggregate(masked_model, masked_scalar);
+        }
+        assert_eq!(worker.nb_pending, 2 * AGGREGATION_QUEUE_SIZE);
+
+        // the worker drains its queue before it finishes
+        let (model_agg, scalar_agg) = worker.finish().await.unwrap();
+        assert_eq!(model_agg.nb_models(), 2 * AGGREGATION_QUEUE_SIZE);
+        assert_eq!(
+            Into::<MaskObject>::into(model_agg),
+            Into::<MaskObject>::into(expected_model_agg)
+        );
+        assert_eq!(
+            Into::<MaskObject>::into(scalar_agg),
+            Into::<MaskObject>::into(expected_scalar_agg)
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn integration_aggregation_worker_checkpoints() {
+        let client = redis::Client::new("redis://127.0.0.1/", 10).await.unwrap();
+        client.connection().await.flush_db().await.unwrap();
+
+        let config: MaskConfigPair = utils::mask_settings().into();
+        let model_agg = Aggregation::new(config.vect, 3);
+        let scalar_agg = Aggregation::new(config.unit, 1);
+        let mut worker = AggregationWorker::spawn(&model_agg, &scalar_agg, Some(client.clone()));
+
+        let update_pk = SigningKeyPair::generate().public;
+        for i in 0..(AGGREGATION_CHECKPOINT_INTERVAL as u32 + 2) {
+            let (masked_model, masked_scalar) = masked_model_and_scalar(config, &[i, 1, 2], i);
+            client
+                .connection()
+                .await
+                .add_update(
+                    &update_pk,
+                    &LocalSeedDict::new(),
+                    &masked_model,
+                    &masked_scalar,
+                )
+                .await
+                .unwrap();
+            worker.reserve().await.unwrap();
+            worker.aggregate(masked_model, masked_scalar);
+        }
+
+        // the worker checkpoints its aggregations once it finishes
+        let (model_agg, scalar_agg) = worker.finish().await.unwrap();
+        let (checkpointed_model_agg, checkpointed_scalar_agg, nb_models) = client
+            .connection()
+            .await
+            .get_aggregations()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(nb_models, AGGREGATION_CHECKPOINT_INTERVAL + 2);
+        assert_eq!(checkpointed_model_agg, model_agg.into());
+        assert_eq!(checkpointed_scalar_agg, scalar_agg.into());
+        let masked_models = client.connection().await.get_masked_models().await.unwrap();
+        assert!(masked_models.is_empty());
+    }
+
+    #[test]
+    fn test_aggregation_worker_failed() {
+        let config: MaskConfigPair = utils::mask_settings().into();
+        let model_agg = Aggregation::new(config.vect, 3);
+        let scalar_agg = Aggregation::new(config.unit, 1);
+
+        // the worker fails if its task is cancelled by the shutdown of its runtime
+        let mut rt = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut worker = rt.enter(|| AggregationWorker::spawn(&model_agg, &scalar_agg, None));
+        rt.block_on(worker.reserve()).unwrap();
+        drop(rt);
+
+        let mut rt = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (masked_model, masked_scalar) = masked_model_and_scalar(config, &[1, 2, 3], 1);
+        worker.aggregate(masked_model, masked_scalar);
+        assert!(matches!(
+            rt.block_on(worker.reserve()),
+            Err(StateMachineError::InternalError)
+        ));
+        assert!(matches!(
+            rt.block_on(worker.finish()),
+            Err(StateError::RoundError(RoundFailed::Aggregation))
+        ));
+    }
+
     #[tokio::test]
     pub async fn update_to_sum2() {
         let n_updaters = 1;
@@ -314,13 +752,15 @@ mod test {
 
         let mut seed_dict = SeedDict::new();
         seed_dict.insert(summer.pk, HashMap::new());
-        let aggregation = Aggregation::new(utils::mask_settings().into(), model_size);
-        let scalar_agg = Aggregation::new(utils::mask_settings().into(), 1);
+        let mask_config: MaskConfigPair = utils::mask_settings().into();
+        let aggregation = Aggregation::new(mask_config.vect, model_size);
+        let scalar_agg = Aggregation::new(mask_config.unit, 1);
         let update = Update {
             frozen_sum_dict: frozen_sum_dict.clone(),
             seed_dict: seed_dict.clone(),
             model_agg: aggregation.clone(),
             scalar_agg,
+            worker: None,
         };
 
         // Create the state machine
@@ -331,7 +771,7 @@ mod test {
             .with_update_ratio(update_ratio)
             .with_min_sum(n_summers)
             .with_min_update(n_updaters)
-            .with_mask_config(utils::mask_settings().into())
+            .with_mask_config(mask_config)
             .build();
 
         assert!(state_machine.is_update());
@@ -339,12 +779,15 @@ mod test {
         // Create an update request.
         let scalar = 1.0 / (n_updaters as f64 * update_ratio);
         let model = Model::from_primitives(vec![0; model_size].into_iter()).unwrap();
-        let update_msg = updater.compose_update_message(
-            coord_keys.public,
-            &frozen_sum_dict,
-            scalar,
-            model.clone(),
-        );
+        let update_msg = updater
+            .compose_update_message(
+                coord_keys.public,
+                &frozen_sum_dict,
+                scalar,
+                model.clone(),
+                mask_config,
+            )
+            .unwrap();
         let masked_model = utils::masked_model(&update_msg);
         let request_fut = async { request_tx.msg(&update_msg).await.unwrap() };
 
@@ -99,7 +99,9 @@ impl From<Message> for StateMachineRequest {
                 model_mask: sum2.model_mask,
                 scalar_mask: sum2.scalar_mask,
             }),
-            Payload::Chunk(_) => unimplemented!(),
+            Payload::Chunk(_) => {
+                unreachable!("multipart messages are reassembled before reaching the state machine")
+            }
         }
     }
 }
@@ -1,6 +1,7 @@
-use xaynet_core::{common::RoundSeed, crypto::EncryptKeyPair, mask::MaskConfig};
+use xaynet_core::{common::RoundSeed, crypto::EncryptKeyPair, mask::MaskConfigPair};
 
 use crate::state_machine::{
+    control::ControlSender,
     events::EventSubscriber,
     phases::{self, Handler, Phase, PhaseState, Shared},
     requests::RequestSender,
@@ -12,18 +13,20 @@ use crate::state_machine::{
 pub struct StateMachineBuilder<P> {
     shared: Shared,
     request_tx: RequestSender,
+    control_tx: ControlSender,
     event_subscriber: EventSubscriber,
     phase_state: P,
 }
 
 impl StateMachineBuilder<phases::Idle> {
     pub fn new() -> Self {
-        let (shared, event_subscriber, request_tx) = utils::init_shared();
+        let (shared, event_subscriber, request_tx, control_tx) = utils::init_shared();
 
         let phase_state = phases::Idle;
         StateMachineBuilder {
             shared,
             request_tx,
+            control_tx,
             event_subscriber,
             phase_state,
         }
@@ -41,6 +44,7 @@ where
             request_tx,
             event_subscriber,
             phase_state,
+            ..
         } = self;
 
         // Make sure the events that the listeners have are up to date
@@ -68,6 +72,11 @@ where
         (state_machine, request_tx, event_subscriber)
     }
 
+    /// Returns a handle to send control requests to the state machine that is built.
+    pub fn control_sender(&self) -> ControlSender {
+        self.control_tx.clone()
+    }
+
     #[allow(dead_code)]
     pub fn with_keys(mut self, keys: EncryptKeyPair) -> Self {
         self.shared.state.round_params.pk = keys.public.clone();
@@ -100,8 +109,13 @@ where
         self
     }
 
-    pub fn with_mask_config(mut self, mask_config: MaskConfig) -> Self {
-        self.shared.state.mask_config = mask_config;
+    pub fn with_min_sum_time(mut self, min_sum_time: u64) -> Self {
+        self.shared.state.min_sum_time = min_sum_time;
+        self
+    }
+
+    pub fn with_mask_config(mut self, mask_config: MaskConfigPair) -> Self {
+        self.shared.state.round_params.mask_config = mask_config;
         self
     }
 
@@ -119,12 +133,14 @@ where
         let Self {
             shared,
             request_tx,
+            control_tx,
             event_subscriber,
             ..
         } = self;
         StateMachineBuilder {
             shared,
             request_tx,
+            control_tx,
             event_subscriber,
             phase_state,
         }
@@ -2,21 +2,37 @@ pub mod builder;
 pub mod impls;
 pub mod utils;
 
+use serial_test::serial;
 use xaynet_core::{
     common::RoundSeed,
-    crypto::{ByteObject, EncryptKeyPair},
+    crypto::{ByteObject, EncryptKeyPair, SigningKeyPair},
     mask::{FromPrimitives, Model},
 };
 
-use crate::state_machine::{
-    events::Event,
-    phases::PhaseName,
-    tests::{
-        builder::StateMachineBuilder,
-        utils::{enable_logging, generate_summer, generate_updater},
+use crate::{
+    state_machine::{
+        coordinator::CoordinatorState,
+        events::{DictionaryUpdate, Event},
+        phases::PhaseName,
+        tests::{
+            builder::StateMachineBuilder,
+            utils::{
+                enable_logging,
+                generate_summer,
+                generate_updater,
+                mask_settings,
+                model_settings,
+                pet_settings,
+            },
+        },
+        StateMachine,
     },
+    storage::redis,
 };
 
+#[cfg(feature = "metrics")]
+use crate::metrics::MetricsSender;
+
 #[tokio::test]
 async fn full_round() {
     enable_logging();
@@ -57,13 +73,16 @@ async fn full_round() {
     assert!(state_machine.is_update());
 
     // Update phase
+    let mask_config = events.params_listener().get_latest().event.mask_config;
     let transition_task = tokio::spawn(async { state_machine.next().await.unwrap() });
     let sum_dict = events.sum_dict_listener().get_latest().event.unwrap();
     let scalar = 1.0 / (n_updaters as f64 * update_ratio);
     let model = Model::from_primitives(vec![0; model_size].into_iter()).unwrap();
     for _ in 0..3 {
         let updater = generate_updater(&seed, sum_ratio, update_ratio);
-        let msg = updater.compose_update_message(coord_pk, &sum_dict, scalar, model.clone());
+        let msg = updater
+            .compose_update_message(coord_pk, &sum_dict, scalar, model.clone(), mask_config)
+            .unwrap();
         requests.msg(&msg).await.unwrap();
     }
     let state_machine = transition_task.await.unwrap();
@@ -73,10 +92,20 @@ async fn full_round() {
     let seed_dict = events.seed_dict_listener().get_latest().event.unwrap();
     let mask_length = events.mask_length_listener().get_latest().event.unwrap();
     let msg_1 = summer_1
-        .compose_sum2_message(coord_pk, seed_dict.get(&summer_1.pk).unwrap(), mask_length)
+        .compose_sum2_message(
+            coord_pk,
+            seed_dict.get(&summer_1.pk).unwrap(),
+            mask_length,
+            mask_config,
+        )
         .unwrap();
     let msg_2 = summer_2
-        .compose_sum2_message(coord_pk, seed_dict.get(&summer_2.pk).unwrap(), mask_length)
+        .compose_sum2_message(
+            coord_pk,
+            seed_dict.get(&summer_2.pk).unwrap(),
+            mask_length,
+            mask_config,
+        )
         .unwrap();
     let req_1 = async { requests.msg(&msg_1).await.unwrap() };
     let req_2 = async { requests.msg(&msg_2).await.unwrap() };
@@ -111,3 +140,103 @@ async fn full_round() {
     assert!(state_machine.is_shutdown());
     assert!(state_machine.next().await.is_none())
 }
+
+#[tokio::test]
+#[serial]
+async fn integration_restore_update_phase() {
+    let client = redis::Client::new("redis://127.0.0.1/", 10).await.unwrap();
+    client.connection().await.flush_db().await.unwrap();
+
+    // Store a coordinator that was interrupted in the update phase with different settings
+    let mut state = CoordinatorState::new(pet_settings(), mask_settings(), model_settings());
+    state.round_id = 7;
+    state.phase = PhaseName::Update;
+    state.min_update_count = 100;
+    client
+        .connection()
+        .await
+        .set_coordinator_state(&state)
+        .await
+        .unwrap();
+    state.min_update_count = pet_settings().min_update_count;
+    let SigningKeyPair { public: sum_pk, .. } = SigningKeyPair::generate();
+    let EncryptKeyPair {
+        public: ephm_pk, ..
+    } = EncryptKeyPair::generate();
+    client
+        .connection()
+        .await
+        .add_sum_participant(&sum_pk, &ephm_pk)
+        .await
+        .unwrap();
+
+    let (state_machine, _requests, _control, events) = StateMachine::new(
+        pet_settings(),
+        mask_settings(),
+        model_settings(),
+        client,
+        #[cfg(feature = "metrics")]
+        MetricsSender(),
+    )
+    .await
+    .unwrap();
+    assert!(state_machine.is_update());
+
+    // The round is resumed with the configured settings
+    let update_state = state_machine.into_update_phase_state();
+    assert_eq!(update_state.shared.state, state);
+    assert_eq!(
+        update_state.inner.frozen_sum_dict().get(&sum_pk),
+        Some(&ephm_pk)
+    );
+    assert!(update_state
+        .inner
+        .seed_dict()
+        .get(&sum_pk)
+        .unwrap()
+        .is_empty());
+
+    // The restored sum dictionary must be available to the participants
+    let sum_dict = events.sum_dict_listener().get_latest();
+    assert_eq!(sum_dict.round_id, 7);
+    match sum_dict.event {
+        DictionaryUpdate::New(sum_dict) => assert_eq!(sum_dict.get(&sum_pk), Some(&ephm_pk)),
+        DictionaryUpdate::Invalidate => panic!("sum dictionary was not restored"),
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn integration_restore_retried_sum2_phase() {
+    let client = redis::Client::new("redis://127.0.0.1/", 10).await.unwrap();
+    client.connection().await.flush_db().await.unwrap();
+
+    // Store a coordinator that was interrupted while retrying the sum2 phase
+    let mut state = CoordinatorState::new(pet_settings(), mask_settings(), model_settings());
+    state.round_id = 7;
+    state.phase = PhaseName::Sum2;
+    state.sum2_retried = true;
+    client
+        .connection()
+        .await
+        .set_coordinator_state(&state)
+        .await
+        .unwrap();
+
+    let (state_machine, _requests, _control, _events) = StateMachine::new(
+        pet_settings(),
+        mask_settings(),
+        model_settings(),
+        client,
+        #[cfg(feature = "metrics")]
+        MetricsSender(),
+    )
+    .await
+    .unwrap();
+    assert!(state_machine.is_sum2());
+
+    // The restored sum2 phase is still a retry
+    let sum2_state = state_machine.into_sum2_phase_state();
+    assert_eq!(sum2_state.shared.state, state);
+    assert!(sum2_state.shared.state.sum2_retried);
+}
@@ -8,8 +8,9 @@ use xaynet_core::{
 };
 
 use crate::{
-    settings::{MaskSettings, ModelSettings, PetSettings},
+    settings::{MaskSettings, ModelSettings, PetSettings, ScalarMaskSettings},
     state_machine::{
+        control::{ControlReceiver, ControlSender},
         coordinator::CoordinatorState,
         events::{EventPublisher, EventSubscriber},
         phases::{PhaseName, Shared},
@@ -21,6 +22,12 @@ use xaynet_client::{Participant, Task};
 #[cfg(feature = "metrics")]
 use crate::metrics::MetricsSender;
 
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    sync::mpsc,
+    thread,
+};
 use tracing_subscriber::*;
 
 pub fn enable_logging() {
@@ -58,6 +65,12 @@ pub fn mask_settings() -> MaskSettings {
         data_type: DataType::F32,
         bound_type: BoundType::B0,
         model_type: ModelType::M3,
+        scalar: Some(ScalarMaskSettings {
+            group_type: GroupType::Prime,
+            data_type: DataType::F64,
+            bound_type: BoundType::B0,
+            model_type: ModelType::M3,
+        }),
     }
 }
 
@@ -72,10 +85,14 @@ pub fn pet_settings() -> PetSettings {
 }
 
 pub fn model_settings() -> ModelSettings {
-    ModelSettings { size: 1 }
+    ModelSettings {
+        size: 1,
+        history_limit: 10,
+        initial_model: None,
+    }
 }
 
-pub fn init_shared() -> (Shared, EventSubscriber, RequestSender) {
+pub fn init_shared() -> (Shared, EventSubscriber, RequestSender, ControlSender) {
     let coordinator_state =
         CoordinatorState::new(pet_settings(), mask_settings(), model_settings());
 
@@ -87,16 +104,20 @@ pub fn init_shared() -> (Shared, EventSubscriber, RequestSender) {
     );
 
     let (request_rx, request_tx) = RequestReceiver::new();
+    let (control_rx, control_tx) = ControlReceiver::new();
     (
         Shared::new(
             coordinator_state,
             event_publisher,
             request_rx,
+            control_rx,
+            None,
             #[cfg(feature = "metrics")]
             MetricsSender(),
         ),
         event_subscriber,
         request_tx,
+        control_tx,
     )
 }
 
@@ -154,3 +175,45 @@ pub fn local_seed_dict(msg: &Message) -> LocalSeedDict {
         panic!("not an update message");
     }
 }
+
+/// Starts a fake Redis server which acknowledges every command and forwards the raw commands.
+pub fn fake_redis() -> (String, mpsc::Receiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("redis://{}", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                while let Ok(n) = stream.read(&mut buf) {
+                    if n == 0 || tx.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                    if stream.write_all(b"+OK\r\n").is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    (url, rx)
+}
+
+/// Extracts the value of the `SET coordinator_state <value>` command.
+pub fn stored_state(commands: &[u8]) -> CoordinatorState {
+    let key = b"coordinator_state\r\n$";
+    let start = commands
+        .windows(key.len())
+        .position(|window| window == key)
+        .expect("the coordinator state was not stored")
+        + key.len();
+    let len_end = start + commands[start..].iter().position(|b| *b == b'\r').unwrap();
+    let len: usize = std::str::from_utf8(&commands[start..len_end])
+        .unwrap()
+        .parse()
+        .unwrap();
+    let value = commands[len_end + 2..len_end + 2 + len].to_vec();
+    ::redis::from_redis_value(&::redis::Value::Data(value)).unwrap()
+}
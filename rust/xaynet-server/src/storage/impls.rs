@@ -1,10 +1,18 @@
-use crate::state_machine::coordinator::CoordinatorState;
+use crate::{
+    settings::PetSettings,
+    state_machine::{
+        coordinator::{CoordinatorState, GlobalModelInfo},
+        phases::PhaseName,
+    },
+};
 use derive_more::{From, Into};
 use paste::paste;
 use redis::{ErrorKind, FromRedisValue, RedisError, RedisResult, RedisWrite, ToRedisArgs, Value};
 use xaynet_core::{
-    crypto::{ByteObject, PublicEncryptKey, PublicSigningKey},
-    mask::{EncryptedMaskSeed, MaskObject},
+    common::{RoundParameters, RoundSeed},
+    crypto::{ByteObject, EncryptKeyPair, PublicEncryptKey, PublicSigningKey},
+    mask::{EncryptedMaskSeed, MaskConfig, MaskObject, Model},
+    CoordinatorPublicKey,
 };
 
 fn redis_type_error(desc: &'static str, details: Option<String>) -> RedisError {
@@ -154,11 +162,138 @@ macro_rules! impl_bincode_redis_traits {
     };
 }
 
+/// The prefix of a versioned [`CoordinatorState`].
+///
+/// The unversioned states of earlier releases start with the length of the public key of the
+/// coordinator instead.
+const COORDINATOR_STATE_MAGIC: &[u8; 4] = b"XNCS";
+
+/// The version of the layout of the [`CoordinatorState`].
+///
+/// The version must be increased whenever the layout changes, since the stored state can't be
+/// read otherwise.
+const COORDINATOR_STATE_VERSION: u16 = 1;
+
 // CoordinatorState is pretty straightforward:
 // - all the sequences have known length (
 // - no untagged enum
 // so bincode will not panic.
-impl_bincode_redis_traits!(CoordinatorState);
+impl FromRedisValue for CoordinatorState {
+    fn from_redis_value(v: &Value) -> RedisResult<CoordinatorState> {
+        let bytes = match *v {
+            Value::Data(ref bytes) => bytes,
+            _ => {
+                return Err(redis_type_error(
+                    "Response not CoordinatorState compatible",
+                    None,
+                ))
+            }
+        };
+        let invalid =
+            |e: bincode::Error| redis_type_error("Invalid CoordinatorState", Some(e.to_string()));
+
+        if !bytes.starts_with(COORDINATOR_STATE_MAGIC) {
+            return bincode::deserialize::<LegacyCoordinatorState>(bytes)
+                .map(Into::into)
+                .map_err(invalid);
+        }
+        let data = &bytes[COORDINATOR_STATE_MAGIC.len()..];
+        match data.get(..2) {
+            Some(&[low, high]) if u16::from_le_bytes([low, high]) == COORDINATOR_STATE_VERSION => {
+                bincode::deserialize(&data[2..]).map_err(invalid)
+            }
+            Some(&[low, high]) => Err(redis_type_error(
+                "Unsupported CoordinatorState version",
+                Some(u16::from_le_bytes([low, high]).to_string()),
+            )),
+            _ => Err(redis_type_error("Invalid CoordinatorState", None)),
+        }
+    }
+}
+
+impl ToRedisArgs for CoordinatorState {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        let mut data = COORDINATOR_STATE_MAGIC.to_vec();
+        data.extend_from_slice(&COORDINATOR_STATE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut data, self).unwrap();
+        data.write_redis_args(out)
+    }
+}
+
+impl<'a> ToRedisArgs for &'a CoordinatorState {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        (*self).write_redis_args(out)
+    }
+}
+
+/// The unversioned [`CoordinatorState`] of earlier releases.
+#[derive(Serialize, Deserialize)]
+struct LegacyCoordinatorState {
+    keys: EncryptKeyPair,
+    round_id: u64,
+    round_params: LegacyRoundParameters,
+    min_sum_count: usize,
+    min_update_count: usize,
+    min_sum_time: u64,
+    min_update_time: u64,
+    max_sum_time: u64,
+    max_update_time: u64,
+    mask_config: MaskConfig,
+    model_size: usize,
+}
+
+/// The [`RoundParameters`] of earlier releases.
+#[derive(Serialize, Deserialize)]
+struct LegacyRoundParameters {
+    pk: CoordinatorPublicKey,
+    sum: f64,
+    update: f64,
+    seed: RoundSeed,
+}
+
+impl From<LegacyCoordinatorState> for CoordinatorState {
+    /// Migrates the state of an idle coordinator, since earlier releases didn't resume rounds.
+    ///
+    /// Only the round ID of an idle coordinator is restored, hence the settings which didn't
+    /// exist in earlier releases are just set to their defaults.
+    fn from(legacy: LegacyCoordinatorState) -> Self {
+        let pet_settings = PetSettings::default();
+        Self {
+            keys: legacy.keys,
+            round_id: legacy.round_id,
+            phase: PhaseName::Idle,
+            round_params: RoundParameters {
+                pk: legacy.round_params.pk,
+                sum: legacy.round_params.sum,
+                update: legacy.round_params.update,
+                seed: legacy.round_params.seed,
+                mask_config: legacy.mask_config.into(),
+            },
+            min_sum_count: legacy.min_sum_count,
+            min_update_count: legacy.min_update_count,
+            min_sum_time: legacy.min_sum_time,
+            min_update_time: legacy.min_update_time,
+            max_sum_time: legacy.max_sum_time,
+            max_update_time: legacy.max_update_time,
+            min_idle_time: pet_settings.min_idle_time,
+            fractions_policy: pet_settings.fractions_policy,
+            mask_quorum: pet_settings.mask_quorum,
+            error_policy: pet_settings.error_policy,
+            sanity_checks: pet_settings.sanity_checks,
+            failed_rounds: 0,
+            sum2_retried: false,
+            model_size: legacy.model_size,
+            model_history_limit: 0,
+            pending_pet_settings: None,
+        }
+    }
+}
 
 #[derive(From, Into, Serialize, Deserialize)]
 pub(crate) struct MaskObjectRead(MaskObject);
@@ -187,6 +322,35 @@ impl<'a> ToRedisArgs for &'a MaskObjectWrite<'a> {
     }
 }
 
+impl_bincode_redis_traits!(GlobalModelInfo);
+
+#[derive(From, Into, Serialize, Deserialize)]
+pub(crate) struct ModelRead(Model);
+
+impl_bincode_redis_traits!(ModelRead);
+
+#[derive(From, Serialize)]
+pub(crate) struct ModelWrite<'a>(&'a Model);
+
+impl ToRedisArgs for ModelWrite<'_> {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        let data = bincode::serialize(self).unwrap();
+        data.write_redis_args(out)
+    }
+}
+
+impl<'a> ToRedisArgs for &'a ModelWrite<'a> {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        (*self).write_redis_args(out)
+    }
+}
+
 #[derive(Ord, PartialOrd, Eq, PartialEq, Debug)]
 pub enum AddSumParticipant {
     Ok,
@@ -224,3 +388,73 @@ impl FromRedisValue for DeleteSumParticipant {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_machine::tests::utils::{mask_settings, model_settings, pet_settings};
+    use xaynet_core::mask::{BoundType, DataType, GroupType, ModelType};
+
+    fn coordinator_state_bytes(state: &CoordinatorState) -> Vec<u8> {
+        state.to_redis_args().pop().unwrap()
+    }
+
+    #[test]
+    fn test_coordinator_state() {
+        let state = CoordinatorState::new(pet_settings(), mask_settings(), model_settings());
+        let bytes = coordinator_state_bytes(&state);
+        assert_eq!(&bytes[..4], COORDINATOR_STATE_MAGIC);
+        assert_eq!(
+            CoordinatorState::from_redis_value(&Value::Data(bytes)).unwrap(),
+            state
+        );
+    }
+
+    #[test]
+    fn test_coordinator_state_unsupported_version() {
+        let state = CoordinatorState::new(pet_settings(), mask_settings(), model_settings());
+        let mut bytes = coordinator_state_bytes(&state);
+        bytes[4..6].copy_from_slice(&(COORDINATOR_STATE_VERSION + 1).to_le_bytes());
+        let err = CoordinatorState::from_redis_value(&Value::Data(bytes)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TypeError);
+        assert_eq!(err.detail(), Some("2"));
+    }
+
+    #[test]
+    fn test_legacy_coordinator_state() {
+        let keys = EncryptKeyPair::generate();
+        let mask_config = MaskConfig {
+            group_type: GroupType::Prime,
+            data_type: DataType::F32,
+            bound_type: BoundType::B0,
+            model_type: ModelType::M3,
+        };
+        let legacy_state = LegacyCoordinatorState {
+            keys: keys.clone(),
+            round_id: 42,
+            round_params: LegacyRoundParameters {
+                pk: keys.public,
+                sum: 0.4,
+                update: 0.5,
+                seed: RoundSeed::generate(),
+            },
+            min_sum_count: 1,
+            min_update_count: 3,
+            min_sum_time: 0,
+            min_update_time: 0,
+            max_sum_time: 604800,
+            max_update_time: 604800,
+            mask_config,
+            model_size: 4,
+        };
+        let bytes = bincode::serialize(&legacy_state).unwrap();
+
+        // the coordinator keeps its credentials and round ID, but it doesn't resume the round
+        let state = CoordinatorState::from_redis_value(&Value::Data(bytes)).unwrap();
+        assert_eq!(state.keys, keys);
+        assert_eq!(state.round_id, 42);
+        assert_eq!(state.phase, PhaseName::Idle);
+        assert_eq!(state.round_params.mask_config, mask_config.into());
+        assert_eq!(state.model_size, 4);
+    }
+}
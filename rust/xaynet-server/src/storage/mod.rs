@@ -1,4 +1,5 @@
 pub(crate) mod impls;
 pub mod redis;
+pub mod registry;
 
 pub use self::impls::AddSumParticipant;
@@ -22,15 +22,52 @@
 //!         "UpdateParticipantPublicKey_1": EncryptedMaskSeed,
 //!         "UpdateParticipantPublicKey_2": EncryptedMaskSeed
 //!     }
+//!     // Masked models and scalars of the update participants which haven't been checkpointed yet
+//!     "masked_models": [ // list
+//!         mask_object_1, // bincode encoded string
+//!         mask_object_2
+//!     ],
+//!     "masked_scalars": [ // list
+//!         mask_object_1, // bincode encoded string
+//!         mask_object_2
+//!     ],
+//!     // Checkpoints of the model and scalar aggregations
+//!     "model_aggregation": mask_object, // bincode encoded string
+//!     "scalar_aggregation": mask_object, // bincode encoded string
+//!     "aggregated_updates": 12341, // number of aggregated masked models and scalars
+//!     // Sum2 participants
+//!     "sum2_participants": [ // set
+//!         SumParticipantPublicKey_1,
+//!         SumParticipantPublicKey_2
+//!     ],
 //!     // Mask dict
 //!     "mask_dict": [ // sorted set
 //!         (mask_object_1, 12341), // (mask: bincode encoded string, score/counter: number)
 //!         (mask_object_2, 1)
+//!     ],
+//!     // Scalar mask dict
+//!     "scalar_mask_dict": [ // sorted set
+//!         (mask_object_1, 12341), // (mask: bincode encoded string, score/counter: number)
+//!         (mask_object_2, 1)
+//!     ],
+//!     // Model history
+//!     "global_models": { // hash
+//!         "1": model_1, // (round ID: number, model: bincode encoded string)
+//!         "2": model_2
+//!     },
+//!     "global_model_infos": { // hash
+//!         "1": global_model_info_1, // (round ID: number, info: bincode encoded string)
+//!         "2": global_model_info_2
+//!     },
+//!     // Participant registry
+//!     "allowed_participants": [ // set
+//!         ParticipantPublicKey_1,
+//!         ParticipantPublicKey_2
 //!     ]
 //! }
 //! ```
 use crate::{
-    state_machine::coordinator::CoordinatorState,
+    state_machine::coordinator::{CoordinatorState, GlobalModelInfo, MaskDict},
     storage::impls::{
         AddSumParticipant,
         DeleteSumParticipant,
@@ -38,21 +75,32 @@ use crate::{
         EncryptedMaskSeedWrite,
         MaskObjectRead,
         MaskObjectWrite,
+        ModelRead,
+        ModelWrite,
         PublicEncryptKeyRead,
         PublicEncryptKeyWrite,
         PublicSigningKeyRead,
         PublicSigningKeyWrite,
     },
 };
-use redis::{aio::ConnectionManager, AsyncCommands, IntoConnectionInfo, RedisError, RedisResult};
+use redis::{
+    aio::ConnectionManager,
+    AsyncCommands,
+    IntoConnectionInfo,
+    Pipeline,
+    RedisError,
+    RedisResult,
+};
 use std::{
     collections::{HashMap, HashSet},
+    fmt,
     sync::Arc,
 };
 use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use xaynet_core::{
-    mask::{EncryptedMaskSeed, MaskObject},
+    mask::{EncryptedMaskSeed, MaskObject, Model},
     LocalSeedDict,
+    ParticipantPublicKey,
     SeedDict,
     SumDict,
     SumParticipantEphemeralPublicKey,
@@ -66,9 +114,6 @@ pub struct Client {
     semaphore: Arc<Semaphore>,
 }
 
-#[cfg(test)]
-use std::fmt;
-#[cfg(test)]
 impl fmt::Debug for Client {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Client")
@@ -120,9 +165,7 @@ impl Client {
 
 impl Connection {
     /// Retrieves a [`CoordinatorState`] or `None` when the [`CoordinatorState`] does not exist.
-    // currently only used for testing but later required for restoring the coordinator
-    #[allow(dead_code)]
-    async fn get_coordinator_state(mut self) -> RedisResult<Option<CoordinatorState>> {
+    pub async fn get_coordinator_state(mut self) -> RedisResult<Option<CoordinatorState>> {
         debug!("get coordinator state");
         // https://redis.io/commands/get
         // > Get the value of key. If the key does not exist the special value nil is returned.
@@ -325,50 +368,127 @@ impl Connection {
         // Therefore we should agree on which checks are necessary.
 
         let mut pipe = redis::pipe();
+        add_local_seed_dict(&mut pipe, update_pk, update);
+        pipe.atomic().query_async(&mut self.connection).await
+    }
 
-        // https://redis.io/commands/sadd
-        // > Specified members that are already a member of this set are ignored.
-        //   If key does not exist, a new set is created before adding the specified members.
-        //   An error is returned when the value stored at key is not a set.
+    /// Updates the [`SeedDict`] with the seeds from the given ['UpdateParticipantPublicKey'] and
+    /// stores its masked model and masked scalar.
+    ///
+    /// All changes are applied in a single transaction, see [`update_seed_dict`] for the
+    /// guarantees that this provides. The masked model and scalar are kept until they are
+    /// contained in the checkpoints of the aggregations, see [`checkpoint_aggregations`].
+    ///
+    /// [`update_seed_dict`]: Connection::update_seed_dict
+    /// [`checkpoint_aggregations`]: Connection::checkpoint_aggregations
+    pub async fn add_update(
+        mut self,
+        update_pk: &UpdateParticipantPublicKey,
+        update: &LocalSeedDict,
+        masked_model: &MaskObject,
+        masked_scalar: &MaskObject,
+    ) -> RedisResult<()> {
+        debug!("add update of update participant with pk {:?}", update_pk);
+        let mut pipe = redis::pipe();
+        add_local_seed_dict(&mut pipe, update_pk, update);
+
+        // https://redis.io/commands/rpush
         // > Return value
-        //   Integer reply: the number of elements that were added to the set, not including all the
-        //   elements already present into the set.
+        //   Integer reply: the length of the list after the push operations.
         //
-        // TODO: not sure if we need this here. We used the Set in #394 to count and return
-        // the number of update participants. However, we can not rely on this returned
-        // number when we later process the updates in parallel.
-        // (the responses will likely be received by the coordinator in a different order than they were sent)
-        // We can add a separate method that returns the number of update participants and check at
-        // the end of the update phase if this number (number of update participants) is equal to
-        // the number (number of successful update messages) in the coordinator.
-        pipe.sadd(
-            "update_participants",
-            PublicSigningKeyWrite::from(update_pk),
-        )
-        .ignore();
+        // We ignore the return value because we are not interested in it.
+        pipe.rpush("masked_models", MaskObjectWrite::from(masked_model))
+            .ignore();
+        pipe.rpush("masked_scalars", MaskObjectWrite::from(masked_scalar))
+            .ignore();
+        pipe.atomic().query_async(&mut self.connection).await
+    }
 
-        // https://redis.io/commands/hsetnx
-        // > Sets field in the hash stored at key to value, only if field does not yet exist.
-        //   If key does not exist, a new key holding a hash is created. If field already exists,
-        //   this operation has no effect.
+    /// Retrieves the masked models of all update participants or an empty list when no masked
+    /// model has been stored.
+    pub async fn get_masked_models(mut self) -> RedisResult<Vec<MaskObject>> {
+        debug!("get masked models");
+        // https://redis.io/commands/lrange
         // > Return value
-        //   Integer reply, specifically:
-        //   1 if field is a new field in the hash and value was set.
-        //   0 if field already exists in the hash and no operation was performed.
+        //   Array reply: list of elements in the specified range.
+        let result: Vec<MaskObjectRead> = self.connection.lrange("masked_models", 0, -1).await?;
+        Ok(result.into_iter().map(|mask| mask.into()).collect())
+    }
+
+    /// Retrieves the masked scalars of all update participants or an empty list when no masked
+    /// scalar has been stored.
+    pub async fn get_masked_scalars(mut self) -> RedisResult<Vec<MaskObject>> {
+        debug!("get masked scalars");
+        // https://redis.io/commands/lrange
+        // > Return value
+        //   Array reply: list of elements in the specified range.
+        let result: Vec<MaskObjectRead> = self.connection.lrange("masked_scalars", 0, -1).await?;
+        Ok(result.into_iter().map(|mask| mask.into()).collect())
+    }
+
+    /// Stores the checkpoints of the model and scalar aggregations of the given number of masked
+    /// models and scalars and removes the given number of masked models and scalars, which are
+    /// contained in the checkpoints, from the front of their lists.
+    ///
+    /// All changes are applied in a single transaction, hence each stored masked model and
+    /// scalar is either contained in the checkpoints or in the lists.
+    pub async fn checkpoint_aggregations(
+        mut self,
+        model_agg: &MaskObject,
+        scalar_agg: &MaskObject,
+        nb_models: usize,
+        nb_removed: usize,
+    ) -> RedisResult<()> {
+        debug!("checkpoint the aggregations of {} masked models", nb_models);
+        let mut pipe = redis::pipe();
+        // https://redis.io/commands/set
+        // > Simple string reply: OK if SET was executed correctly.
+        pipe.set("model_aggregation", MaskObjectWrite::from(model_agg))
+            .ignore();
+        pipe.set("scalar_aggregation", MaskObjectWrite::from(scalar_agg))
+            .ignore();
+        pipe.set("aggregated_updates", nb_models).ignore();
+
+        // https://redis.io/commands/ltrim
+        // > Return value
+        //   Simple string reply
         //
-        // The return value `0` is not interpreted as error in Redis.
-        // TODO: Is it ok to ignore the returned value?
-        for (sum_pk, encr_seed) in update {
-            pipe.hset_nx(
-                PublicSigningKeyWrite::from(sum_pk),
-                PublicSigningKeyWrite::from(update_pk),
-                EncryptedMaskSeedWrite::from(encr_seed),
-            )
+        // Out of range indexes result in an empty list, which removes the key.
+        pipe.ltrim("masked_models", nb_removed as isize, -1)
+            .ignore();
+        pipe.ltrim("masked_scalars", nb_removed as isize, -1)
             .ignore();
-        }
         pipe.atomic().query_async(&mut self.connection).await
     }
 
+    /// Retrieves the checkpoints of the model and scalar aggregations together with the number of
+    /// aggregated masked models and scalars or `None` if nothing has been checkpointed.
+    pub async fn get_aggregations(
+        mut self,
+    ) -> RedisResult<Option<(MaskObject, MaskObject, usize)>> {
+        debug!("get aggregations");
+        // https://redis.io/commands/get
+        // > Return value
+        //   Bulk string reply: the value of key, or nil when key does not exist.
+        let result: (
+            Option<MaskObjectRead>,
+            Option<MaskObjectRead>,
+            Option<usize>,
+        ) = redis::pipe()
+            .atomic()
+            .get("model_aggregation")
+            .get("scalar_aggregation")
+            .get("aggregated_updates")
+            .query_async(&mut self.connection)
+            .await?;
+        Ok(match result {
+            (Some(model_agg), Some(scalar_agg), Some(nb_models)) => {
+                Some((model_agg.into(), scalar_agg.into(), nb_models))
+            }
+            _ => None,
+        })
+    }
+
     /// Updates the mask dictionary with the given [`MaskObject`].
     ///
     /// The score/counter of the given mask is incremented by `1`.
@@ -405,6 +525,183 @@ impl Connection {
             .collect())
     }
 
+    /// Marks the given [`SumParticipantPublicKey`] as sum2 participant and increments the
+    /// score/counter of its model mask and scalar mask by `1`.
+    ///
+    /// All changes are applied in a single transaction.
+    pub async fn add_masks(
+        mut self,
+        sum_pk: &SumParticipantPublicKey,
+        model_mask: &MaskObject,
+        scalar_mask: &MaskObject,
+    ) -> RedisResult<()> {
+        debug!("add masks of sum participant with pk {:?}", sum_pk);
+        let mut pipe = redis::pipe();
+
+        // https://redis.io/commands/sadd
+        // > Return value
+        //   Integer reply: the number of elements that were added to the set, not including all the
+        //   elements already present into the set.
+        pipe.sadd("sum2_participants", PublicSigningKeyWrite::from(sum_pk))
+            .ignore();
+
+        // https://redis.io/commands/zincrby
+        // We ignore the return values because we are not interested in them.
+        pipe.zincr("mask_dict", MaskObjectWrite::from(model_mask), 1_usize)
+            .ignore();
+        pipe.zincr(
+            "scalar_mask_dict",
+            MaskObjectWrite::from(scalar_mask),
+            1_usize,
+        )
+        .ignore();
+        pipe.atomic().query_async(&mut self.connection).await
+    }
+
+    /// Retrieves the [`SumParticipantPublicKey`] of all sum2 participants or an empty set when
+    /// no sum2 participant exists.
+    pub async fn get_sum2_participants(mut self) -> RedisResult<HashSet<SumParticipantPublicKey>> {
+        debug!("get public keys of all sum2 participants");
+        // https://redis.io/commands/smembers
+        // > Return value
+        //   Array reply: all elements of the set.
+        let result: HashSet<PublicSigningKeyRead> =
+            self.connection.smembers("sum2_participants").await?;
+        Ok(result.into_iter().map(|pk| pk.into()).collect())
+    }
+
+    /// Retrieves the model mask dictionary or an empty [`MaskDict`] when it does not exist.
+    pub async fn get_mask_dict(self) -> RedisResult<MaskDict> {
+        debug!("get model mask dictionary");
+        self.get_masks("mask_dict").await
+    }
+
+    /// Retrieves the scalar mask dictionary or an empty [`MaskDict`] when it does not exist.
+    pub async fn get_scalar_mask_dict(self) -> RedisResult<MaskDict> {
+        debug!("get scalar mask dictionary");
+        self.get_masks("scalar_mask_dict").await
+    }
+
+    async fn get_masks(mut self, key: &str) -> RedisResult<MaskDict> {
+        // https://redis.io/commands/zrange
+        // > Return value
+        //   Array reply: list of elements in the specified range (optionally with their scores,
+        //   in case the WITHSCORES option is given).
+        let result: Vec<(MaskObjectRead, usize)> =
+            self.connection.zrange_withscores(key, 0, -1).await?;
+        Ok(result
+            .into_iter()
+            .map(|(mask, count)| (mask.into(), count))
+            .collect())
+    }
+
+    /// Stores a global model and its metadata in the model history. If the history then holds
+    /// more than `limit` models, the oldest ones are deleted.
+    pub async fn add_global_model(
+        mut self,
+        info: &GlobalModelInfo,
+        model: &Model,
+        limit: usize,
+    ) -> RedisResult<()> {
+        debug!("add global model of round {}", info.round_id);
+        // https://redis.io/commands/hset
+        // > If field already exists in the hash, it is overwritten.
+        // We ignore the return values because we are not interested in them.
+        let mut pipe = redis::pipe();
+        pipe.hset("global_models", info.round_id, ModelWrite::from(model))
+            .ignore();
+        pipe.hset("global_model_infos", info.round_id, info)
+            .ignore();
+        pipe.atomic().query_async(&mut self.connection).await?;
+
+        // https://redis.io/commands/hkeys
+        // > Return value:
+        //   Array reply: list of fields in the hash, or an empty list when key does not exist.
+        let mut round_ids: Vec<u64> = self.connection.hkeys("global_model_infos").await?;
+        if round_ids.len() <= limit {
+            return Ok(());
+        }
+        round_ids.sort_unstable();
+        let outdated = &round_ids[..round_ids.len() - limit];
+        debug!("delete {} outdated global models", outdated.len());
+
+        // https://redis.io/commands/hdel
+        // > Specified fields that do not exist within this hash are ignored.
+        let mut pipe = redis::pipe();
+        pipe.hdel("global_models", outdated).ignore();
+        pipe.hdel("global_model_infos", outdated).ignore();
+        pipe.atomic().query_async(&mut self.connection).await
+    }
+
+    /// Retrieves the global model of the given round or `None` when it is not in the model
+    /// history.
+    pub async fn get_global_model(mut self, round_id: u64) -> RedisResult<Option<Model>> {
+        debug!("get global model of round {}", round_id);
+        // https://redis.io/commands/hget
+        // > Return value
+        //   Bulk string reply: the value associated with field, or nil when field is not present
+        //   in the hash or key does not exist.
+        let result: Option<ModelRead> = self.connection.hget("global_models", round_id).await?;
+        Ok(result.map(Into::into))
+    }
+
+    /// Retrieves the metadata of all global models in the model history, ordered by round ID.
+    pub async fn get_global_model_infos(mut self) -> RedisResult<Vec<GlobalModelInfo>> {
+        debug!("get global model infos");
+        // https://redis.io/commands/hvals
+        // > Return value
+        //   Array reply: list of values in the hash, or an empty list when key does not exist.
+        let mut infos: Vec<GlobalModelInfo> = self.connection.hvals("global_model_infos").await?;
+        infos.sort_unstable_by_key(|info| info.round_id);
+        Ok(infos)
+    }
+
+    /// Adds a participant to the participant registry.
+    ///
+    /// Returns `true` if the participant was added or `false` if it was already registered.
+    pub async fn add_allowed_participant(mut self, pk: &ParticipantPublicKey) -> RedisResult<bool> {
+        debug!("add participant with pk {:?} to the registry", pk);
+        // https://redis.io/commands/sadd
+        // > Return value
+        //   Integer reply: the number of elements that were added to the set, not including all the
+        //   elements already present into the set.
+        self.connection
+            .sadd("allowed_participants", PublicSigningKeyWrite::from(pk))
+            .await
+    }
+
+    /// Removes a participant from the participant registry.
+    ///
+    /// Returns `true` if the participant was removed or `false` if it was not registered.
+    pub async fn remove_allowed_participant(
+        mut self,
+        pk: &ParticipantPublicKey,
+    ) -> RedisResult<bool> {
+        debug!("remove participant with pk {:?} from the registry", pk);
+        // https://redis.io/commands/srem
+        // > Return value
+        //   Integer reply: the number of members that were removed from the set, not including non
+        //   existing members.
+        self.connection
+            .srem("allowed_participants", PublicSigningKeyWrite::from(pk))
+            .await
+    }
+
+    /// Checks whether a participant is in the participant registry.
+    pub async fn contains_allowed_participant(
+        mut self,
+        pk: &ParticipantPublicKey,
+    ) -> RedisResult<bool> {
+        // https://redis.io/commands/sismember
+        // > Return value
+        //   Integer reply, specifically:
+        //   1 if the element is a member of the set.
+        //   0 if the element is not a member of the set, or if key does not exist.
+        self.connection
+            .sismember("allowed_participants", PublicSigningKeyWrite::from(pk))
+            .await
+    }
+
     /// Deletes all data in the current database.
     pub async fn flush_db(mut self) -> RedisResult<()> {
         debug!("flush current database");
@@ -416,7 +713,8 @@ impl Connection {
             .await
     }
 
-    /// Deletes the dictionaries [`SumDict`], [`SeedDict`] and mask dictionary.
+    /// Deletes the dictionaries [`SumDict`], [`SeedDict`] and mask dictionaries as well as the
+    /// masked models and scalars.
     pub async fn flush_dicts(mut self) -> RedisResult<()> {
         debug!("flush all dictionaries");
         // https://redis.io/commands/hkeys
@@ -441,8 +739,17 @@ impl Connection {
             pipe.del(sum_pk).ignore();
         }
 
-        //delete mask dict
+        //delete masked models and scalars
+        pipe.del("masked_models").ignore();
+        pipe.del("masked_scalars").ignore();
+        pipe.del("model_aggregation").ignore();
+        pipe.del("scalar_aggregation").ignore();
+        pipe.del("aggregated_updates").ignore();
+
+        //delete mask dicts
+        pipe.del("sum2_participants").ignore();
         pipe.del("mask_dict").ignore();
+        pipe.del("scalar_mask_dict").ignore();
         pipe.atomic().query_async(&mut self.connection).await
     }
 
@@ -454,15 +761,68 @@ impl Connection {
     }
 }
 
+/// Adds the commands that update the [`SeedDict`] with the seeds from the given
+/// ['UpdateParticipantPublicKey'] to `pipe`.
+fn add_local_seed_dict(
+    pipe: &mut Pipeline,
+    update_pk: &UpdateParticipantPublicKey,
+    update: &LocalSeedDict,
+) {
+    // https://redis.io/commands/sadd
+    // > Specified members that are already a member of this set are ignored.
+    //   If key does not exist, a new set is created before adding the specified members.
+    //   An error is returned when the value stored at key is not a set.
+    // > Return value
+    //   Integer reply: the number of elements that were added to the set, not including all the
+    //   elements already present into the set.
+    //
+    // TODO: not sure if we need this here. We used the Set in #394 to count and return
+    // the number of update participants. However, we can not rely on this returned
+    // number when we later process the updates in parallel.
+    // (the responses will likely be received by the coordinator in a different order than they were sent)
+    // We can add a separate method that returns the number of update participants and check at
+    // the end of the update phase if this number (number of update participants) is equal to
+    // the number (number of successful update messages) in the coordinator.
+    pipe.sadd(
+        "update_participants",
+        PublicSigningKeyWrite::from(update_pk),
+    )
+    .ignore();
+
+    // https://redis.io/commands/hsetnx
+    // > Sets field in the hash stored at key to value, only if field does not yet exist.
+    //   If key does not exist, a new key holding a hash is created. If field already exists,
+    //   this operation has no effect.
+    // > Return value
+    //   Integer reply, specifically:
+    //   1 if field is a new field in the hash and value was set.
+    //   0 if field already exists in the hash and no operation was performed.
+    //
+    // The return value `0` is not interpreted as error in Redis.
+    // TODO: Is it ok to ignore the returned value?
+    for (sum_pk, encr_seed) in update {
+        pipe.hset_nx(
+            PublicSigningKeyWrite::from(sum_pk),
+            PublicSigningKeyWrite::from(update_pk),
+            EncryptedMaskSeedWrite::from(encr_seed),
+        )
+        .ignore();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::state_machine::tests::utils::{mask_settings, model_settings, pet_settings};
+    use crate::state_machine::{
+        events::MessageCounts,
+        tests::utils::{mask_settings, model_settings, pet_settings},
+    };
     use num::{bigint::BigUint, traits::identities::Zero};
     use serial_test::serial;
     use xaynet_core::{
-        crypto::{EncryptKeyPair, SigningKeyPair},
-        mask::{BoundType, DataType, GroupType, MaskConfig, MaskObject, ModelType},
+        common::RoundSeed,
+        crypto::{ByteObject, EncryptKeyPair, SigningKeyPair},
+        mask::{BoundType, DataType, FromPrimitives, GroupType, MaskConfig, MaskObject, ModelType},
     };
 
     fn create_mask(byte_size: usize) -> MaskObject {
@@ -658,6 +1018,163 @@ mod tests {
         assert_eq!(sum_dict.len(), 0);
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn integration_add_update() {
+        // test the writing and reading of an update
+        let client = init_client().await;
+
+        let SigningKeyPair { public: sum_pk, .. } = SigningKeyPair::generate();
+        let EncryptKeyPair {
+            public: sum_ephm_pk,
+            ..
+        } = EncryptKeyPair::generate();
+        client
+            .connection()
+            .await
+            .add_sum_participant(&sum_pk, &sum_ephm_pk)
+            .await
+            .unwrap();
+
+        let SigningKeyPair {
+            public: update_pk, ..
+        } = SigningKeyPair::generate();
+        let mut local_seed_dict = LocalSeedDict::new();
+        local_seed_dict.insert(sum_pk, EncryptedMaskSeed::zeroed());
+        let masked_model = create_mask(10);
+        let masked_scalar = create_mask(1);
+        client
+            .connection()
+            .await
+            .add_update(&update_pk, &local_seed_dict, &masked_model, &masked_scalar)
+            .await
+            .unwrap();
+
+        let seed_dict = client.connection().await.get_seed_dict().await.unwrap();
+        assert_eq!(
+            seed_dict.get(&sum_pk).unwrap().get(&update_pk).unwrap(),
+            &EncryptedMaskSeed::zeroed()
+        );
+
+        let masked_models = client.connection().await.get_masked_models().await.unwrap();
+        assert_eq!(masked_models, vec![masked_model]);
+        let masked_scalars = client
+            .connection()
+            .await
+            .get_masked_scalars()
+            .await
+            .unwrap();
+        assert_eq!(masked_scalars, vec![masked_scalar]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn integration_checkpoint_aggregations() {
+        let client = init_client().await;
+        let no_aggregations = client.connection().await.get_aggregations().await.unwrap();
+        assert!(no_aggregations.is_none());
+
+        let update_pk = SigningKeyPair::generate().public;
+        let local_seed_dict = LocalSeedDict::new();
+        for _ in 0..3 {
+            client
+                .connection()
+                .await
+                .add_update(
+                    &update_pk,
+                    &local_seed_dict,
+                    &create_mask(10),
+                    &create_mask(1),
+                )
+                .await
+                .unwrap();
+        }
+
+        // the checkpointed masked models and scalars are removed from the front of the lists
+        let model_agg = create_mask(10);
+        let scalar_agg = create_mask(1);
+        client
+            .connection()
+            .await
+            .checkpoint_aggregations(&model_agg, &scalar_agg, 2, 2)
+            .await
+            .unwrap();
+        let aggregations = client.connection().await.get_aggregations().await.unwrap();
+        assert_eq!(aggregations, Some((model_agg, scalar_agg, 2)));
+        let masked_models = client.connection().await.get_masked_models().await.unwrap();
+        assert_eq!(masked_models.len(), 1);
+        let masked_scalars = client
+            .connection()
+            .await
+            .get_masked_scalars()
+            .await
+            .unwrap();
+        assert_eq!(masked_scalars.len(), 1);
+
+        // ensure that flush_dicts removes the checkpoints
+        client.connection().await.flush_dicts().await.unwrap();
+        let no_aggregations = client.connection().await.get_aggregations().await.unwrap();
+        assert!(no_aggregations.is_none());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn integration_add_masks() {
+        // test the writing and reading of the masks of two sum2 participants
+        // that sent the same masks
+        let client = init_client().await;
+
+        let model_mask = create_mask(10);
+        let scalar_mask = create_mask(1);
+        let mut sum_pks = vec![];
+        for _ in 0..2 {
+            let SigningKeyPair { public: sum_pk, .. } = SigningKeyPair::generate();
+            client
+                .connection()
+                .await
+                .add_masks(&sum_pk, &model_mask, &scalar_mask)
+                .await
+                .unwrap();
+            sum_pks.push(sum_pk);
+        }
+
+        let sum2_participants = client
+            .connection()
+            .await
+            .get_sum2_participants()
+            .await
+            .unwrap();
+        assert_eq!(sum2_participants.len(), 2);
+        for sum_pk in sum_pks.iter() {
+            assert!(sum2_participants.contains(sum_pk));
+        }
+
+        let mask_dict = client.connection().await.get_mask_dict().await.unwrap();
+        assert_eq!(mask_dict.len(), 1);
+        assert_eq!(mask_dict.get(&model_mask), Some(&2));
+
+        let scalar_mask_dict = client
+            .connection()
+            .await
+            .get_scalar_mask_dict()
+            .await
+            .unwrap();
+        assert_eq!(scalar_mask_dict.len(), 1);
+        assert_eq!(scalar_mask_dict.get(&scalar_mask), Some(&2));
+
+        // ensure that flush_dicts removes the masks
+        client.connection().await.flush_dicts().await.unwrap();
+        let mask_dict = client.connection().await.get_mask_dict().await.unwrap();
+        assert!(mask_dict.is_empty());
+        let sum2_participants = client
+            .connection()
+            .await
+            .get_sum2_participants()
+            .await
+            .unwrap();
+        assert!(sum2_participants.is_empty());
+    }
+
     #[tokio::test]
     #[serial]
     async fn integration_flush_dicts_return() {
@@ -666,4 +1183,92 @@ mod tests {
         let res = client.connection().await.flush_db().await;
         assert!(res.is_ok())
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn integration_allowed_participants() {
+        // test the registration of participants, which is kept when the dictionaries are flushed
+        let client = init_client().await;
+        let pk = SigningKeyPair::generate().public;
+
+        let is_allowed = client
+            .connection()
+            .await
+            .contains_allowed_participant(&pk)
+            .await;
+        assert!(!is_allowed.unwrap());
+        let added = client.connection().await.add_allowed_participant(&pk).await;
+        assert!(added.unwrap());
+        let added = client.connection().await.add_allowed_participant(&pk).await;
+        assert!(!added.unwrap());
+
+        client.connection().await.flush_dicts().await.unwrap();
+        let is_allowed = client
+            .connection()
+            .await
+            .contains_allowed_participant(&pk)
+            .await;
+        assert!(is_allowed.unwrap());
+
+        let removed = client
+            .connection()
+            .await
+            .remove_allowed_participant(&pk)
+            .await;
+        assert!(removed.unwrap());
+        let removed = client
+            .connection()
+            .await
+            .remove_allowed_participant(&pk)
+            .await;
+        assert!(!removed.unwrap());
+        let is_allowed = client
+            .connection()
+            .await
+            .contains_allowed_participant(&pk)
+            .await;
+        assert!(!is_allowed.unwrap());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn integration_add_global_model_with_limit() {
+        // test the writing and reading of the model history
+        // the model of the first round is deleted once the limit is exceeded
+        let client = init_client().await;
+
+        for round_id in 1..=3 {
+            let info = GlobalModelInfo {
+                round_id,
+                seed: RoundSeed::generate(),
+                messages: MessageCounts::default(),
+            };
+            let model = Model::from_primitives(vec![round_id as i32; 4].into_iter()).unwrap();
+            client
+                .connection()
+                .await
+                .add_global_model(&info, &model, 2)
+                .await
+                .unwrap();
+        }
+
+        let infos = client
+            .connection()
+            .await
+            .get_global_model_infos()
+            .await
+            .unwrap();
+        assert_eq!(
+            infos.iter().map(|info| info.round_id).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+
+        let model = client.connection().await.get_global_model(1).await.unwrap();
+        assert!(model.is_none());
+        let model = client.connection().await.get_global_model(3).await.unwrap();
+        assert_eq!(
+            model,
+            Some(Model::from_primitives(vec![3_i32; 4].into_iter()).unwrap())
+        );
+    }
 }
@@ -0,0 +1,154 @@
+//! A registry of the participants which are allowed to take part in the PET protocol.
+
+use std::{collections::HashSet, fs, io, path::Path, sync::Arc};
+
+use ::redis::RedisError;
+use thiserror::Error;
+use xaynet_core::{crypto::ByteObject, ParticipantPublicKey};
+
+use crate::{settings::RegistrySettings, storage::redis};
+
+/// Errors related to the participant registry.
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    #[error("failed to read the participant registry: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid participant public key in line {0} of the participant registry")]
+    InvalidKey(usize),
+    #[error("the participant registry cannot be modified at runtime")]
+    Immutable,
+    #[error("failed to access the participant registry in Redis: {0}")]
+    Redis(#[from] RedisError),
+}
+
+#[derive(Clone, Debug)]
+/// A registry of the allowed participant public keys.
+pub enum ParticipantRegistry {
+    /// There is no registry, hence all participants are allowed.
+    Disabled,
+    /// A static registry which has been loaded from a file.
+    File(Arc<HashSet<ParticipantPublicKey>>),
+    /// A registry in Redis which can be modified at runtime.
+    Redis(redis::Client),
+}
+
+impl ParticipantRegistry {
+    /// Creates the participant registry for the given settings.
+    ///
+    /// # Errors
+    /// Fails if the file of a static registry cannot be read or contains an invalid key.
+    pub fn new(
+        settings: Option<RegistrySettings>,
+        redis: redis::Client,
+    ) -> Result<Self, RegistryError> {
+        Ok(match settings {
+            None => Self::Disabled,
+            Some(RegistrySettings::File { path }) => {
+                Self::File(Arc::new(load_participants(&path)?))
+            }
+            Some(RegistrySettings::Redis) => Self::Redis(redis),
+        })
+    }
+
+    /// Checks whether the participant is allowed to take part in the PET protocol.
+    ///
+    /// # Errors
+    /// Fails if the registry in Redis cannot be accessed.
+    pub async fn is_allowed(&self, pk: &ParticipantPublicKey) -> Result<bool, RegistryError> {
+        match self {
+            Self::Disabled => Ok(true),
+            Self::File(participants) => Ok(participants.contains(pk)),
+            Self::Redis(redis) => Ok(redis
+                .connection()
+                .await
+                .contains_allowed_participant(pk)
+                .await?),
+        }
+    }
+
+    /// Registers a participant.
+    ///
+    /// Returns `true` if the participant was registered or `false` if it was already registered.
+    ///
+    /// # Errors
+    /// Fails if the registry is not kept in Redis or if it cannot be accessed.
+    pub async fn register(&self, pk: &ParticipantPublicKey) -> Result<bool, RegistryError> {
+        match self {
+            Self::Disabled | Self::File(_) => Err(RegistryError::Immutable),
+            Self::Redis(redis) => Ok(redis.connection().await.add_allowed_participant(pk).await?),
+        }
+    }
+
+    /// Unregisters a participant.
+    ///
+    /// Returns `true` if the participant was unregistered or `false` if it was not registered.
+    ///
+    /// # Errors
+    /// Fails if the registry is not kept in Redis or if it cannot be accessed.
+    pub async fn unregister(&self, pk: &ParticipantPublicKey) -> Result<bool, RegistryError> {
+        match self {
+            Self::Disabled | Self::File(_) => Err(RegistryError::Immutable),
+            Self::Redis(redis) => Ok(redis
+                .connection()
+                .await
+                .remove_allowed_participant(pk)
+                .await?),
+        }
+    }
+}
+
+/// Loads the participant public keys from a file.
+fn load_participants(path: &Path) -> Result<HashSet<ParticipantPublicKey>, RegistryError> {
+    parse_participants(&fs::read_to_string(path)?)
+}
+
+/// Parses one hex encoded participant public key per line. Empty lines and lines starting with
+/// `#` are skipped.
+fn parse_participants(content: &str) -> Result<HashSet<ParticipantPublicKey>, RegistryError> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            ParticipantPublicKey::from_hex(line).ok_or(RegistryError::InvalidKey(number))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xaynet_core::crypto::SigningKeyPair;
+
+    #[test]
+    fn test_parse_participants() {
+        let pk_1 = SigningKeyPair::generate().public;
+        let pk_2 = SigningKeyPair::generate().public;
+        let content = format!(
+            "# allowed participants\n{}\n\n  {}  \n",
+            pk_1.to_hex(),
+            pk_2.to_hex().to_uppercase(),
+        );
+        let participants = parse_participants(&content).unwrap();
+        assert_eq!(participants, vec![pk_1, pk_2].into_iter().collect());
+    }
+
+    #[test]
+    fn test_parse_participants_invalid() {
+        let pk = SigningKeyPair::generate().public;
+        let hex = pk.to_hex();
+        let invalid_keys = vec![
+            hex[1..].to_string(),
+            format!("+{}", &hex[1..]),
+            format!("{}00", hex),
+        ];
+        for invalid in invalid_keys {
+            let content = format!("{}\n{}", hex, invalid);
+            assert!(matches!(
+                parse_participants(&content),
+                Err(RegistryError::InvalidKey(2))
+            ));
+        }
+    }
+}